@@ -0,0 +1,87 @@
+//! Benchmarks of the core AST operations.
+//!
+//! The operations measured here are the ones whose cost is dominated by the
+//! crate's own design decisions — the `Rc` layering, the token-stream
+//! machinery and the serde schema — rather than by the parser: building,
+//! deserializing, traversing, rendering and structurally diffing a module.
+//! Run with `cargo bench -p ast`.
+
+use ast::Ast;
+use ast::HasRepr;
+use ast::HasSpan;
+
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+
+
+// ==========================
+// === Synthetic modules  ===
+// ==========================
+
+/// A module of `lines` lines, each an assignment of a ten-operand sum, e.g.
+/// `x17 = a0 + a1 + … + a9`. Roughly the shape distribution of a graph
+/// editor scene: wide modules of moderately deep expressions.
+fn module(lines:usize) -> Ast {
+    let line = |index:usize| {
+        let mut sum = Ast::var("a0");
+        for operand in 1..10 {
+            sum = Ast::infix(sum, "+", Ast::var(format!("a{}", operand)));
+        }
+        Ast::infix(Ast::var(format!("x{}", index)), "=", sum)
+    };
+    Ast::module((0..lines).map(|index| Some(line(index))).collect())
+}
+
+
+
+// ==================
+// === Benchmarks ===
+// ==================
+
+fn benchmarks(criterion:&mut Criterion) {
+    let module = module(1000);
+    let json   = serde_json::to_string(&module).unwrap();
+
+    criterion.bench_function("deserialize 1000-line module", |bencher| {
+        bencher.iter(|| {
+            let ast:Ast = serde_json::from_str(black_box(&json)).unwrap();
+            ast
+        })
+    });
+
+    criterion.bench_function("traverse 1000-line module", |bencher| {
+        bencher.iter(|| black_box(&module).iterate_subtree().count())
+    });
+
+    criterion.bench_function("recompute spans (rebuild every node)", |bencher| {
+        // `with_shape` re-layers the length cache, so a full map_children
+        // rebuild measures span recomputation over the whole tree.
+        fn rebuild(ast:&Ast) -> Ast {
+            ast.with_shape(ast.shape().map_children(rebuild))
+        }
+        bencher.iter(|| {
+            let rebuilt = rebuild(black_box(&module));
+            black_box(rebuilt.span())
+        })
+    });
+
+    criterion.bench_function("repr of 1000-line module", |bencher| {
+        bencher.iter(|| black_box(&module).repr())
+    });
+
+    criterion.bench_function("structural diff via digests", |bencher| {
+        let edited = module.set_node(&[500,2], Ast::var("changed")).unwrap();
+        bencher.iter(|| {
+            let mut cache = ast::digest::DigestCache::default();
+            let original  = cache.digest_of(black_box(&module));
+            let changed   = cache.digest_of(black_box(&edited));
+            original != changed
+        })
+    });
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
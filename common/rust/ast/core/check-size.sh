@@ -0,0 +1,22 @@
+#!/bin/sh
+# Guards the size of the AST crate compiled for the IDE's wasm bundle.
+#
+# Builds the crate with the small-footprint feature set (no serde, no uuid
+# generation) and fails if the produced wasm exceeds the budget. Run it from
+# the crate root. Requires the wasm32-unknown-unknown target.
+
+set -e
+
+# The budget, in bytes. Bump it consciously, with a justification in the
+# commit message.
+BUDGET=${AST_WASM_BUDGET:-2500000}
+
+cargo build --release --target wasm32-unknown-unknown --no-default-features
+WASM=../../../../target/wasm32-unknown-unknown/release/ast.wasm
+
+SIZE=$(wc -c < "$WASM")
+echo "ast.wasm: $SIZE bytes (budget: $BUDGET)"
+if [ "$SIZE" -gt "$BUDGET" ]; then
+    echo "error: wasm size regression — $SIZE exceeds the budget of $BUDGET" >&2
+    exit 1
+fi
@@ -0,0 +1,25 @@
+//! Fuzzes `Ast` deserialization and the invariants built on top of it.
+//!
+//! Trees arrive over the network from the parser service, so the
+//! deserializer processes untrusted input. Beyond not panicking while
+//! decoding, a tree that *does* decode must be safe to walk: the repr,
+//! span and children machinery all trust the decoded structure. Run with
+//! `cargo fuzz run ast_deserialization` from the crate root.
+
+#![no_main]
+
+use ast::HasRepr;
+use ast::HasSpan;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data:&[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Ok(ast) = serde_json::from_str::<ast::Ast>(text) {
+            let _ = ast.repr();
+            let _ = ast.span();
+            for node in ast.iterate_subtree() {
+                let _ = node.children();
+            }
+        }
+    }
+});
@@ -0,0 +1,237 @@
+//! Light-weight semantic analysis helpers.
+//!
+//! Binding-name suggestion gives every "create node" action a variable
+//! name that says something about the expression — `sum` for an addition,
+//! `vector` for a `Vector.new` call — instead of `var1`, `var2` counters.
+//! Unrecognized-token classification turns the parser's flat "unrecognized"
+//! into a structured hint the editor can show and offer a fix for.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::HasRepr;
+use crate::Shape;
+
+use std::collections::HashSet;
+
+
+
+// ========================
+// === Name suggestions ===
+// ========================
+
+/// Suggests a readable variable name for the expression, avoiding the
+/// names already used in the enclosing scope.
+///
+/// The base name is derived from the expression (see `base_name`); if it is
+/// taken, a numeric suffix is appended — `sum`, `sum1`, `sum2`, …
+pub fn suggest_binding_name(expr:&Ast, used:&HashSet<String>) -> String {
+    let base = base_name(expr);
+    if !used.contains(&base) {
+        return base;
+    }
+    let mut counter = 1;
+    loop {
+        let candidate = format!("{}{}", base, counter);
+        if !used.contains(&candidate) {
+            return candidate;
+        }
+        counter += 1;
+    }
+}
+
+/// The name the expression reads as, without de-duplication.
+///
+/// Applications are named after their function, access chains after their
+/// last segment, constructors after themselves (lowercased), arithmetic
+/// after its result; anything else falls back to `node`.
+pub fn base_name(expr:&Ast) -> String {
+    match expr.shape() {
+        Shape::Var(var)   => var.name.clone(),
+        Shape::Cons(cons) => lowercased(&cons.name),
+        Shape::Number {..} => "number".to_string(),
+        Shape::TextLineRaw {..} | Shape::TextLineFmt {..}
+        | Shape::TextBlockRaw {..} | Shape::TextBlockFmt {..} => "text".to_string(),
+        Shape::Prefix(prefix) => base_name(&prefix.func),
+        Shape::Infix(infix)   => match infix.opr.repr().as_str() {
+            "." => base_name(&infix.rarg),
+            "+" => "sum".to_string(),
+            "-" => "difference".to_string(),
+            "*" => "product".to_string(),
+            "/" => "quotient".to_string(),
+            _   => "node".to_string(),
+        },
+        _ => "node".to_string(),
+    }
+}
+
+fn lowercased(name:&str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None        => String::new(),
+    }
+}
+
+
+
+// =====================================
+// === Unrecognized token diagnosis  ===
+// =====================================
+
+/// A structured reading of an `Unrecognized` node's raw text.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum UnrecognizedKind {
+    /// The text is made of operator characters but is not a known operator;
+    /// likely a typo of the suggested one.
+    OperatorTypo {
+        /// The known operator closest to the text.
+        suggestion : String,
+    },
+    /// The text contains a character the language never accepts.
+    InvalidCharacter {
+        /// The offending character.
+        character : char,
+    },
+    /// The text contains an unpaired quote.
+    MismatchedQuote,
+    /// No better reading; the generic "unrecognized" it always was.
+    Unknown,
+}
+
+impl UnrecognizedKind {
+    /// The replacement text to offer as a quick fix, if there is one.
+    pub fn suggestion(&self) -> Option<&str> {
+        match self {
+            UnrecognizedKind::OperatorTypo {suggestion} => Some(suggestion),
+            _                                           => None,
+        }
+    }
+}
+
+/// The operators the classifier suggests corrections towards.
+const KNOWN_OPERATORS:&[&str] = &[
+    "=", "==", "!=", ">=", "<=", "<", ">", "+", "-", "*", "/", "%", "^",
+    ".", ",", "->", "<-", "|", "&", ":", "+=", "-=",
+];
+
+/// Classifies the raw text of an `Unrecognized` node.
+pub fn classify_unrecognized(text:&str) -> UnrecognizedKind {
+    let is_operator_char = |chr:char| "=!<>+-*/%^.,|&:".contains(chr);
+
+    let double_quotes = text.chars().filter(|chr| *chr == '"').count();
+    let single_quotes = text.chars().filter(|chr| *chr == '\'').count();
+    if double_quotes % 2 == 1 || single_quotes % 2 == 1 {
+        return UnrecognizedKind::MismatchedQuote;
+    }
+
+    if !text.is_empty() && text.chars().all(is_operator_char) {
+        if let Some(suggestion) = closest_operator(text) {
+            return UnrecognizedKind::OperatorTypo {suggestion};
+        }
+    }
+
+    let allowed = |chr:char| {
+        chr.is_alphanumeric() || chr.is_whitespace() || is_operator_char(chr)
+            || "_()[]{}#\'\"`$@".contains(chr)
+    };
+    if let Some(character) = text.chars().find(|chr| !allowed(*chr)) {
+        return UnrecognizedKind::InvalidCharacter {character};
+    }
+
+    UnrecognizedKind::Unknown
+}
+
+/// The known operator within edit distance one of the text, if any.
+fn closest_operator(text:&str) -> Option<String> {
+    KNOWN_OPERATORS.iter()
+        .find(|operator| edit_distance_is_one(text, operator))
+        .map(|operator| operator.to_string())
+}
+
+/// Checks whether the two strings differ by exactly one edit — a
+/// substitution, insertion or deletion. Enough for typo detection on
+/// operators, which are at most a few characters long.
+fn edit_distance_is_one(a:&str, b:&str) -> bool {
+    let a:Vec<char> = a.chars().collect();
+    let b:Vec<char> = b.chars().collect();
+    match a.len() as isize - b.len() as isize {
+        0 => a.iter().zip(&b).filter(|(x,y)| x != y).count() == 1,
+        1 => skips_one(&a,&b),
+        -1 => skips_one(&b,&a),
+        _ => false,
+    }
+}
+
+/// Checks whether `longer` equals `shorter` with one extra character.
+fn skips_one(longer:&[char], shorter:&[char]) -> bool {
+    let mut l = 0;
+    let mut s = 0;
+    let mut skipped = false;
+    while l < longer.len() {
+        if s < shorter.len() && longer[l] == shorter[s] {
+            l += 1;
+            s += 1;
+        } else if skipped {
+            return false;
+        } else {
+            skipped = true;
+            l += 1;
+        }
+    }
+    s == shorter.len()
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn used(names:&[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn names_read_off_the_expression() {
+        let call = Ast::prefix(Ast::var("sum"), Ast::var("xs"));
+        assert_eq!(base_name(&call), "sum");
+
+        // `Vector.new 1` — the last access segment names the result.
+        let access  = Ast::infix(Ast::cons("Vector"), ".", Ast::var("new"));
+        let applied = Ast::prefix(access, Ast::number("1"));
+        assert_eq!(base_name(&applied), "new");
+
+        assert_eq!(base_name(&Ast::cons("Point")), "point");
+        assert_eq!(base_name(&Ast::infix(Ast::var("a"), "+", Ast::var("b"))), "sum");
+        assert_eq!(base_name(&Ast::number("5")), "number");
+        assert_eq!(base_name(&Ast::blank()), "node");
+    }
+
+    #[test]
+    fn unrecognized_text_is_classified() {
+        assert_eq!(classify_unrecognized("=-"),
+            UnrecognizedKind::OperatorTypo {suggestion:"=".to_string()});
+        assert_eq!(classify_unrecognized("==="),
+            UnrecognizedKind::OperatorTypo {suggestion:"==".to_string()});
+        assert_eq!(classify_unrecognized("\"abc"), UnrecognizedKind::MismatchedQuote);
+        assert_eq!(classify_unrecognized("a\u{7}b"),
+            UnrecognizedKind::InvalidCharacter {character:'\u{7}'});
+        assert_eq!(classify_unrecognized("foo bar"), UnrecognizedKind::Unknown);
+
+        let typo = classify_unrecognized("=-");
+        assert_eq!(typo.suggestion(), Some("="));
+    }
+
+    #[test]
+    fn suggestions_avoid_used_names() {
+        let sum = Ast::infix(Ast::var("a"), "+", Ast::var("b"));
+        assert_eq!(suggest_binding_name(&sum, &used(&[])), "sum");
+        assert_eq!(suggest_binding_name(&sum, &used(&["sum"])), "sum1");
+        assert_eq!(suggest_binding_name(&sum, &used(&["sum","sum1"])), "sum2");
+    }
+}
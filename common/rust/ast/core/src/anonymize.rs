@@ -0,0 +1,179 @@
+//! Anonymization of ASTs for bug reports.
+//!
+//! `anonymize` replaces identifier names and literal contents with
+//! deterministic placeholders while preserving the tree structure, node
+//! spans and ids. Users can attach a failing AST to a bug report without
+//! leaking proprietary code, and since the replacement is deterministic
+//! (the same name maps to the same placeholder), patterns like repeated
+//! usages of one identifier survive and the original failure usually still
+//! reproduces.
+
+use crate::Ast;
+use crate::SegmentFmt;
+use crate::SegmentPlain;
+use crate::SegmentRaw;
+use crate::Shape;
+use crate::TextBlockLine;
+use crate::TextLine;
+use crate::TextLineFmt;
+use crate::TextLineRaw;
+
+use std::collections::HashMap;
+
+
+
+// ==================
+// === Anonymizer ===
+// ==================
+
+/// Anonymizes the tree, replacing identifier names and literal contents
+/// with deterministic placeholders. Structure, spans and ids are preserved.
+pub fn anonymize(ast:&Ast) -> Ast {
+    Anonymizer::default().run(ast)
+}
+
+/// The state of an anonymization pass: the identifier renaming table.
+///
+/// A value of this type can be reused across multiple trees to rename
+/// identifiers consistently between them.
+#[derive(Debug,Default)]
+pub struct Anonymizer {
+    names : HashMap<String,usize>,
+}
+
+impl Anonymizer {
+    /// Anonymizes a single tree. See `anonymize`.
+    pub fn run(&mut self, ast:&Ast) -> Ast {
+        let shape = match ast.shape() {
+            Shape::Var(t)  => Shape::Var (crate::Var  {name : self.ident(&t.name,false)}),
+            Shape::Cons(t) => Shape::Cons(crate::Cons {name : self.ident(&t.name,true)}),
+            Shape::Number(t) => Shape::Number(crate::Number {
+                base : t.base.clone(),
+                int  : t.int.chars().map(|_| '0').collect(),
+            }),
+            Shape::Unrecognized(t) =>
+                Shape::Unrecognized(crate::Unrecognized {str : scrub(&t.str)}),
+            Shape::Comment(t) =>
+                Shape::Comment(crate::Comment {lines : t.lines.iter().map(|l| scrub(l)).collect()}),
+            Shape::TextLineRaw(t) => Shape::TextLineRaw(TextLineRaw {
+                text : t.text.iter().map(scrub_raw_segment).collect(),
+            }),
+            Shape::TextLineFmt(t) => Shape::TextLineFmt(TextLineFmt {
+                text : t.text.iter().map(|seg| self.scrub_fmt_segment(seg)).collect(),
+            }),
+            Shape::TextBlockRaw(t) => Shape::TextBlockRaw(crate::TextBlockRaw {
+                text   : t.text.iter().map(|line| TextBlockLine {
+                    empty_lines : line.empty_lines.clone(),
+                    text        : line.text.iter().map(scrub_raw_segment).collect(),
+                }).collect(),
+                spaces : t.spaces,
+                offset : t.offset,
+            }),
+            Shape::TextBlockFmt(t) => Shape::TextBlockFmt(crate::TextBlockFmt {
+                text   : t.text.iter().map(|line| TextBlockLine {
+                    empty_lines : line.empty_lines.clone(),
+                    text        : line.text.iter().map(|seg| self.scrub_fmt_segment(seg)).collect(),
+                }).collect(),
+                spaces : t.spaces,
+                offset : t.offset,
+            }),
+            Shape::TextUnclosed(t) => Shape::TextUnclosed(crate::TextUnclosed {
+                line : match &t.line {
+                    TextLine::TextLineRaw(line) => TextLine::TextLineRaw(TextLineRaw {
+                        text : line.text.iter().map(scrub_raw_segment).collect(),
+                    }),
+                    TextLine::TextLineFmt(line) => TextLine::TextLineFmt(TextLineFmt {
+                        text : line.text.iter().map(|seg| self.scrub_fmt_segment(seg)).collect(),
+                    }),
+                },
+            }),
+            other => other.map_children(|child| self.run(child)),
+        };
+        ast.with_shape(shape)
+    }
+
+    /// The placeholder for an identifier, rendered to the original name's
+    /// length so that spans do not shift. Uniqueness can be lost when the
+    /// original name is shorter than the rendered index; span preservation
+    /// takes precedence.
+    fn ident(&mut self, name:&str, capitalized:bool) -> String {
+        let next  = self.names.len();
+        let index = *self.names.entry(name.to_string()).or_insert(next);
+        let head  = if capitalized {'A'} else {'a'};
+        let body  = format!("{}{}", head, index);
+        let len   = name.chars().count();
+        let pad   = std::iter::repeat('_');
+        body.chars().chain(pad).take(len).collect()
+    }
+
+    fn scrub_fmt_segment(&mut self, segment:&SegmentFmt<Ast>) -> SegmentFmt<Ast> {
+        match segment {
+            SegmentFmt::SegmentPlain(seg) =>
+                SegmentFmt::SegmentPlain(SegmentPlain {value : scrub(&seg.value)}),
+            SegmentFmt::SegmentExpr(seg) => SegmentFmt::SegmentExpr(crate::SegmentExpr {
+                value : seg.value.as_ref().map(|ast| self.run(ast)),
+            }),
+            SegmentFmt::SegmentEscape(seg) => SegmentFmt::SegmentEscape(seg.clone()),
+        }
+    }
+}
+
+/// Replaces text contents with `x`-es, preserving whitespace so the shape
+/// of the literal remains recognizable.
+fn scrub(text:&str) -> String {
+    text.chars().map(|c| if c.is_whitespace() {c} else {'x'}).collect()
+}
+
+fn scrub_raw_segment(segment:&SegmentRaw) -> SegmentRaw {
+    match segment {
+        SegmentRaw::SegmentPlain(seg) =>
+            SegmentRaw::SegmentPlain(SegmentPlain {value : scrub(&seg.value)}),
+        SegmentRaw::SegmentRawEscape(seg) => SegmentRaw::SegmentRawEscape(seg.clone()),
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HasRepr;
+    use crate::HasSpan;
+    use crate::Id;
+
+    #[test]
+    fn anonymization_preserves_structure_spans_and_ids() {
+        let id  = Id::new_v4();
+        let ast = Ast::infix(
+            Ast::prefix(Ast::var("secret"), Ast::var("payload")).with_id(id),
+            "+",
+            Ast::var("secret"));
+        let anon = anonymize(&ast);
+        assert_eq!(anon.span(), ast.span());
+        assert_eq!(anon.children().len(), ast.children().len());
+        assert_eq!(anon.children()[0].id(), Some(id));
+        assert!(!anon.repr().contains("secret"));
+        assert!(!anon.repr().contains("payload"));
+    }
+
+    #[test]
+    fn same_name_maps_to_same_placeholder() {
+        let ast  = Ast::infix(Ast::var("secret"), "+", Ast::var("secret"));
+        let anon = anonymize(&ast);
+        match (anon.children()[0].shape(), anon.children()[2].shape()) {
+            (Shape::Var(a),Shape::Var(b)) => assert_eq!(a.name, b.name),
+            other => panic!("expected two vars, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn literals_are_scrubbed() {
+        let ast  = Ast::number("1234");
+        let anon = anonymize(&ast);
+        assert_eq!(anon.repr(), "0000");
+    }
+}
@@ -0,0 +1,157 @@
+//! Type ascriptions — `name : Type` — and signature lines.
+//!
+//! An ascription is ordinary infix syntax, so nothing here is a new shape;
+//! these helpers just give the convention a name: building ascription
+//! nodes, recognizing them, and pairing a signature line in a module with
+//! the definition it describes, the way the IDE needs to show and edit type
+//! annotations.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::HasRepr;
+use crate::Shape;
+
+
+
+// ==================
+// === Ascription ===
+// ==================
+
+/// A recognized ascription: the ascribed expression and its type.
+#[derive(Clone,Copy,Debug)]
+pub struct Ascription<'a> {
+    /// The expression being ascribed — for signatures, the defined name.
+    pub target : &'a Ast,
+    /// The ascribed type.
+    pub signature : &'a Ast,
+}
+
+/// Builds an ascription node: `target : signature`.
+pub fn ascribe(target:Ast, signature:Ast) -> Ast {
+    Ast::infix(target, ":", signature)
+}
+
+/// Recognizes an ascription node.
+pub fn as_ascription(ast:&Ast) -> Option<Ascription<'_>> {
+    match ast.shape() {
+        Shape::Infix(infix) if infix.opr.repr() == ":" => Some(Ascription {
+            target    : &infix.larg,
+            signature : &infix.rarg,
+        }),
+        _ => None,
+    }
+}
+
+/// The name a line defines, if it is a definition (an `=` binding); for
+/// function definitions the arguments are stripped.
+pub fn defined_name(line:&Ast) -> Option<&str> {
+    let infix = match line.shape() {
+        Shape::Infix(infix) if infix.opr.repr() == "=" => infix,
+        _                                              => return None,
+    };
+    let mut target = &infix.larg;
+    while let Shape::Prefix(prefix) = target.shape() {
+        target = &prefix.func;
+    }
+    match target.shape() {
+        Shape::Var(var) => Some(&var.name),
+        _               => None,
+    }
+}
+
+
+
+// ==========================
+// === Signature linking  ===
+// ==========================
+
+/// A signature line paired with the definition it describes.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct LinkedSignature {
+    /// Index of the signature line within the module.
+    pub signature_line : usize,
+    /// Index of the definition line, if one follows.
+    ///
+    /// `None` marks a dangling signature — a diagnostic-worthy situation.
+    pub definition_line : Option<usize>,
+}
+
+/// Pairs the signature lines of a module with their definitions.
+///
+/// A signature describes the next definition of the same name; only blank
+/// lines and comments may stand between them, matching how the engine
+/// associates the two.
+pub fn link_signatures(module:&Ast) -> Vec<LinkedSignature> {
+    let lines = match module.shape() {
+        Shape::Module(module) => &module.lines,
+        _                     => return Vec::new(),
+    };
+    let mut linked = Vec::new();
+    for (index,line) in lines.iter().enumerate() {
+        let elem = match &line.elem {
+            Some(elem) => elem,
+            None       => continue,
+        };
+        let name = match as_ascription(elem) {
+            Some(ascription) => match ascription.target.shape() {
+                Shape::Var(var) => &var.name,
+                _               => continue,
+            },
+            None => continue,
+        };
+        let mut definition_line = None;
+        for (offset,line) in lines[index+1..].iter().enumerate() {
+            match &line.elem {
+                None => continue,
+                Some(elem) if matches!(elem.shape(), Shape::Comment(_)) => continue,
+                Some(elem) => {
+                    if defined_name(elem) == Some(name) {
+                        definition_line = Some(index + 1 + offset);
+                    }
+                    break;
+                }
+            }
+        }
+        linked.push(LinkedSignature {signature_line:index, definition_line});
+    }
+    linked
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn building_and_recognizing() {
+        let ascription = ascribe(Ast::var("foo"), Ast::cons("Number"));
+        assert_eq!(ascription.repr(), "foo : Number");
+
+        let recognized = as_ascription(&ascription).unwrap();
+        assert_eq!(recognized.target.repr(), "foo");
+        assert_eq!(recognized.signature.repr(), "Number");
+        assert!(as_ascription(&Ast::var("foo")).is_none());
+    }
+
+    #[test]
+    fn signatures_link_to_their_definitions() {
+        let module = Ast::module(vec![
+            Some(ascribe(Ast::var("foo"), Ast::cons("Number"))),
+            None,
+            Some(Ast::infix(Ast::prefix(Ast::var("foo"), Ast::var("x")), "=", Ast::var("x"))),
+            Some(ascribe(Ast::var("bar"), Ast::cons("Text"))),
+            Some(Ast::infix(Ast::var("baz"), "=", Ast::number("1"))),
+        ]);
+        let linked = link_signatures(&module);
+        assert_eq!(linked, vec![
+            LinkedSignature {signature_line:0, definition_line:Some(2)},
+            LinkedSignature {signature_line:3, definition_line:None},
+        ]);
+    }
+}
@@ -0,0 +1,168 @@
+//! A persistent on-disk cache of parse results.
+//!
+//! Re-parsing an unchanged project on every IDE start wastes seconds, and
+//! the parse result is a pure function of the source text and the parser
+//! version — exactly the shape of thing worth caching. Each module's tree
+//! is stored under the project's cache directory together with a SHA-1 of
+//! the source it was parsed from and the parser version that produced it;
+//! on load, a mismatch of either simply misses, and so does any file the
+//! deserializer rejects, so a stale or corrupt cache can never produce a
+//! wrong tree — only a re-parse.
+
+use prelude::*;
+
+use crate::Ast;
+
+use serde::Deserialize;
+use serde::Serialize;
+use sha1::Digest as _;
+use sha1::Sha1;
+
+use std::path::Path;
+use std::path::PathBuf;
+
+
+
+// ================
+// === AstCache ===
+// ================
+
+/// One cached parse result, as stored on disk.
+///
+/// The envelope is bincode, but the tree inside it is JSON bytes: the id
+/// and length layers are `serde(flatten)`ed into the shape, and flattening
+/// only round-trips through self-describing formats.
+#[derive(Clone,Debug,Serialize,Deserialize)]
+struct Entry {
+    /// The version of the parser that produced the tree.
+    parser_version : String,
+    /// Hex SHA-1 of the source text the tree was parsed from.
+    source_hash : String,
+    /// The parse result, JSON-encoded.
+    ast : Vec<u8>,
+}
+
+/// A parse-result cache rooted in a project's cache directory.
+#[derive(Clone,Debug)]
+pub struct AstCache {
+    root           : PathBuf,
+    parser_version : String,
+}
+
+impl AstCache {
+    /// A cache under the given directory, keyed to the given parser
+    /// version. The directory is created on first store.
+    pub fn new(root:impl Into<PathBuf>, parser_version:impl Str) -> AstCache {
+        AstCache {root:root.into(), parser_version:parser_version.into()}
+    }
+
+    /// Persists a module's parse result.
+    pub fn store(&self, module:&str, source:&str, ast:&Ast) -> std::io::Result<()> {
+        let entry = Entry {
+            parser_version : self.parser_version.clone(),
+            source_hash    : source_hash(source),
+            ast            : serde_json::to_vec(ast).map_err(to_io)?,
+        };
+        let bytes = bincode::serialize(&entry).map_err(to_io)?;
+        std::fs::create_dir_all(&self.root)?;
+        std::fs::write(self.entry_path(module), bytes)
+    }
+
+    /// The cached parse result for the module, provided the cached source
+    /// hash and parser version still match. Any mismatch — or a missing or
+    /// unreadable entry — is a miss.
+    pub fn load(&self, module:&str, source:&str) -> Option<Ast> {
+        let bytes = std::fs::read(self.entry_path(module)).ok()?;
+        let entry:Entry = bincode::deserialize(&bytes).ok()?;
+        let valid = entry.parser_version == self.parser_version
+            && entry.source_hash == source_hash(source);
+        if valid { serde_json::from_slice(&entry.ast).ok() } else { None }
+    }
+
+    /// Drops the module's cached entry, if any.
+    pub fn evict(&self, module:&str) {
+        let _ = std::fs::remove_file(self.entry_path(module));
+    }
+
+    /// The cache directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where the module's entry lives. Module names are qualified
+    /// (`Base.List`); the dots become dashes on disk.
+    fn entry_path(&self, module:&str) -> PathBuf {
+        self.root.join(format!("{}.ast", module.replace('.', "-")))
+    }
+}
+
+/// Wraps an encoding failure as an io error, so `store` has a single
+/// error type.
+fn to_io(error:impl Into<Box<dyn std::error::Error + Send + Sync>>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
+
+/// Hex SHA-1 of the source text.
+fn source_hash(source:&str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input(source.as_bytes());
+    let mut hex = String::new();
+    for byte in hasher.result() {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cache in a fresh temporary directory.
+    fn cache(version:&str) -> AstCache {
+        let unique = format!("enso-ast-cache-test-{}-{:p}", std::process::id(), &version);
+        AstCache::new(std::env::temp_dir().join(unique), version)
+    }
+
+    #[test]
+    fn cached_trees_reload_for_unchanged_sources() {
+        let cache  = cache("1.0");
+        let source = "a + b";
+        let ast    = Ast::infix(Ast::var("a"), "+", Ast::var("b"));
+        cache.store("Project.Main", source, &ast).unwrap();
+
+        assert_eq!(cache.load("Project.Main", source), Some(ast));
+        // An edited source must re-parse.
+        assert_eq!(cache.load("Project.Main", "a + c"), None);
+        // So must a module that was never stored.
+        assert_eq!(cache.load("Project.Other", source), None);
+
+        cache.evict("Project.Main");
+        assert_eq!(cache.load("Project.Main", source), None);
+        let _ = std::fs::remove_dir_all(cache.root());
+    }
+
+    #[test]
+    fn version_and_corruption_invalidate_entries() {
+        let cache  = cache("1.0");
+        let source = "x";
+        let ast    = Ast::var("x");
+        cache.store("M", source, &ast).unwrap();
+
+        // The same directory read by a newer parser misses.
+        let newer = AstCache::new(cache.root(), "2.0");
+        assert_eq!(newer.load("M", source), None);
+
+        // A truncated entry misses instead of failing.
+        let path = cache.root().join("M.ast");
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len()/2]).unwrap();
+        assert_eq!(cache.load("M", source), None);
+        let _ = std::fs::remove_dir_all(cache.root());
+    }
+}
@@ -0,0 +1,152 @@
+//! The clipboard payload format for AST fragments.
+//!
+//! Copying nodes between projects (or instances of the IDE) needs more than
+//! plain text: the ids of the copied nodes must survive, as the metadata
+//! attached to them (positions, visualization choices) travels by id. The
+//! payload therefore carries the serialized nodes alongside their reprs and
+//! an opaque metadata blob, wrapped in a versioned envelope. Pasting is
+//! forgiving: anything that is not our envelope — code copied from an
+//! external editor, or an envelope of an unknown version — is taken as plain
+//! text.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::HasRepr;
+use crate::Unrecognized;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+
+
+// ===============
+// === Payload ===
+// ===============
+
+/// The format marker of the envelope. Bumped on incompatible changes, so an
+/// old IDE pasting a new payload degrades to plain text instead of failing.
+pub const FORMAT:&str = "enso-ast-clipboard/1";
+
+/// The clipboard envelope: the copied fragments plus the format marker.
+#[derive(Clone,Debug,Deserialize,PartialEq,Serialize)]
+pub struct Payload {
+    /// The format marker; always `FORMAT`.
+    pub format : String,
+    /// The copied fragments, in their original order.
+    pub fragments : Vec<Fragment>,
+}
+
+/// A single copied node.
+#[derive(Clone,Debug,Deserialize,PartialEq,Serialize)]
+pub struct Fragment {
+    /// The plain text of the fragment — what external applications see and
+    /// what the fallback path pastes.
+    pub code : String,
+    /// The node itself, ids included.
+    pub ast : Ast,
+    /// An opaque blob for the copier's use, e.g. the metadata of the copied
+    /// nodes. Not interpreted here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata : Option<serde_json::Value>,
+}
+
+
+
+// ========================
+// === (De)serializing  ===
+// ========================
+
+/// Serializes nodes into a clipboard payload with no metadata attached.
+pub fn to_clipboard(asts:&[Ast]) -> String {
+    to_clipboard_with_metadata(asts, None)
+}
+
+/// Serializes nodes into a clipboard payload, attaching the given metadata
+/// blob to every fragment.
+pub fn to_clipboard_with_metadata(asts:&[Ast], metadata:Option<serde_json::Value>) -> String {
+    let fragments = asts.iter().map(|ast| Fragment {
+        code     : ast.repr(),
+        ast      : ast.clone(),
+        metadata : metadata.clone(),
+    }).collect();
+    let payload = Payload {format:FORMAT.to_string(), fragments};
+    serde_json::to_string(&payload).expect("AST clipboard payload must serialize")
+}
+
+/// Decodes a clipboard payload back into nodes.
+///
+/// If the text is our envelope, the original nodes are restored, ids and
+/// all. Any other text — plain code from an external editor, or an envelope
+/// of an unknown version — yields one `Unrecognized` node per non-empty
+/// line; the caller is expected to hand those to the parser.
+pub fn from_clipboard(text:&str) -> serde_json::Result<Vec<Ast>> {
+    if looks_like_envelope(text) {
+        let payload:Payload = serde_json::from_str(text)?;
+        Ok(payload.fragments.into_iter().map(|fragment| fragment.ast).collect())
+    } else {
+        let lines = text.lines().filter(|line| !line.trim().is_empty());
+        Ok(lines.map(|line| Ast::from_shape(Unrecognized {str:line.to_string()})).collect())
+    }
+}
+
+/// Checks whether the text is an envelope of the version we understand.
+/// Inspects only the format marker, so a corrupt envelope of our version is
+/// reported as an error rather than pasted as code.
+fn looks_like_envelope(text:&str) -> bool {
+    let value:Option<serde_json::Value> = serde_json::from_str(text).ok();
+    match value {
+        Some(serde_json::Value::Object(object)) => {
+            object.get("format").and_then(|format| format.as_str()) == Some(FORMAT)
+        }
+        _ => false,
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Id;
+
+    #[test]
+    fn roundtrip_preserves_ids() {
+        let id    = Id::from_u128(7);
+        let infix = Ast::infix(Ast::var("foo"), "+", Ast::var("bar")).with_id(id);
+        let text  = to_clipboard(&[infix.clone(), Ast::var("baz")]);
+
+        let pasted = from_clipboard(&text).unwrap();
+        assert_eq!(pasted, vec![infix, Ast::var("baz")]);
+        assert_eq!(pasted[0].id(), Some(id));
+    }
+
+    #[test]
+    fn external_text_degrades_to_unrecognized() {
+        let pasted = from_clipboard("foo + bar\n\nbaz").unwrap();
+        assert_eq!(pasted.len(), 2);
+        assert_eq!(pasted[0].repr(), "foo + bar");
+        assert_eq!(pasted[1].repr(), "baz");
+        assert!(matches!(pasted[0].shape(), crate::Shape::Unrecognized(_)));
+    }
+
+    #[test]
+    fn corrupt_envelope_is_an_error() {
+        let corrupt = format!(r#"{{"format":"{}","fragments":[42]}}"#, FORMAT);
+        assert!(from_clipboard(&corrupt).is_err());
+    }
+
+    #[test]
+    fn metadata_survives_the_trip() {
+        let blob = serde_json::json!({"position":{"x":1,"y":2}});
+        let text = to_clipboard_with_metadata(&[Ast::var("a")], Some(blob.clone()));
+
+        let payload:Payload = serde_json::from_str(&text).unwrap();
+        assert_eq!(payload.fragments[0].metadata, Some(blob));
+    }
+}
@@ -0,0 +1,329 @@
+//! Detaching comments from a tree and re-attaching them later.
+//!
+//! Automated refactorings work on expressions and are blind to the
+//! comment lines sitting between them, so a transform that rebuilds a
+//! block would silently drop them. The fix is to take the comments out
+//! first: `strip` removes every comment line and records, for each one,
+//! the ids of the nearest real lines around it. After the transform has
+//! run, `reinsert` puts each comment back next to whichever of its
+//! anchors survived — after the line it used to follow, or failing that
+//! before the line it used to precede, or at the end of the module when
+//! both are gone. Comments keep their own nodes (and ids) throughout.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::BlockLine;
+use crate::Id;
+use crate::Shape;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+
+
+// ===============
+// === Anchors ===
+// ===============
+
+/// Where a comment used to live: the root-node ids of the nearest
+/// non-comment lines around it in its own line sequence.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct Anchor {
+    /// The id of the nearest preceding real line, if any.
+    pub before : Option<Id>,
+    /// The id of the nearest following real line, if any.
+    pub after : Option<Id>,
+}
+
+/// A comment taken out of the tree, with enough context to put it back.
+#[derive(Clone,Debug,PartialEq)]
+pub struct DetachedComment {
+    /// The comment node itself.
+    pub comment : Ast,
+    /// Where it came from.
+    pub anchor : Anchor,
+}
+
+
+
+// =================
+// === Stripping ===
+// =================
+
+/// Removes every comment line from the tree, returning the stripped tree
+/// and the comments with their anchors.
+///
+/// A block whose lines are *all* comments is left alone — stripping it
+/// would leave a body the language has no spelling for.
+pub fn strip(ast:&Ast) -> (Ast,Vec<DetachedComment>) {
+    let mut comments = Vec::new();
+    let stripped = strip_node(ast, &mut comments);
+    (stripped,comments)
+}
+
+fn strip_node(ast:&Ast, comments:&mut Vec<DetachedComment>) -> Ast {
+    let shape = ast.shape().map_children(|child| strip_node(child,comments));
+    let shape = match shape {
+        Shape::Module(mut module) => {
+            module.lines = strip_lines(module.lines, comments);
+            Shape::Module(module)
+        }
+        Shape::Block(block) => {
+            // The block's own comments are only detached if a real line is
+            // left to carry the block; nested ones were already handled by
+            // the child traversal above.
+            let mut own = Vec::new();
+            let stripped = strip_lines(block_lines(&block), &mut own);
+            match rebuild_block(block.clone(), stripped) {
+                Some(rebuilt) => {
+                    comments.extend(own);
+                    Shape::Block(rebuilt)
+                }
+                None => Shape::Block(block),
+            }
+        }
+        other => other,
+    };
+    ast.with_shape(shape)
+}
+
+type Lines = Vec<BlockLine<Option<Ast>>>;
+
+fn is_comment(line:&BlockLine<Option<Ast>>) -> bool {
+    matches!(line.elem.as_ref().map(|elem| elem.shape()), Some(Shape::Comment(_)))
+}
+
+/// The id of the line's root node, for lines that anchor comments.
+fn line_id(line:&BlockLine<Option<Ast>>) -> Option<Id> {
+    if is_comment(line) { return None; }
+    line.elem.as_ref().and_then(|elem| elem.id())
+}
+
+fn strip_lines(lines:Lines, comments:&mut Vec<DetachedComment>) -> Lines {
+    let mut result = Vec::new();
+    for (ix,line) in lines.iter().enumerate() {
+        if !is_comment(line) {
+            result.push(line.clone());
+            continue;
+        }
+        let comment = line.elem.clone().expect("comment lines have an element");
+        let before  = lines[..ix].iter().rev().find_map(line_id);
+        let after   = lines[ix+1..].iter().find_map(line_id);
+        comments.push(DetachedComment {comment, anchor:Anchor {before,after}});
+    }
+    result
+}
+
+/// A block's full line sequence, first line included.
+fn block_lines(block:&crate::Block<Ast>) -> Lines {
+    let first = BlockLine {elem:Some(block.first_line.elem.clone()), off:block.first_line.off};
+    let mut lines = vec![first];
+    lines.extend(block.lines.iter().cloned());
+    lines
+}
+
+/// The block with the given line sequence, or `None` when no line is left
+/// to be the first. Blank lines floating to the front join the leading
+/// empty lines.
+fn rebuild_block(mut block:crate::Block<Ast>, lines:Lines) -> Option<crate::Block<Ast>> {
+    let mut lines = lines.into_iter().peekable();
+    while matches!(lines.peek(), Some(line) if line.elem.is_none()) {
+        block.empty_lines.push(lines.next().expect("peeked").off);
+    }
+    let first = lines.next()?;
+    block.first_line = BlockLine {elem:first.elem?, off:first.off};
+    block.lines = lines.collect();
+    Some(block)
+}
+
+
+
+// ====================
+// === Re-insertion ===
+// ====================
+
+/// Puts detached comments back into the (possibly transformed) tree.
+///
+/// Each comment goes after the line whose root node carries its `before`
+/// anchor; if that line is gone, before the line carrying its `after`
+/// anchor; if both are gone, at the end of the module. Returns the tree
+/// and the comments that could not be placed at all — which only happens
+/// when the root is not a module.
+pub fn reinsert(ast:&Ast, comments:&[DetachedComment]) -> (Ast,Vec<DetachedComment>) {
+    let mut roots = HashSet::new();
+    collect_line_roots(ast, &mut roots);
+
+    let mut insert_after  : HashMap<Id,Vec<Ast>> = HashMap::new();
+    let mut insert_before : HashMap<Id,Vec<Ast>> = HashMap::new();
+    let mut homeless = Vec::new();
+    for detached in comments {
+        match (detached.anchor.before, detached.anchor.after) {
+            (Some(id),_) if roots.contains(&id) =>
+                insert_after.entry(id).or_default().push(detached.comment.clone()),
+            (_,Some(id)) if roots.contains(&id) =>
+                insert_before.entry(id).or_default().push(detached.comment.clone()),
+            _ => homeless.push(detached.clone()),
+        }
+    }
+
+    let mut result = reinsert_node(ast, &insert_after, &insert_before);
+    if let Shape::Module(module) = result.shape() {
+        let mut module = module.clone();
+        for detached in homeless.drain(..) {
+            module.lines.push(BlockLine {elem:Some(detached.comment), off:0});
+        }
+        result = result.with_shape(Shape::Module(module));
+    }
+    (result,homeless)
+}
+
+/// The root-node ids of every module and block line in the tree.
+fn collect_line_roots(ast:&Ast, roots:&mut HashSet<Id>) {
+    match ast.shape() {
+        Shape::Module(module) => roots.extend(module.lines.iter().filter_map(line_id)),
+        Shape::Block(block)   => roots.extend(block_lines(block).iter().filter_map(line_id)),
+        _                     => {}
+    }
+    for child in ast.children() {
+        collect_line_roots(child, roots);
+    }
+}
+
+fn reinsert_node
+(ast:&Ast, after:&HashMap<Id,Vec<Ast>>, before:&HashMap<Id,Vec<Ast>>) -> Ast {
+    let shape = ast.shape().map_children(|child| reinsert_node(child,after,before));
+    let shape = match shape {
+        Shape::Module(mut module) => {
+            module.lines = reinsert_lines(module.lines, after, before);
+            Shape::Module(module)
+        }
+        Shape::Block(block) => {
+            let lines = reinsert_lines(block_lines(&block), after, before);
+            match rebuild_block(block.clone(), lines) {
+                Some(rebuilt) => Shape::Block(rebuilt),
+                None          => Shape::Block(block),
+            }
+        }
+        other => other,
+    };
+    ast.with_shape(shape)
+}
+
+fn reinsert_lines
+(lines:Lines, after:&HashMap<Id,Vec<Ast>>, before:&HashMap<Id,Vec<Ast>>) -> Lines {
+    let comment_line = |comment:&Ast| BlockLine {elem:Some(comment.clone()), off:0};
+    let mut result = Vec::new();
+    for line in lines {
+        let id = line_id(&line);
+        if let Some(comments) = id.and_then(|id| before.get(&id)) {
+            result.extend(comments.iter().map(comment_line));
+        }
+        result.push(line.clone());
+        if let Some(comments) = id.and_then(|id| after.get(&id)) {
+            result.extend(comments.iter().map(comment_line));
+        }
+    }
+    result
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HasRepr;
+
+    fn line(id:u128, expr:Ast) -> Option<Ast> {
+        Some(expr.with_id(Id::from_u128(id)))
+    }
+
+    fn comment(text:&str) -> Option<Ast> {
+        Some(Ast::comment(vec![format!(" {}", text)]))
+    }
+
+    #[test]
+    fn stripping_and_reinserting_roundtrips() {
+        let module = Ast::module(vec![
+            comment("header"),
+            line(1, Ast::var("a")),
+            comment("between"),
+            line(2, Ast::var("b")),
+        ]);
+        let (stripped,comments) = strip(&module);
+        assert_eq!(stripped.repr(), "a\nb");
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].anchor, Anchor {before:None, after:Some(Id::from_u128(1))});
+        assert_eq!(comments[1].anchor, Anchor {
+            before : Some(Id::from_u128(1)),
+            after  : Some(Id::from_u128(2)),
+        });
+
+        let (restored,homeless) = reinsert(&stripped, &comments);
+        assert_eq!(restored.repr(), module.repr());
+        assert!(homeless.is_empty());
+    }
+
+    #[test]
+    fn comments_survive_the_loss_of_one_anchor() {
+        let module = Ast::module(vec![
+            line(1, Ast::var("a")),
+            comment("kept"),
+            line(2, Ast::var("b")),
+        ]);
+        let (_,comments) = strip(&module);
+        // A transform removed line 1 entirely; the comment falls through
+        // to its following anchor.
+        let transformed = Ast::module(vec![line(2, Ast::var("b"))]);
+        let (restored,homeless) = reinsert(&transformed, &comments);
+        assert_eq!(restored.repr(), "# kept\nb");
+        assert!(homeless.is_empty());
+
+        // With both anchors gone the comment lands at the module's end.
+        let emptied = Ast::module(vec![line(3, Ast::var("c"))]);
+        let (restored,homeless) = reinsert(&emptied, &comments);
+        assert_eq!(restored.repr(), "c\n# kept");
+        assert!(homeless.is_empty());
+    }
+
+    #[test]
+    fn block_comments_detach_and_return() {
+        let body = Ast::from_shape(crate::Block {
+            ty          : crate::BlockType::Continuous {},
+            indent      : 4,
+            empty_lines : vec![],
+            first_line  : BlockLine {elem:comment("why").unwrap(), off:0},
+            lines       : vec![BlockLine {elem:line(1, Ast::var("x")), off:0}],
+            is_orphan   : false,
+        });
+        let module = Ast::module(vec![line(2, Ast::infix(Ast::var("f"), "=", body))]);
+        let (stripped,comments) = strip(&module);
+        assert_eq!(stripped.repr(), "f = \n    x");
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].anchor, Anchor {before:None, after:Some(Id::from_u128(1))});
+
+        let (restored,_) = reinsert(&stripped, &comments);
+        assert_eq!(restored.repr(), module.repr());
+    }
+
+    #[test]
+    fn an_all_comment_block_is_left_alone() {
+        let body = Ast::from_shape(crate::Block {
+            ty          : crate::BlockType::Continuous {},
+            indent      : 4,
+            empty_lines : vec![],
+            first_line  : BlockLine {elem:comment("todo").unwrap(), off:0},
+            lines       : vec![],
+            is_orphan   : false,
+        });
+        let module = Ast::module(vec![line(1, Ast::infix(Ast::var("f"), "=", body))]);
+        let (stripped,comments) = strip(&module);
+        assert_eq!(stripped.repr(), module.repr());
+        assert!(comments.is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+//! Warnings attached to AST nodes.
+//!
+//! The tooling notices things the parser does not care about — deprecated
+//! syntax, formatting ambiguous enough to mislead a reader — and wants the
+//! hints to travel with the tree rather than in a separate channel that can
+//! fall out of sync. `WithDiagnostics` is a `Layer` carrying them; it
+//! serializes the wrapped value flattened, with the warnings under an
+//! optional `diagnostics` field, so a peer that does not know the field
+//! (the Scala side) sees exactly the payload it always did and a missing
+//! field deserializes as no warnings.
+
+use prelude::*;
+
+use crate::Layer;
+
+#[cfg(feature="serialization")]
+use serde::Serialize;
+#[cfg(feature="serialization")]
+use serde::Deserialize;
+
+
+
+// ===============
+// === Warning ===
+// ===============
+
+/// The category of a warning, for filtering and for icon choice.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum WarningKind {
+    /// The construct still parses but is slated for removal.
+    DeprecatedSyntax,
+    /// The layout is legal but easy to misread, e.g. misleading indentation.
+    AmbiguousFormatting,
+}
+
+/// A single warning attached to a node.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct Warning {
+    /// The warning's category.
+    pub kind : WarningKind,
+    /// The human-readable explanation.
+    pub message : String,
+}
+
+impl Warning {
+    /// Creates a warning of the given kind.
+    pub fn new(kind:WarningKind, message:impl Str) -> Warning {
+        Warning {kind, message:message.into()}
+    }
+}
+
+
+
+// =======================
+// === WithDiagnostics ===
+// =======================
+
+/// A layer attaching warnings to the wrapped value.
+#[derive(Clone,Debug,Default,PartialEq,Eq,Shrinkwrap)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct WithDiagnostics<T> {
+    /// The wrapped value.
+    #[shrinkwrap(main_field)]
+    #[cfg_attr(feature="serialization", serde(flatten))]
+    pub wrapped : T,
+    /// The warnings attached to the value. Absent from the serialized form
+    /// when empty, so warning-free payloads are byte-identical to unwrapped
+    /// ones.
+    #[cfg_attr(feature="serialization", serde(default, skip_serializing_if="Vec::is_empty"))]
+    pub diagnostics : Vec<Warning>,
+}
+
+impl<T> Layer<T> for WithDiagnostics<T> {
+    fn layered(wrapped:T) -> Self {
+        WithDiagnostics {wrapped, diagnostics:Vec::new()}
+    }
+    fn unlayer(self) -> T {
+        self.wrapped
+    }
+    fn as_inner(&self) -> &T {
+        &self.wrapped
+    }
+}
+
+impl<T> WithDiagnostics<T> {
+    /// This value with the warning appended.
+    pub fn with_warning(mut self, warning:Warning) -> Self {
+        self.diagnostics.push(warning);
+        self
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+#[cfg(feature="serialization")]
+mod tests {
+    use super::*;
+
+    use crate::Var;
+
+    #[test]
+    fn warning_free_payloads_serialize_unchanged() {
+        let plain   = Var {name:"x".to_string()};
+        let layered = WithDiagnostics::layered(plain.clone());
+        assert_eq!(serde_json::to_string(&layered).unwrap(),
+                   serde_json::to_string(&plain).unwrap());
+    }
+
+    #[test]
+    fn warnings_roundtrip_and_their_absence_is_the_default() {
+        let layered = WithDiagnostics::layered(Var {name:"x".to_string()})
+            .with_warning(Warning::new(WarningKind::DeprecatedSyntax, "use `y`"));
+        let json = serde_json::to_string(&layered).unwrap();
+        assert!(json.contains("diagnostics"));
+        let back:WithDiagnostics<Var> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, layered);
+
+        // A peer that never heard of the field sends payloads without it.
+        let bare:WithDiagnostics<Var> = serde_json::from_str(r#"{"name":"x"}"#).unwrap();
+        assert!(bare.diagnostics.is_empty());
+        assert_eq!(bare.wrapped.name, "x");
+    }
+}
@@ -0,0 +1,176 @@
+//! Content-hash digests of AST nodes.
+//!
+//! A digest is a stable, structural SHA-1 hash of a node's text. Two nodes
+//! with the same representation hash the same regardless of their ids, so
+//! digests can be used by caching layers and for the language-server's
+//! version digests. Digests compose: a node's hash is built from its local
+//! tokens and the digests of its children, so with a `DigestCache` only the
+//! subtrees that actually changed get rehashed after an edit.
+
+use crate::Ast;
+use crate::HasTokens;
+use crate::Id;
+use crate::Token;
+use crate::TokenConsumer;
+
+use sha1::Digest as _;
+use sha1::Sha1;
+
+use std::collections::HashMap;
+
+
+
+// ==============
+// === Digest ===
+// ==============
+
+/// A SHA-1 digest of a node's structure and text.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub struct Digest(pub [u8;20]);
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ast {
+    /// Computes this node's digest, without any caching. For hashing many
+    /// nodes of an evolving tree, prefer `DigestCache`.
+    pub fn digest(&self) -> Digest {
+        digest_impl(self, &mut None)
+    }
+}
+
+fn digest_impl(ast:&Ast, cache:&mut Option<&mut HashMap<Id,Digest>>) -> Digest {
+    if let (Some(cache),Some(id)) = (cache.as_ref(),ast.id()) {
+        if let Some(digest) = cache.get(&id) {
+            return *digest;
+        }
+    }
+    let mut consumer = DigestConsumer {hasher:Sha1::new(), cache};
+    // The shape kind seeds the hash — tokens alone cannot tell `Var("x")`
+    // from `Opr("x")`, as both feed the same text.
+    consumer.hasher.input(b"kind:");
+    consumer.hasher.input(ast.shape().name().as_bytes());
+    ast.shape().feed_to(&mut consumer);
+    let mut bytes = [0;20];
+    bytes.copy_from_slice(&consumer.hasher.result());
+    let digest = Digest(bytes);
+    if let (Some(cache),Some(id)) = (consumer.cache.as_mut(),ast.id()) {
+        cache.insert(id,digest);
+    }
+    digest
+}
+
+/// A token consumer that hashes the local tokens directly and child nodes
+/// through their (possibly cached) digests.
+struct DigestConsumer<'a,'b> {
+    hasher : Sha1,
+    cache  : &'a mut Option<&'b mut HashMap<Id,Digest>>,
+}
+
+impl<'t,'a,'b> TokenConsumer<'t> for DigestConsumer<'a,'b> {
+    fn feed(&mut self, token:Token<'t>) {
+        match token {
+            Token::Off(off) => {
+                self.hasher.input(b"off:");
+                self.hasher.input(off.to_le_bytes());
+            }
+            Token::Chr(chr) => {
+                let mut buffer = [0;4];
+                self.hasher.input(b"chr:");
+                self.hasher.input(chr.encode_utf8(&mut buffer).as_bytes());
+            }
+            Token::Str(str) => {
+                self.hasher.input(b"str:");
+                self.hasher.input(str.len().to_le_bytes());
+                self.hasher.input(str.as_bytes());
+            }
+            Token::Ast(ast) => {
+                self.hasher.input(b"ast:");
+                let child = digest_impl(ast,self.cache);
+                self.hasher.input(child.0);
+            }
+        }
+    }
+}
+
+
+
+// ===================
+// === DigestCache ===
+// ===================
+
+/// A per-node digest memo, keyed by node id.
+///
+/// After an edit, only the nodes along the changed path get fresh ids, so
+/// hashing the new tree through the cache rehashes just those and looks the
+/// untouched subtrees up.
+#[derive(Debug,Default)]
+pub struct DigestCache {
+    cache : HashMap<Id,Digest>,
+}
+
+impl DigestCache {
+    /// Creates an empty cache.
+    pub fn new() -> DigestCache {
+        Default::default()
+    }
+
+    /// Computes the node's digest, reusing and filling the cache.
+    pub fn digest_of(&mut self, ast:&Ast) -> Digest {
+        digest_impl(ast, &mut Some(&mut self.cache))
+    }
+
+    /// Number of memoized node digests.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Checks if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_is_structural() {
+        let a = Ast::var("foo");
+        let b = Ast::var("foo").with_id(Id::new_v4());
+        let c = Ast::var("bar");
+        assert_eq!(a.digest(), b.digest());
+        assert_ne!(a.digest(), c.digest());
+    }
+
+    #[test]
+    fn digest_distinguishes_shapes_with_same_repr() {
+        assert_ne!(Ast::var("x").digest(), Ast::opr("x").digest());
+    }
+
+    #[test]
+    fn cache_is_reused_across_trees() {
+        let shared = Ast::var("foo").with_id(Id::new_v4());
+        let tree1  = Ast::prefix(shared.clone(), Ast::var("a"));
+        let tree2  = Ast::prefix(shared.clone(), Ast::var("b"));
+
+        let mut cache = DigestCache::new();
+        let digest1   = cache.digest_of(&tree1);
+        assert_eq!(cache.digest_of(&tree1), digest1);
+        assert_ne!(cache.digest_of(&tree2), digest1);
+        assert_eq!(cache.digest_of(&shared), shared.digest());
+    }
+}
@@ -0,0 +1,262 @@
+//! A tiny interpreter over the constant subset of the AST.
+//!
+//! The searcher wants to preview a value next to an entry before the
+//! engine has computed anything, which is only safe for expressions whose
+//! value is evident from the tree alone: number and text literals, the
+//! arithmetic and concatenation operators over them, and vector literals
+//! of such expressions. Everything else — variables, calls, anything with
+//! effects — is *not evaluable*, and that is a first-class answer here,
+//! not a failure: the preview simply stays empty and the caller can say
+//! why.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::HasRepr;
+use crate::SegmentFmt;
+use crate::SegmentRaw;
+use crate::Shape;
+use crate::number::NumberError;
+use crate::vector::VectorView;
+
+
+
+// =============
+// === Value ===
+// =============
+
+/// A previewed constant value.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Value {
+    /// A numeric value.
+    Number(f64),
+    /// A text value.
+    Text(String),
+    /// A list of values.
+    List(Vec<Value>),
+}
+
+impl Value {
+    /// The value's kind, for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Text(_)   => "text",
+            Value::List(_)   => "list",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Number(value) => write!(f, "{}", value),
+            Value::Text(value)   => write!(f, "{}", value),
+            Value::List(values)  => {
+                write!(f, "[")?;
+                for (ix,value) in values.iter().enumerate() {
+                    if ix > 0 { write!(f, ", ")?; }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+
+
+// ==============
+// === Errors ===
+// ==============
+
+/// Why an expression has no preview.
+#[derive(Clone,Debug,PartialEq)]
+pub enum EvalError {
+    /// The expression is outside the constant subset.
+    NotEvaluable {
+        /// The text of the offending (sub)expression.
+        repr : String,
+    },
+    /// A supported operator was given operands it does not work on.
+    TypeMismatch {
+        /// The operator's name.
+        operator : String,
+        /// The kind of the left operand.
+        left : &'static str,
+        /// The kind of the right operand.
+        right : &'static str,
+    },
+    /// A number literal whose value cannot be read.
+    BadNumber(NumberError),
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EvalError::NotEvaluable {repr} =>
+                write!(f, "`{}` is not evaluable without the engine", repr),
+            EvalError::TypeMismatch {operator,left,right} =>
+                write!(f, "`{}` does not work on a {} and a {}", operator, left, right),
+            EvalError::BadNumber(error) =>
+                write!(f, "{}", error),
+        }
+    }
+}
+
+
+
+// ==================
+// === Evaluation ===
+// ==================
+
+/// The constant value of the expression, or the reason it has none.
+pub fn eval(ast:&Ast) -> std::result::Result<Value,EvalError> {
+    if let Some(vector) = VectorView::from_ast(ast) {
+        let elements = vector.elements();
+        let elements = elements.iter().map(eval);
+        return Ok(Value::List(elements.collect::<std::result::Result<_,_>>()?));
+    }
+    match ast.shape() {
+        Shape::Number(number) => {
+            let value = number.to_value().map_err(EvalError::BadNumber)?;
+            Ok(Value::Number(value as f64))
+        }
+        Shape::TextLineRaw(line) => {
+            let mut text = String::new();
+            for segment in &line.text {
+                match segment {
+                    SegmentRaw::SegmentPlain(plain) => text.push_str(&plain.value),
+                    SegmentRaw::SegmentRawEscape(_) => return not_evaluable(ast),
+                }
+            }
+            Ok(Value::Text(text))
+        }
+        Shape::TextLineFmt(line) => {
+            let mut text = String::new();
+            for segment in &line.text {
+                match segment {
+                    SegmentFmt::SegmentPlain(plain) => text.push_str(&plain.value),
+                    SegmentFmt::SegmentExpr(expr) => if let Some(expr) = &expr.value {
+                        text.push_str(&eval(expr)?.to_string())
+                    }
+                    SegmentFmt::SegmentEscape(_) => return not_evaluable(ast),
+                }
+            }
+            Ok(Value::Text(text))
+        }
+        Shape::Group(group) => match &group.body {
+            Some(body) => eval(body),
+            None       => not_evaluable(ast),
+        }
+        Shape::Infix(infix) => {
+            let operator = infix.opr.repr();
+            let left     = eval(&infix.larg)?;
+            let right    = eval(&infix.rarg)?;
+            apply(&operator, left, right).unwrap_or_else(|| not_evaluable(ast))
+        }
+        _ => not_evaluable(ast),
+    }
+}
+
+/// The supported binary operators. `None` for an unsupported operator;
+/// a `TypeMismatch` for a supported one applied across kinds.
+fn apply(operator:&str, left:Value, right:Value)
+-> Option<std::result::Result<Value,EvalError>> {
+    let mismatch = EvalError::TypeMismatch {
+        operator : operator.to_string(),
+        left     : left.kind(),
+        right    : right.kind(),
+    };
+    let value = match (operator, left, right) {
+        ("+", Value::Number(l), Value::Number(r)) => Value::Number(l + r),
+        ("-", Value::Number(l), Value::Number(r)) => Value::Number(l - r),
+        ("*", Value::Number(l), Value::Number(r)) => Value::Number(l * r),
+        ("/", Value::Number(l), Value::Number(r)) => Value::Number(l / r),
+        ("+", Value::Text(l),   Value::Text(r))   => Value::Text(l + &r),
+        ("+", Value::List(l),   Value::List(r))   =>
+            Value::List(l.into_iter().chain(r).collect()),
+        ("+", _, _) | ("-", _, _) | ("*", _, _) | ("/", _, _) =>
+            return Some(Err(mismatch)),
+        _ => return None,
+    };
+    Some(Ok(value))
+}
+
+/// The expression's not-evaluable report.
+fn not_evaluable(ast:&Ast) -> std::result::Result<Value,EvalError> {
+    Err(EvalError::NotEvaluable {repr:ast.repr()})
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::SegmentExpr;
+    use crate::SegmentPlain;
+    use crate::TextLineFmt;
+
+    fn text(value:&str) -> Ast {
+        let segment = SegmentRaw::SegmentPlain(SegmentPlain {value:value.to_string()});
+        Ast::from_shape(crate::TextLineRaw {text:vec![segment]})
+    }
+
+    #[test]
+    fn constants_evaluate() {
+        let sum = Ast::infix(Ast::number("1"), "+", Ast::number("2"));
+        let all = Ast::infix(sum, "*", Ast::number("10"));
+        assert_eq!(eval(&all), Ok(Value::Number(30.0)));
+        assert_eq!(eval(&all).unwrap().to_string(), "30");
+
+        let greeting = Ast::infix(text("hello "), "+", text("world"));
+        assert_eq!(eval(&greeting), Ok(Value::Text("hello world".to_string())));
+
+        let vector = VectorView::new(vec![Ast::number("1"), greeting]).to_ast();
+        let listed = eval(&vector).unwrap();
+        assert_eq!(listed.to_string(), "[1, hello world]");
+
+        let grouped = Ast::grouped(Ast::infix(Ast::number("6"), "/", Ast::number("4")));
+        assert_eq!(eval(&grouped), Ok(Value::Number(1.5)));
+    }
+
+    #[test]
+    fn splices_evaluate_recursively() {
+        let splice = SegmentFmt::SegmentExpr(SegmentExpr {
+            value : Some(Ast::infix(Ast::number("2"), "+", Ast::number("2"))),
+        });
+        let plain = SegmentFmt::SegmentPlain(SegmentPlain {value:"n = ".to_string()});
+        let line  = Ast::from_shape(TextLineFmt {text:vec![plain,splice]});
+        assert_eq!(eval(&line), Ok(Value::Text("n = 4".to_string())));
+    }
+
+    #[test]
+    fn everything_else_reports_not_evaluable() {
+        let class = |ast:&Ast| eval(ast).unwrap_err();
+        assert_eq!(class(&Ast::var("x")),
+                   EvalError::NotEvaluable {repr:"x".to_string()});
+        assert_eq!(class(&Ast::prefix(Ast::var("f"), Ast::number("1"))),
+                   EvalError::NotEvaluable {repr:"f 1".to_string()});
+
+        // A non-constant leaf is reported, not the whole expression.
+        let mixed = Ast::infix(Ast::number("1"), "+", Ast::var("x"));
+        assert_eq!(class(&mixed), EvalError::NotEvaluable {repr:"x".to_string()});
+
+        // Supported operators complain about kinds; unsupported ones about
+        // the expression.
+        let kinds = Ast::infix(Ast::number("1"), "+", text("x"));
+        assert_eq!(class(&kinds), EvalError::TypeMismatch {
+            operator : "+".to_string(),
+            left     : "number",
+            right    : "text",
+        });
+        let unsupported = Ast::infix(Ast::number("1"), "==", Ast::number("1"));
+        assert_eq!(class(&unsupported),
+                   EvalError::NotEvaluable {repr:"1 == 1".to_string()});
+    }
+}
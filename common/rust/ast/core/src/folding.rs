@@ -0,0 +1,134 @@
+//! Computing code-folding ranges from the block structure.
+//!
+//! Editors fold by lines, so the ranges are reported as line numbers: a
+//! range says that the lines after `first_line`, up to and including
+//! `last_line`, collapse into it. Foldable constructs are the ones whose
+//! shape guarantees a multi-line repr — blocks (including `def` bodies),
+//! text blocks and multi-line comments.
+
+use prelude::*;
+
+use crate::search;
+use crate::Ast;
+use crate::HasRepr;
+use crate::Index;
+use crate::Shape;
+
+
+
+// ====================
+// === FoldingRange ===
+// ====================
+
+/// A collapsible region, in zero-based line numbers.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct FoldingRange {
+    /// The line that stays visible when the region is collapsed.
+    pub first_line : usize,
+    /// The last collapsed line.
+    pub last_line : usize,
+}
+
+/// The collapsible regions of the tree, in textual order.
+pub fn folding_ranges(ast:&Ast) -> Vec<FoldingRange> {
+    let repr = ast.repr();
+    let line_starts = line_starts(&repr);
+    let mut ranges  = Vec::new();
+    for hit in search::all_nodes(ast) {
+        let foldable = matches!(ast.get_node(&hit.crumbs).unwrap().shape(),
+            Shape::Block        {..} |
+            Shape::TextBlockRaw {..} |
+            Shape::TextBlockFmt {..} |
+            Shape::Comment      {..});
+        if !foldable {
+            continue;
+        }
+        let first_line = line_of(&line_starts, hit.span.index);
+        let last_line  = line_of(&line_starts, hit.span.end());
+        if last_line > first_line {
+            let range = FoldingRange {first_line,last_line};
+            // A def body and its block share the region; report it once.
+            if ranges.last() != Some(&range) {
+                ranges.push(range);
+            }
+        }
+    }
+    ranges
+}
+
+/// Offsets at which the lines of the text start.
+fn line_starts(text:&str) -> Vec<usize> {
+    let newlines = text.char_indices().filter(|(_,chr)| *chr == '\n');
+    std::iter::once(0).chain(newlines.map(|(offset,_)| offset + 1)).collect()
+}
+
+/// The zero-based number of the line containing the given position. A
+/// position pointing at a newline counts into the line the newline ends.
+fn line_of(line_starts:&[usize], index:Index) -> usize {
+    match line_starts.binary_search(&index.value) {
+        Ok(line)   => line,
+        Err(line)  => line - 1,
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Block;
+    use crate::BlockLine;
+    use crate::BlockType;
+
+    fn block(exprs:Vec<Ast>) -> Ast {
+        let mut exprs      = exprs.into_iter();
+        let first_line     = BlockLine {elem:exprs.next().unwrap(), off:0};
+        let lines          = exprs.map(|elem| BlockLine {elem:Some(elem), off:0}).collect();
+        Ast::from_shape(Block {
+            ty          : BlockType::Discontinuous {},
+            indent      : 4,
+            empty_lines : vec![],
+            first_line,
+            lines,
+            is_orphan   : false,
+        })
+    }
+
+    #[test]
+    fn block_bodies_fold_from_their_header_line() {
+        let body   = block(vec![Ast::var("x"), Ast::var("y")]);
+        // No offset between `=` and the block — the newline is the block's.
+        let main   = Ast::from_shape(crate::Infix {
+            larg : Ast::var("main"),
+            loff : 1,
+            opr  : Ast::opr("="),
+            roff : 0,
+            rarg : body,
+        });
+        let module = Ast::module(vec![Some(main), None, Some(Ast::var("tail"))]);
+        assert_eq!(module.repr(), "main =\n    x\n    y\n\ntail");
+
+        let ranges = folding_ranges(&module);
+        assert_eq!(ranges, vec![FoldingRange {first_line:0, last_line:2}]);
+    }
+
+    #[test]
+    fn multi_line_comments_fold() {
+        let comment = Ast::from_shape(crate::Comment {
+            lines : vec![" first".to_string(), " second".to_string()],
+        });
+        let module = Ast::module(vec![Some(comment)]);
+        assert_eq!(folding_ranges(&module).len(), 1);
+    }
+
+    #[test]
+    fn single_line_nodes_do_not_fold() {
+        let module = Ast::module(vec![Some(Ast::infix(Ast::var("a"), "+", Ast::var("b")))]);
+        assert!(folding_ranges(&module).is_empty());
+    }
+}
@@ -0,0 +1,365 @@
+//! Offset normalization — the "reformat file" action.
+//!
+//! The AST stores every whitespace offset explicitly, so reformatting is a
+//! pure tree transformation: rewrite the offsets to the style's canonical
+//! values and the repr comes out normalized. The formatter returns the new
+//! tree together with the spans (in the original text) of the nodes whose
+//! own offsets changed, so the editor can limit redrawing and the caller can
+//! tell a no-op reformat from a real one.
+
+use prelude::*;
+
+use crate::child_offsets;
+use crate::Ast;
+use crate::BlockLine;
+use crate::HasSpan;
+use crate::Index;
+use crate::Shape;
+use crate::Size;
+use crate::Span;
+
+
+
+// =============
+// === Style ===
+// =============
+
+/// The formatting style: the canonical values the offsets are rewritten to.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Style {
+    /// Spaces on each side of a binary operator. The accessor dot is always
+    /// kept tight, regardless of this setting.
+    pub operator_spacing : usize,
+    /// Indentation of block bodies.
+    pub indent : usize,
+    /// Maximum number of consecutive blank lines between module-level
+    /// definitions; longer runs are collapsed.
+    pub blank_lines_between_defs : usize,
+    /// The column limit for `wrap`. Expressions wider than this are
+    /// re-wrapped into a continuation block.
+    pub max_width : usize,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            operator_spacing         : 1,
+            indent                   : 4,
+            blank_lines_between_defs : 1,
+            max_width                : 80,
+        }
+    }
+}
+
+impl Style {
+    /// The spacing around the given operator node.
+    fn spacing_for(&self, opr:&Ast) -> usize {
+        match opr.shape() {
+            Shape::Opr(opr) if opr.name == "." => 0,
+            _                                  => self.operator_spacing,
+        }
+    }
+}
+
+
+
+// ==================
+// === Formatting ===
+// ==================
+
+/// Normalizes the offsets of the tree according to the style.
+///
+/// Returns the reformatted tree and the spans, in the original text, of the
+/// nodes whose own offsets were rewritten. Ids are retained throughout, so
+/// metadata attached to the nodes survives reformatting.
+pub fn format(ast:&Ast, style:&Style) -> (Ast,Vec<Span>) {
+    let mut changed = Vec::new();
+    let formatted   = format_node(ast, Index::new(0), style, &mut changed);
+    (formatted,changed)
+}
+
+fn format_node(ast:&Ast, offset:Index, style:&Style, changed:&mut Vec<Span>) -> Ast {
+    // Children first, each at its absolute offset in the original text.
+    let offsets  = child_offsets(ast);
+    let mut next = 0;
+    let shape    = ast.shape().map_children(|child| {
+        let child_offset = offset + Size::new(offsets[next]);
+        next += 1;
+        format_node(child, child_offset, style, changed)
+    });
+
+    let own_span = Span::new(offset, Size::new(ast.span()));
+    let shape    = match shape {
+        Shape::Infix(mut infix) => {
+            let spacing = style.spacing_for(&infix.opr);
+            if infix.loff != spacing || infix.roff != spacing {
+                changed.push(own_span);
+            }
+            infix.loff = spacing;
+            infix.roff = spacing;
+            Shape::Infix(infix)
+        }
+        Shape::SectionLeft(mut section) => {
+            let spacing = style.spacing_for(&section.opr);
+            if section.off != spacing {
+                changed.push(own_span);
+            }
+            section.off = spacing;
+            Shape::SectionLeft(section)
+        }
+        Shape::SectionRight(mut section) => {
+            let spacing = style.spacing_for(&section.opr);
+            if section.off != spacing {
+                changed.push(own_span);
+            }
+            section.off = spacing;
+            Shape::SectionRight(section)
+        }
+        Shape::Block(mut block) => {
+            let trimmed_empty = block.empty_lines.iter().all(|line| *line == 0);
+            let trimmed_lines = block.first_line.off == 0
+                && block.lines.iter().all(|line| line.off == 0);
+            if block.indent != style.indent || !trimmed_empty || !trimmed_lines {
+                changed.push(own_span);
+            }
+            block.indent = style.indent;
+            // Blank lines and line ends carry no visible text; their stored
+            // lengths are stray whitespace and are dropped.
+            for line in &mut block.empty_lines {
+                *line = 0;
+            }
+            block.first_line.off = 0;
+            for line in &mut block.lines {
+                line.off = 0;
+            }
+            Shape::Block(block)
+        }
+        Shape::Module(mut module) => {
+            let lines = collapse_blank_lines(module.lines.clone(), style.blank_lines_between_defs);
+            if module.lines != lines {
+                changed.push(own_span);
+            }
+            module.lines = lines;
+            Shape::Module(module)
+        }
+        other => other,
+    };
+    ast.with_shape(shape)
+}
+
+/// Strips trailing whitespace from module lines and collapses runs of blank
+/// lines longer than the limit.
+fn collapse_blank_lines
+(lines:Vec<BlockLine<Option<Ast>>>, limit:usize) -> Vec<BlockLine<Option<Ast>>> {
+    let mut result = Vec::with_capacity(lines.len());
+    let mut blanks = 0;
+    for mut line in lines {
+        match line.elem {
+            None => {
+                blanks += 1;
+                if blanks <= limit {
+                    result.push(BlockLine {elem:None, off:0});
+                }
+            }
+            Some(_) => {
+                blanks   = 0;
+                line.off = 0;
+                result.push(line);
+            }
+        }
+    }
+    result
+}
+
+
+
+// ================
+// === Wrapping ===
+// ================
+
+/// Re-wraps a long operator chain into a continuation block — the "tidy
+/// long node expression" action.
+///
+/// The leftmost operand becomes the block's first line and every further
+/// link becomes an operator-leading line (`+ bar`, stored as a right
+/// section), so the wrapped form is ordinary, parseable code:
+///
+/// ```text
+/// aaa + bbb + ccc    ~>    aaa
+///                              + bbb
+///                              + ccc
+/// ```
+///
+/// One link per line: the links may mix operators of different
+/// precedence, so lines are never merged — `unwrap` can then restore the
+/// exact original nesting. Expressions already within `style.max_width`,
+/// and expressions that are not operator chains, are returned unchanged.
+/// Ids are retained; the chain's own id moves onto the block.
+pub fn wrap(ast:&Ast, style:&Style) -> Ast {
+    if ast.span() <= style.max_width {
+        return ast.clone();
+    }
+    let (head,links) = spine(ast);
+    if links.is_empty() {
+        return ast.clone();
+    }
+    let lines = links.into_iter().map(|(opr,arg)| {
+        let section = crate::SectionRight {opr, off:1, arg};
+        BlockLine {elem:Some(Ast::from_shape(section)), off:0}
+    }).collect();
+    let block = crate::Block {
+        ty          : crate::BlockType::Continuous {},
+        indent      : style.indent,
+        empty_lines : vec![],
+        first_line  : BlockLine {elem:head, off:0},
+        lines,
+        is_orphan   : false,
+    };
+    Ast::new(Shape::Block(block), ast.id())
+}
+
+/// Folds a continuation block produced by `wrap` back into the single
+/// operator chain it came from; `None` for anything that is not such a
+/// block. The block's id moves back onto the chain.
+pub fn unwrap(ast:&Ast) -> Option<Ast> {
+    let block = match ast.shape() {
+        Shape::Block(block) => block,
+        _                   => return None,
+    };
+    let mut acc = block.first_line.elem.clone();
+    for line in &block.lines {
+        let section = match line.elem.as_ref().map(|elem| elem.shape()) {
+            Some(Shape::SectionRight(section)) => section,
+            _                                  => return None,
+        };
+        acc = Ast::from_shape(crate::Infix {
+            larg : acc,
+            loff : 1,
+            opr  : section.opr.clone(),
+            roff : section.off,
+            rarg : section.arg.clone(),
+        });
+    }
+    Some(Ast::new(acc.shape().clone(), ast.id()))
+}
+
+/// The left spine of an operator chain: the leftmost operand and the
+/// (operator, right operand) links above it, in textual order. The walk
+/// follows any infix operator, so mixed-operator chains come out as one
+/// spine in the order the parser associated them.
+fn spine(ast:&Ast) -> (Ast,Vec<(Ast,Ast)>) {
+    let mut links   = Vec::new();
+    let mut current = ast.clone();
+    while let Shape::Infix(infix) = current.shape() {
+        links.push((infix.opr.clone(), infix.rarg.clone()));
+        let larg = infix.larg.clone();
+        current  = larg;
+    }
+    links.reverse();
+    (current,links)
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HasRepr;
+
+    use crate::BlockType;
+
+    #[test]
+    fn operator_spacing_is_normalized() {
+        let infix = Ast::from_shape(crate::Infix {
+            larg : Ast::var("foo"),
+            loff : 0,
+            opr  : Ast::opr("+"),
+            roff : 3,
+            rarg : Ast::var("bar"),
+        });
+        let (formatted,changed) = format(&infix, &Style::default());
+        assert_eq!(formatted.repr(), "foo + bar");
+        assert_eq!(changed, vec![Span::new(Index::new(0), Size::new("foo+   bar".len()))]);
+    }
+
+    #[test]
+    fn accessor_dot_stays_tight() {
+        let access = Ast::infix(Ast::var("foo"), ".", Ast::var("bar"));
+        let (formatted,changed) = format(&access, &Style::default());
+        assert_eq!(formatted.repr(), "foo.bar");
+        assert_eq!(changed.len(), 1);
+    }
+
+    #[test]
+    fn already_canonical_code_reports_no_changes() {
+        let infix  = Ast::infix(Ast::var("a"), "+", Ast::var("b"));
+        let module = Ast::module(vec![Some(infix)]);
+        let (formatted,changed) = format(&module, &Style::default());
+        assert_eq!(formatted.repr(), module.repr());
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn blank_line_runs_are_collapsed() {
+        let module = Ast::module(vec![
+            Some(Ast::var("a")), None, None, None, Some(Ast::var("b")),
+        ]);
+        let (formatted,changed) = format(&module, &Style::default());
+        assert_eq!(formatted.repr(), "a\n\nb");
+        assert_eq!(changed.len(), 1);
+    }
+
+    #[test]
+    fn block_indent_and_line_ends_are_canonicalized() {
+        let block = Ast::from_shape(crate::Block {
+            ty          : BlockType::Discontinuous {},
+            indent      : 2,
+            empty_lines : vec![],
+            first_line  : BlockLine {elem:Ast::var("a"), off:3},
+            lines       : vec![BlockLine {elem:Some(Ast::var("b")), off:0}],
+            is_orphan   : false,
+        });
+        let (formatted,_) = format(&block, &Style::default());
+        assert_eq!(formatted.repr(), "\n    a\n    b");
+    }
+
+    #[test]
+    fn long_chains_wrap_under_the_column_limit() {
+        let style = Style {max_width:10, ..Style::default()};
+        let sum   = Ast::infix(Ast::var("aaa"), "+", Ast::var("bbb"));
+        let chain = Ast::infix(sum, "-", Ast::var("ccc"));
+        let wrapped = wrap(&chain, &style);
+        assert_eq!(wrapped.repr(), "\n    aaa\n    + bbb\n    - ccc");
+
+        // Narrow enough expressions are left alone.
+        let short = Ast::infix(Ast::var("a"), "+", Ast::var("b"));
+        assert_eq!(wrap(&short, &style).repr(), "a + b");
+        // So are wide expressions with nothing to wrap on.
+        let atom = Ast::var("a_very_long_variable_name");
+        assert_eq!(wrap(&atom, &style).repr(), atom.repr());
+    }
+
+    #[test]
+    fn wrapping_roundtrips_to_an_equivalent_chain() {
+        let style = Style {max_width:10, ..Style::default()};
+        let id    = crate::Id::from_u128(7);
+        let inner = Ast::infix(Ast::var("aaa"), "*", Ast::var("bbb"));
+        let chain = Ast::infix(inner, "+", Ast::infix(Ast::var("c"), "*", Ast::var("d")))
+            .with_id(id);
+
+        let wrapped = wrap(&chain, &style);
+        assert_eq!(wrapped.id(), Some(id));
+        // The higher-precedence right operand stays on its line whole…
+        assert_eq!(wrapped.repr(), "\n    aaa\n    * bbb\n    + c * d");
+
+        // …so unwrapping restores the exact original nesting.
+        let unwrapped = unwrap(&wrapped).unwrap();
+        assert_eq!(unwrapped, chain);
+        assert!(unwrap(&chain).is_none());
+    }
+}
@@ -0,0 +1,210 @@
+//! Semantic token classification for syntax highlighting.
+//!
+//! Editors usually re-lex the text with regexes to color it; with the AST at
+//! hand that is both redundant and less precise — the tree already knows
+//! which `x` is a variable and which quote opens a literal. `highlight`
+//! flattens a tree into classified spans. Spans may nest (an interpolated
+//! expression sits inside its text literal's span); they are emitted in
+//! textual order with the enclosing span first, so painting them in order
+//! gives the right result.
+
+use prelude::*;
+
+use crate::child_offsets;
+use crate::Ast;
+use crate::HasSpan;
+use crate::Index;
+use crate::SegmentFmt;
+use crate::SegmentRaw;
+use crate::Shape;
+use crate::Size;
+use crate::Span;
+
+
+
+// ============
+// === Kind ===
+// ============
+
+/// The semantic class of a highlighted span.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Kind {
+    /// A variable name.
+    Variable,
+    /// A constructor name.
+    Constructor,
+    /// An operator or modifier.
+    Operator,
+    /// A number literal (or a dangling base).
+    Number,
+    /// A text literal.
+    Text,
+    /// An escape sequence within a text literal.
+    Escape,
+    /// A comment.
+    Comment,
+    /// A keyword — the head token of a macro segment.
+    Keyword,
+    /// A parse problem: unrecognized tokens, invalid suffixes, unclosed
+    /// literals.
+    Error,
+}
+
+
+
+// ====================
+// === Highlighting ===
+// ====================
+
+/// Classifies the tree into highlighted spans, in textual order.
+pub fn highlight(ast:&Ast) -> Vec<(Span,Kind)> {
+    let mut spans = Vec::new();
+    walk(ast, Index::new(0), &mut spans);
+    spans
+}
+
+fn walk(ast:&Ast, offset:Index, spans:&mut Vec<(Span,Kind)>) {
+    let span = Span::new(offset, Size::new(ast.span()));
+    match ast.shape() {
+        Shape::Var          {..} => spans.push((span,Kind::Variable)),
+        Shape::Cons         {..} => spans.push((span,Kind::Constructor)),
+        Shape::Opr          {..} => spans.push((span,Kind::Operator)),
+        Shape::Mod          {..} => spans.push((span,Kind::Operator)),
+        Shape::Number       {..} => spans.push((span,Kind::Number)),
+        Shape::DanglingBase {..} => spans.push((span,Kind::Number)),
+        Shape::Comment      {..} => spans.push((span,Kind::Comment)),
+        Shape::Unrecognized {..} => spans.push((span,Kind::Error)),
+        Shape::InvalidSuffix{..} => spans.push((span,Kind::Error)),
+        Shape::TextUnclosed {..} => spans.push((span,Kind::Error)),
+        Shape::TextLineRaw(line) => {
+            spans.push((span,Kind::Text));
+            // Content starts after the opening quote.
+            raw_segments(&line.text, offset + Size::new(1), spans);
+        }
+        Shape::TextLineFmt(line) => {
+            spans.push((span,Kind::Text));
+            fmt_segments(&line.text, offset + Size::new(1), spans);
+        }
+        Shape::TextBlockRaw {..} => spans.push((span,Kind::Text)),
+        Shape::TextBlockFmt {..} => spans.push((span,Kind::Text)),
+        Shape::Match(shape) => {
+            // Segment heads are the macro's keywords; everything else is
+            // ordinary code. `resolved` does not appear in the text.
+            let children = ast.children();
+            let offsets  = child_offsets(ast);
+            let mut next = 0;
+            if shape.pfx.is_some() {
+                walk(children[next], offset + Size::new(offsets[next]), spans);
+                next += 1;
+            }
+            for seg in &shape.segs {
+                let head_offset = offset + Size::new(offsets[next]);
+                let head_span   = Span::new(head_offset, Size::new(children[next].span()));
+                spans.push((head_span,Kind::Keyword));
+                next += 1;
+                for _ in &seg.wrapped.body {
+                    walk(children[next], offset + Size::new(offsets[next]), spans);
+                    next += 1;
+                }
+            }
+        }
+        _ => {
+            let offsets = child_offsets(ast);
+            for (child,child_offset) in ast.children().into_iter().zip(offsets) {
+                walk(child, offset + Size::new(child_offset), spans);
+            }
+        }
+    }
+}
+
+fn raw_segments(segments:&[SegmentRaw], mut offset:Index, spans:&mut Vec<(Span,Kind)>) {
+    for segment in segments {
+        let size = Size::new(segment.span());
+        if let SegmentRaw::SegmentRawEscape(_) = segment {
+            spans.push((Span::new(offset,size),Kind::Escape));
+        }
+        offset += size;
+    }
+}
+
+fn fmt_segments(segments:&[SegmentFmt<Ast>], mut offset:Index, spans:&mut Vec<(Span,Kind)>) {
+    for segment in segments {
+        let size = Size::new(segment.span());
+        match segment {
+            SegmentFmt::SegmentEscape(_) => {
+                spans.push((Span::new(offset,size),Kind::Escape));
+            }
+            SegmentFmt::SegmentExpr(expr) => {
+                // The expression sits between the backticks.
+                if let Some(value) = &expr.value {
+                    walk(value, offset + Size::new(1), spans);
+                }
+            }
+            SegmentFmt::SegmentPlain(_) => {}
+        }
+        offset += size;
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Escape;
+    use crate::HasRepr;
+    use crate::SegmentEscape;
+    use crate::SegmentExpr;
+    use crate::SegmentPlain;
+    use crate::TextLineFmt;
+
+    fn classes(ast:&Ast) -> Vec<Kind> {
+        highlight(ast).into_iter().map(|(_,kind)| kind).collect()
+    }
+
+    #[test]
+    fn plain_code_classification() {
+        // `List.sum xs + 1`
+        let access = Ast::infix(Ast::cons("List"), ".", Ast::var("sum"));
+        let call   = Ast::prefix(access, Ast::var("xs"));
+        let ast    = Ast::infix(call, "+", Ast::number("1"));
+        let expected = vec![
+            Kind::Constructor, Kind::Operator, Kind::Variable, Kind::Variable,
+            Kind::Operator, Kind::Number,
+        ];
+        assert_eq!(classes(&ast), expected);
+
+        // Every span slices the repr at a sensible place.
+        let repr = ast.repr();
+        for (span,_) in highlight(&ast) {
+            assert!(span.end().value <= repr.len());
+        }
+    }
+
+    #[test]
+    fn escapes_and_interpolations_inside_text() {
+        // `'a\n`x`'`
+        let line = Ast::from_shape(TextLineFmt {text:vec![
+            SegmentFmt::SegmentPlain(SegmentPlain {value:"a".to_string()}),
+            SegmentFmt::SegmentEscape(SegmentEscape {code:Escape::Character {c:'n'}}),
+            SegmentFmt::SegmentExpr(SegmentExpr {value:Some(Ast::var("x"))}),
+        ]});
+        assert_eq!(line.repr(), "'a\\n`x`'");
+
+        let spans = highlight(&line);
+        assert_eq!(spans[0], (Span::new(Index::new(0), Size::new(8)), Kind::Text));
+        assert_eq!(spans[1], (Span::new(Index::new(2), Size::new(2)), Kind::Escape));
+        assert_eq!(spans[2], (Span::new(Index::new(5), Size::new(1)), Kind::Variable));
+    }
+
+    #[test]
+    fn parse_problems_are_errors() {
+        let ast = Ast::from_shape(crate::Unrecognized {str:"??".to_string()});
+        assert_eq!(classes(&ast), vec![Kind::Error]);
+    }
+}
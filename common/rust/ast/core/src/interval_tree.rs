@@ -0,0 +1,192 @@
+//! An interval tree over half-open `usize` ranges.
+//!
+//! The span map, diagnostics, folding and highlighting all ask the same two
+//! questions — "which intervals contain this point" and "which intervals
+//! overlap this range" — so the structure answering them lives here, once,
+//! rather than as an ad-hoc scan in each of them.
+//!
+//! The tree is an implicit one: the intervals sit in a vector sorted by
+//! start, and each implicit subtree (the recursion on vector halves)
+//! remembers the maximum end within it, which lets queries prune whole
+//! halves. Bulk construction from sorted input is linear; point insertion
+//! is linear too (it shifts the vector), which fits the usage — the maps
+//! are built wholesale and queried many times.
+
+use prelude::*;
+
+
+
+// ====================
+// === IntervalTree ===
+// ====================
+
+/// A stored interval: `start..end` with a payload.
+#[derive(Clone,Debug,PartialEq,Eq)]
+struct Entry<T> {
+    start : usize,
+    end   : usize,
+    value : T,
+}
+
+/// An interval tree over half-open ranges.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct IntervalTree<T> {
+    /// The intervals, sorted by start.
+    entries : Vec<Entry<T>>,
+    /// For each index, the maximum end within the implicit subtree whose
+    /// midpoint it is.
+    max_ends : Vec<usize>,
+}
+
+impl<T> IntervalTree<T> {
+    /// Creates an empty tree.
+    pub fn new() -> IntervalTree<T> {
+        IntervalTree {entries:Vec::new(), max_ends:Vec::new()}
+    }
+
+    /// Builds a tree from intervals already sorted by start.
+    ///
+    /// Panics if the input is out of order — the caller promised it sorted.
+    pub fn from_sorted(intervals:impl IntoIterator<Item=(std::ops::Range<usize>,T)>)
+    -> IntervalTree<T> {
+        let entries:Vec<Entry<T>> = intervals.into_iter()
+            .map(|(range,value)| Entry {start:range.start, end:range.end, value})
+            .collect();
+        assert!(entries.windows(2).all(|pair| pair[0].start <= pair[1].start),
+            "from_sorted requires intervals sorted by start");
+        let mut tree = IntervalTree {entries, max_ends:Vec::new()};
+        tree.rebuild_max_ends();
+        tree
+    }
+
+    /// Inserts an interval, keeping the start order.
+    pub fn insert(&mut self, range:std::ops::Range<usize>, value:T) {
+        let position = self.entries.iter()
+            .position(|entry| entry.start > range.start)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(position, Entry {start:range.start, end:range.end, value});
+        self.rebuild_max_ends();
+    }
+
+    /// Number of stored intervals.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks whether the tree holds no intervals.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The payloads of the intervals containing the point, in start order.
+    pub fn stab(&self, point:usize) -> Vec<&T> {
+        let mut found = Vec::new();
+        self.query(0, self.entries.len(), point, point + 1, &mut found);
+        found
+    }
+
+    /// The payloads of the intervals overlapping `range`, in start order.
+    /// An empty range overlaps nothing.
+    pub fn overlapping(&self, range:std::ops::Range<usize>) -> Vec<&T> {
+        let mut found = Vec::new();
+        if range.start < range.end {
+            self.query(0, self.entries.len(), range.start, range.end, &mut found);
+        }
+        found
+    }
+
+    /// Collects payloads of intervals overlapping `from..to` within the
+    /// implicit subtree `lo..hi`.
+    fn query<'a>(&'a self, lo:usize, hi:usize, from:usize, to:usize, found:&mut Vec<&'a T>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        // Every interval here ends at or before the query start: no overlap.
+        if self.max_ends[mid] <= from {
+            return;
+        }
+        self.query(lo, mid, from, to, found);
+        let entry = &self.entries[mid];
+        if entry.start < to && from < entry.end {
+            found.push(&entry.value);
+        }
+        // Everything to the right starts at or after this start; if even
+        // this start is past the query end, so are they.
+        if entry.start < to {
+            self.query(mid + 1, hi, from, to, found);
+        }
+    }
+
+    fn rebuild_max_ends(&mut self) {
+        self.max_ends = vec![0; self.entries.len()];
+        self.compute_max_end(0, self.entries.len());
+    }
+
+    /// Computes the subtree maximum for `lo..hi`; returns it.
+    fn compute_max_end(&mut self, lo:usize, hi:usize) -> usize {
+        if lo >= hi {
+            return 0;
+        }
+        let mid     = (lo + hi) / 2;
+        let left    = self.compute_max_end(lo, mid);
+        let right   = self.compute_max_end(mid + 1, hi);
+        let maximum = self.entries[mid].end.max(left).max(right);
+        self.max_ends[mid] = maximum;
+        maximum
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> IntervalTree<&'static str> {
+        IntervalTree::from_sorted(vec![
+            (0..9,  "root"),
+            (0..5,  "left"),
+            (4..5,  "x1"),
+            (6..7,  "plus"),
+            (8..9,  "x2"),
+        ])
+    }
+
+    #[test]
+    fn stabbing_finds_exactly_the_containing_intervals() {
+        let tree = tree();
+        assert_eq!(tree.stab(4), vec![&"root",&"left",&"x1"]);
+        assert_eq!(tree.stab(5), vec![&"root"]);
+        assert_eq!(tree.stab(9), Vec::<&&str>::new());
+    }
+
+    #[test]
+    fn overlap_queries_use_half_open_semantics() {
+        let tree = tree();
+        assert_eq!(tree.overlapping(4..9), vec![&"root",&"left",&"x1",&"plus",&"x2"]);
+        assert_eq!(tree.overlapping(5..6), vec![&"root"]);
+        assert!(tree.overlapping(3..3).is_empty());
+    }
+
+    #[test]
+    fn insertion_keeps_queries_correct() {
+        let mut tree = IntervalTree::new();
+        tree.insert(2..8, "outer");
+        tree.insert(0..1, "before");
+        tree.insert(3..4, "inner");
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.stab(3), vec![&"outer",&"inner"]);
+        assert_eq!(tree.overlapping(0..3), vec![&"before",&"outer"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted by start")]
+    fn unsorted_bulk_input_is_rejected() {
+        IntervalTree::from_sorted(vec![(5..6,()), (0..1,())]);
+    }
+}
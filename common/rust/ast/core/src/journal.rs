@@ -0,0 +1,219 @@
+//! A journal of tree mutations.
+//!
+//! "How did my file get into this state" is unanswerable after the fact:
+//! every mutation returns a fresh tree and the history is gone. The
+//! journal closes that gap — route the modifying calls through it and
+//! every edit leaves an entry saying where it happened (crumbs), what the
+//! text there was before and after, and when. The sink the entries go to
+//! is pluggable, so a bug reporter can ship them, a test can inspect
+//! them, and production code that wants no journal pays for none.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::BlockLine;
+use crate::Crumb;
+use crate::Crumbs;
+use crate::HasRepr;
+use crate::Shape;
+
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+
+
+// ===============
+// === Entries ===
+// ===============
+
+/// One recorded mutation: the node at `crumbs` went from `old` to `new`.
+///
+/// Line insertion and removal are recorded against the module node (the
+/// crumbs lead to it), with the module's whole text before and after —
+/// verbose, but unambiguous to replay.
+#[derive(Clone,Debug)]
+pub struct Entry {
+    /// The modifying operation's name.
+    pub operation : &'static str,
+    /// The path from the root to the modified node.
+    pub crumbs : Crumbs,
+    /// The modified node's text before the mutation.
+    pub old : String,
+    /// The modified node's text after it.
+    pub new : String,
+    /// When the mutation happened.
+    pub timestamp : SystemTime,
+}
+
+/// A destination for journal entries.
+pub trait Sink : Debug {
+    /// Accepts one entry.
+    fn record(&self, entry:Entry);
+}
+
+/// A sink that keeps the entries in memory, for tests and bug reports.
+#[derive(Debug,Default)]
+pub struct Memory {
+    entries : RefCell<Vec<Entry>>,
+}
+
+impl Memory {
+    /// The entries recorded so far, in order.
+    pub fn entries(&self) -> Vec<Entry> {
+        self.entries.borrow().clone()
+    }
+}
+
+impl Sink for Memory {
+    fn record(&self, entry:Entry) {
+        self.entries.borrow_mut().push(entry);
+    }
+}
+
+/// A sink that drops everything — the "journal disabled" plug.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct Discard;
+
+impl Sink for Discard {
+    fn record(&self, _entry:Entry) {}
+}
+
+
+
+// ===============
+// === Journal ===
+// ===============
+
+/// The journalling front of the modifying APIs. Each method mirrors one
+/// of them, performs the edit and records it; a failed edit (bad crumbs,
+/// wrong shape) records nothing.
+#[derive(Clone,Debug)]
+pub struct Journal {
+    sink : Rc<dyn Sink>,
+}
+
+impl Journal {
+    /// A journal writing to the given sink.
+    pub fn new(sink:Rc<dyn Sink>) -> Journal {
+        Journal {sink}
+    }
+
+    /// A journal that records nothing.
+    pub fn disabled() -> Journal {
+        Journal::new(Rc::new(Discard))
+    }
+
+    /// `Ast::set_node`, journalled.
+    pub fn set_node(&self, root:&Ast, crumbs:&[Crumb], node:Ast) -> Option<Ast> {
+        let old    = root.get_node(crumbs)?.repr();
+        let new    = node.repr();
+        let result = root.set_node(crumbs, node)?;
+        self.record("set_node", crumbs.to_vec(), old, new);
+        Some(result)
+    }
+
+    /// `Ast::splice`, journalled.
+    pub fn splice(&self, root:&Ast, crumbs:&[Crumb], subtree:Ast) -> Option<Ast> {
+        let old    = root.get_node(crumbs)?.repr();
+        let new    = subtree.repr();
+        let result = root.splice(crumbs, subtree)?;
+        self.record("splice", crumbs.to_vec(), old, new);
+        Some(result)
+    }
+
+    /// Inserts a line into a module node, journalled. `None` when the node
+    /// is not a module or the index is past its end.
+    pub fn insert_line(&self, module:&Ast, index:usize, elem:Option<Ast>) -> Option<Ast> {
+        self.edit_lines("insert_line", module, |lines| {
+            if index > lines.len() { return false; }
+            lines.insert(index, BlockLine {elem:elem.clone(), off:0});
+            true
+        })
+    }
+
+    /// Removes a line from a module node, journalled. `None` when the node
+    /// is not a module or the index is out of bounds.
+    pub fn remove_line(&self, module:&Ast, index:usize) -> Option<Ast> {
+        self.edit_lines("remove_line", module, |lines| {
+            if index >= lines.len() { return false; }
+            lines.remove(index);
+            true
+        })
+    }
+
+    fn edit_lines
+    ( &self
+    , operation : &'static str
+    , module    : &Ast
+    , edit      : impl Fn(&mut Vec<BlockLine<Option<Ast>>>) -> bool
+    ) -> Option<Ast> {
+        let mut shape = match module.shape() {
+            Shape::Module(module) => module.clone(),
+            _                     => return None,
+        };
+        if !edit(&mut shape.lines) {
+            return None;
+        }
+        let result = module.with_shape(Shape::Module(shape));
+        self.record(operation, Vec::new(), module.repr(), result.repr());
+        Some(result)
+    }
+
+    fn record(&self, operation:&'static str, crumbs:Crumbs, old:String, new:String) {
+        self.sink.record(Entry {operation, crumbs, old, new, timestamp:SystemTime::now()});
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_leave_a_trail() {
+        let sink    = Rc::new(Memory::default());
+        let journal = Journal::new(sink.clone());
+        let root    = Ast::module(vec![Some(Ast::infix(Ast::var("a"), "+", Ast::var("b")))]);
+
+        let root = journal.set_node(&root, &[0,2], Ast::var("c")).unwrap();
+        assert_eq!(root.repr(), "a + c");
+        let root = journal.insert_line(&root, 1, Some(Ast::var("d"))).unwrap();
+        assert_eq!(root.repr(), "a + c\nd");
+
+        let entries = sink.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].operation, "set_node");
+        assert_eq!(entries[0].crumbs, vec![0,2]);
+        assert_eq!(entries[0].old, "b");
+        assert_eq!(entries[0].new, "c");
+        assert_eq!(entries[1].operation, "insert_line");
+        assert_eq!(entries[1].old, "a + c");
+        assert_eq!(entries[1].new, "a + c\nd");
+        assert!(entries[0].timestamp <= entries[1].timestamp);
+    }
+
+    #[test]
+    fn failed_edits_record_nothing() {
+        let sink    = Rc::new(Memory::default());
+        let journal = Journal::new(sink.clone());
+        let root    = Ast::var("a");
+
+        assert!(journal.set_node(&root, &[5], Ast::var("b")).is_none());
+        assert!(journal.insert_line(&root, 0, None).is_none());
+        assert!(journal.remove_line(&root, 0).is_none());
+        assert!(sink.entries().is_empty());
+    }
+
+    #[test]
+    fn a_disabled_journal_still_edits() {
+        let journal = Journal::disabled();
+        let root    = Ast::module(vec![Some(Ast::var("a"))]);
+        let root    = journal.remove_line(&root, 0).unwrap();
+        assert_eq!(root.repr(), "");
+    }
+}
@@ -0,0 +1,160 @@
+//! A JSON escape hatch for AST interchange.
+//!
+//! The Scala side evolves its AST serialization faster than this crate
+//! follows. A Rust process sitting in the middle of that traffic must not
+//! destroy fields it does not understand: this module deserializes a tree
+//! together with a side map of the unknown fields (addressed by JSON
+//! pointer), and re-emits them when the tree is serialized again, so a
+//! pass-through is byte-faithful even across schema drift. The side map
+//! stays valid as long as the tree's structure is unchanged — an edited
+//! node's extras are the editor's to migrate or drop.
+
+use prelude::*;
+
+use crate::Ast;
+
+use serde_json::Value;
+
+
+
+// ===================
+// === Conversions ===
+// ===================
+
+/// The tree as a JSON value.
+pub fn to_json_value(ast:&Ast) -> Value {
+    serde_json::to_value(ast).expect("AST serialization cannot fail")
+}
+
+/// A tree read back from a JSON value.
+pub fn from_json_value(value:Value) -> serde_json::Result<Ast> {
+    serde_json::from_value(value)
+}
+
+
+
+// ========================
+// === Field preserving ===
+// ========================
+
+/// The fields of a JSON tree this crate's schema does not know, keyed by
+/// the JSON pointer of the object they sat in.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct PreservedFields {
+    fields : Vec<(String,Value)>,
+}
+
+impl PreservedFields {
+    /// Checks whether any unknown fields were encountered.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// Reads a tree, setting aside every field the schema does not know.
+///
+/// The unknown fields are found by re-serializing the parsed tree and
+/// structurally comparing it with the input, which keeps this independent
+/// of how the deserializer is generated.
+pub fn from_json_value_preserving
+(value:&Value) -> serde_json::Result<(Ast,PreservedFields)> {
+    let ast   = from_json_value(value.clone())?;
+    let known = to_json_value(&ast);
+    let mut fields = Vec::new();
+    collect_unknown(value, &known, String::new(), &mut fields);
+    Ok((ast, PreservedFields {fields}))
+}
+
+/// Serializes the tree with the set-aside fields put back in place.
+pub fn to_json_value_preserving(ast:&Ast, preserved:&PreservedFields) -> Value {
+    let mut value = to_json_value(ast);
+    for (pointer,extra) in &preserved.fields {
+        insert_at(&mut value, pointer, extra.clone());
+    }
+    value
+}
+
+/// Records the fields of `original` that `known` lacks, recursing into the
+/// values both sides share.
+fn collect_unknown
+(original:&Value, known:&Value, pointer:String, out:&mut Vec<(String,Value)>) {
+    match (original,known) {
+        (Value::Object(original), Value::Object(known)) => {
+            for (key,value) in original {
+                let child = format!("{}/{}", pointer, escape_key(key));
+                match known.get(key) {
+                    Some(counterpart) => collect_unknown(value, counterpart, child, out),
+                    None              => out.push((child, value.clone())),
+                }
+            }
+        }
+        (Value::Array(original), Value::Array(known)) => {
+            for (index,(value,counterpart)) in original.iter().zip(known).enumerate() {
+                let child = format!("{}/{}", pointer, index);
+                collect_unknown(value, counterpart, child, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Puts one set-aside field back, provided its parent object still exists.
+fn insert_at(target:&mut Value, pointer:&str, extra:Value) {
+    let split = match pointer.rfind('/') {
+        Some(split) => split,
+        None        => return,
+    };
+    let key = unescape_key(&pointer[split+1..]);
+    if let Some(Value::Object(parent)) = target.pointer_mut(&pointer[..split]) {
+        parent.insert(key, extra);
+    }
+}
+
+/// Escapes a key for use in a JSON pointer (RFC 6901).
+fn escape_key(key:&str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// The inverse of `escape_key`.
+fn unescape_key(key:&str) -> String {
+    key.replace("~1", "/").replace("~0", "~")
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_roundtrip() {
+        let ast  = Ast::infix(Ast::var("a"), "+", Ast::var("b"));
+        let back = from_json_value(to_json_value(&ast)).unwrap();
+        assert_eq!(back, ast);
+    }
+
+    #[test]
+    fn unknown_fields_survive_a_pass_through() {
+        let ast       = Ast::prefix(Ast::var("foo"), Ast::var("x"));
+        let mut value = to_json_value(&ast);
+        // A newer peer decorated the root and a nested object with fields
+        // this schema has never heard of.
+        value.as_object_mut().unwrap()
+            .insert("metadata".to_string(), serde_json::json!({"x":1}));
+        value.pointer_mut("/Prefix/func").unwrap().as_object_mut().unwrap()
+            .insert("docTag".to_string(), Value::from("summary"));
+
+        let (back,preserved) = from_json_value_preserving(&value).unwrap();
+        assert_eq!(back, ast);
+        assert!(!preserved.is_empty());
+        assert_eq!(to_json_value_preserving(&back, &preserved), value);
+
+        // Without the extras nothing unknown is set aside.
+        let (_,none) = from_json_value_preserving(&to_json_value(&ast)).unwrap();
+        assert!(none.is_empty());
+    }
+}
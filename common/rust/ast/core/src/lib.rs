@@ -0,0 +1,1898 @@
+//! The abstract syntax tree of the Enso language, as produced by the parser
+//! and consumed by the IDE.
+//!
+//! The types here mirror the Scala parser's output schema — changing any
+//! (de)serialization-visible detail requires a matching change on the Scala
+//! side.
+
+#![warn(missing_docs)]
+
+pub mod analysis;
+pub mod anonymize;
+pub mod ascription;
+#[cfg(feature="serialization")]
+pub mod cache;
+#[cfg(feature="serialization")]
+pub mod clipboard;
+pub mod comments;
+pub mod diagnostics;
+pub mod digest;
+pub mod eval;
+pub mod folding;
+pub mod format;
+pub mod highlight;
+pub mod interval_tree;
+#[cfg(feature="serialization")]
+pub mod json;
+pub mod journal;
+pub mod location;
+pub mod macros;
+pub mod merge;
+pub mod number;
+pub mod opr;
+#[cfg(feature="serialization")]
+pub mod partial;
+pub mod pipeline;
+pub mod placeholders;
+pub mod prefix;
+pub mod search;
+pub mod snippets;
+pub mod span_map;
+pub mod stats;
+pub mod text;
+pub mod transform;
+pub mod unicode;
+pub mod vector;
+pub mod widgets;
+pub mod workspace;
+
+pub use location::Index;
+pub use location::Size;
+pub use location::Span;
+
+use prelude::*;
+
+use ast_macros::*;
+#[cfg(feature="serialization")]
+use serde::Serialize;
+#[cfg(feature="serialization")]
+use serde::Deserialize;
+use uuid::Uuid;
+
+
+
+// ==============
+// === Layers ===
+// ==============
+
+/// A layer that can be placed on top of another type, enriching it with new
+/// data while still deref-ing to the wrapped value.
+///
+/// The trait works in both directions: a layer can be put on and peeled
+/// off again, so generic code can unwrap an annotation stack without
+/// knowing the concrete order the layers were applied in.
+pub trait Layer<T> {
+    /// Wraps the lower layer.
+    fn layered(t:T) -> Self;
+    /// Peels the layer off, returning the wrapped value and dropping the
+    /// layer's own data.
+    fn unlayer(self) -> T;
+    /// Borrows the wrapped value. Unlike the `Deref` the layers also
+    /// provide, this names the target type, so it stays usable in generic
+    /// contexts.
+    fn as_inner(&self) -> &T;
+}
+
+/// Unique identifier of an AST node, stable across edits.
+pub type Id = Uuid;
+
+/// A layer attaching an optional unique identifier.
+#[derive(Clone,Debug,PartialEq,Eq,Shrinkwrap)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct WithID<T> {
+    /// The wrapped value.
+    #[shrinkwrap(main_field)]
+    #[cfg_attr(feature="serialization", serde(flatten))]
+    pub wrapped : T,
+    /// Unique identifier of the node.
+    pub id : Option<Id>,
+}
+
+impl<T> Layer<T> for WithID<T> {
+    fn layered(wrapped:T) -> Self {
+        WithID {wrapped, id:None}
+    }
+    fn unlayer(self) -> T {
+        self.wrapped
+    }
+    fn as_inner(&self) -> &T {
+        &self.wrapped
+    }
+}
+
+/// A layer caching the textual length of the wrapped value.
+#[derive(Clone,Debug,PartialEq,Eq,Shrinkwrap)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct WithLength<T> {
+    /// The wrapped value.
+    #[shrinkwrap(main_field)]
+    #[cfg_attr(feature="serialization", serde(flatten))]
+    pub wrapped : T,
+    /// Length of the textual representation, in characters.
+    pub len : usize,
+}
+
+impl<T:HasSpan> Layer<T> for WithLength<T> {
+    fn layered(wrapped:T) -> Self {
+        let len = wrapped.span();
+        WithLength {wrapped,len}
+    }
+    fn unlayer(self) -> T {
+        self.wrapped
+    }
+    fn as_inner(&self) -> &T {
+        &self.wrapped
+    }
+}
+
+
+
+// ==============
+// === Tokens ===
+// ==============
+
+/// A single element of a node's textual representation.
+#[derive(Clone,Copy,Debug)]
+pub enum Token<'a> {
+    /// A number of space characters.
+    Off(usize),
+    /// A single character.
+    Chr(char),
+    /// A string chunk.
+    Str(&'a str),
+    /// A child node.
+    Ast(&'a Ast),
+}
+
+/// An entity that consumes tokens, e.g. to measure or print a node.
+///
+/// The lifetime parameter is the lifetime of the fed tokens — consumers that
+/// retain token contents (like the child collector) tie it to their own
+/// storage, while measuring consumers stay generic over it.
+pub trait TokenConsumer<'a> {
+    /// Consumes a single token.
+    fn feed(&mut self, token:Token<'a>);
+}
+
+/// An entity that can describe its textual representation as tokens.
+pub trait HasTokens {
+    /// Feeds the tokens of `self` to the consumer, in textual order.
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>);
+}
+
+impl<T:HasTokens> HasTokens for Option<T> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        if let Some(value) = self {
+            value.feed_to(consumer)
+        }
+    }
+}
+
+impl<T:HasTokens> HasTokens for Vec<T> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        for value in self {
+            value.feed_to(consumer)
+        }
+    }
+}
+
+impl HasTokens for Ast {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        consumer.feed(Token::Ast(self))
+    }
+}
+
+impl HasTokens for String {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        consumer.feed(Token::Str(self))
+    }
+}
+
+impl HasTokens for char {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        consumer.feed(Token::Chr(*self))
+    }
+}
+
+
+
+// ====================
+// === Span & Repr ===
+// ====================
+
+/// An entity which knows the length of its textual representation.
+pub trait HasSpan {
+    /// Length of the textual representation, in characters.
+    fn span(&self) -> usize;
+}
+
+/// An entity which can print its textual representation.
+pub trait HasRepr {
+    /// Appends the textual representation to the target.
+    fn write_repr(&self, target:&mut String);
+
+    /// The textual representation.
+    fn repr(&self) -> String {
+        let mut target = String::new();
+        self.write_repr(&mut target);
+        target
+    }
+}
+
+/// A consumer that measures the total span of the fed tokens.
+#[derive(Debug,Default)]
+struct SpanConsumer {
+    span : usize,
+}
+
+impl<'a> TokenConsumer<'a> for SpanConsumer {
+    fn feed(&mut self, token:Token<'a>) {
+        self.span += match token {
+            Token::Off(off) => off,
+            Token::Chr(_)   => 1,
+            Token::Str(str) => str.chars().count(),
+            // The length cached in the node's `WithLength` layer — must not
+            // go through `HasSpan`, which would feed the node back here.
+            Token::Ast(ast) => ast.len(),
+        }
+    }
+}
+
+/// A consumer that prints the fed tokens.
+#[derive(Debug,Default)]
+struct ReprConsumer {
+    repr : String,
+}
+
+impl<'a> TokenConsumer<'a> for ReprConsumer {
+    fn feed(&mut self, token:Token<'a>) {
+        match token {
+            Token::Off(off) => self.repr.extend(std::iter::repeat_n(' ', off)),
+            Token::Chr(chr) => self.repr.push(chr),
+            Token::Str(str) => self.repr.push_str(str),
+            // Descend into the shape rather than the node itself — the
+            // node's `HasRepr` would feed it back here.
+            Token::Ast(ast) => ast.shape().write_repr(&mut self.repr),
+        }
+    }
+}
+
+impl<T:HasTokens> HasSpan for T {
+    fn span(&self) -> usize {
+        let mut consumer = SpanConsumer::default();
+        self.feed_to(&mut consumer);
+        consumer.span
+    }
+}
+
+impl<T:HasTokens> HasRepr for T {
+    fn write_repr(&self, target:&mut String) {
+        let mut consumer = ReprConsumer::default();
+        self.feed_to(&mut consumer);
+        target.push_str(&consumer.repr);
+    }
+}
+
+/// An entity that contains a countable number of items — lines of a block,
+/// segments of a macro usage and the like. Gives callers one uniform way of
+/// asking for sizes instead of a per-shape field access.
+pub trait HasLength {
+    /// Number of contained items.
+    fn len(&self) -> usize;
+
+    /// Checks whether there are no items.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+
+
+// ===========
+// === Ast ===
+// ===========
+
+/// An error raised by the generated `TryFrom` downcasts when the node holds
+/// a different shape variant than requested.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct UnexpectedShape;
+
+/// The AST node.
+///
+/// A cheaply clonable, immutable handle to the node's shape enriched with an
+/// optional id and a cached span.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Ast {
+    wrapped : Rc<WithID<WithLength<Shape<Ast>>>>,
+}
+
+impl Deref for Ast {
+    type Target = WithID<WithLength<Shape<Ast>>>;
+    fn deref(&self) -> &Self::Target {
+        &self.wrapped
+    }
+}
+
+impl Ast {
+    /// Creates a node from a shape, generating no id.
+    pub fn new<S:Into<Shape<Ast>>>(shape:S, id:Option<Id>) -> Ast {
+        let shape       = shape.into();
+        let with_length = WithLength::layered(shape);
+        let with_id     = WithID {wrapped:with_length, id};
+        Ast {wrapped:Rc::new(with_id)}
+    }
+
+    /// Creates a node from a shape with no id.
+    pub fn from_shape<S:Into<Shape<Ast>>>(shape:S) -> Ast {
+        Ast::new(shape,None)
+    }
+
+    /// The shape of this node.
+    pub fn shape(&self) -> &Shape<Ast> {
+        &self.wrapped.wrapped.wrapped
+    }
+
+    /// The id of this node, if any.
+    pub fn id(&self) -> Option<Id> {
+        self.wrapped.id
+    }
+
+    /// This node with the id replaced.
+    pub fn with_id(&self, id:Id) -> Ast {
+        Ast::new(self.shape().clone(), Some(id))
+    }
+
+    /// This node with the shape replaced. The id is retained.
+    pub fn with_shape<S:Into<Shape<Ast>>>(&self, shape:S) -> Ast {
+        Ast::new(shape, self.id())
+    }
+
+    /// Length of this node's textual representation, in characters.
+    pub fn len(&self) -> usize {
+        self.wrapped.wrapped.len
+    }
+
+    /// Whether the textual representation of this node is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Length of this node's textual representation measured under the
+    /// given policy.
+    pub fn span_with(&self, policy:unicode::LengthPolicy) -> usize {
+        unicode::span_with(self.shape(), policy)
+    }
+
+    /// Iterates over this node and all its descendants, depth-first,
+    /// parents before children.
+    pub fn iterate_subtree(&self) -> impl Iterator<Item=&Ast> + '_ {
+        internal::iterate_subtree(self)
+    }
+
+    /// The direct child nodes of this node, in textual order.
+    pub fn children(&self) -> Vec<&Ast> {
+        internal::children(self)
+    }
+
+    /// The child node under given crumbs, or `None` if any crumb is out of
+    /// bounds.
+    pub fn get_node(&self, crumbs:&[Crumb]) -> Option<&Ast> {
+        let mut current = self;
+        for crumb in crumbs {
+            current = current.children().into_iter().nth(*crumb)?;
+        }
+        Some(current)
+    }
+
+    /// This tree with the node under given crumbs replaced by `node`.
+    /// Returns `None` if any crumb is out of bounds.
+    pub fn set_node(&self, crumbs:&[Crumb], node:Ast) -> Option<Ast> {
+        match crumbs {
+            []                   => Some(node),
+            [first, deeper @ ..] => {
+                let child   = self.children().into_iter().nth(*first)?;
+                let updated = child.set_node(deeper, node)?;
+                Some(self.with_child(*first, updated))
+            }
+        }
+    }
+
+    /// A detached copy of the subtree under given crumbs, or `None` if any
+    /// crumb is out of bounds.
+    ///
+    /// As nodes are immutable, the returned handle is fully independent of
+    /// this tree. Ids are kept, so code that moves a fragment elsewhere
+    /// (collapse-to-function, copy/paste) can still correlate the nodes with
+    /// their metadata.
+    pub fn extract_subtree(&self, crumbs:&[Crumb]) -> Option<Ast> {
+        self.get_node(crumbs).cloned()
+    }
+
+    /// This tree with the subtree under given crumbs replaced — the reverse
+    /// of `extract_subtree`. Returns `None` if any crumb is out of bounds.
+    ///
+    /// Every node along the path is rebuilt, so the cached spans account for
+    /// the new subtree's length; ids of the rebuilt ancestors are retained.
+    pub fn splice(&self, crumbs:&[Crumb], subtree:Ast) -> Option<Ast> {
+        self.set_node(crumbs, subtree)
+    }
+
+    /// This node with its `index`-th child (in textual order) replaced.
+    fn with_child(&self, index:usize, new_child:Ast) -> Ast {
+        let mut counter = 0;
+        let shape = self.shape().map_children(|child| {
+            let replace = counter == index;
+            counter += 1;
+            if replace { new_child.clone() } else { child.clone() }
+        });
+        self.with_shape(shape)
+    }
+}
+
+/// A single step on a path from a node to one of its descendants: the index
+/// of a child, in textual order.
+pub type Crumb = usize;
+
+/// A path from a node to one of its descendants.
+pub type Crumbs = Vec<Crumb>;
+
+// ====================
+// === Constructors ===
+// ====================
+
+/// Constructors of the basic node shapes, as used by code that composes
+/// expressions programmatically (e.g. the searcher).
+impl Ast {
+    /// Creates a `_` placeholder node.
+    pub fn blank() -> Ast {
+        Ast::from_shape(Blank {})
+    }
+
+    /// Creates a variable node.
+    pub fn var(name:impl Str) -> Ast {
+        Ast::from_shape(Var {name:name.into()})
+    }
+
+    /// Creates a constructor name node.
+    pub fn cons(name:impl Str) -> Ast {
+        Ast::from_shape(Cons {name:name.into()})
+    }
+
+    /// Creates an operator node.
+    pub fn opr(name:impl Str) -> Ast {
+        Ast::from_shape(Opr {name:name.into()})
+    }
+
+    /// Creates a number literal node.
+    pub fn number(int:impl Str) -> Ast {
+        Ast::from_shape(Number {base:None, int:int.into()})
+    }
+
+    /// Creates a prefix application node: `func arg`.
+    pub fn prefix(func:Ast, arg:Ast) -> Ast {
+        Ast::from_shape(Prefix {func, off:1, arg})
+    }
+
+    /// Creates an infix application node: `larg opr rarg`.
+    pub fn infix(larg:Ast, opr:impl Str, rarg:Ast) -> Ast {
+        let opr = Ast::opr(opr);
+        Ast::from_shape(Infix {larg, loff:1, opr, roff:1, rarg})
+    }
+
+    /// Creates a left operator section: `arg opr`, e.g. `2+`.
+    pub fn section_left(arg:Ast, opr:impl Str) -> Ast {
+        let opr = Ast::opr(opr);
+        Ast::from_shape(SectionLeft {arg, off:0, opr})
+    }
+
+    /// Creates a right operator section: `opr arg`, e.g. `+2`.
+    pub fn section_right(opr:impl Str, arg:Ast) -> Ast {
+        let opr = Ast::opr(opr);
+        Ast::from_shape(SectionRight {opr, off:0, arg})
+    }
+
+    /// Creates an operand-less operator section: `opr`, e.g. `+`.
+    pub fn section_sides(opr:impl Str) -> Ast {
+        let opr = Ast::opr(opr);
+        Ast::from_shape(SectionSides {opr})
+    }
+
+    /// Creates a module node from expression lines. An empty vector is a
+    /// valid, empty module — what a fresh source file parses to.
+    pub fn module(exprs:Vec<Option<Ast>>) -> Ast {
+        let lines = exprs.into_iter().map(|elem| BlockLine {elem, off:0}).collect();
+        Ast::from_shape(Module {lines})
+    }
+
+    /// Creates a disable comment node; each line is prefixed with `#`.
+    pub fn comment(lines:Vec<String>) -> Ast {
+        Ast::from_shape(Comment {lines})
+    }
+
+    /// Creates an import declaration from the path segments, e.g.
+    /// `import Base.List` from `["Base","List"]`.
+    pub fn import(path:&[&str]) -> Ast {
+        let path = path.iter().enumerate().map(|(index,segment)| Shifted {
+            off     : if index == 0 { 1 } else { 0 },
+            wrapped : Ast::cons(*segment),
+        }).collect();
+        Ast::from_shape(Import {path})
+    }
+
+    /// Wraps the expression in parentheses: `(expr)`.
+    pub fn grouped(body:Ast) -> Ast {
+        Ast::from_shape(Group {loff:0, body:Some(body), roff:0})
+    }
+}
+
+/// Inserts `arg` as the `index`-th argument of the prefix application chain
+/// rooted at `expr`.
+///
+/// If the chain has fewer than `index` arguments, `_` placeholders are
+/// appended until the argument can be placed at the requested position. This
+/// is how the searcher fills a concrete argument into a partially known
+/// call.
+pub fn apply_argument(expr:&Ast, index:usize, arg:Ast) -> Ast {
+    // Deconstruct the chain: innermost function and arguments in order.
+    let mut args    = Vec::new();
+    let mut current = expr.clone();
+    while let Shape::Prefix(prefix) = current.shape() {
+        args.push((prefix.off, prefix.arg.clone()));
+        let func = prefix.func.clone();
+        current  = func;
+    }
+    args.reverse();
+    while args.len() < index {
+        args.push((1, Ast::blank()));
+    }
+    args.insert(index, (1, arg));
+    // Rebuild the chain.
+    let mut result = current;
+    for (off,arg) in args {
+        result = Ast::from_shape(Prefix {func:result, off, arg});
+    }
+    result
+}
+
+impl Display for Ast {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.repr())
+    }
+}
+
+#[cfg(feature="serialization")]
+impl Serialize for Ast {
+    fn serialize<S:serde::Serializer>(&self, serializer:S) -> std::result::Result<S::Ok,S::Error> {
+        self.wrapped.serialize(serializer)
+    }
+}
+
+#[cfg(feature="serialization")]
+impl<'de> Deserialize<'de> for Ast {
+    fn deserialize<D:serde::Deserializer<'de>>(deserializer:D)
+    -> std::result::Result<Ast,D::Error> {
+        let with_id = WithID::<WithLength<Shape<Ast>>>::deserialize(deserializer)?;
+        Ok(Ast {wrapped:Rc::new(with_id)})
+    }
+}
+
+
+
+// =============
+// === Shape ===
+// =============
+
+/// A shifted value — a value preceded by a whitespace offset.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct Shifted<T> {
+    /// Offset of preceding whitespace, in characters.
+    pub off : usize,
+    /// The value.
+    pub wrapped : T,
+}
+
+impl<T:HasTokens> HasTokens for Shifted<T> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        consumer.feed(Token::Off(self.off));
+        self.wrapped.feed_to(consumer);
+    }
+}
+
+/// A line of a block: its element and the trailing whitespace.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct BlockLine<T> {
+    /// The line's content.
+    pub elem : T,
+    /// Trailing whitespace, in characters.
+    pub off : usize,
+}
+
+impl<T:HasTokens> HasTokens for BlockLine<T> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        self.elem.feed_to(consumer);
+        consumer.feed(Token::Off(self.off));
+    }
+}
+
+/// Type of a block.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum BlockType {
+    /// A block that is an argument of some expression, e.g. a body of `=`.
+    Continuous {},
+    /// A block that is not an argument.
+    Discontinuous {},
+}
+
+/// Defines the shape of the AST node, parametrized by the type of children.
+///
+/// The variants mirror the Scala parser's AST cases. The `#[ast(flat)]`
+/// macro generates a standalone struct for each variant (e.g. `Var`,
+/// `Prefix<T>`) together with conversions between them and the enum.
+#[ast(flat)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum Shape<T> {
+    /// Tokens the parser could not make sense of.
+    Unrecognized  { str : String },
+    /// The `_` placeholder.
+    Blank         { },
+    /// A variable name.
+    Var           { name : String },
+    /// A constructor (capitalized) name.
+    Cons          { name : String },
+    /// An operator name.
+    Opr           { name : String },
+    /// A modifier operator, e.g. the `+=` in `x += 1`.
+    Mod           { name : String },
+    /// A number literal, optionally with an explicit radix, e.g. `16_ff`.
+    Number        { base : Option<String>, int : String },
+    /// A number base without the actual number, e.g. the `16_` alone.
+    DanglingBase  { base : String },
+    /// An invalid identifier — a valid one followed by an invalid suffix.
+    InvalidSuffix { elem : T, suffix : String },
+
+    /// A single-line raw text literal.
+    TextLineRaw   { text : Vec<SegmentRaw> },
+    /// A single-line formatted (interpolated) text literal.
+    TextLineFmt   { text : Vec<SegmentFmt<T>> },
+    /// A multi-line raw text literal.
+    TextBlockRaw  { text : Vec<TextBlockLine<SegmentRaw>>, spaces : usize, offset : usize },
+    /// A multi-line formatted text literal.
+    TextBlockFmt  { text : Vec<TextBlockLine<SegmentFmt<T>>>, spaces : usize, offset : usize },
+    /// A text literal with no closing quote.
+    TextUnclosed  { line : TextLine<T> },
+
+    /// A function application.
+    Prefix        { func : T, off : usize, arg : T },
+    /// A binary operator application.
+    Infix         { larg : T, loff : usize, opr : T, roff : usize, rarg : T },
+    /// An operator section with only the left operand, e.g. `2+`.
+    SectionLeft   { arg : T, off : usize, opr : T },
+    /// An operator section with only the right operand, e.g. `+2`.
+    SectionRight  { opr : T, off : usize, arg : T },
+    /// An operator section with no operands, e.g. `+`.
+    SectionSides  { opr : T },
+
+    /// An expression wrapped in parentheses.
+    Group         { loff : usize, body : Option<T>, roff : usize },
+    /// A sequence of expression lines with equal indentation.
+    Block         { ty          : BlockType
+                  , indent      : usize
+                  , empty_lines : Vec<usize>
+                  , first_line  : BlockLine<T>
+                  , lines       : Vec<BlockLine<Option<T>>>
+                  , is_orphan   : bool
+                  },
+    /// The topmost structure — a sequence of lines with no indentation.
+    Module        { lines : Vec<BlockLine<Option<T>>> },
+
+    /// A disable comment. Lines keep their text verbatim (including leading
+    /// spaces), so the user's formatting is reproduced faithfully.
+    Comment       { lines : Vec<String> },
+    /// An import declaration, e.g. `import Base.List`. The first segment's
+    /// offset is the space after the keyword; later offsets precede their
+    /// `.` separators.
+    Import        { path : Vec<Shifted<T>> },
+    /// A usage of a macro, e.g. `if a then b else c`.
+    Match         { pfx : Option<T>, segs : Vec<Shifted<MacroMatchSegment<T>>>, resolved : Option<T> },
+    /// A mixfix operator usage — name segments paired with arguments.
+    Mixfix        { name : Vec<T>, args : Vec<Shifted<T>> },
+
+    /// A type definition, e.g. `def Maybe a` with an optional body block
+    /// listing its constructors and methods.
+    Def           { name : T, args : Vec<Shifted<T>>, body : Option<Shifted<T>> },
+}
+
+
+
+// ====================
+// === Text shapes ===
+// ====================
+
+/// A line of a text block literal, possibly preceded by empty lines.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct TextBlockLine<T> {
+    /// Lengths of the empty lines preceding this one.
+    pub empty_lines : Vec<usize>,
+    /// The segments of the line.
+    pub text : Vec<T>,
+}
+
+/// A single line text literal — either raw or formatted.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum TextLine<T> {
+    /// A raw text line.
+    TextLineRaw(TextLineRaw),
+    /// A formatted text line.
+    TextLineFmt(TextLineFmt<T>),
+}
+
+impl<T:HasTokens> HasTokens for TextLine<T> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        match self {
+            TextLine::TextLineRaw(line) => {
+                consumer.feed(Token::Chr('"'));
+                line.text.feed_to(consumer);
+                consumer.feed(Token::Chr('"'));
+            }
+            TextLine::TextLineFmt(line) => {
+                consumer.feed(Token::Chr('\''));
+                line.text.feed_to(consumer);
+                consumer.feed(Token::Chr('\''));
+            }
+        }
+    }
+}
+
+/// A segment of a raw text literal.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum SegmentRaw {
+    /// A plain text chunk.
+    SegmentPlain(SegmentPlain),
+    /// An escape sequence.
+    SegmentRawEscape(SegmentRawEscape),
+}
+
+/// A segment of a formatted text literal.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum SegmentFmt<T> {
+    /// A plain text chunk.
+    SegmentPlain(SegmentPlain),
+    /// An interpolated expression, e.g. `` `foo` ``.
+    SegmentExpr(SegmentExpr<T>),
+    /// An escape sequence.
+    SegmentEscape(SegmentEscape),
+}
+
+/// A plain text chunk.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct SegmentPlain {
+    /// The text.
+    pub value : String,
+}
+
+/// An escape sequence allowed in a raw text literal.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct SegmentRawEscape {
+    /// The escape code.
+    pub code : RawEscape,
+}
+
+/// An interpolated expression inside a formatted text literal.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct SegmentExpr<T> {
+    /// The expression; `None` for an empty ` `` `.
+    pub value : Option<T>,
+}
+
+/// An escape sequence inside a formatted text literal.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct SegmentEscape {
+    /// The escape code.
+    pub code : Escape,
+}
+
+/// Escapes allowed in raw text literals.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum RawEscape {
+    /// A backslash at the end of the literal.
+    Unfinished {},
+    /// A backslash followed by an invalid escape character.
+    Invalid {
+        /// The invalid character.
+        str : char,
+    },
+    /// An escaped backslash.
+    Slash {},
+    /// An escaped quote.
+    Quote {},
+    /// An escaped raw quote.
+    RawQuote {},
+}
+
+/// Escapes allowed in formatted text literals.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum Escape {
+    /// A simple character escape, e.g. `\n`.
+    Character {
+        /// The escape character.
+        c : char,
+    },
+    /// A control character escape, e.g. `\ETB`.
+    Control {
+        /// Name of the control character.
+        name : String,
+        /// Its code.
+        code : u8,
+    },
+    /// A numeric escape, e.g. `\10`.
+    Number {
+        /// The digits.
+        digits : String,
+    },
+    /// A 16-bit unicode escape, e.g. `©`.
+    Unicode16 {
+        /// The digits.
+        digits : String,
+    },
+    /// A 32-bit unicode escape.
+    Unicode32 {
+        /// The digits.
+        digits : String,
+    },
+}
+
+impl HasTokens for SegmentRaw {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        match self {
+            SegmentRaw::SegmentPlain(seg)     => seg.feed_to(consumer),
+            SegmentRaw::SegmentRawEscape(seg) => seg.feed_to(consumer),
+        }
+    }
+}
+
+impl<T:HasTokens> HasTokens for SegmentFmt<T> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        match self {
+            SegmentFmt::SegmentPlain(seg)  => seg.feed_to(consumer),
+            SegmentFmt::SegmentExpr(seg)   => seg.feed_to(consumer),
+            SegmentFmt::SegmentEscape(seg) => seg.feed_to(consumer),
+        }
+    }
+}
+
+impl HasTokens for SegmentPlain {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        consumer.feed(Token::Str(&self.value))
+    }
+}
+
+impl HasTokens for SegmentRawEscape {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        consumer.feed(Token::Chr('\\'));
+        self.code.feed_to(consumer);
+    }
+}
+
+impl<T:HasTokens> HasTokens for SegmentExpr<T> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        consumer.feed(Token::Chr('`'));
+        self.value.feed_to(consumer);
+        consumer.feed(Token::Chr('`'));
+    }
+}
+
+impl HasTokens for SegmentEscape {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        consumer.feed(Token::Chr('\\'));
+        self.code.feed_to(consumer);
+    }
+}
+
+impl HasTokens for RawEscape {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        match self {
+            RawEscape::Unfinished {}    => {}
+            RawEscape::Invalid {str}    => consumer.feed(Token::Chr(*str)),
+            RawEscape::Slash {}         => consumer.feed(Token::Chr('\\')),
+            RawEscape::Quote {}         => consumer.feed(Token::Chr('\'')),
+            RawEscape::RawQuote {}      => consumer.feed(Token::Chr('"')),
+        }
+    }
+}
+
+impl HasTokens for Escape {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        match self {
+            Escape::Character {c}      => consumer.feed(Token::Chr(*c)),
+            Escape::Control {name,..}  => consumer.feed(Token::Str(name)),
+            Escape::Number {digits}    => consumer.feed(Token::Str(digits)),
+            Escape::Unicode16 {digits} => {
+                consumer.feed(Token::Chr('u'));
+                consumer.feed(Token::Str(digits));
+            }
+            Escape::Unicode32 {digits} => {
+                consumer.feed(Token::Chr('U'));
+                consumer.feed(Token::Str(digits));
+            }
+        }
+    }
+}
+
+
+
+// ====================
+// === Macro match ===
+// ====================
+
+/// A single segment of a macro usage, e.g. the `if a` of `if a then b`.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct MacroMatchSegment<T> {
+    /// The segment's head — the keyword or operator that introduced it.
+    pub head : T,
+    /// The tokens consumed by the segment's pattern.
+    pub body : Vec<Shifted<T>>,
+}
+
+impl<T:HasTokens> HasTokens for MacroMatchSegment<T> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        self.head.feed_to(consumer);
+        self.body.feed_to(consumer);
+    }
+}
+
+
+
+// =====================
+// === Shape tokens ===
+// =====================
+
+impl HasTokens for Shape<Ast> {
+    fn feed_to<'a>(&'a self, consumer:&mut impl TokenConsumer<'a>) {
+        // Deliberately without a wildcard arm: spans and pretty-printing are
+        // both computed from this feed, so a new variant must state its
+        // tokens here before the crate compiles again (see
+        // `with_shape_variants!`). A fallback would turn that omission into
+        // wrong spans at runtime instead.
+        match self {
+            Shape::Unrecognized(t)  => consumer.feed(Token::Str(&t.str)),
+            Shape::Blank(_)         => consumer.feed(Token::Chr('_')),
+            Shape::Var(t)           => consumer.feed(Token::Str(&t.name)),
+            Shape::Cons(t)          => consumer.feed(Token::Str(&t.name)),
+            Shape::Opr(t)           => consumer.feed(Token::Str(&t.name)),
+            Shape::Mod(t)           => {
+                // A modifier stores its base operator name; the trailing `=`
+                // is part of the representation only.
+                consumer.feed(Token::Str(&t.name));
+                consumer.feed(Token::Chr('='));
+            }
+            Shape::Number(t)        => {
+                if let Some(base) = &t.base {
+                    consumer.feed(Token::Str(base));
+                    consumer.feed(Token::Chr('_'));
+                }
+                consumer.feed(Token::Str(&t.int));
+            }
+            Shape::DanglingBase(t)  => {
+                consumer.feed(Token::Str(&t.base));
+                consumer.feed(Token::Chr('_'));
+            }
+            Shape::InvalidSuffix(t) => {
+                t.elem.feed_to(consumer);
+                consumer.feed(Token::Str(&t.suffix));
+            }
+            Shape::TextLineRaw(t)   => {
+                consumer.feed(Token::Chr('"'));
+                t.text.feed_to(consumer);
+                consumer.feed(Token::Chr('"'));
+            }
+            Shape::TextLineFmt(t)   => {
+                consumer.feed(Token::Chr('\''));
+                t.text.feed_to(consumer);
+                consumer.feed(Token::Chr('\''));
+            }
+            Shape::TextBlockRaw(t)  => feed_text_block(consumer, "\"\"\"", &t.text, t.spaces, t.offset),
+            Shape::TextBlockFmt(t)  => feed_text_block(consumer, "'''", &t.text, t.spaces, t.offset),
+            Shape::TextUnclosed(t)  => match &t.line {
+                // An unclosed literal is its line without the closing quote.
+                TextLine::TextLineRaw(line) => {
+                    consumer.feed(Token::Chr('"'));
+                    line.text.feed_to(consumer);
+                }
+                TextLine::TextLineFmt(line) => {
+                    consumer.feed(Token::Chr('\''));
+                    line.text.feed_to(consumer);
+                }
+            },
+            Shape::Prefix(t)        => {
+                t.func.feed_to(consumer);
+                consumer.feed(Token::Off(t.off));
+                t.arg.feed_to(consumer);
+            }
+            Shape::Infix(t)         => {
+                t.larg.feed_to(consumer);
+                consumer.feed(Token::Off(t.loff));
+                t.opr.feed_to(consumer);
+                consumer.feed(Token::Off(t.roff));
+                t.rarg.feed_to(consumer);
+            }
+            Shape::SectionLeft(t)   => {
+                t.arg.feed_to(consumer);
+                consumer.feed(Token::Off(t.off));
+                t.opr.feed_to(consumer);
+            }
+            Shape::SectionRight(t)  => {
+                t.opr.feed_to(consumer);
+                consumer.feed(Token::Off(t.off));
+                t.arg.feed_to(consumer);
+            }
+            Shape::SectionSides(t)  => t.opr.feed_to(consumer),
+            Shape::Group(t)         => {
+                consumer.feed(Token::Chr('('));
+                consumer.feed(Token::Off(t.loff));
+                t.body.feed_to(consumer);
+                consumer.feed(Token::Off(t.roff));
+                consumer.feed(Token::Chr(')'));
+            }
+            Shape::Block(t)         => {
+                for empty_line_space in &t.empty_lines {
+                    consumer.feed(Token::Chr('\n'));
+                    consumer.feed(Token::Off(*empty_line_space));
+                }
+                consumer.feed(Token::Chr('\n'));
+                consumer.feed(Token::Off(t.indent));
+                t.first_line.feed_to(consumer);
+                for line in &t.lines {
+                    consumer.feed(Token::Chr('\n'));
+                    if line.elem.is_some() {
+                        consumer.feed(Token::Off(t.indent));
+                    }
+                    line.feed_to(consumer);
+                }
+            }
+            Shape::Module(t)        => {
+                let mut first = true;
+                for line in &t.lines {
+                    if !first {
+                        consumer.feed(Token::Chr('\n'));
+                    }
+                    line.feed_to(consumer);
+                    first = false;
+                }
+            }
+            Shape::Comment(t)       => {
+                let mut first = true;
+                for line in &t.lines {
+                    if !first {
+                        consumer.feed(Token::Chr('\n'));
+                    }
+                    consumer.feed(Token::Chr('#'));
+                    consumer.feed(Token::Str(line));
+                    first = false;
+                }
+            }
+            Shape::Import(t)        => {
+                consumer.feed(Token::Str("import"));
+                for (index,segment) in t.path.iter().enumerate() {
+                    consumer.feed(Token::Off(segment.off));
+                    if index > 0 {
+                        consumer.feed(Token::Chr('.'));
+                    }
+                    segment.wrapped.feed_to(consumer);
+                }
+            }
+            Shape::Match(t)         => {
+                t.pfx.feed_to(consumer);
+                t.segs.feed_to(consumer);
+            }
+            Shape::Mixfix(t)        => {
+                let mut args = t.args.iter();
+                for (index,name) in t.name.iter().enumerate() {
+                    if index > 0 {
+                        consumer.feed(Token::Off(1));
+                    }
+                    name.feed_to(consumer);
+                    if let Some(arg) = args.next() {
+                        arg.feed_to(consumer);
+                    }
+                }
+            }
+            Shape::Def(t)           => {
+                consumer.feed(Token::Str("def"));
+                consumer.feed(Token::Off(1));
+                t.name.feed_to(consumer);
+                t.args.feed_to(consumer);
+                t.body.feed_to(consumer);
+            }
+        }
+    }
+}
+
+impl Def<Ast> {
+    /// Creates a type definition with standard single-space offsets.
+    ///
+    /// The body, if any, should be a block; it is attached with no extra
+    /// offset, as a block's representation starts with its own newline.
+    pub fn new(name:Ast, args:Vec<Ast>, body:Option<Ast>) -> Def<Ast> {
+        let args = args.into_iter().map(|arg| Shifted {off:1, wrapped:arg}).collect();
+        let body = body.map(|body| Shifted {off:0, wrapped:body});
+        Def {name,args,body}
+    }
+
+    /// The constructor declarations listed in the definition's body block.
+    ///
+    /// A line declares a constructor when it is a `Cons` or a prefix
+    /// application whose innermost function is a `Cons`.
+    pub fn constructors(&self) -> Vec<&Ast> {
+        let mut result = Vec::new();
+        if let Some(body) = &self.body {
+            if let Shape::Block(block) = body.wrapped.shape() {
+                let lines = std::iter::once(Some(&block.first_line.elem))
+                    .chain(block.lines.iter().map(|line| line.elem.as_ref()));
+                for line in lines.flatten() {
+                    let mut head = line;
+                    while let Shape::Prefix(prefix) = head.shape() {
+                        head = &prefix.func;
+                    }
+                    if let Shape::Cons(_) = head.shape() {
+                        result.push(line);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Which form of a macro usage a traversal should descend into.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum MacroTraversal {
+    /// Visit the surface segments — what the user typed. This is what
+    /// pretty-printing and anything span-related must use.
+    Surface,
+    /// Visit the resolved form when available, falling back to the surface
+    /// segments. For analyses interested in the macro's meaning.
+    PreferResolved,
+}
+
+/// A macro usage seen through a chosen representation.
+#[derive(Clone,Copy,Debug)]
+pub enum MacroRepresentation<'a> {
+    /// The resolved (expanded) form.
+    Resolved(&'a Ast),
+    /// The surface segments, as the resolution is unavailable.
+    Surface(&'a Match<Ast>),
+}
+
+impl Match<Ast> {
+    /// The resolved form if the parser provided one, the surface form
+    /// otherwise.
+    pub fn resolved_or_surface(&self) -> MacroRepresentation<'_> {
+        match &self.resolved {
+            Some(resolved) => MacroRepresentation::Resolved(resolved),
+            None           => MacroRepresentation::Surface(self),
+        }
+    }
+
+    /// All surface nodes of the usage, in textual order: the prefix part (if
+    /// any), then each segment's head and body.
+    pub fn segments_asts(&self) -> Vec<&Ast> {
+        let mut result = Vec::new();
+        result.extend(self.pfx.as_ref());
+        for seg in &self.segs {
+            result.push(&seg.wrapped.head);
+            result.extend(seg.wrapped.body.iter().map(|sast| &sast.wrapped));
+        }
+        result
+    }
+}
+
+impl Ast {
+    /// Like `iterate_subtree`, but macro usages are traversed according to
+    /// the given mode.
+    pub fn iterate_subtree_with(&self, mode:MacroTraversal) -> impl Iterator<Item=&Ast> + '_ {
+        let mut result = Vec::new();
+        collect_subtree(self, mode, &mut result);
+        result.into_iter()
+    }
+}
+
+fn collect_subtree<'a>(ast:&'a Ast, mode:MacroTraversal, result:&mut Vec<&'a Ast>) {
+    result.push(ast);
+    let resolved = match (mode,ast.shape()) {
+        (MacroTraversal::PreferResolved, Shape::Match(t)) => t.resolved.as_ref(),
+        _                                                 => None,
+    };
+    match resolved {
+        Some(resolved) => collect_subtree(resolved, mode, result),
+        None => for child in ast.children() {
+            collect_subtree(child, mode, result)
+        }
+    }
+}
+
+/// A single segment of a mixfix usage: a name part paired with its argument.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct MixfixSegment {
+    /// The name part, e.g. the `if` of `if a then b`.
+    pub name : Ast,
+    /// The argument following the name part, if any.
+    pub arg : Option<Shifted<Ast>>,
+}
+
+impl Mixfix<Ast> {
+    /// The usage as a list of name/argument segments.
+    pub fn segments(&self) -> Vec<MixfixSegment> {
+        let mut args = self.args.iter();
+        self.name.iter().map(|name| MixfixSegment {
+            name : name.clone(),
+            arg  : args.next().cloned(),
+        }).collect()
+    }
+
+    /// The equivalent macro `Match` form. Both forms print identically.
+    pub fn into_match(&self) -> Match<Ast> {
+        let segs = self.segments().into_iter().enumerate().map(|(index,segment)| Shifted {
+            off     : if index == 0 { 0 } else { 1 },
+            wrapped : MacroMatchSegment {
+                head : segment.name,
+                body : segment.arg.into_iter().collect(),
+            },
+        }).collect();
+        Match {pfx:None, segs, resolved:None}
+    }
+
+    /// Interprets a macro `Match` as a mixfix usage. Returns `None` for
+    /// matches that do not fit the mixfix scheme (e.g. ones with a prefix
+    /// part).
+    pub fn from_match(ast_match:&Match<Ast>) -> Option<Mixfix<Ast>> {
+        if ast_match.pfx.is_some() {
+            return None;
+        }
+        let mut name = Vec::new();
+        let mut args = Vec::new();
+        for seg in &ast_match.segs {
+            if seg.wrapped.body.len() > 1 {
+                return None;
+            }
+            name.push(seg.wrapped.head.clone());
+            args.extend(seg.wrapped.body.iter().cloned());
+        }
+        Some(Mixfix {name,args})
+    }
+}
+
+/// Invokes a callback macro with the complete list of `Shape` variant names.
+///
+/// The list is the single source of truth for per-variant dispatchers: the
+/// generated matches carry no wildcard arm, so a variant added to the enum
+/// but not here fails to compile (non-exhaustive match), and a variant
+/// listed here but removed from the enum fails too. This is what keeps
+/// per-variant code — names, and by the same discipline the token feed that
+/// spans and pretty-printing are computed from — from silently missing a
+/// case at runtime.
+macro_rules! with_shape_variants {
+    ($callback:ident) => {
+        $callback! {
+            Unrecognized Blank Var Cons Opr Mod Number DanglingBase InvalidSuffix
+            TextLineRaw TextLineFmt TextBlockRaw TextBlockFmt TextUnclosed
+            Prefix Infix SectionLeft SectionRight SectionSides
+            Group Block Module
+            Comment Import Match Mixfix
+            Def
+        }
+    };
+}
+
+macro_rules! generate_shape_name {
+    ($($variant:ident)*) => {
+        impl<T> Shape<T> {
+            /// Name of the shape's kind, e.g. `"Var"`.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Shape::$variant {..} => stringify!($variant),)*
+                }
+            }
+        }
+    };
+}
+with_shape_variants!(generate_shape_name);
+
+impl<T> Shape<T> {
+    /// Whether the shape represents a parse problem.
+    pub fn is_error(&self) -> bool {
+        matches!(self,
+            Shape::Unrecognized {..} | Shape::InvalidSuffix {..} | Shape::TextUnclosed {..}
+            | Shape::DanglingBase {..})
+    }
+}
+
+impl Shape<Ast> {
+    /// This shape with every child node replaced by the result of `f`.
+    ///
+    /// Children are visited in textual order — the same order in which
+    /// `feed_to` emits them.
+    pub fn map_children(&self, mut f:impl FnMut(&Ast)->Ast) -> Shape<Ast> {
+        let f = &mut f;
+        match self {
+            Shape::Unrecognized(_) | Shape::Blank(_) | Shape::Var(_) | Shape::Cons(_)
+            | Shape::Opr(_) | Shape::Mod(_) | Shape::Number(_) | Shape::DanglingBase(_)
+            | Shape::TextLineRaw(_) | Shape::TextBlockRaw(_) | Shape::Comment(_) => self.clone(),
+            Shape::InvalidSuffix(t) => InvalidSuffix {
+                elem   : f(&t.elem),
+                suffix : t.suffix.clone(),
+            }.into(),
+            Shape::TextLineFmt(t) => TextLineFmt {
+                text : map_segments_fmt(&t.text,f),
+            }.into(),
+            Shape::TextBlockFmt(t) => TextBlockFmt {
+                text : t.text.iter().map(|line| TextBlockLine {
+                    empty_lines : line.empty_lines.clone(),
+                    text        : map_segments_fmt(&line.text,f),
+                }).collect(),
+                spaces : t.spaces,
+                offset : t.offset,
+            }.into(),
+            Shape::TextUnclosed(t) => TextUnclosed {
+                line : match &t.line {
+                    TextLine::TextLineRaw(line) => TextLine::TextLineRaw(line.clone()),
+                    TextLine::TextLineFmt(line) => TextLine::TextLineFmt(TextLineFmt {
+                        text : map_segments_fmt(&line.text,f),
+                    }),
+                },
+            }.into(),
+            Shape::Prefix(t) => Prefix {
+                func : f(&t.func),
+                off  : t.off,
+                arg  : f(&t.arg),
+            }.into(),
+            Shape::Infix(t) => Infix {
+                larg : f(&t.larg),
+                loff : t.loff,
+                opr  : f(&t.opr),
+                roff : t.roff,
+                rarg : f(&t.rarg),
+            }.into(),
+            Shape::SectionLeft(t) => SectionLeft {
+                arg : f(&t.arg),
+                off : t.off,
+                opr : f(&t.opr),
+            }.into(),
+            Shape::SectionRight(t) => SectionRight {
+                opr : f(&t.opr),
+                off : t.off,
+                arg : f(&t.arg),
+            }.into(),
+            Shape::SectionSides(t) => SectionSides {
+                opr : f(&t.opr),
+            }.into(),
+            Shape::Group(t) => Group {
+                loff : t.loff,
+                body : t.body.as_ref().map(&mut *f),
+                roff : t.roff,
+            }.into(),
+            Shape::Block(t) => Block {
+                ty          : t.ty,
+                indent      : t.indent,
+                empty_lines : t.empty_lines.clone(),
+                first_line  : BlockLine {elem:f(&t.first_line.elem), off:t.first_line.off},
+                lines       : t.lines.iter().map(|line| BlockLine {
+                    elem : line.elem.as_ref().map(&mut *f),
+                    off  : line.off,
+                }).collect(),
+                is_orphan   : t.is_orphan,
+            }.into(),
+            Shape::Module(t) => Module {
+                lines : t.lines.iter().map(|line| BlockLine {
+                    elem : line.elem.as_ref().map(&mut *f),
+                    off  : line.off,
+                }).collect(),
+            }.into(),
+            Shape::Match(t) => Match {
+                pfx      : t.pfx.as_ref().map(&mut *f),
+                segs     : t.segs.iter().map(|seg| Shifted {
+                    off     : seg.off,
+                    wrapped : MacroMatchSegment {
+                        head : f(&seg.wrapped.head),
+                        body : seg.wrapped.body.iter().map(|sast| Shifted {
+                            off     : sast.off,
+                            wrapped : f(&sast.wrapped),
+                        }).collect(),
+                    },
+                }).collect(),
+                // The resolved form does not contribute tokens, so it is not
+                // a child in the textual order and is left untouched.
+                resolved : t.resolved.clone(),
+            }.into(),
+            Shape::Import(t) => Import {
+                path : t.path.iter().map(|segment| Shifted {
+                    off     : segment.off,
+                    wrapped : f(&segment.wrapped),
+                }).collect(),
+            }.into(),
+            Shape::Mixfix(t) => Mixfix {
+                name : t.name.iter().map(&mut *f).collect(),
+                args : t.args.iter().map(|arg| Shifted {
+                    off     : arg.off,
+                    wrapped : f(&arg.wrapped),
+                }).collect(),
+            }.into(),
+            Shape::Def(t) => Def {
+                name : f(&t.name),
+                args : t.args.iter().map(|arg| Shifted {
+                    off     : arg.off,
+                    wrapped : f(&arg.wrapped),
+                }).collect(),
+                body : t.body.as_ref().map(|body| Shifted {
+                    off     : body.off,
+                    wrapped : f(&body.wrapped),
+                }),
+            }.into(),
+        }
+    }
+}
+
+/// Maps the expression children of formatted text segments.
+fn map_segments_fmt
+(segments:&[SegmentFmt<Ast>], f:&mut impl FnMut(&Ast)->Ast) -> Vec<SegmentFmt<Ast>> {
+    segments.iter().map(|segment| match segment {
+        SegmentFmt::SegmentExpr(expr) => SegmentFmt::SegmentExpr(SegmentExpr {
+            value : expr.value.as_ref().map(&mut *f),
+        }),
+        other => other.clone(),
+    }).collect()
+}
+
+/// Feeds the tokens of a text block literal.
+fn feed_text_block<'a,T:HasTokens>
+( consumer : &mut impl TokenConsumer<'a>
+, quote    : &'static str
+, lines    : &'a [TextBlockLine<T>]
+, spaces   : usize
+, offset   : usize) {
+    consumer.feed(Token::Str(quote));
+    consumer.feed(Token::Off(spaces));
+    for line in lines {
+        for empty_line_space in &line.empty_lines {
+            consumer.feed(Token::Chr('\n'));
+            consumer.feed(Token::Off(*empty_line_space));
+        }
+        consumer.feed(Token::Chr('\n'));
+        consumer.feed(Token::Off(offset));
+        line.text.feed_to(consumer);
+    }
+}
+
+
+
+// ===================
+// === Item counts ===
+// ===================
+
+impl<T> HasLength for Module<T> {
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+impl<T> HasLength for Block<T> {
+    fn len(&self) -> usize {
+        1 + self.lines.len()
+    }
+}
+
+impl<T> HasLength for Import<T> {
+    fn len(&self) -> usize {
+        self.path.len()
+    }
+}
+
+impl<T> HasLength for Mixfix<T> {
+    fn len(&self) -> usize {
+        self.args.len()
+    }
+}
+
+impl<T> HasLength for Match<T> {
+    fn len(&self) -> usize {
+        self.segs.len()
+    }
+}
+
+impl HasLength for Comment {
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+impl HasLength for TextBlockRaw {
+    fn len(&self) -> usize {
+        self.text.len()
+    }
+}
+
+impl<T> HasLength for TextBlockFmt<T> {
+    fn len(&self) -> usize {
+        self.text.len()
+    }
+}
+
+
+
+// ================
+// === Internal ===
+// ================
+
+mod internal {
+    use super::*;
+
+    /// A consumer that collects the child nodes of a single node.
+    #[derive(Default)]
+    struct ChildrenConsumer<'a> {
+        children : Vec<&'a Ast>,
+    }
+
+    impl<'a> TokenConsumer<'a> for ChildrenConsumer<'a> {
+        fn feed(&mut self, token:Token<'a>) {
+            if let Token::Ast(ast) = token {
+                self.children.push(ast)
+            }
+        }
+    }
+
+    /// The direct child nodes of given node, in textual order.
+    pub fn children(ast:&Ast) -> Vec<&Ast> {
+        let mut consumer = ChildrenConsumer::default();
+        ast.shape().feed_to(&mut consumer);
+        consumer.children
+    }
+
+    /// A consumer that records the absolute offset of each child node.
+    #[derive(Default)]
+    struct ChildOffsetConsumer {
+        offset  : usize,
+        offsets : Vec<usize>,
+    }
+
+    impl<'a> TokenConsumer<'a> for ChildOffsetConsumer {
+        fn feed(&mut self, token:Token<'a>) {
+            if let Token::Ast(_) = token {
+                self.offsets.push(self.offset);
+            }
+            self.offset += match token {
+                Token::Off(off) => off,
+                Token::Chr(_)   => 1,
+                Token::Str(str) => str.chars().count(),
+                Token::Ast(ast) => ast.span(),
+            };
+        }
+    }
+
+    /// The offsets (in characters, relative to the node's start) of the
+    /// node's children, aligned with the result of `children`.
+    pub fn child_offsets(ast:&Ast) -> Vec<usize> {
+        let mut consumer = ChildOffsetConsumer::default();
+        ast.shape().feed_to(&mut consumer);
+        consumer.offsets
+    }
+
+    /// Iterates the whole subtree, depth-first, parents before children.
+    pub fn iterate_subtree(ast:&Ast) -> impl Iterator<Item=&Ast> + '_ {
+        SubtreeIterator {stack:vec![ast]}
+    }
+
+    /// An explicit-stack depth-first iterator over a subtree.
+    struct SubtreeIterator<'a> {
+        stack : Vec<&'a Ast>,
+    }
+
+    impl<'a> Iterator for SubtreeIterator<'a> {
+        type Item = &'a Ast;
+        fn next(&mut self) -> Option<Self::Item> {
+            let node = self.stack.pop()?;
+            let mut children = children(node);
+            children.reverse();
+            self.stack.extend(children);
+            Some(node)
+        }
+    }
+}
+
+pub use internal::children;
+pub use internal::child_offsets;
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn var_repr_and_span() {
+        let node = Ast::var("foo");
+        assert_eq!(node.repr(), "foo");
+        assert_eq!(node.span(), 3);
+    }
+
+    #[test]
+    fn infix_repr() {
+        let node = Ast::infix(Ast::var("a"), "+", Ast::var("b"));
+        assert_eq!(node.repr(), "a + b");
+        assert_eq!(node.span(), 5);
+    }
+
+    #[test]
+    fn subtree_iteration_order() {
+        let node = Ast::infix(Ast::var("a"), "+", Ast::var("b"));
+        let reprs = node.iterate_subtree().map(|ast| ast.repr()).collect_vec();
+        assert_eq!(reprs, vec!["a + b","a","+","b"]);
+    }
+
+    #[test]
+    fn section_constructors() {
+        assert_eq!(Ast::section_left(Ast::number("2"), "+").repr(),  "2+");
+        assert_eq!(Ast::section_right("+", Ast::number("2")).repr(), "+2");
+        assert_eq!(Ast::section_sides("+").repr(),                   "+");
+    }
+
+    #[test]
+    fn applying_arguments_pads_with_blanks() {
+        let expr   = Ast::prefix(Ast::var("foo"), Ast::var("a"));
+        let result = apply_argument(&expr, 2, Ast::var("b"));
+        assert_eq!(result.repr(), "foo a _ b");
+
+        let result = apply_argument(&expr, 0, Ast::var("b"));
+        assert_eq!(result.repr(), "foo b a");
+    }
+
+    #[test]
+    fn layers_peel_off_generically() {
+        // A generic caller can unwrap a stack without naming its order.
+        fn peel<L:Layer<T>, T>(layer:L) -> T {
+            layer.unlayer()
+        }
+        let measured = WithLength::layered("foo".to_string());
+        let tagged   = WithID::layered(measured);
+        assert_eq!(tagged.as_inner().len, 3);
+        let measured = peel(tagged);
+        assert_eq!(measured.as_inner(), "foo");
+        assert_eq!(peel(measured), "foo");
+    }
+
+    #[test]
+    fn modifier_repr_includes_the_equals_sign() {
+        let node = Ast::from_shape(Mod {name:"+".to_string()});
+        assert_eq!(node.repr(), "+=");
+        assert_eq!(node.span(), 2);
+    }
+
+    #[test]
+    fn unclosed_text_has_no_closing_quote() {
+        let line = TextLineRaw {
+            text : vec![SegmentRaw::SegmentPlain(SegmentPlain {value:"abc".to_string()})],
+        };
+        let closed   = Ast::from_shape(line.clone());
+        let unclosed = Ast::from_shape(TextUnclosed {line:TextLine::TextLineRaw(line)});
+        assert_eq!(closed.repr(),   "\"abc\"");
+        assert_eq!(unclosed.repr(), "\"abc");
+        assert_eq!(unclosed.span(), closed.span() - 1);
+    }
+
+    #[test]
+    fn group_repr_and_span() {
+        let group = Ast::grouped(Ast::infix(Ast::var("a"), "+", Ast::var("b")));
+        assert_eq!(group.repr(), "(a + b)");
+        assert_eq!(group.span(), 7);
+        let empty = Ast::from_shape(Group {loff:0, body:None, roff:0});
+        assert_eq!(empty.repr(), "()");
+    }
+
+    #[test]
+    fn def_roundtrip_with_body() {
+        let body = Ast::from_shape(Block {
+            ty          : BlockType::Discontinuous {},
+            indent      : 4,
+            empty_lines : vec![],
+            first_line  : BlockLine {elem:Ast::cons("Nothing"), off:0},
+            lines       : vec![BlockLine {
+                elem : Some(Ast::prefix(Ast::cons("Just"), Ast::var("a"))),
+                off  : 0,
+            }],
+            is_orphan   : false,
+        });
+        let def = Def::new(Ast::cons("Maybe"), vec![Ast::var("a")], Some(body));
+        assert_eq!(def.constructors().len(), 2);
+        let node = Ast::from_shape(def);
+        assert_eq!(node.repr(), "def Maybe a\n    Nothing\n    Just a");
+        assert_eq!(node.span(), node.repr().chars().count());
+    }
+
+    #[test]
+    fn mixfix_repr_and_match_conversion() {
+        let mixfix = Mixfix {
+            name : vec![Ast::var("if"), Ast::var("then"), Ast::var("else")],
+            args : vec![
+                Shifted {off:1, wrapped:Ast::var("a")},
+                Shifted {off:1, wrapped:Ast::var("b")},
+                Shifted {off:1, wrapped:Ast::var("c")},
+            ],
+        };
+        assert_eq!(Ast::from_shape(mixfix.clone()).repr(), "if a then b else c");
+        assert_eq!(mixfix.segments().len(), 3);
+
+        let as_match = mixfix.into_match();
+        assert_eq!(Ast::from_shape(as_match.clone()).repr(), "if a then b else c");
+        let back = Mixfix::from_match(&as_match).unwrap();
+        assert_eq!(back, mixfix);
+    }
+
+    #[test]
+    fn macro_traversal_modes() {
+        let surface = MacroMatchSegment {
+            head : Ast::var("if"),
+            body : vec![Shifted {off:1, wrapped:Ast::var("cond")}],
+        };
+        let usage = Match {
+            pfx      : None,
+            segs     : vec![Shifted {off:0, wrapped:surface}],
+            resolved : Some(Ast::prefix(Ast::var("ifThenElse"), Ast::var("cond"))),
+        };
+        assert!(matches!(usage.resolved_or_surface(), MacroRepresentation::Resolved(_)));
+        assert_eq!(usage.segments_asts().len(), 2);
+
+        let ast = Ast::from_shape(usage);
+        // Pretty printing always uses the surface form.
+        assert_eq!(ast.repr(), "if cond");
+        let surface_names  = ast.iterate_subtree().count();
+        let resolved_names = ast.iterate_subtree_with(MacroTraversal::PreferResolved)
+            .filter(|node| matches!(node.shape(), Shape::Var(_)))
+            .count();
+        assert_eq!(surface_names, 3);
+        assert_eq!(resolved_names, 2);
+    }
+
+    #[test]
+    fn comment_keeps_user_spacing() {
+        let comment = Ast::comment(vec![" first".to_string(), "  second".to_string()]);
+        assert_eq!(comment.repr(), "# first\n#  second");
+        assert_eq!(comment.span(), comment.repr().chars().count());
+    }
+
+    #[test]
+    fn import_repr_and_span() {
+        let import = Ast::import(&["Base","List"]);
+        assert_eq!(import.repr(), "import Base.List");
+        assert_eq!(import.span(), 16);
+    }
+
+    #[test]
+    fn number_repr() {
+        let number = Number {base:Some("16".to_string()), int:"ff".to_string()};
+        assert_eq!(Ast::from_shape(number).repr(), "16_ff");
+    }
+
+    #[test]
+    fn empty_module_is_sane_everywhere() {
+        // What a fresh, empty source file parses to.
+        let module = Ast::module(vec![]);
+        assert_eq!(module.span(), 0);
+        assert_eq!(module.repr(), "");
+        assert!(module.children().is_empty());
+        assert_eq!(module.iterate_subtree().count(), 1);
+        assert_eq!(crate::stats::stats(&module).node_count, 1);
+
+        // A module with one empty line still renders its newline-free form.
+        let module = Ast::module(vec![None]);
+        assert_eq!(module.repr(), "");
+        assert_eq!(module.span(), 0);
+    }
+
+    #[test]
+    fn downcasts_and_predicates() {
+        use std::convert::TryFrom;
+        let ast = Ast::var("foo");
+        assert!(ast.is_var());
+        assert!(!ast.is_cons());
+        let var = <&Var>::try_from(&ast).unwrap();
+        assert_eq!(var.name, "foo");
+        let var = Var::try_from(ast.clone()).unwrap();
+        assert_eq!(var.name, "foo");
+        assert_eq!(<&Prefix<Ast>>::try_from(&ast), Err(UnexpectedShape));
+    }
+
+    #[test]
+    fn pathological_whitespace_survives_editing() {
+        // `foo   +bar  ` — no space before the operator, three after, plus
+        // trailing spaces kept by the enclosing block line.
+        let infix = Ast::from_shape(Infix {
+            larg : Ast::var("foo"),
+            loff : 3,
+            opr  : Ast::opr("+"),
+            roff : 0,
+            rarg : Ast::var("bar"),
+        });
+        let module = Ast::from_shape(Module {
+            lines : vec![BlockLine {elem:Some(infix), off:2}],
+        });
+        let original = module.repr();
+        assert_eq!(original, "foo   +bar  ");
+
+        // Replacing a leaf must not disturb any of the offsets around it.
+        let target  = module.get_node(&[0,2]).unwrap();
+        assert_eq!(target.repr(), "bar");
+        let edited  = module.set_node(&[0,2], Ast::var("quux")).unwrap();
+        assert_eq!(edited.repr(), "foo   +quux  ");
+
+        // A chain view roundtrip is offset-exact as well.
+        let infix   = module.get_node(&[0]).unwrap();
+        let chain   = crate::opr::Chain::from_ast(infix, "+").unwrap();
+        assert_eq!(chain.fold().repr(), infix.repr());
+    }
+
+    #[test]
+    fn subtree_extraction_and_splicing() {
+        let id     = Id::from_u128(1);
+        let infix  = Ast::infix(Ast::var("foo"), "+", Ast::var("bar").with_id(id));
+        let module = Ast::module(vec![Some(infix)]);
+        assert_eq!(module.repr(), "foo + bar");
+
+        // Extraction keeps the id and detaches the subtree.
+        let subtree = module.extract_subtree(&[0,2]).unwrap();
+        assert_eq!(subtree.repr(), "bar");
+        assert_eq!(subtree.id(), Some(id));
+        assert!(module.extract_subtree(&[0,7]).is_none());
+
+        // Splicing a subtree of a different length recomputes the spans of
+        // everything on the path.
+        let spliced = module.splice(&[0,2], Ast::var("quux")).unwrap();
+        assert_eq!(spliced.repr(), "foo + quux");
+        assert_eq!(spliced.span(), "foo + quux".len());
+        assert_eq!(spliced.get_node(&[0]).unwrap().span(), "foo + quux".len());
+
+        // And the round trip is the identity.
+        let restored = spliced.splice(&[0,2], subtree).unwrap();
+        assert_eq!(restored.repr(), module.repr());
+        assert_eq!(restored.extract_subtree(&[0,2]).unwrap().id(), Some(id));
+    }
+
+    #[test]
+    fn item_counts() {
+        let module = Module::<Ast> {lines:vec![]};
+        assert_eq!(module.len(), 0);
+        assert!(module.is_empty());
+
+        let import = match Ast::import(&["Base","List"]).shape() {
+            Shape::Import(import) => import.clone(),
+            other                 => panic!("expected an import, got {:?}", other),
+        };
+        assert_eq!(import.len(), 2);
+        assert!(!import.is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+//! Strongly typed text locations: `Index`, `Size` and `Span`.
+//!
+//! Raw `usize` offsets and lengths are interchangeable by accident, which
+//! keeps producing bugs where an offset is added where a length was
+//! expected. These newtypes make the distinction explicit and define only
+//! the arithmetic that makes sense: an index plus a size is an index, the
+//! difference of two indexes is a size, and so on.
+
+#[cfg(feature="serialization")]
+use serde::Deserialize;
+#[cfg(feature="serialization")]
+use serde::Serialize;
+
+use std::ops::Add;
+use std::ops::AddAssign;
+use std::ops::Sub;
+
+
+
+// =============
+// === Index ===
+// =============
+
+/// A position in a text, in characters from its beginning.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct Index {
+    /// The value.
+    pub value : usize,
+}
+
+impl Index {
+    /// Creates an index from a raw value.
+    pub fn new(value:usize) -> Index {
+        Index {value}
+    }
+}
+
+impl From<usize> for Index {
+    fn from(value:usize) -> Index {
+        Index {value}
+    }
+}
+
+impl Add<Size> for Index {
+    type Output = Index;
+    fn add(self, size:Size) -> Index {
+        Index {value : self.value + size.value}
+    }
+}
+
+impl AddAssign<Size> for Index {
+    fn add_assign(&mut self, size:Size) {
+        self.value += size.value;
+    }
+}
+
+impl Sub<Index> for Index {
+    type Output = Size;
+    fn sub(self, other:Index) -> Size {
+        Size {value : self.value - other.value}
+    }
+}
+
+impl Sub<Size> for Index {
+    type Output = Index;
+    fn sub(self, size:Size) -> Index {
+        Index {value : self.value - size.value}
+    }
+}
+
+
+
+// ============
+// === Size ===
+// ============
+
+/// A length of a text fragment, in characters.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,PartialOrd,Ord,Hash)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct Size {
+    /// The value.
+    pub value : usize,
+}
+
+impl Size {
+    /// Creates a size from a raw value.
+    pub fn new(value:usize) -> Size {
+        Size {value}
+    }
+
+    /// Checks if the size is zero.
+    pub fn is_empty(self) -> bool {
+        self.value == 0
+    }
+}
+
+impl From<usize> for Size {
+    fn from(value:usize) -> Size {
+        Size {value}
+    }
+}
+
+impl Add for Size {
+    type Output = Size;
+    fn add(self, other:Size) -> Size {
+        Size {value : self.value + other.value}
+    }
+}
+
+impl AddAssign for Size {
+    fn add_assign(&mut self, other:Size) {
+        self.value += other.value;
+    }
+}
+
+impl Sub for Size {
+    type Output = Size;
+    fn sub(self, other:Size) -> Size {
+        Size {value : self.value - other.value}
+    }
+}
+
+
+
+// ============
+// === Span ===
+// ============
+
+/// A fragment of a text: where it starts and how long it is.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq,Hash)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct Span {
+    /// Position of the first character.
+    pub index : Index,
+    /// Number of characters.
+    pub size : Size,
+}
+
+impl Ord for Span {
+    /// Spans are ordered the way nodes appear in a pre-order traversal of a
+    /// tree: by position, and for equal positions the longer span — the
+    /// enclosing node — first. Sorting any node list by this key therefore
+    /// yields the deterministic parents-before-children textual order.
+    fn cmp(&self, other:&Span) -> std::cmp::Ordering {
+        self.index.cmp(&other.index).then(other.size.cmp(&self.size))
+    }
+}
+
+impl PartialOrd for Span {
+    fn partial_cmp(&self, other:&Span) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Span {
+    /// Creates a span from its position and length.
+    pub fn new(index:Index, size:Size) -> Span {
+        Span {index,size}
+    }
+
+    /// A span of given length starting at the text's beginning.
+    pub fn from_beginning(size:Size) -> Span {
+        Span {index:Index::new(0), size}
+    }
+
+    /// Position right after the last character.
+    pub fn end(self) -> Index {
+        self.index + self.size
+    }
+
+    /// Checks if the position falls within this span.
+    pub fn contains(self, index:Index) -> bool {
+        self.index <= index && index < self.end()
+    }
+
+    /// Checks if the other span is fully contained in this one.
+    pub fn contains_span(self, other:Span) -> bool {
+        self.index <= other.index && other.end() <= self.end()
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_and_size_arithmetic() {
+        let index = Index::new(5);
+        let size  = Size::new(3);
+        assert_eq!(index + size, Index::new(8));
+        assert_eq!(Index::new(8) - index, size);
+        assert_eq!(size + Size::new(2), Size::new(5));
+    }
+
+    #[test]
+    fn span_ordering_is_position_then_reverse_length() {
+        let outer = Span::new(Index::new(0), Size::new(5));
+        let inner = Span::new(Index::new(0), Size::new(2));
+        let later = Span::new(Index::new(1), Size::new(9));
+        let mut spans = vec![later, inner, outer];
+        spans.sort();
+        assert_eq!(spans, vec![outer, inner, later]);
+    }
+
+    #[test]
+    fn span_queries() {
+        let span = Span::new(Index::new(4), Size::new(2));
+        assert_eq!(span.end(), Index::new(6));
+        assert!(span.contains(Index::new(5)));
+        assert!(!span.contains(Index::new(6)));
+        assert!(Span::new(Index::new(4), Size::new(3)).contains_span(span));
+        assert!(!span.contains_span(Span::new(Index::new(5), Size::new(2))));
+    }
+}
@@ -0,0 +1,299 @@
+//! Macro patterns and their evaluation.
+//!
+//! Macro definitions describe what may appear between their segments with
+//! patterns: sequences, alternatives, repetitions, exclusions and token
+//! classes, the latter optionally constrained by spacing or (for operators)
+//! by maximum precedence. This module defines those patterns and evaluates
+//! them against token streams, so the Rust side can check which alternatives
+//! of a definition actually match — the basis for native macro resolution
+//! experiments and for explaining `Ambiguous` matches instead of merely
+//! reporting them.
+
+pub mod registry;
+
+use crate::Ast;
+use crate::Shape;
+use crate::Shifted;
+
+#[cfg(feature="serialization")]
+use serde::Deserialize;
+#[cfg(feature="serialization")]
+use serde::Serialize;
+
+use std::collections::HashSet;
+
+
+
+// ===============
+// === Pattern ===
+// ===============
+
+/// A pattern over a stream of spaced AST items.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum Pattern {
+    /// Matches zero tokens; always succeeds.
+    Nothing,
+    /// Matches the sub-patterns one after another.
+    Seq(Vec<Pattern>),
+    /// Matches any of the alternatives.
+    Or(Vec<Pattern>),
+    /// Matches zero or more repetitions of the sub-pattern.
+    Many(Box<Pattern>),
+    /// Matches `ok`, but only where `not` does not match.
+    Except {
+        /// The forbidden pattern.
+        not : Box<Pattern>,
+        /// The pattern to match.
+        ok : Box<Pattern>,
+    },
+    /// Matches a single token of the given class.
+    Tok(Tok),
+}
+
+impl Pattern {
+    /// A single-token pattern with no spacing constraint.
+    pub fn tok(class:TokenClass) -> Pattern {
+        Pattern::Tok(Tok {class, spaced:None})
+    }
+}
+
+/// A single-token pattern: the token's class plus an optional spacing
+/// constraint (whether the token must, or must not, be preceded by spaces).
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct Tok {
+    /// The class of tokens that match.
+    pub class : TokenClass,
+    /// `Some(true)` — spaces required before the token; `Some(false)` —
+    /// forbidden; `None` — no constraint.
+    pub spaced : Option<bool>,
+}
+
+/// A class of tokens matched by a `Tok` pattern.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub enum TokenClass {
+    /// Any single item.
+    Any,
+    /// A variable name.
+    Var,
+    /// A constructor name.
+    Cons,
+    /// A number literal.
+    Number,
+    /// A text literal.
+    Text,
+    /// A block.
+    Block,
+    /// An operator, optionally restricted to precedence not above the given
+    /// level.
+    Opr {
+        /// Maximum allowed precedence, if constrained.
+        max_prec : Option<usize>,
+    },
+}
+
+
+
+// ==================
+// === Precedence ===
+// ==================
+
+/// The precedence level of an operator, from its name.
+///
+/// The level is decided by the operator's first character, mirroring the
+/// engine's table: assignment binds loosest, then arrows, logic, comparison,
+/// arithmetic, and access binds tightest.
+pub fn precedence_of(opr:&str) -> usize {
+    match opr.chars().next() {
+        Some('=')             => 1,
+        Some('-') if opr == "->" => 2,
+        Some('|')             => 3,
+        Some('&')             => 4,
+        Some('<') | Some('>') => 5,
+        Some('+') | Some('-') => 6,
+        Some('*') | Some('/') | Some('%') => 7,
+        Some('^')             => 8,
+        Some('.')             => 10,
+        _                     => 5,
+    }
+}
+
+
+
+// ==================
+// === Evaluation ===
+// ==================
+
+/// All the numbers of items the pattern can consume from the front of the
+/// stream. An empty set means the pattern cannot match here at all.
+///
+/// Working with the full set (rather than the first match) keeps `Many` and
+/// `Or` exact and lets the callers detect ambiguity.
+pub fn match_lengths(pattern:&Pattern, stream:&[Shifted<Ast>]) -> HashSet<usize> {
+    let mut lengths = HashSet::new();
+    match pattern {
+        Pattern::Nothing => {
+            lengths.insert(0);
+        }
+        Pattern::Seq(parts) => {
+            lengths.insert(0);
+            for part in parts {
+                let mut next = HashSet::new();
+                for consumed in &lengths {
+                    for more in match_lengths(part, &stream[*consumed..]) {
+                        next.insert(consumed + more);
+                    }
+                }
+                lengths = next;
+            }
+        }
+        Pattern::Or(alternatives) => {
+            for alternative in alternatives {
+                lengths.extend(match_lengths(alternative, stream));
+            }
+        }
+        Pattern::Many(part) => {
+            lengths.insert(0);
+            loop {
+                let mut grown = false;
+                for consumed in lengths.clone() {
+                    for more in match_lengths(part, &stream[consumed..]) {
+                        // Zero-length repetitions would loop forever.
+                        if more > 0 && lengths.insert(consumed + more) {
+                            grown = true;
+                        }
+                    }
+                }
+                if !grown {
+                    break;
+                }
+            }
+        }
+        Pattern::Except {not,ok} => {
+            if match_lengths(not, stream).is_empty() {
+                lengths = match_lengths(ok, stream);
+            }
+        }
+        Pattern::Tok(tok) => {
+            if let Some(item) = stream.first() {
+                if token_matches(tok,item) {
+                    lengths.insert(1);
+                }
+            }
+        }
+    }
+    lengths
+}
+
+/// Checks whether the pattern matches the whole stream.
+pub fn matches(pattern:&Pattern, stream:&[Shifted<Ast>]) -> bool {
+    match_lengths(pattern, stream).contains(&stream.len())
+}
+
+/// The indexes of the `Or` alternatives that match the whole stream.
+///
+/// More than one index means the match is ambiguous; the list lets an error
+/// message name the clashing alternatives instead of just saying
+/// "ambiguous".
+pub fn matching_alternatives(alternatives:&[Pattern], stream:&[Shifted<Ast>]) -> Vec<usize> {
+    alternatives.iter().enumerate()
+        .filter(|(_,alternative)| matches(alternative, stream))
+        .map(|(index,_)| index)
+        .collect()
+}
+
+fn token_matches(tok:&Tok, item:&Shifted<Ast>) -> bool {
+    if let Some(spaced) = tok.spaced {
+        if (item.off > 0) != spaced {
+            return false;
+        }
+    }
+    match &tok.class {
+        TokenClass::Any    => true,
+        TokenClass::Var    => matches!(item.wrapped.shape(), Shape::Var(_)),
+        TokenClass::Cons   => matches!(item.wrapped.shape(), Shape::Cons(_)),
+        TokenClass::Number => matches!(item.wrapped.shape(), Shape::Number(_)),
+        TokenClass::Text   => matches!(item.wrapped.shape(),
+            Shape::TextLineRaw(_) | Shape::TextLineFmt(_) | Shape::TextBlockRaw(_)
+            | Shape::TextBlockFmt(_)),
+        TokenClass::Block  => matches!(item.wrapped.shape(), Shape::Block(_)),
+        TokenClass::Opr {max_prec} => match item.wrapped.shape() {
+            Shape::Opr(opr) => match max_prec {
+                Some(max_prec) => precedence_of(&opr.name) <= *max_prec,
+                None           => true,
+            },
+            _ => false,
+        },
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(ast:Ast, off:usize) -> Shifted<Ast> {
+        Shifted {off, wrapped:ast}
+    }
+
+    #[test]
+    fn sequences_and_repetition() {
+        // `a + b + c` as a stream of spaced items.
+        let stream = vec![
+            item(Ast::var("a"),0), item(Ast::opr("+"),1), item(Ast::var("b"),1),
+            item(Ast::opr("+"),1), item(Ast::var("c"),1),
+        ];
+        let operand = Pattern::tok(TokenClass::Var);
+        let chain   = Pattern::Seq(vec![
+            operand.clone(),
+            Pattern::Many(Box::new(Pattern::Seq(vec![
+                Pattern::tok(TokenClass::Opr {max_prec:None}),
+                operand,
+            ]))),
+        ]);
+        assert!(matches(&chain, &stream));
+        assert!(matches(&chain, &stream[0..1]));
+        assert!(!matches(&chain, &stream[0..2]));
+    }
+
+    #[test]
+    fn precedence_constraint_on_operators() {
+        let stream  = vec![item(Ast::opr("="),1)];
+        let lassoc  = Pattern::tok(TokenClass::Opr {max_prec:Some(1)});
+        let tight   = Pattern::tok(TokenClass::Opr {max_prec:Some(0)});
+        assert!(matches(&lassoc, &stream));
+        assert!(!matches(&tight, &stream));
+        assert!(precedence_of(".") > precedence_of("+"));
+        assert!(precedence_of("+") > precedence_of("="));
+    }
+
+    #[test]
+    fn spacing_constraint() {
+        let spaced    = vec![item(Ast::var("a"),1)];
+        let unspaced  = vec![item(Ast::var("a"),0)];
+        let pattern   = Pattern::Tok(Tok {class:TokenClass::Var, spaced:Some(true)});
+        assert!(matches(&pattern, &spaced));
+        assert!(!matches(&pattern, &unspaced));
+    }
+
+    #[test]
+    fn except_and_ambiguity_reporting() {
+        let stream  = vec![item(Ast::var("then"),1)];
+        let keyword = Pattern::tok(TokenClass::Var);
+        let body    = Pattern::Except {
+            not : Box::new(keyword.clone()),
+            ok  : Box::new(Pattern::tok(TokenClass::Any)),
+        };
+        assert!(!matches(&body, &stream));
+
+        let alternatives = vec![keyword, Pattern::tok(TokenClass::Any)];
+        assert_eq!(matching_alternatives(&alternatives, &stream), vec![0,1]);
+    }
+}
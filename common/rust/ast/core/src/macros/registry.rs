@@ -0,0 +1,150 @@
+//! The registry of known macro definitions.
+//!
+//! The engine is the source of truth for macro definitions; it exports them
+//! in a serialized form which this registry loads. With the definitions at
+//! hand, the IDE can look a macro up by its head token — for macro-aware
+//! completion, and as the entry point of native resolution built on
+//! `macros::match_lengths`.
+
+use prelude::*;
+
+use crate::macros::Pattern;
+
+#[cfg(feature="serialization")]
+use serde::Deserialize;
+#[cfg(feature="serialization")]
+use serde::Serialize;
+
+use std::collections::HashMap;
+
+
+
+// ==================
+// === Definition ===
+// ==================
+
+/// A definition of a single macro: its segments and precedence.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct Definition {
+    /// Human-readable name of the macro, e.g. `if_then_else`.
+    pub name : String,
+    /// The segments, in order. The first segment's head is the token the
+    /// macro is looked up by.
+    pub segments : Vec<SegmentDefinition>,
+    /// Precedence of the whole construct.
+    pub precedence : usize,
+}
+
+impl Definition {
+    /// The token that introduces this macro.
+    pub fn head(&self) -> &str {
+        self.segments.first().map_or("", |segment| segment.head.as_str())
+    }
+}
+
+/// A single segment of a macro definition: its introducing token and the
+/// pattern describing what may follow it.
+#[derive(Clone,Debug,PartialEq,Eq)]
+#[cfg_attr(feature="serialization", derive(Serialize,Deserialize))]
+pub struct SegmentDefinition {
+    /// The keyword or operator introducing the segment, e.g. `if`.
+    pub head : String,
+    /// The pattern of the segment's body.
+    pub body : Pattern,
+}
+
+
+
+// ================
+// === Registry ===
+// ================
+
+/// The known macro definitions, indexed by their head token.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct Registry {
+    by_head : HashMap<String,Vec<Definition>>,
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Registry {
+        default()
+    }
+
+    /// Adds a definition. Multiple definitions may share a head token —
+    /// resolution between them is the pattern matcher's job.
+    pub fn register(&mut self, definition:Definition) {
+        let head = definition.head().to_string();
+        self.by_head.entry(head).or_default().push(definition);
+    }
+
+    /// The definitions introduced by the given head token.
+    pub fn lookup(&self, head:&str) -> &[Definition] {
+        self.by_head.get(head).map_or(&[], |definitions| definitions.as_slice())
+    }
+
+    /// Number of registered definitions.
+    pub fn len(&self) -> usize {
+        self.by_head.values().map(Vec::len).sum()
+    }
+
+    /// Checks whether the registry holds no definitions.
+    pub fn is_empty(&self) -> bool {
+        self.by_head.is_empty()
+    }
+
+    /// Loads a registry from the serialized list of definitions, as exported
+    /// by the engine.
+    #[cfg(feature="serialization")]
+    pub fn from_json(text:&str) -> serde_json::Result<Registry> {
+        let definitions:Vec<Definition> = serde_json::from_str(text)?;
+        let mut registry = Registry::new();
+        for definition in definitions {
+            registry.register(definition);
+        }
+        Ok(registry)
+    }
+}
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macros::TokenClass;
+
+    fn if_then_else() -> Definition {
+        let expression = Pattern::tok(TokenClass::Any);
+        Definition {
+            name       : "if_then_else".to_string(),
+            segments   : vec![
+                SegmentDefinition {head:"if".to_string(),   body:expression.clone()},
+                SegmentDefinition {head:"then".to_string(), body:expression.clone()},
+                SegmentDefinition {head:"else".to_string(), body:expression},
+            ],
+            precedence : 1,
+        }
+    }
+
+    #[test]
+    fn lookup_by_head_token() {
+        let mut registry = Registry::new();
+        registry.register(if_then_else());
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.lookup("if").len(), 1);
+        assert_eq!(registry.lookup("if")[0].name, "if_then_else");
+        assert!(registry.lookup("then").is_empty());
+    }
+
+    #[cfg(feature="serialization")]
+    #[test]
+    fn loading_from_serialized_form() {
+        let exported = serde_json::to_string(&vec![if_then_else()]).unwrap();
+        let registry = Registry::from_json(&exported).unwrap();
+        assert_eq!(registry.lookup("if"), std::slice::from_ref(&if_then_else()));
+    }
+}
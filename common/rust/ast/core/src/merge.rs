@@ -0,0 +1,309 @@
+//! Three-way structural merge of ASTs.
+//!
+//! Collaborative editing and VCS integration both end up with the same
+//! problem: a common ancestor tree and two independently edited
+//! descendants. Merging the *text* loses everything the tree knows —
+//! node ids, and therefore all attached metadata — so the merge happens
+//! here, above the text level. Nodes are matched by id and module/block
+//! lines by the id of their root node, which makes "both sides edited
+//! different lines" and "one side edited a node the other moved" merge
+//! cleanly. Where both sides changed the same node in different ways the
+//! merge keeps the left version and reports a conflict, so the caller
+//! decides instead of the merge guessing.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::BlockLine;
+use crate::HasRepr;
+use crate::Id;
+use crate::Shape;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+
+
+// =================
+// === Conflicts ===
+// =================
+
+/// One place where the two sides changed the same thing differently.
+///
+/// The reprs are of the three versions of the disputed node; a side that
+/// deleted the node reports `None`. The merged tree contains the left
+/// version (or, for a left-side deletion, the right one), so resolving a
+/// conflict means locating the node by `id` and substituting.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Conflict {
+    /// The id of the disputed node, if it has one.
+    pub id : Option<Id>,
+    /// The ancestor version's text.
+    pub base : Option<String>,
+    /// The left version's text.
+    pub left : Option<String>,
+    /// The right version's text.
+    pub right : Option<String>,
+}
+
+/// The result of a merge: the combined tree and whatever could not be
+/// combined.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Merged {
+    /// The merged tree.
+    pub ast : Ast,
+    /// The places where both sides disagree. Empty means a clean merge.
+    pub conflicts : Vec<Conflict>,
+}
+
+
+
+// =============
+// === Merge ===
+// =============
+
+/// Merges two descendants of a common ancestor tree.
+pub fn merge(base:&Ast, left:&Ast, right:&Ast) -> Merged {
+    let mut conflicts = Vec::new();
+    let ast = merge_node(base, left, right, &mut conflicts);
+    Merged {ast,conflicts}
+}
+
+fn merge_node(base:&Ast, left:&Ast, right:&Ast, conflicts:&mut Vec<Conflict>) -> Ast {
+    // The trivial cases: at most one side changed anything here.
+    if left == right { return left.clone(); }
+    if left == base  { return right.clone(); }
+    if right == base { return left.clone(); }
+    // Both sides changed the subtree. Line sequences merge line-wise…
+    if let (Shape::Module(b), Shape::Module(l), Shape::Module(r))
+        = (base.shape(), left.shape(), right.shape()) {
+        let lines = merge_lines(&b.lines, &l.lines, &r.lines, conflicts);
+        return left.with_shape(Shape::Module(crate::Module {lines}));
+    }
+    // …and nodes of the same shape with the same children slots merge
+    // child-wise, so edits to different operands of one expression don't
+    // conflict. The non-child fields (offsets) come from the left side.
+    let same_skeleton = base.shape().name() == left.shape().name()
+        && left.shape().name() == right.shape().name()
+        && base.children().len() == left.children().len()
+        && left.children().len() == right.children().len();
+    if same_skeleton {
+        let base_children  = base.children();
+        let right_children = right.children();
+        let mut next       = 0;
+        let shape = left.shape().map_children(|child| {
+            let merged = merge_node(base_children[next], child, right_children[next], conflicts);
+            next += 1;
+            merged
+        });
+        return left.with_shape(shape);
+    }
+    // Irreconcilable: keep the left version and let the caller decide.
+    conflicts.push(Conflict {
+        id    : base.id(),
+        base  : Some(base.repr()),
+        left  : Some(left.repr()),
+        right : Some(right.repr()),
+    });
+    left.clone()
+}
+
+
+
+// ==================
+// === Line merge ===
+// ==================
+
+type Lines = Vec<BlockLine<Option<Ast>>>;
+
+/// How a line is matched across the three versions: by the id of its root
+/// node, or — for blank and id-less lines — by its text.
+#[derive(Clone,Debug,PartialEq,Eq,Hash)]
+enum LineKey {
+    Id(Id),
+    Text(String),
+}
+
+fn line_key(line:&BlockLine<Option<Ast>>) -> LineKey {
+    match line.elem.as_ref().and_then(|elem| elem.id()) {
+        Some(id) => LineKey::Id(id),
+        None     => LineKey::Text(line.elem.as_ref().map_or(default(), |elem| elem.repr())),
+    }
+}
+
+fn line_index(lines:&Lines) -> HashMap<LineKey,&BlockLine<Option<Ast>>> {
+    lines.iter().map(|line| (line_key(line), line)).collect()
+}
+
+/// The deleted-vs-modified report for a line present in `base` but absent
+/// from one side.
+fn deletion_conflict(base:&BlockLine<Option<Ast>>, kept:&BlockLine<Option<Ast>>, kept_left:bool)
+-> Conflict {
+    let repr = |line:&BlockLine<Option<Ast>>| line.elem.as_ref().map(|elem| elem.repr());
+    Conflict {
+        id    : base.elem.as_ref().and_then(|elem| elem.id()),
+        base  : repr(base),
+        left  : if kept_left  { repr(kept) } else { None },
+        right : if !kept_left { repr(kept) } else { None },
+    }
+}
+
+fn merge_lines(base:&Lines, left:&Lines, right:&Lines, conflicts:&mut Vec<Conflict>) -> Lines {
+    let base_index  = line_index(base);
+    let right_index = line_index(right);
+    let left_keys:HashSet<_> = left.iter().map(line_key).collect();
+
+    // The left sequence is the spine: lines both versions kept are merged
+    // in place, left additions stay where left put them, and deletions by
+    // the right side are honoured when left did not also edit the line.
+    let mut result = Vec::new();
+    let mut keys   = Vec::new();
+    for line in left {
+        let key = line_key(line);
+        match (base_index.get(&key), right_index.get(&key)) {
+            (Some(base_line), Some(right_line)) => {
+                let elem = match (&base_line.elem, &line.elem, &right_line.elem) {
+                    (Some(b),Some(l),Some(r)) => Some(merge_node(b,l,r,conflicts)),
+                    _                         => line.elem.clone(),
+                };
+                result.push(BlockLine {elem, off:line.off});
+            }
+            (Some(base_line), None) => {
+                // Right deleted the line. Accept unless left edited it.
+                if line.elem == base_line.elem { continue; }
+                conflicts.push(deletion_conflict(base_line, line, true));
+                result.push(line.clone());
+            }
+            (None, _) => result.push(line.clone()),
+        }
+        keys.push(key);
+    }
+
+    // Lines the right side added (and left never saw) go right after the
+    // nearest preceding line that survived; lines it edited but left
+    // deleted are conflicts, reported and re-inserted the same way.
+    let mut insert_at = 0;
+    for line in right {
+        let key = line_key(line);
+        if let Some(position) = keys.iter().position(|existing| *existing == key) {
+            insert_at = position + 1;
+            continue;
+        }
+        let keep = match base_index.get(&key) {
+            None => !left_keys.contains(&key),
+            Some(base_line) => {
+                if line.elem == base_line.elem { continue; }
+                conflicts.push(deletion_conflict(base_line, line, false));
+                true
+            }
+        };
+        if keep {
+            result.insert(insert_at, line.clone());
+            keys.insert(insert_at, key);
+            insert_at += 1;
+        }
+    }
+    result
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module line whose root node carries the given id.
+    fn line(id:u128, expr:Ast) -> Option<Ast> {
+        Some(expr.with_id(Id::from_u128(id)))
+    }
+
+    #[test]
+    fn independent_edits_merge_cleanly() {
+        let base = Ast::module(vec![
+            line(1, Ast::infix(Ast::var("a"), "=", Ast::number("1"))),
+            line(2, Ast::infix(Ast::var("b"), "=", Ast::number("2"))),
+        ]);
+        // Left renames line 1's variable; right changes line 2's value.
+        let left = Ast::module(vec![
+            line(1, Ast::infix(Ast::var("x"), "=", Ast::number("1"))),
+            line(2, Ast::infix(Ast::var("b"), "=", Ast::number("2"))),
+        ]);
+        let right = Ast::module(vec![
+            line(1, Ast::infix(Ast::var("a"), "=", Ast::number("1"))),
+            line(2, Ast::infix(Ast::var("b"), "=", Ast::number("3"))),
+        ]);
+        let merged = merge(&base, &left, &right);
+        assert_eq!(merged.ast.repr(), "x = 1\nb = 3");
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn edits_to_different_children_of_one_node_merge() {
+        let base  = Ast::infix(Ast::var("a"), "+", Ast::var("b")).with_id(Id::from_u128(1));
+        let left  = Ast::infix(Ast::var("x"), "+", Ast::var("b")).with_id(Id::from_u128(1));
+        let right = Ast::infix(Ast::var("a"), "+", Ast::var("y")).with_id(Id::from_u128(1));
+        let merged = merge(&base, &left, &right);
+        assert_eq!(merged.ast.repr(), "x + y");
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn additions_and_deletions_combine() {
+        let base = Ast::module(vec![
+            line(1, Ast::var("a")),
+            line(2, Ast::var("b")),
+            line(3, Ast::var("c")),
+        ]);
+        // Left deletes `b` and appends `d`; right inserts `e` after `b`.
+        let left = Ast::module(vec![
+            line(1, Ast::var("a")),
+            line(3, Ast::var("c")),
+            line(4, Ast::var("d")),
+        ]);
+        let right = Ast::module(vec![
+            line(1, Ast::var("a")),
+            line(2, Ast::var("b")),
+            line(5, Ast::var("e")),
+            line(3, Ast::var("c")),
+        ]);
+        let merged = merge(&base, &left, &right);
+        assert_eq!(merged.ast.repr(), "a\ne\nc\nd");
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn competing_edits_keep_left_and_report() {
+        let base  = Ast::module(vec![line(1, Ast::var("a"))]);
+        let left  = Ast::module(vec![line(1, Ast::cons("Left"))]);
+        let right = Ast::module(vec![line(1, Ast::number("9"))]);
+        let merged = merge(&base, &left, &right);
+        assert_eq!(merged.ast.repr(), "Left");
+        assert_eq!(merged.conflicts, vec![Conflict {
+            id    : Some(Id::from_u128(1)),
+            base  : Some("a".to_string()),
+            left  : Some("Left".to_string()),
+            right : Some("9".to_string()),
+        }]);
+    }
+
+    #[test]
+    fn deleting_an_edited_line_is_a_conflict() {
+        let base  = Ast::module(vec![line(1, Ast::var("a")), line(2, Ast::var("b"))]);
+        let left  = Ast::module(vec![line(2, Ast::var("b"))]);
+        let right = Ast::module(vec![line(1, Ast::var("a2")), line(2, Ast::var("b"))]);
+        let merged = merge(&base, &left, &right);
+        // The edited line survives, before the untouched one, and the
+        // disagreement is on record.
+        assert_eq!(merged.ast.repr(), "a2\nb");
+        assert_eq!(merged.conflicts, vec![Conflict {
+            id    : Some(Id::from_u128(1)),
+            base  : Some("a".to_string()),
+            left  : None,
+            right : Some("a2".to_string()),
+        }]);
+    }
+}
@@ -0,0 +1,219 @@
+//! Structured editing of number literals.
+//!
+//! The visual number widget edits literals without going through text: it
+//! reads the numeric value out of a `Number`, changes it, and writes it
+//! back — in the base the user originally chose, with the digit set
+//! validated, so the widget can never emit a literal the parser would
+//! reject. The half-typed states need helpers too: a `DanglingBase` —
+//! `16_` with no digits yet — can be completed into a proper radix literal
+//! once the user supplies them, or stripped back to the plain decimal it
+//! started as.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::DanglingBase;
+use crate::Number;
+use crate::Shape;
+
+
+
+// ==============
+// === Errors ===
+// ==============
+
+/// A problem with a number literal's spelling.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum NumberError {
+    /// The base is not an integer in the supported 2–36 range.
+    InvalidBase {
+        /// The literal's base text.
+        base : String,
+    },
+    /// A digit is not legal in the literal's base.
+    InvalidDigit {
+        /// The offending character.
+        digit : char,
+        /// The base the digit was checked against.
+        base : u32,
+    },
+    /// The value does not fit the editing range.
+    Overflow,
+}
+
+impl Display for NumberError {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NumberError::InvalidBase {base} =>
+                write!(f, "invalid number base `{}`; expected 2 to 36", base),
+            NumberError::InvalidDigit {digit,base} =>
+                write!(f, "digit `{}` is not valid in base {}", digit, base),
+            NumberError::Overflow =>
+                write!(f, "the number does not fit the editable range"),
+        }
+    }
+}
+
+
+
+// ==============
+// === Number ===
+// ==============
+
+impl Number {
+    /// The radix the digits are spelled in: the literal's base, or ten.
+    pub fn radix(&self) -> std::result::Result<u32,NumberError> {
+        let base = match &self.base {
+            None       => return Ok(10),
+            Some(base) => base,
+        };
+        match base.parse::<u32>() {
+            Ok(radix) if (2..=36).contains(&radix) => Ok(radix),
+            _ => Err(NumberError::InvalidBase {base:base.clone()}),
+        }
+    }
+
+    /// The numeric value of the literal.
+    pub fn to_value(&self) -> std::result::Result<u64,NumberError> {
+        let radix = self.radix()?;
+        let mut value:u64 = 0;
+        for digit in self.int.chars() {
+            let digit_value = digit.to_digit(radix)
+                .ok_or(NumberError::InvalidDigit {digit, base:radix})?;
+            value = value.checked_mul(radix as u64)
+                .and_then(|value| value.checked_add(digit_value as u64))
+                .ok_or(NumberError::Overflow)?;
+        }
+        Ok(value)
+    }
+
+    /// A literal of the given value, spelled in the given base (`None` for
+    /// plain decimal).
+    pub fn from_value
+    (base:Option<String>, value:u64) -> std::result::Result<Number,NumberError> {
+        let number = Number {base, int:String::new()};
+        number.with_value(value)
+    }
+
+    /// This literal edited to the given value — the base (and so the digit
+    /// alphabet) the user chose is preserved.
+    pub fn with_value(&self, value:u64) -> std::result::Result<Number,NumberError> {
+        let radix = self.radix()?;
+        Ok(Number {base:self.base.clone(), int:spell(value,radix)})
+    }
+
+    /// Checks that every digit is legal for the literal's base.
+    pub fn validate(&self) -> std::result::Result<(),NumberError> {
+        self.to_value().map(|_| ())
+    }
+}
+
+/// Spells a value in the given radix, lowercase, without a sign.
+fn spell(value:u64, radix:u32) -> String {
+    if value == 0 {
+        return "0".to_string();
+    }
+    let mut digits = Vec::new();
+    let mut value  = value;
+    while value > 0 {
+        let digit = (value % radix as u64) as u32;
+        digits.push(std::char::from_digit(digit,radix).expect("digit is below the radix"));
+        value /= radix as u64;
+    }
+    digits.iter().rev().collect()
+}
+
+
+
+// =====================
+// === DanglingBase  ===
+// =====================
+
+/// Completes a dangling base with the given digits: `16_` plus `ff` makes
+/// the literal `16_ff`.
+pub fn complete(dangling:&DanglingBase, int:impl Str) -> Number {
+    Number {base:Some(dangling.base.clone()), int:int.into()}
+}
+
+/// Strips a dangling base back to the decimal literal before the `_`:
+/// `16_` becomes `16`.
+pub fn strip(dangling:&DanglingBase) -> Number {
+    Number {base:None, int:dangling.base.clone()}
+}
+
+/// Completes a `DanglingBase` node into a `Number` node, keeping its id;
+/// `None` for nodes of any other shape.
+pub fn complete_node(node:&Ast, int:impl Str) -> Option<Ast> {
+    match node.shape() {
+        Shape::DanglingBase(dangling) => Some(Ast::new(complete(dangling,int), node.id())),
+        _                             => None,
+    }
+}
+
+/// Strips a `DanglingBase` node back to a decimal `Number` node, keeping
+/// its id; `None` for nodes of any other shape.
+pub fn strip_node(node:&Ast) -> Option<Ast> {
+    match node.shape() {
+        Shape::DanglingBase(dangling) => Some(Ast::new(strip(dangling), node.id())),
+        _                             => None,
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HasRepr;
+    use crate::Id;
+
+    #[test]
+    fn values_roundtrip_in_the_chosen_base() {
+        let hex = Number {base:Some("16".to_string()), int:"ff".to_string()};
+        assert_eq!(hex.to_value(), Ok(255));
+        assert_eq!(hex.with_value(256).unwrap().int, "100");
+        assert_eq!(Ast::from_shape(hex.with_value(256).unwrap()).repr(), "16_100");
+
+        let decimal = Number {base:None, int:"42".to_string()};
+        assert_eq!(decimal.to_value(), Ok(42));
+        assert_eq!(decimal.with_value(0).unwrap().int, "0");
+
+        let binary = Number::from_value(Some("2".to_string()), 5).unwrap();
+        assert_eq!(binary.int, "101");
+    }
+
+    #[test]
+    fn bad_spellings_are_typed_errors() {
+        let bad_digit = Number {base:Some("2".to_string()), int:"102".to_string()};
+        assert_eq!(bad_digit.to_value(), Err(NumberError::InvalidDigit {digit:'2', base:2}));
+        assert!(bad_digit.validate().is_err());
+
+        let bad_base = Number {base:Some("99".to_string()), int:"0".to_string()};
+        assert_eq!(bad_base.radix(), Err(NumberError::InvalidBase {base:"99".to_string()}));
+
+        let huge = Number {base:None, int:"99999999999999999999999".to_string()};
+        assert_eq!(huge.to_value(), Err(NumberError::Overflow));
+    }
+
+    #[test]
+    fn dangling_base_completion_and_stripping() {
+        let id       = Id::from_u128(9);
+        let dangling = Ast::from_shape(DanglingBase {base:"16".to_string()}).with_id(id);
+        assert_eq!(dangling.repr(), "16_");
+
+        let completed = complete_node(&dangling, "ff").unwrap();
+        assert_eq!(completed.repr(), "16_ff");
+        assert_eq!(completed.id(), Some(id));
+
+        let stripped = strip_node(&dangling).unwrap();
+        assert_eq!(stripped.repr(), "16");
+        assert_eq!(stripped.id(), Some(id));
+
+        assert!(complete_node(&Ast::var("x"), "ff").is_none());
+    }
+}
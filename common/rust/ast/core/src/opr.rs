@@ -0,0 +1,280 @@
+//! A flattened view of chains built from repeated usage of the same binary
+//! operator — most importantly the `.`-access chains like `foo.bar.baz`.
+//!
+//! Connection endpoints in the IDE are defined in terms of these chains, so
+//! they must support element insertion and removal as well as extraction of
+//! the target (the "self" argument).
+
+use crate::Ast;
+use crate::HasRepr;
+use crate::Infix;
+use crate::SectionLeft;
+use crate::SectionRight;
+use crate::Shape;
+
+/// Name of the access (method invocation) operator.
+pub const ACCESS:&str = ".";
+
+/// Checks if the node is the access operator.
+pub fn is_access_opr(ast:&Ast) -> bool {
+    matches!(ast.shape(), Shape::Opr(opr) if opr.name == ACCESS)
+}
+
+
+
+// ===============
+// === Element ===
+// ===============
+
+/// A single link of the chain: the operator with its surrounding offsets and
+/// the operand to its right (`None` for a section).
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Element {
+    /// Whitespace before the operator.
+    pub loff : usize,
+    /// The operator node.
+    pub opr : Ast,
+    /// Whitespace after the operator.
+    pub roff : usize,
+    /// The right-hand operand, if any.
+    pub operand : Option<Ast>,
+}
+
+
+
+// =============
+// === Chain ===
+// =============
+
+/// A flattened same-operator chain. For the left-associative operators we
+/// support, `a.b.c` flattens to the target `a` and elements `.b`, `.c`.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Chain {
+    /// The leftmost operand — the "self" of an access chain. `None` for
+    /// target-less chains like `.foo.bar`.
+    pub target : Option<Ast>,
+    /// The chain links, in textual order.
+    pub elements : Vec<Element>,
+    /// Name of the operator the chain is built from.
+    pub operator : String,
+}
+
+impl Chain {
+    /// Deconstructs a `.`-access chain. Returns `None` if the root node is
+    /// not an access operator application.
+    pub fn from_access(ast:&Ast) -> Option<Chain> {
+        Chain::from_ast(ast,ACCESS)
+    }
+
+    /// Deconstructs a chain of applications of the operator with given name.
+    /// Returns `None` if the root node is not such an application.
+    pub fn from_ast(ast:&Ast, operator:&str) -> Option<Chain> {
+        let mut elements = Vec::new();
+        let mut current  = ast.clone();
+        let target = loop {
+            match current.shape() {
+                Shape::Infix(infix) if infix.opr.repr() == operator => {
+                    elements.push(Element {
+                        loff    : infix.loff,
+                        opr     : infix.opr.clone(),
+                        roff    : infix.roff,
+                        operand : Some(infix.rarg.clone()),
+                    });
+                    let larg = infix.larg.clone();
+                    current  = larg;
+                }
+                Shape::SectionLeft(section) if section.opr.repr() == operator => {
+                    elements.push(Element {
+                        loff    : section.off,
+                        opr     : section.opr.clone(),
+                        roff    : 0,
+                        operand : None,
+                    });
+                    let arg = section.arg.clone();
+                    current = arg;
+                }
+                Shape::SectionRight(section) if section.opr.repr() == operator => {
+                    elements.push(Element {
+                        loff    : 0,
+                        opr     : section.opr.clone(),
+                        roff    : section.off,
+                        operand : Some(section.arg.clone()),
+                    });
+                    break None;
+                }
+                _ => break Some(current.clone()),
+            }
+        };
+        elements.reverse();
+        if elements.is_empty() {
+            return None;
+        }
+        let operator = operator.to_string();
+        Some(Chain {target,elements,operator})
+    }
+
+    /// The target (the "self" argument) of the chain, if any.
+    pub fn target(&self) -> Option<&Ast> {
+        self.target.as_ref()
+    }
+
+    /// All operands of the chain in textual order: the target (if present)
+    /// followed by each element's operand.
+    pub fn operands(&self) -> Vec<Option<&Ast>> {
+        let mut operands = vec![self.target.as_ref()];
+        operands.extend(self.elements.iter().map(|element| element.operand.as_ref()));
+        operands
+    }
+
+    /// Inserts an operand at given position (0 inserts a new target, pushing
+    /// the old one into the chain). The new operator gets the default
+    /// spacing; all the existing offsets are left untouched.
+    pub fn insert_operand(&mut self, index:usize, operand:Ast) {
+        let off = self.default_offset();
+        self.insert_operand_with_offsets(index, operand, off, off)
+    }
+
+    /// Like `insert_operand`, but with explicit whitespace around the new
+    /// operator — for callers that carry over spacing captured elsewhere and
+    /// need it preserved bit-for-bit.
+    pub fn insert_operand_with_offsets
+    (&mut self, index:usize, operand:Ast, loff:usize, roff:usize) {
+        let opr     = Ast::opr(&self.operator);
+        let element = |operand| Element {loff, opr, roff, operand};
+        if index == 0 {
+            let old_target = self.target.replace(operand);
+            self.elements.insert(0, element(old_target));
+        } else {
+            self.elements.insert(index - 1, element(Some(operand)));
+        }
+    }
+
+    /// Removes and returns the operand at given position. Removing the
+    /// target promotes the first element's operand into its place.
+    pub fn remove_operand(&mut self, index:usize) -> Option<Ast> {
+        if index == 0 {
+            let removed = self.target.take()?;
+            if !self.elements.is_empty() {
+                let first   = self.elements.remove(0);
+                self.target = first.operand;
+            }
+            Some(removed)
+        } else {
+            let element = (index - 1 < self.elements.len())
+                .then(|| self.elements.remove(index - 1))?;
+            element.operand
+        }
+    }
+
+    /// Folds the chain back into a nested operator application.
+    pub fn fold(&self) -> Ast {
+        let mut result = self.target.clone();
+        for element in &self.elements {
+            let folded = match (result,element.operand.clone()) {
+                (Some(larg),Some(rarg)) => Ast::from_shape(Infix {
+                    larg,
+                    loff : element.loff,
+                    opr  : element.opr.clone(),
+                    roff : element.roff,
+                    rarg,
+                }),
+                (Some(arg),None) => Ast::from_shape(SectionLeft {
+                    arg,
+                    off : element.loff,
+                    opr : element.opr.clone(),
+                }),
+                (None,Some(arg)) => Ast::from_shape(SectionRight {
+                    opr : element.opr.clone(),
+                    off : element.roff,
+                    arg,
+                }),
+                (None,None) => Ast::section_sides(&self.operator),
+            };
+            result = Some(folded);
+        }
+        result.unwrap_or_else(|| Ast::section_sides(&self.operator))
+    }
+
+    /// The whitespace to use around a freshly inserted operator.
+    fn default_offset(&self) -> usize {
+        if self.operator == ACCESS { 0 } else { 1 }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `here.foo.bar`
+    fn access_chain() -> Ast {
+        let inner = Ast::from_shape(Infix {
+            larg : Ast::var("here"),
+            loff : 0,
+            opr  : Ast::opr(ACCESS),
+            roff : 0,
+            rarg : Ast::var("foo"),
+        });
+        Ast::from_shape(Infix {
+            larg : inner,
+            loff : 0,
+            opr  : Ast::opr(ACCESS),
+            roff : 0,
+            rarg : Ast::var("bar"),
+        })
+    }
+
+    #[test]
+    fn access_chain_deconstruction() {
+        let chain = Chain::from_access(&access_chain()).unwrap();
+        assert_eq!(chain.target().unwrap().repr(), "here");
+        assert_eq!(chain.elements.len(), 2);
+        assert_eq!(chain.fold().repr(), "here.foo.bar");
+    }
+
+    #[test]
+    fn general_operator_chain() {
+        let sum   = Ast::infix(Ast::infix(Ast::var("a"), "+", Ast::var("b")), "+", Ast::var("c"));
+        let chain = Chain::from_ast(&sum, "+").unwrap();
+        assert_eq!(chain.operands().len(), 3);
+        assert_eq!(chain.fold().repr(), "a + b + c");
+        // A `-` at the root means this is not a `+` chain.
+        let diff = Ast::infix(Ast::var("a"), "-", Ast::var("b"));
+        assert_eq!(Chain::from_ast(&diff, "+"), None);
+    }
+
+    #[test]
+    fn targetless_chain() {
+        let section = Ast::from_shape(SectionRight {
+            opr : Ast::opr(ACCESS),
+            off : 0,
+            arg : Ast::var("foo"),
+        });
+        let chain = Chain::from_access(&section).unwrap();
+        assert_eq!(chain.target(), None);
+        assert_eq!(chain.fold().repr(), ".foo");
+    }
+
+    #[test]
+    fn operand_insertion_and_removal() {
+        let mut chain = Chain::from_access(&access_chain()).unwrap();
+        chain.insert_operand(1, Ast::var("baz"));
+        assert_eq!(chain.fold().repr(), "here.baz.foo.bar");
+        let removed = chain.remove_operand(0).unwrap();
+        assert_eq!(removed.repr(), "here");
+        assert_eq!(chain.fold().repr(), "baz.foo.bar");
+    }
+
+    #[test]
+    fn insertion_with_explicit_offsets() {
+        let sum       = Ast::infix(Ast::var("a"), "+", Ast::var("b"));
+        let mut chain = Chain::from_ast(&sum, "+").unwrap();
+        chain.insert_operand_with_offsets(2, Ast::var("c"), 0, 3);
+        assert_eq!(chain.fold().repr(), "a + b+   c");
+    }
+}
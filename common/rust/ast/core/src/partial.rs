@@ -0,0 +1,215 @@
+//! Partial deserialization: materializing only the nodes of interest.
+//!
+//! Features like the project outline read the module structure and the
+//! definition headers and never look inside expression bodies — yet opening
+//! a file used to deserialize every node of every expression. This module
+//! walks the serialized JSON and builds a tree in which only nodes of the
+//! selected kinds are materialized; every other subtree is kept as the raw
+//! JSON blob it arrived as, reconstructible into a full `Ast` on demand.
+//!
+//! The walk relies on the shape of this crate's serialization: every node
+//! is an object holding its variant under a capitalized key next to `len`
+//! and `id`. Children are listed in serialization order, which is not
+//! necessarily textual order — callers that care about positions should
+//! materialize and use spans.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::Id;
+
+use serde_json::Value;
+use std::collections::HashSet;
+
+
+
+// ===============
+// === Partial ===
+// ===============
+
+/// A node of a partially deserialized tree.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Partial {
+    /// A node of a selected kind, with its own children partial in turn.
+    Node(PartialNode),
+    /// A subtree of an unselected kind, kept as raw JSON.
+    Deferred(Value),
+}
+
+/// A materialized node of the partial tree.
+#[derive(Clone,Debug,PartialEq)]
+pub struct PartialNode {
+    /// The variant name, e.g. `"Def"`.
+    pub kind : String,
+    /// The node's id, if it carries one.
+    pub id : Option<Id>,
+    /// The node's textual length.
+    pub len : usize,
+    /// The node's full raw JSON, for on-demand materialization.
+    value : Value,
+    /// The node's children, in serialization order.
+    pub children : Vec<Partial>,
+}
+
+impl Partial {
+    /// The variant name of the node, whether materialized or not.
+    pub fn kind(&self) -> Option<&str> {
+        match self {
+            Partial::Node(node)      => Some(&node.kind),
+            Partial::Deferred(value) => node_kind(value).map(|(kind,_)| kind),
+        }
+    }
+
+    /// Deserializes the subtree in full.
+    pub fn materialize(&self) -> serde_json::Result<Ast> {
+        let value = match self {
+            Partial::Node(node)      => &node.value,
+            Partial::Deferred(value) => value,
+        };
+        serde_json::from_value(value.clone())
+    }
+
+    /// The materialized descendants, depth first.
+    pub fn nodes(&self) -> Vec<&PartialNode> {
+        let mut result = Vec::new();
+        if let Partial::Node(node) = self {
+            result.push(node);
+            for child in &node.children {
+                result.extend(child.nodes());
+            }
+        }
+        result
+    }
+}
+
+/// Builds a partial tree from a serialized node, materializing only the
+/// kinds in `selected`. A node of an unselected kind defers its whole
+/// subtree — selected descendants inside it stay raw too, since reaching
+/// them would mean deserializing the very expressions being skipped.
+pub fn deserialize_selected(value:&Value, selected:&HashSet<String>) -> Partial {
+    let (kind,body) = match node_kind(value) {
+        Some(found) => found,
+        None        => return Partial::Deferred(value.clone()),
+    };
+    if !selected.contains(kind) {
+        return Partial::Deferred(value.clone());
+    }
+    let id = value.get("id")
+        .and_then(|id| serde_json::from_value(id.clone()).ok());
+    let len = value.get("len")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as usize;
+    let mut children = Vec::new();
+    collect_children(body, selected, &mut children);
+    Partial::Node(PartialNode {
+        kind : kind.to_string(),
+        id,
+        len,
+        value : value.clone(),
+        children,
+    })
+}
+
+/// Recognizes a serialized node: an object holding `len` and exactly one
+/// capitalized variant key. Returns the variant name and its body.
+fn node_kind(value:&Value) -> Option<(&str,&Value)> {
+    let object = value.as_object()?;
+    if !object.contains_key("len") {
+        return None;
+    }
+    let mut variants = object.iter().filter(|(key,_)| {
+        key.chars().next().is_some_and(char::is_uppercase)
+    });
+    let (kind,body) = variants.next()?;
+    if variants.next().is_some() {
+        return None;
+    }
+    Some((kind,body))
+}
+
+/// Finds the child nodes inside a variant's body, recursing through the
+/// arrays and plain objects of the encoding but stopping at node objects.
+fn collect_children(body:&Value, selected:&HashSet<String>, out:&mut Vec<Partial>) {
+    match body {
+        _ if node_kind(body).is_some() =>
+            out.push(deserialize_selected(body, selected)),
+        Value::Array(items) => for item in items {
+            collect_children(item, selected, out);
+        },
+        Value::Object(fields) => for (_,field) in fields {
+            collect_children(field, selected, out);
+        },
+        _ => {}
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HasRepr;
+
+    fn selected(kinds:&[&str]) -> HashSet<String> {
+        kinds.iter().map(|kind| kind.to_string()).collect()
+    }
+
+    #[test]
+    fn only_selected_kinds_are_materialized() {
+        // `main = foo x` — the definition spine is wanted, the body is not.
+        let body   = Ast::prefix(Ast::var("foo"), Ast::var("x"));
+        let def    = Ast::infix(Ast::var("main"), "=", body.clone());
+        let value  = serde_json::to_value(&def).unwrap();
+        let partial = deserialize_selected(&value, &selected(&["Infix","Var","Opr"]));
+
+        let root = match &partial {
+            Partial::Node(node) => node,
+            Partial::Deferred(_) => panic!("the root should be materialized"),
+        };
+        assert_eq!(root.kind, "Infix");
+        assert_eq!(root.len, def.repr().chars().count());
+
+        let kinds:Vec<Option<&str>> = root.children.iter()
+            .map(|child| child.kind())
+            .collect();
+        assert!(kinds.contains(&Some("Var")));
+        assert!(kinds.contains(&Some("Prefix")));
+        let deferred = root.children.iter()
+            .find(|child| matches!(child, Partial::Deferred(_)))
+            .unwrap();
+        assert_eq!(deferred.kind(), Some("Prefix"));
+    }
+
+    #[test]
+    fn deferred_subtrees_materialize_on_demand() {
+        let body    = Ast::prefix(Ast::var("foo"), Ast::var("x"));
+        let def     = Ast::infix(Ast::var("main"), "=", body.clone());
+        let value   = serde_json::to_value(&def).unwrap();
+        let partial = deserialize_selected(&value, &selected(&["Infix"]));
+
+        assert_eq!(partial.materialize().unwrap(), def);
+        let root = partial.nodes();
+        assert_eq!(root.len(), 1);
+        let children = match &partial {
+            Partial::Node(node)  => &node.children,
+            Partial::Deferred(_) => unreachable!(),
+        };
+        let deferred_body = children.iter()
+            .find(|child| child.kind() == Some("Prefix"))
+            .unwrap();
+        assert_eq!(deferred_body.materialize().unwrap(), body);
+    }
+
+    #[test]
+    fn unselected_roots_are_deferred_whole() {
+        let value   = serde_json::to_value(Ast::var("x")).unwrap();
+        let partial = deserialize_selected(&value, &selected(&["Module"]));
+        assert!(matches!(partial, Partial::Deferred(_)));
+        assert_eq!(partial.kind(), Some("Var"));
+    }
+}
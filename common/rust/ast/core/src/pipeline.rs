@@ -0,0 +1,436 @@
+//! A pipeline of named analysis passes with declared dependencies.
+//!
+//! The analyses over a tree keep growing — span map, alias analysis,
+//! connection discovery — and each used to recompute its prerequisites
+//! privately. Here a pass declares what it needs by name; the pipeline runs
+//! prerequisites first, hands their cached outputs to the pass, and keeps
+//! every output until the tree changes. Outputs are type-erased (`Rc<dyn
+//! Any>`), so passes of unrelated result types share one cache; a consumer
+//! downcasts to the type it knows the pass produces.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::Id;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+
+
+// ==============
+// === Errors ===
+// ==============
+
+/// A problem with the pass graph or its execution.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum PipelineError {
+    /// A pass of that name is already registered.
+    DuplicatePass {
+        /// The contested name.
+        name : String,
+    },
+    /// A requested or depended-upon pass is not registered.
+    UnknownPass {
+        /// The missing name.
+        name : String,
+    },
+    /// The dependency declarations form a cycle through the named pass.
+    CircularDependency {
+        /// A pass on the cycle.
+        name : String,
+    },
+    /// No tree has been supplied to analyze.
+    NoAst,
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PipelineError::DuplicatePass {name} =>
+                write!(f, "a pass named `{}` is already registered", name),
+            PipelineError::UnknownPass {name} =>
+                write!(f, "no pass named `{}` is registered", name),
+            PipelineError::CircularDependency {name} =>
+                write!(f, "pass dependencies form a cycle through `{}`", name),
+            PipelineError::NoAst =>
+                write!(f, "the pipeline has no tree to analyze"),
+        }
+    }
+}
+
+
+
+// ============
+// === Pass ===
+// ============
+
+/// A type-erased pass output.
+pub type PassOutput = Rc<dyn Any>;
+
+/// The outputs a pass's dependencies produced, handed to it on execution.
+#[derive(Debug,Default)]
+pub struct Outputs {
+    outputs : HashMap<String,PassOutput>,
+}
+
+impl Outputs {
+    /// The output of the named pass, downcast to its known result type.
+    /// Present for every declared dependency.
+    pub fn get<T:'static>(&self, name:&str) -> Option<&T> {
+        self.outputs.get(name).and_then(|output| output.downcast_ref())
+    }
+}
+
+/// A single analysis or transform over the tree.
+pub trait Pass {
+    /// The pass's unique name, used in dependency declarations.
+    fn name(&self) -> &'static str;
+    /// The names of the passes whose outputs this one reads.
+    fn dependencies(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+    /// Checks whether an edit to the given nodes invalidates this pass's
+    /// cached output. The conservative default says yes to any edit; a pass
+    /// that only reads part of the tree can override this with a sharper
+    /// test. Staleness still propagates along dependency edges regardless —
+    /// a pass reading a stale input is stale however clean its own region.
+    fn is_stale(&self, changed:&HashSet<Id>) -> bool {
+        let _ = changed;
+        true
+    }
+    /// Computes the pass's output. The outputs of all declared dependencies
+    /// are available in `deps`.
+    fn run(&self, ast:&Ast, deps:&Outputs) -> PassOutput;
+}
+
+
+
+// ================
+// === Pipeline ===
+// ================
+
+/// The registered passes and their cached outputs for the current tree.
+#[derive(Default)]
+pub struct Pipeline {
+    passes : HashMap<String,Rc<dyn Pass>>,
+    cache  : HashMap<String,PassOutput>,
+    ast    : Option<Ast>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline.
+    pub fn new() -> Pipeline {
+        default()
+    }
+
+    /// Registers a pass. Dependencies may be registered in any order; they
+    /// are resolved when an output is requested.
+    pub fn register
+    (&mut self, pass:impl Pass+'static) -> std::result::Result<(),PipelineError> {
+        let name = pass.name().to_string();
+        if self.passes.contains_key(&name) {
+            return Err(PipelineError::DuplicatePass {name});
+        }
+        self.passes.insert(name, Rc::new(pass));
+        Ok(())
+    }
+
+    /// Supplies the tree to analyze, dropping every cached output.
+    pub fn set_ast(&mut self, ast:Ast) {
+        self.ast = Some(ast);
+        self.cache.clear();
+    }
+
+    /// Supplies an edited tree together with the ids of the changed nodes,
+    /// dropping only the cached outputs the edit makes stale.
+    ///
+    /// A pass is stale when its own `is_stale` says so for the changed ids,
+    /// or — transitively — when any of its dependencies is; the rest of the
+    /// cache survives, which is what makes repeated small edits cheap.
+    pub fn update_ast(&mut self, ast:Ast, changed:&HashSet<Id>) {
+        self.ast = Some(ast);
+        let mut stale:HashSet<String> = self.passes.iter()
+            .filter(|(_,pass)| pass.is_stale(changed))
+            .map(|(name,_)| name.clone())
+            .collect();
+        loop {
+            let inherited:Vec<String> = self.passes.iter()
+                .filter(|(name,pass)| {
+                    !stale.contains(*name)
+                        && pass.dependencies().iter().any(|dep| stale.contains(*dep))
+                })
+                .map(|(name,_)| name.clone())
+                .collect();
+            if inherited.is_empty() {
+                break;
+            }
+            stale.extend(inherited);
+        }
+        self.cache.retain(|name,_| !stale.contains(name));
+    }
+
+    /// The analyzed tree, if any.
+    pub fn ast(&self) -> Option<&Ast> {
+        self.ast.as_ref()
+    }
+
+    /// Checks whether the named pass's output is cached.
+    pub fn is_cached(&self, name:&str) -> bool {
+        self.cache.contains_key(name)
+    }
+
+    /// The output of the named pass, computing it (and its prerequisites)
+    /// only where no cached result exists.
+    pub fn get(&mut self, name:&str) -> std::result::Result<PassOutput,PipelineError> {
+        let mut visiting = HashSet::new();
+        self.resolve(name, &mut visiting)
+    }
+
+    /// The output of the named pass, downcast to its known result type.
+    /// Panics when the requested type is not what the pass produces — that
+    /// is a bug at the call site, not a runtime condition.
+    pub fn get_as<T:'static>
+    (&mut self, name:&str) -> std::result::Result<Rc<T>,PipelineError> {
+        let output = self.get(name)?;
+        Ok(output.downcast().unwrap_or_else(|_| panic!("the pass produces a different output type")))
+    }
+
+    fn resolve
+    (&mut self, name:&str, visiting:&mut HashSet<String>)
+    -> std::result::Result<PassOutput,PipelineError> {
+        if let Some(cached) = self.cache.get(name) {
+            return Ok(cached.clone());
+        }
+        if !visiting.insert(name.to_string()) {
+            return Err(PipelineError::CircularDependency {name:name.to_string()});
+        }
+        let pass = self.passes.get(name).cloned()
+            .ok_or_else(|| PipelineError::UnknownPass {name:name.to_string()})?;
+        let mut deps = Outputs::default();
+        for dependency in pass.dependencies() {
+            let output = self.resolve(dependency, visiting)?;
+            deps.outputs.insert(dependency.to_string(), output);
+        }
+        let ast    = self.ast.clone().ok_or(PipelineError::NoAst)?;
+        let output = pass.run(&ast, &deps);
+        self.cache.insert(name.to_string(), output.clone());
+        visiting.remove(name);
+        Ok(output)
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Block;
+    use crate::HasRepr;
+    use crate::BlockLine;
+    use crate::BlockType;
+    use crate::Module;
+
+    use std::cell::Cell;
+
+    /// A pass counting how many times it ran, for cache assertions.
+    struct Counting {
+        name : &'static str,
+        deps : Vec<&'static str>,
+        runs : Rc<Cell<usize>>,
+    }
+
+    impl Pass for Counting {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn dependencies(&self) -> Vec<&'static str> {
+            self.deps.clone()
+        }
+        fn run(&self, ast:&Ast, deps:&Outputs) -> PassOutput {
+            self.runs.set(self.runs.get() + 1);
+            let inherited:usize = self.deps.iter()
+                .map(|dep| *deps.get::<usize>(dep).unwrap())
+                .sum();
+            Rc::new(inherited + ast.repr().chars().count())
+        }
+    }
+
+    fn counting(name:&'static str, deps:Vec<&'static str>) -> (Counting,Rc<Cell<usize>>) {
+        let runs = Rc::new(Cell::new(0));
+        (Counting {name, deps, runs:runs.clone()}, runs)
+    }
+
+    #[test]
+    fn prerequisites_run_once_and_outputs_are_cached() {
+        let (spans,span_runs)   = counting("span-map", vec![]);
+        let (aliases,alias_runs) = counting("alias-analysis", vec!["span-map"]);
+        let (conns,conn_runs)    = counting("connections", vec!["alias-analysis","span-map"]);
+
+        let mut pipeline = Pipeline::new();
+        pipeline.register(spans).unwrap();
+        pipeline.register(aliases).unwrap();
+        pipeline.register(conns).unwrap();
+        pipeline.set_ast(Ast::var("x"));
+
+        let first = pipeline.get_as::<usize>("connections").unwrap();
+        // span-map = 1, alias-analysis = 1 + 1, connections = 2 + 1 + 1.
+        assert_eq!(*first, 4);
+        let again = pipeline.get_as::<usize>("connections").unwrap();
+        assert_eq!(first, again);
+        // The diamond ran each pass exactly once.
+        assert_eq!(span_runs.get(),  1);
+        assert_eq!(alias_runs.get(), 1);
+        assert_eq!(conn_runs.get(),  1);
+        assert!(pipeline.is_cached("span-map"));
+    }
+
+    #[test]
+    fn a_new_tree_drops_the_cache() {
+        let (pass,runs)  = counting("span-map", vec![]);
+        let mut pipeline = Pipeline::new();
+        pipeline.register(pass).unwrap();
+        pipeline.set_ast(Ast::var("x"));
+        pipeline.get("span-map").unwrap();
+        pipeline.set_ast(Ast::var("longer"));
+        assert!(!pipeline.is_cached("span-map"));
+        assert_eq!(*pipeline.get_as::<usize>("span-map").unwrap(), 6);
+        assert_eq!(runs.get(), 2);
+    }
+
+    /// A pass that only reads the nodes in `scope`; an empty scope means it
+    /// reads nothing of the tree itself (it only folds dependency outputs).
+    struct Scoped {
+        name  : &'static str,
+        deps  : Vec<&'static str>,
+        scope : HashSet<Id>,
+        runs  : Rc<Cell<usize>>,
+    }
+
+    impl Pass for Scoped {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn dependencies(&self) -> Vec<&'static str> {
+            self.deps.clone()
+        }
+        fn is_stale(&self, changed:&HashSet<Id>) -> bool {
+            changed.iter().any(|id| self.scope.contains(id))
+        }
+        fn run(&self, _ast:&Ast, _deps:&Outputs) -> PassOutput {
+            self.runs.set(self.runs.get() + 1);
+            Rc::new(())
+        }
+    }
+
+    fn scoped
+    (name:&'static str, deps:Vec<&'static str>, scope:&[Id]) -> (Scoped,Rc<Cell<usize>>) {
+        let runs = Rc::new(Cell::new(0));
+        let scope = scope.iter().cloned().collect();
+        (Scoped {name, deps, scope, runs:runs.clone()}, runs)
+    }
+
+    /// A module of two definitions whose bodies are blocks, with ids on the
+    /// nested body nodes — the shape of a typical nested-block edit.
+    fn two_defs(main_body_id:Id, other_body_id:Id) -> Ast {
+        let block = |body:Ast| Ast::from_shape(Block {
+            ty          : BlockType::Discontinuous {},
+            indent      : 4,
+            empty_lines : vec![],
+            first_line  : BlockLine {elem:body, off:0},
+            lines       : vec![],
+            is_orphan   : false,
+        });
+        let main  = Ast::infix(Ast::var("main"),  "=", block(Ast::var("a").with_id(main_body_id)));
+        let other = Ast::infix(Ast::var("other"), "=", block(Ast::var("b").with_id(other_body_id)));
+        Ast::from_shape(Module {
+            lines : vec![
+                BlockLine {elem:Some(main),  off:0},
+                BlockLine {elem:Some(other), off:0},
+            ],
+        })
+    }
+
+    #[test]
+    fn nested_block_edits_only_stale_the_touched_region() {
+        let main_id  = Id::from_u128(1);
+        let other_id = Id::from_u128(2);
+        let module   = two_defs(main_id, other_id);
+
+        let (main_pass,main_runs)   = scoped("main-outline",  vec![], &[main_id]);
+        let (other_pass,other_runs) = scoped("other-outline", vec![], &[other_id]);
+        let (joined,joined_runs)    = scoped(
+            "outline", vec!["main-outline","other-outline"], &[]);
+
+        let mut pipeline = Pipeline::new();
+        pipeline.register(main_pass).unwrap();
+        pipeline.register(other_pass).unwrap();
+        pipeline.register(joined).unwrap();
+        pipeline.set_ast(module.clone());
+        pipeline.get("outline").unwrap();
+        assert_eq!((main_runs.get(), other_runs.get(), joined_runs.get()), (1,1,1));
+
+        // An edit deep in `other`'s block leaves `main`'s analysis cached;
+        // the joined pass is stale only through its dependency.
+        let changed:HashSet<Id> = [other_id].iter().cloned().collect();
+        pipeline.update_ast(module.clone(), &changed);
+        assert!( pipeline.is_cached("main-outline"));
+        assert!(!pipeline.is_cached("other-outline"));
+        assert!(!pipeline.is_cached("outline"));
+        pipeline.get("outline").unwrap();
+        assert_eq!((main_runs.get(), other_runs.get(), joined_runs.get()), (1,2,2));
+
+        // An edit in `main`'s block stales the other side of the diamond.
+        let changed:HashSet<Id> = [main_id].iter().cloned().collect();
+        pipeline.update_ast(module, &changed);
+        assert!(!pipeline.is_cached("main-outline"));
+        assert!( pipeline.is_cached("other-outline"));
+        pipeline.get("outline").unwrap();
+        assert_eq!((main_runs.get(), other_runs.get(), joined_runs.get()), (2,2,3));
+    }
+
+    #[test]
+    fn the_default_staleness_is_conservative() {
+        let (pass,runs)  = counting("span-map", vec![]);
+        let mut pipeline = Pipeline::new();
+        pipeline.register(pass).unwrap();
+        pipeline.set_ast(Ast::var("x"));
+        pipeline.get("span-map").unwrap();
+        pipeline.update_ast(Ast::var("x"), &HashSet::new());
+        assert!(!pipeline.is_cached("span-map"));
+        pipeline.get("span-map").unwrap();
+        assert_eq!(runs.get(), 2);
+    }
+
+    #[test]
+    fn graph_problems_are_reported() {
+        let (orphan,_)   = counting("orphan", vec!["missing"]);
+        let mut pipeline = Pipeline::new();
+        pipeline.register(orphan).unwrap();
+        pipeline.set_ast(Ast::var("x"));
+        assert!(matches!(pipeline.get("missing"),
+            Err(PipelineError::UnknownPass {ref name}) if name == "missing"));
+        assert!(matches!(pipeline.get("orphan"),
+            Err(PipelineError::UnknownPass {ref name}) if name == "missing"));
+
+        let (a,_) = counting("a", vec!["b"]);
+        let (b,_) = counting("b", vec!["a"]);
+        let mut cyclic = Pipeline::new();
+        cyclic.register(a).unwrap();
+        cyclic.register(b).unwrap();
+        cyclic.set_ast(Ast::var("x"));
+        assert!(matches!(cyclic.get("a"), Err(PipelineError::CircularDependency {..})));
+
+        let (dup,_)  = counting("a", vec![]);
+        let (dup2,_) = counting("a", vec![]);
+        let mut pipeline = Pipeline::new();
+        pipeline.register(dup).unwrap();
+        assert!(matches!(pipeline.register(dup2), Err(PipelineError::DuplicatePass {..})));
+    }
+}
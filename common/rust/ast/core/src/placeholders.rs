@@ -0,0 +1,100 @@
+//! Bookkeeping of `_` placeholders (`Blank` nodes) within an expression.
+//!
+//! The graph editor lets the user drop a connection onto a placeholder; to
+//! support that reliably we must be able to enumerate the holes of an
+//! expression, substitute a chosen one and tell how many remain.
+
+use crate::Ast;
+use crate::Crumbs;
+use crate::Shape;
+
+
+
+// ===================
+// === Placeholder ===
+// ===================
+
+/// A single `_` placeholder found within an expression.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Placeholder {
+    /// Path from the expression's root to the placeholder node.
+    pub crumbs : Crumbs,
+}
+
+/// Finds all `_` placeholders within the expression, in textual order.
+pub fn find_placeholders(ast:&Ast) -> Vec<Placeholder> {
+    let mut found = Vec::new();
+    collect(ast, &mut Vec::new(), &mut found);
+    found
+}
+
+fn collect(ast:&Ast, crumbs:&mut Crumbs, found:&mut Vec<Placeholder>) {
+    if let Shape::Blank(_) = ast.shape() {
+        found.push(Placeholder {crumbs:crumbs.clone()});
+    }
+    for (index,child) in ast.children().into_iter().enumerate() {
+        crumbs.push(index);
+        collect(child, crumbs, found);
+        crumbs.pop();
+    }
+}
+
+/// The number of `_` placeholders remaining within the expression.
+pub fn count_holes(ast:&Ast) -> usize {
+    find_placeholders(ast).len()
+}
+
+/// Substitutes the `index`-th placeholder (in textual order) with given node.
+///
+/// Returns `None` if the expression has fewer placeholders.
+pub fn substitute_placeholder(ast:&Ast, index:usize, node:Ast) -> Option<Ast> {
+    let placeholder = find_placeholders(ast).into_iter().nth(index)?;
+    ast.set_node(&placeholder.crumbs, node)
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HasRepr;
+
+    /// `foo _ (_ + x)`
+    fn example() -> Ast {
+        let paren_body = Ast::infix(Ast::blank(), "+", Ast::var("x"));
+        let chain      = Ast::prefix(Ast::var("foo"), Ast::blank());
+        Ast::prefix(chain, paren_body)
+    }
+
+    #[test]
+    fn finding_placeholders() {
+        let ast          = example();
+        let placeholders = find_placeholders(&ast);
+        assert_eq!(placeholders.len(), 2);
+        assert_eq!(count_holes(&ast), 2);
+        for placeholder in &placeholders {
+            let node = ast.get_node(&placeholder.crumbs).unwrap();
+            assert_eq!(node.repr(), "_");
+        }
+    }
+
+    #[test]
+    fn substitution_in_textual_order() {
+        let ast    = example();
+        let result = substitute_placeholder(&ast, 0, Ast::var("a")).unwrap();
+        assert_eq!(count_holes(&result), 1);
+        let result = substitute_placeholder(&result, 0, Ast::var("b")).unwrap();
+        assert_eq!(count_holes(&result), 0);
+        assert_eq!(result.repr(), "foo a b + x");
+    }
+
+    #[test]
+    fn substituting_missing_placeholder() {
+        let ast = Ast::var("no_holes_here");
+        assert_eq!(substitute_placeholder(&ast, 0, Ast::blank()), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! A flattened view of a prefix application chain, like `foo a b c`.
+//!
+//! The graph editor manipulates call arguments all the time; this view spares
+//! it the recursive deconstruction of nested `Prefix` nodes.
+
+use crate::Ast;
+use crate::Crumbs;
+use crate::Prefix;
+use crate::Shape;
+use crate::Shifted;
+
+
+
+// =================
+// === Argument ===
+// =================
+
+/// A single argument of the chain, along with the path leading to it in the
+/// folded AST.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Argument {
+    /// The argument with its preceding whitespace.
+    pub sast : Shifted<Ast>,
+    /// Path from the folded chain's root to this argument's node.
+    pub crumbs : Crumbs,
+}
+
+
+
+// =============
+// === Chain ===
+// =============
+
+/// A flattened prefix application chain: the applied function and its
+/// arguments in textual order.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Chain {
+    /// The innermost function being applied.
+    pub func : Ast,
+    /// The arguments, in textual order.
+    pub args : Vec<Shifted<Ast>>,
+}
+
+impl Chain {
+    /// Deconstructs a prefix application into a chain. Returns `None` if the
+    /// node is not a prefix application at all.
+    pub fn from_ast(ast:&Ast) -> Option<Chain> {
+        match ast.shape() {
+            Shape::Prefix(_) => Some(Chain::from_ast_non_strict(ast)),
+            _                => None,
+        }
+    }
+
+    /// Deconstructs a node into a chain, treating a non-prefix node as a
+    /// chain with no arguments.
+    pub fn from_ast_non_strict(ast:&Ast) -> Chain {
+        let mut args    = Vec::new();
+        let mut current = ast.clone();
+        while let Shape::Prefix(prefix) = current.shape() {
+            args.push(Shifted {off:prefix.off, wrapped:prefix.arg.clone()});
+            let func = prefix.func.clone();
+            current  = func;
+        }
+        args.reverse();
+        Chain {func:current, args}
+    }
+
+    /// The arguments of the chain, each with its offset and the crumbs
+    /// locating it within the folded AST.
+    pub fn args(&self) -> Vec<Argument> {
+        let count = self.args.len();
+        self.args.iter().enumerate().map(|(index,sast)| {
+            // Each enclosing application adds one `func` (index 0) step; the
+            // argument itself is the second child (index 1) of its node.
+            let mut crumbs:Crumbs = std::iter::repeat_n(0, count - 1 - index).collect();
+            crumbs.push(1);
+            Argument {sast:sast.clone(), crumbs}
+        }).collect()
+    }
+
+    /// Inserts an argument at given position. An index equal to the argument
+    /// count appends.
+    ///
+    /// # Panics
+    /// Panics if the index exceeds the argument count.
+    pub fn insert_arg(&mut self, index:usize, sast:Shifted<Ast>) {
+        self.args.insert(index,sast);
+    }
+
+    /// Removes and returns the argument at given position, or `None` if there
+    /// is no such argument.
+    pub fn remove_arg(&mut self, index:usize) -> Option<Shifted<Ast>> {
+        (index < self.args.len()).then(|| self.args.remove(index))
+    }
+
+    /// Folds the chain back into a nested prefix application.
+    pub fn fold(&self) -> Ast {
+        let mut result = self.func.clone();
+        for arg in &self.args {
+            let prefix = Prefix {func:result, off:arg.off, arg:arg.wrapped.clone()};
+            result = Ast::from_shape(prefix);
+        }
+        result
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HasRepr;
+
+    fn chain_of(repr_parts:&[&str]) -> Chain {
+        let mut ast = Ast::var(repr_parts[0]);
+        for part in &repr_parts[1..] {
+            ast = Ast::prefix(ast, Ast::var(*part));
+        }
+        Chain::from_ast(&ast).unwrap()
+    }
+
+    #[test]
+    fn deconstruction_and_fold_roundtrip() {
+        let chain = chain_of(&["foo","a","b"]);
+        assert_eq!(chain.func.repr(), "foo");
+        assert_eq!(chain.args.len(), 2);
+        assert_eq!(chain.fold().repr(), "foo a b");
+    }
+
+    #[test]
+    fn non_prefix_is_not_a_chain() {
+        assert_eq!(Chain::from_ast(&Ast::var("foo")), None);
+        let non_strict = Chain::from_ast_non_strict(&Ast::var("foo"));
+        assert!(non_strict.args.is_empty());
+    }
+
+    #[test]
+    fn argument_crumbs_point_at_arguments() {
+        let chain  = chain_of(&["foo","a","b","c"]);
+        let folded = chain.fold();
+        for (index,argument) in chain.args().iter().enumerate() {
+            let node = folded.get_node(&argument.crumbs).unwrap();
+            assert_eq!(node.repr(), chain.args[index].wrapped.repr());
+        }
+    }
+
+    #[test]
+    fn inserting_and_removing_arguments() {
+        let mut chain = chain_of(&["foo","a","c"]);
+        chain.insert_arg(1, Shifted {off:1, wrapped:Ast::var("b")});
+        assert_eq!(chain.fold().repr(), "foo a b c");
+        let removed = chain.remove_arg(0).unwrap();
+        assert_eq!(removed.wrapped.repr(), "a");
+        assert_eq!(chain.fold().repr(), "foo b c");
+        assert_eq!(chain.remove_arg(10), None);
+    }
+}
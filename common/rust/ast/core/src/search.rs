@@ -0,0 +1,194 @@
+//! Searching for nodes within a subtree, yielding both the path (crumbs) and
+//! the absolute span of each hit.
+//!
+//! "Highlight all usages" and similar features are built on top of this, so
+//! that each consumer does not reimplement the traversal.
+
+use crate::child_offsets;
+use crate::location::Index;
+use crate::location::Size;
+use crate::location::Span;
+use crate::Ast;
+use crate::Crumbs;
+use crate::HasRepr;
+use crate::HasSpan;
+use crate::Shape;
+
+
+
+// =============
+// === Found ===
+// =============
+
+/// A single search hit.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Found {
+    /// Path from the search root to the hit.
+    pub crumbs : Crumbs,
+    /// Absolute location of the hit within the search root's text.
+    pub span : Span,
+}
+
+/// Finds all nodes satisfying the predicate, in textual order.
+pub fn find_all(ast:&Ast, mut predicate:impl FnMut(&Ast)->bool) -> Vec<Found> {
+    let mut found = Vec::new();
+    visit(ast, Index::new(0), &mut Vec::new(), &mut |node| predicate(node), &mut found);
+    found
+}
+
+fn visit
+( ast       : &Ast
+, offset    : Index
+, crumbs    : &mut Crumbs
+, predicate : &mut dyn FnMut(&Ast)->bool
+, found     : &mut Vec<Found>) {
+    if predicate(ast) {
+        found.push(Found {
+            crumbs : crumbs.clone(),
+            span   : Span::new(offset, Size::new(ast.span())),
+        });
+    }
+    let offsets = child_offsets(ast);
+    for (index,(child,child_offset)) in ast.children().into_iter().zip(offsets).enumerate() {
+        crumbs.push(index);
+        visit(child, offset + Size::new(child_offset), crumbs, predicate, found);
+        crumbs.pop();
+    }
+}
+
+/// Every node of the subtree, in the deterministic position order: by
+/// absolute span start, and for nodes starting at the same position the
+/// enclosing one first. This is exactly the order `Span`'s `Ord` defines,
+/// so hit lists from several searches can be merged and brought back to it
+/// with `sort_by_key(|hit| hit.span)`.
+pub fn all_nodes(ast:&Ast) -> Vec<Found> {
+    find_all(ast, |_| true)
+}
+
+/// Checks whether the node is an identifier (variable or constructor) with
+/// given name.
+pub fn is_identifier_named(ast:&Ast, name:&str) -> bool {
+    match ast.shape() {
+        Shape::Var(var)   => var.name  == name,
+        Shape::Cons(cons) => cons.name == name,
+        _                 => false,
+    }
+}
+
+/// Finds all usages of the identifier with given name.
+pub fn find_identifier(ast:&Ast, name:&str) -> Vec<Found> {
+    find_all(ast, |node| is_identifier_named(node,name))
+}
+
+/// Like `find_identifier`, but does not descend into blocks that define the
+/// searched name themselves, as usages there refer to the shadowing
+/// definition.
+pub fn find_identifier_in_scope(ast:&Ast, name:&str) -> Vec<Found> {
+    let mut found = Vec::new();
+    let mut predicate = |node:&Ast| is_identifier_named(node,name);
+    visit_scoped(ast, Index::new(0), &mut Vec::new(), name, &mut predicate, &mut found);
+    found
+}
+
+fn visit_scoped
+( ast       : &Ast
+, offset    : Index
+, crumbs    : &mut Crumbs
+, name      : &str
+, predicate : &mut dyn FnMut(&Ast)->bool
+, found     : &mut Vec<Found>) {
+    if predicate(ast) {
+        found.push(Found {
+            crumbs : crumbs.clone(),
+            span   : Span::new(offset, Size::new(ast.span())),
+        });
+    }
+    let offsets = child_offsets(ast);
+    for (index,(child,child_offset)) in ast.children().into_iter().zip(offsets).enumerate() {
+        if block_defines(child,name) {
+            continue;
+        }
+        crumbs.push(index);
+        visit_scoped(child, offset + Size::new(child_offset), crumbs, name, predicate, found);
+        crumbs.pop();
+    }
+}
+
+/// Checks whether the node is a block with a line defining given name.
+fn block_defines(ast:&Ast, name:&str) -> bool {
+    let block = match ast.shape() {
+        Shape::Block(block) => block,
+        _                   => return false,
+    };
+    let lines = std::iter::once(Some(&block.first_line.elem))
+        .chain(block.lines.iter().map(|line| line.elem.as_ref()));
+    lines.flatten().any(|line| match line.shape() {
+        Shape::Infix(infix) if infix.opr.repr() == "=" => {
+            let mut target = &infix.larg;
+            while let Shape::Prefix(prefix) = target.shape() {
+                target = &prefix.func;
+            }
+            is_identifier_named(target,name)
+        }
+        _ => false,
+    })
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Block;
+    use crate::BlockLine;
+    use crate::BlockType;
+
+    #[test]
+    fn identifier_search_yields_crumbs_and_ranges() {
+        // `foo x + x`
+        let ast  = Ast::infix(Ast::prefix(Ast::var("foo"), Ast::var("x")), "+", Ast::var("x"));
+        let hits = find_identifier(&ast, "x");
+        assert_eq!(hits.len(), 2);
+        let repr = ast.repr();
+        for hit in &hits {
+            assert_eq!(&repr[hit.span.index.value .. hit.span.end().value], "x");
+            assert_eq!(ast.get_node(&hit.crumbs).unwrap().repr(), "x");
+        }
+        assert_eq!(hits[0].span, Span::new(Index::new(4), Size::new(1)));
+        assert_eq!(hits[1].span, Span::new(Index::new(8), Size::new(1)));
+    }
+
+    #[test]
+    fn node_listing_is_sorted_by_the_span_key() {
+        let ast   = Ast::infix(Ast::prefix(Ast::var("foo"), Ast::var("x")), "+", Ast::var("x"));
+        let nodes = all_nodes(&ast);
+        let spans:Vec<Span> = nodes.iter().map(|hit| hit.span).collect();
+        let mut sorted = spans.clone();
+        sorted.sort();
+        assert_eq!(spans, sorted);
+        // The root and its first child both start at zero; the root first.
+        assert_eq!(spans[0], Span::new(Index::new(0), Size::new(ast.span())));
+        assert_eq!(spans[1].index, Index::new(0));
+        assert!(spans[1].size < spans[0].size);
+    }
+
+    #[test]
+    fn scoped_search_skips_shadowing_blocks() {
+        // x + block where the block redefines `x`.
+        let body = Ast::from_shape(Block {
+            ty          : BlockType::Discontinuous {},
+            indent      : 4,
+            empty_lines : vec![],
+            first_line  : BlockLine {elem:Ast::infix(Ast::var("x"), "=", Ast::number("1")), off:0},
+            lines       : vec![],
+            is_orphan   : false,
+        });
+        let ast = Ast::infix(Ast::var("x"), "+", body);
+        assert_eq!(find_identifier(&ast, "x").len(), 2);
+        assert_eq!(find_identifier_in_scope(&ast, "x").len(), 1);
+    }
+}
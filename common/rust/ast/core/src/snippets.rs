@@ -0,0 +1,116 @@
+//! Template instantiation — expressions with named holes.
+//!
+//! A template is an ordinary expression in which variables named `$foo`
+//! stand for holes. Instantiation substitutes each hole with a provided
+//! node, or with a fresh `_` placeholder when none is given, rebuilding the
+//! spans on the way up. The searcher's "create node from example" flow and
+//! snippet insertion both build on this.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::Shape;
+
+use std::collections::HashMap;
+
+
+
+// ================
+// === Template ===
+// ================
+
+/// An expression with `$name` holes.
+#[derive(Clone,Debug,PartialEq,Eq,Shrinkwrap)]
+pub struct Template {
+    /// The underlying expression; holes are variables named `$foo`.
+    pub ast : Ast,
+}
+
+impl Template {
+    /// Wraps an expression as a template.
+    pub fn new(ast:Ast) -> Template {
+        Template {ast}
+    }
+
+    /// Names of the holes, in textual order, without the `$` sigils.
+    /// A hole used twice is listed twice.
+    pub fn holes(&self) -> Vec<String> {
+        self.ast.iterate_subtree().filter_map(|node| {
+            match node.shape() {
+                Shape::Var(var) => hole_name(&var.name).map(str::to_string),
+                _               => None,
+            }
+        }).collect()
+    }
+
+    /// Substitutes the holes with the given nodes; holes with no
+    /// substitution become `_` placeholders.
+    pub fn instantiate(&self, substitutions:&HashMap<String,Ast>) -> Ast {
+        instantiate_node(&self.ast, substitutions)
+    }
+}
+
+fn instantiate_node(ast:&Ast, substitutions:&HashMap<String,Ast>) -> Ast {
+    if let Shape::Var(var) = ast.shape() {
+        if let Some(name) = hole_name(&var.name) {
+            return match substitutions.get(name) {
+                Some(ast) => ast.clone(),
+                None      => Ast::blank(),
+            };
+        }
+    }
+    ast.with_shape(ast.shape().map_children(|child| instantiate_node(child, substitutions)))
+}
+
+/// The hole name, if the variable name denotes a hole.
+fn hole_name(name:&str) -> Option<&str> {
+    let name = name.strip_prefix('$')?;
+    if name.is_empty() { None } else { Some(name) }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HasRepr;
+    use crate::HasSpan;
+
+    fn template() -> Template {
+        // `$fun $arg + $arg`
+        let applied = Ast::prefix(Ast::var("$fun"), Ast::var("$arg"));
+        Template::new(Ast::infix(applied, "+", Ast::var("$arg")))
+    }
+
+    #[test]
+    fn holes_are_listed_in_textual_order() {
+        assert_eq!(template().holes(), vec!["fun","arg","arg"]);
+        assert!(Template::new(Ast::var("$")).holes().is_empty());
+    }
+
+    #[test]
+    fn instantiation_substitutes_and_defaults() {
+        let mut substitutions = HashMap::new();
+        substitutions.insert("fun".to_string(), Ast::var("sum"));
+
+        let instance = template().instantiate(&substitutions);
+        assert_eq!(instance.repr(), "sum _ + _");
+        assert_eq!(instance.span(), "sum _ + _".len());
+    }
+
+    #[test]
+    fn substituted_subtrees_are_used_verbatim() {
+        let mut substitutions = HashMap::new();
+        substitutions.insert("fun".to_string(), Ast::var("avg"));
+        substitutions.insert("arg".to_string(),
+            Ast::infix(Ast::number("1"), "*", Ast::number("2")));
+
+        let instance = template().instantiate(&substitutions);
+        assert_eq!(instance.repr(), "avg 1 * 2 + 1 * 2");
+    }
+}
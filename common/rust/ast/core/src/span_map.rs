@@ -0,0 +1,166 @@
+//! The span map: every node's absolute range, crumbs and id, built in one
+//! traversal.
+//!
+//! Highlighting, hit testing and the id map all need "which node is where"
+//! answers, and each used to re-walk the tree per query. A `SpanMap` walks
+//! it once and then answers stabbing and range queries from the collected
+//! entries; it stays valid as long as the tree it was built from is not
+//! replaced.
+
+use prelude::*;
+
+use crate::child_offsets;
+use crate::interval_tree::IntervalTree;
+use crate::Ast;
+use crate::Crumbs;
+use crate::HasSpan;
+use crate::Id;
+use crate::Index;
+use crate::Size;
+use crate::Span;
+
+
+
+// =============
+// === Entry ===
+// =============
+
+/// One node of the mapped tree.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Entry {
+    /// The node's absolute location.
+    pub span : Span,
+    /// The path from the mapped root to the node.
+    pub crumbs : Crumbs,
+    /// The node's id, if it carries one.
+    pub id : Option<Id>,
+}
+
+
+
+// ===============
+// === SpanMap ===
+// ===============
+
+/// The spans of all nodes of a tree, queryable by position.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct SpanMap {
+    /// The entries, in pre-order — sorted by the `Span` ordering.
+    entries : Vec<Entry>,
+    /// Entry indices keyed by their spans, for position queries.
+    tree : IntervalTree<usize>,
+}
+
+impl SpanMap {
+    /// Maps the tree in a single traversal.
+    pub fn new(ast:&Ast) -> SpanMap {
+        let mut entries = Vec::new();
+        collect(ast, Index::new(0), &mut Vec::new(), &mut entries);
+        // Pre-order spans have non-decreasing starts, so the bulk build
+        // applies directly.
+        let tree = IntervalTree::from_sorted(entries.iter().enumerate()
+            .map(|(ix,entry)| (entry.span.index.value..entry.span.end().value, ix)));
+        SpanMap {entries,tree}
+    }
+
+    /// All entries, in pre-order.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The entries whose spans contain the position, outermost first.
+    pub fn stab(&self, index:Index) -> Vec<&Entry> {
+        self.resolve(self.tree.stab(index.value))
+    }
+
+    /// The innermost node containing the position.
+    pub fn node_at(&self, index:Index) -> Option<&Entry> {
+        self.stab(index).pop()
+    }
+
+    /// The entries whose spans overlap the given span, outermost first.
+    pub fn overlapping(&self, span:Span) -> Vec<&Entry> {
+        self.resolve(self.tree.overlapping(span.index.value..span.end().value))
+    }
+
+    /// Turns tree hits back into entries, restoring the pre-order.
+    fn resolve(&self, mut indices:Vec<&usize>) -> Vec<&Entry> {
+        indices.sort();
+        indices.into_iter().map(|ix| &self.entries[*ix]).collect()
+    }
+
+    /// The id map: spans of the nodes carrying ids, in textual order.
+    pub fn id_map(&self) -> Vec<(Span,Id)> {
+        self.entries.iter()
+            .filter_map(|entry| entry.id.map(|id| (entry.span,id)))
+            .collect()
+    }
+}
+
+fn collect(ast:&Ast, offset:Index, crumbs:&mut Crumbs, entries:&mut Vec<Entry>) {
+    entries.push(Entry {
+        span   : Span::new(offset, Size::new(ast.span())),
+        crumbs : crumbs.clone(),
+        id     : ast.id(),
+    });
+    let offsets = child_offsets(ast);
+    for (index,(child,child_offset)) in ast.children().into_iter().zip(offsets).enumerate() {
+        crumbs.push(index);
+        collect(child, offset + Size::new(child_offset), crumbs, entries);
+        crumbs.pop();
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::HasRepr;
+
+    fn map() -> (Ast,SpanMap) {
+        // `foo x + x`
+        let ast = Ast::infix(Ast::prefix(Ast::var("foo"), Ast::var("x")), "+", Ast::var("x"));
+        let map = SpanMap::new(&ast);
+        (ast,map)
+    }
+
+    #[test]
+    fn stabbing_goes_outermost_to_innermost() {
+        let (ast,map) = map();
+        let hits      = map.stab(Index::new(4));
+        let reprs:Vec<String> = hits.iter()
+            .map(|entry| ast.get_node(&entry.crumbs).unwrap().repr())
+            .collect();
+        assert_eq!(reprs, vec!["foo x + x","foo x","x"]);
+        assert_eq!(map.node_at(Index::new(4)).unwrap().crumbs, vec![0,1]);
+        assert!(map.node_at(Index::new(100)).is_none());
+    }
+
+    #[test]
+    fn range_queries_respect_overlap() {
+        let (_ast,map) = map();
+        // `x + x` — overlaps the root, the application, and three leaves.
+        let touched = map.overlapping(Span::new(Index::new(4), Size::new(5)));
+        assert_eq!(touched.len(), 5);
+        // An empty span at a boundary touches nothing.
+        assert!(map.overlapping(Span::new(Index::new(0), Size::new(0))).is_empty());
+    }
+
+    #[test]
+    fn id_map_lists_identified_nodes_in_order() {
+        let a   = Ast::var("a").with_id(crate::Id::from_u128(1));
+        let b   = Ast::var("b").with_id(crate::Id::from_u128(2));
+        let ast = Ast::infix(a, "+", b);
+        let ids = SpanMap::new(&ast).id_map();
+        assert_eq!(ids, vec![
+            (Span::new(Index::new(0), Size::new(1)), crate::Id::from_u128(1)),
+            (Span::new(Index::new(4), Size::new(1)), crate::Id::from_u128(2)),
+        ]);
+    }
+}
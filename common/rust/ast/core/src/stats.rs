@@ -0,0 +1,90 @@
+//! Cheap structural statistics of a subtree, computed in one traversal.
+//!
+//! Used for telemetry, performance investigations, and heuristics such as
+//! deciding when the IDE should switch to lazy rendering.
+
+use crate::Ast;
+use crate::HasSpan;
+use crate::Shape;
+
+use std::collections::HashMap;
+
+
+
+// =============
+// === Stats ===
+// =============
+
+/// Structural statistics of a subtree.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct Stats {
+    /// Number of nodes per shape kind name.
+    pub node_counts : HashMap<&'static str,usize>,
+    /// Total number of nodes.
+    pub node_count : usize,
+    /// Depth of the deepest node (the root has depth 1).
+    pub max_depth : usize,
+    /// Span of the whole subtree, in characters.
+    pub total_span : usize,
+    /// Number of nodes representing parse problems.
+    pub error_count : usize,
+    /// Number of macro match nodes.
+    pub macro_match_count : usize,
+}
+
+/// Computes the statistics of the given subtree.
+pub fn stats(ast:&Ast) -> Stats {
+    let mut result = Stats {total_span:ast.span(), ..Stats::default()};
+    visit(ast, 1, &mut result);
+    result
+}
+
+fn visit(ast:&Ast, depth:usize, stats:&mut Stats) {
+    *stats.node_counts.entry(ast.shape().name()).or_insert(0) += 1;
+    stats.node_count += 1;
+    stats.max_depth   = stats.max_depth.max(depth);
+    if ast.shape().is_error() {
+        stats.error_count += 1;
+    }
+    if let Shape::Match(_) = ast.shape() {
+        stats.macro_match_count += 1;
+    }
+    for child in ast.children() {
+        visit(child, depth + 1, stats);
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InvalidSuffix;
+
+    #[test]
+    fn stats_of_a_small_expression() {
+        // `foo a + _`
+        let ast   = Ast::infix(Ast::prefix(Ast::var("foo"), Ast::var("a")), "+", Ast::blank());
+        let stats = stats(&ast);
+        assert_eq!(stats.node_counts["Var"], 2);
+        assert_eq!(stats.node_counts["Blank"], 1);
+        assert_eq!(stats.node_count, 6);
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!(stats.total_span, ast.span());
+        assert_eq!(stats.error_count, 0);
+    }
+
+    #[test]
+    fn error_nodes_are_counted() {
+        let bad = Ast::from_shape(InvalidSuffix {
+            elem   : Ast::var("foo"),
+            suffix : "'".to_string(),
+        });
+        let ast = Ast::prefix(bad, Ast::var("a"));
+        assert_eq!(stats(&ast).error_count, 1);
+    }
+}
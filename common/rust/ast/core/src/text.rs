@@ -0,0 +1,304 @@
+//! Manipulation of text literal nodes: conversion between plain Rust
+//! strings and multi-line literals, and structured access to the
+//! interpolations of formatted literals.
+//!
+//! The visualization editor sets multi-line string literals wholesale;
+//! doing that by hand is error-prone, as the block structure keeps empty
+//! lines and indentation out of band. Interpolated one-liners have the
+//! opposite problem — the interesting parts are the spliced expressions,
+//! which the splice helpers expose, replace, and convert to and from an
+//! explicit concatenation.
+
+use crate::Ast;
+use crate::Crumb;
+use crate::HasRepr;
+use crate::SegmentExpr;
+use crate::SegmentFmt;
+use crate::SegmentPlain;
+use crate::SegmentRaw;
+use crate::Shape;
+use crate::TextBlockFmt;
+use crate::TextBlockLine;
+use crate::TextBlockRaw;
+use crate::TextLineFmt;
+
+/// Splits the content into block lines, turning empty (or whitespace-only)
+/// lines into the out-of-band `empty_lines` entries.
+fn lines_of<T>(content:&str, mut segment:impl FnMut(&str)->T) -> Vec<TextBlockLine<T>> {
+    let mut lines   = Vec::new();
+    let mut pending = Vec::new();
+    for line in content.split('\n') {
+        if line.chars().all(|c| c == ' ') {
+            pending.push(line.len());
+        } else {
+            let empty_lines = std::mem::take(&mut pending);
+            lines.push(TextBlockLine {empty_lines, text:vec![segment(line)]});
+        }
+    }
+    if !pending.is_empty() {
+        // The last empty line is a real (content-less) line; the ones before
+        // it stay out of band.
+        pending.pop();
+        lines.push(TextBlockLine {empty_lines:pending, text:Vec::new()});
+    }
+    lines
+}
+
+/// Joins block lines back into the content string.
+fn content_of<T:HasRepr>(lines:&[TextBlockLine<T>]) -> String {
+    let mut parts = Vec::new();
+    for line in lines {
+        for spaces in &line.empty_lines {
+            parts.push(" ".repeat(*spaces));
+        }
+        let mut text = String::new();
+        for segment in &line.text {
+            segment.write_repr(&mut text);
+        }
+        parts.push(text);
+    }
+    parts.join("\n")
+}
+
+/// Creates a raw text block literal node with given content, to be placed at
+/// given indentation.
+pub fn raw_block(content:&str, indent:usize) -> TextBlockRaw {
+    let text = lines_of(content, |line| SegmentRaw::SegmentPlain(SegmentPlain {
+        value : line.to_string(),
+    }));
+    TextBlockRaw {text, spaces:0, offset:indent}
+}
+
+/// Creates a formatted text block literal node with given content, to be
+/// placed at given indentation. The content becomes plain segments; no
+/// interpolation is introduced.
+pub fn fmt_block(content:&str, indent:usize) -> TextBlockFmt<Ast> {
+    let text = lines_of(content, |line| SegmentFmt::SegmentPlain(SegmentPlain {
+        value : line.to_string(),
+    }));
+    TextBlockFmt {text, spaces:0, offset:indent}
+}
+
+/// The content of a raw text block literal, with lines joined by `\n`.
+pub fn raw_block_content(block:&TextBlockRaw) -> String {
+    content_of(&block.text)
+}
+
+/// The content of a formatted text block literal, with lines joined by `\n`.
+/// Interpolated segments are rendered in their source form.
+pub fn fmt_block_content(block:&TextBlockFmt<Ast>) -> String {
+    content_of(&block.text)
+}
+
+
+
+// ===============
+// === Splices ===
+// ===============
+
+/// A spliced expression within a formatted text line.
+#[derive(Clone,Copy,Debug)]
+pub struct Splice<'a> {
+    /// Index of the `SegmentExpr` within the literal's segments.
+    pub segment : usize,
+    /// The expression's crumb relative to the literal node.
+    pub crumb : Crumb,
+    /// The spliced expression.
+    pub expr : &'a Ast,
+}
+
+/// The spliced expressions of a formatted text line, in textual order.
+/// Empty for any other node (including literals with no interpolation).
+pub fn splices(ast:&Ast) -> Vec<Splice<'_>> {
+    let line = match ast.shape() {
+        Shape::TextLineFmt(line) => line,
+        _                        => return Vec::new(),
+    };
+    let mut found = Vec::new();
+    let mut crumb = 0;
+    for (segment,item) in line.text.iter().enumerate() {
+        if let SegmentFmt::SegmentExpr(SegmentExpr {value:Some(expr)}) = item {
+            found.push(Splice {segment, crumb, expr});
+            crumb += 1;
+        }
+    }
+    found
+}
+
+/// The literal with the expression of its `segment`-th segment replaced.
+/// `None` if the node is not a formatted line or the segment is not a
+/// splice. The literal's id is retained.
+pub fn set_splice(ast:&Ast, segment:usize, expr:Ast) -> Option<Ast> {
+    let line = match ast.shape() {
+        Shape::TextLineFmt(line) => line,
+        _                        => return None,
+    };
+    let mut text = line.text.clone();
+    match text.get_mut(segment)? {
+        SegmentFmt::SegmentExpr(splice) => splice.value = Some(expr),
+        _                               => return None,
+    }
+    Some(ast.with_shape(TextLineFmt {text}))
+}
+
+/// Turns a formatted text line into an explicit concatenation: the literal
+/// chunks between the splices become plain literals and everything is
+/// joined with `+`, e.g. `'a`x`b'` becomes `'a' + x + 'b'`. `None` for
+/// other nodes or literals with no splices.
+pub fn to_concatenation(ast:&Ast) -> Option<Ast> {
+    let line = match ast.shape() {
+        Shape::TextLineFmt(line) => line,
+        _                        => return None,
+    };
+    let mut operands = Vec::new();
+    let mut pending  = Vec::new();
+    for item in &line.text {
+        match item {
+            SegmentFmt::SegmentExpr(SegmentExpr {value:Some(expr)}) => {
+                if !pending.is_empty() {
+                    operands.push(Ast::from_shape(TextLineFmt {text:std::mem::take(&mut pending)}));
+                }
+                operands.push(expr.clone());
+            }
+            SegmentFmt::SegmentExpr(SegmentExpr {value:None}) => {}
+            other => pending.push(other.clone()),
+        }
+    }
+    if !pending.is_empty() {
+        operands.push(Ast::from_shape(TextLineFmt {text:pending}));
+    }
+    let mut operands = operands.into_iter();
+    let first        = operands.next()?;
+    let result       = operands.fold(first, |lhs,rhs| Ast::infix(lhs, "+", rhs));
+    // A literal with no splices folds to itself; that is not a conversion.
+    match result.shape() {
+        Shape::Infix {..} => Some(result),
+        _                 => None,
+    }
+}
+
+/// The reverse of `to_concatenation`: folds a `+` chain of formatted text
+/// literals and expressions back into a single formatted line. `None` when
+/// the node is no such chain.
+pub fn from_concatenation(ast:&Ast) -> Option<Ast> {
+    match ast.shape() {
+        Shape::Infix(infix) if infix.opr.repr() == "+" => {}
+        _ => return None,
+    }
+    let mut text = Vec::new();
+    let chain_ok = collect_segments(ast, &mut text);
+    let has_text = text.iter().any(|item| !matches!(item, SegmentFmt::SegmentExpr(_)));
+    if !chain_ok || !has_text {
+        return None;
+    }
+    Some(Ast::from_shape(TextLineFmt {text}))
+}
+
+/// Flattens the `+` chain into formatted segments, left to right.
+fn collect_segments(ast:&Ast, text:&mut Vec<SegmentFmt<Ast>>) -> bool {
+    match ast.shape() {
+        Shape::Infix(infix) if infix.opr.repr() == "+" => {
+            collect_segments(&infix.larg, text) && collect_operand(&infix.rarg, text)
+        }
+        _ => collect_operand(ast, text),
+    }
+}
+
+fn collect_operand(ast:&Ast, text:&mut Vec<SegmentFmt<Ast>>) -> bool {
+    match ast.shape() {
+        Shape::TextLineFmt(line) => text.extend(line.text.iter().cloned()),
+        Shape::TextLineRaw(line) => {
+            for segment in &line.text {
+                match segment {
+                    SegmentRaw::SegmentPlain(plain) =>
+                        text.push(SegmentFmt::SegmentPlain(plain.clone())),
+                    // Raw escapes have no formatted equivalent with the
+                    // same meaning; fall back to a splice-less reading.
+                    SegmentRaw::SegmentRawEscape(_) => return false,
+                }
+            }
+        }
+        _ => text.push(SegmentFmt::SegmentExpr(SegmentExpr {value:Some(ast.clone())})),
+    }
+    true
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_repr_keeps_indentation() {
+        let block = raw_block("first\nsecond", 4);
+        let ast   = Ast::from_shape(block);
+        assert_eq!(ast.repr(), "\"\"\"\n    first\n    second");
+    }
+
+    #[test]
+    fn empty_lines_are_kept_out_of_band() {
+        let block = fmt_block("a\n\nb", 4);
+        assert_eq!(block.text.len(), 2);
+        assert_eq!(block.text[1].empty_lines, vec![0]);
+        let ast = Ast::from_shape(block);
+        assert_eq!(ast.repr(), "'''\n    a\n\n    b");
+    }
+
+    fn interpolated() -> Ast {
+        // `'a`x`b'`
+        Ast::from_shape(TextLineFmt {text:vec![
+            SegmentFmt::SegmentPlain(SegmentPlain {value:"a".to_string()}),
+            SegmentFmt::SegmentExpr(SegmentExpr {value:Some(Ast::var("x"))}),
+            SegmentFmt::SegmentPlain(SegmentPlain {value:"b".to_string()}),
+        ]})
+    }
+
+    #[test]
+    fn splices_are_enumerable_and_replaceable() {
+        let line  = interpolated();
+        let found = splices(&line);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].segment, 1);
+        assert_eq!(found[0].expr.repr(), "x");
+        assert_eq!(line.get_node(&[found[0].crumb]).unwrap().repr(), "x");
+
+        let replaced = set_splice(&line, 1, Ast::var("name")).unwrap();
+        assert_eq!(replaced.repr(), "'a`name`b'");
+        assert!(set_splice(&line, 0, Ast::var("name")).is_none());
+        assert!(splices(&Ast::var("x")).is_empty());
+    }
+
+    #[test]
+    fn concatenation_conversion_roundtrips() {
+        let line   = interpolated();
+        let spread = to_concatenation(&line).unwrap();
+        assert_eq!(spread.repr(), "'a' + x + 'b'");
+
+        let folded = from_concatenation(&spread).unwrap();
+        assert_eq!(folded.repr(), line.repr());
+
+        // Plain literals and exprs alone do not convert.
+        let no_splice = Ast::from_shape(TextLineFmt::<Ast> {text:vec![
+            SegmentFmt::SegmentPlain(SegmentPlain {value:"a".to_string()}),
+        ]});
+        assert!(to_concatenation(&no_splice).is_none());
+        assert!(from_concatenation(&Ast::infix(Ast::var("x"), "+", Ast::var("y"))).is_none());
+    }
+
+    #[test]
+    fn content_roundtrip() {
+        for content in &["a","a\nb","a\n\nb","a\n","a\n  \nb"] {
+            let block = raw_block(content, 2);
+            assert_eq!(&raw_block_content(&block), content,
+                "roundtrip failed for {:?}", content);
+            let block = fmt_block(content, 2);
+            assert_eq!(&fmt_block_content(&block), content,
+                "roundtrip failed for {:?}", content);
+        }
+    }
+}
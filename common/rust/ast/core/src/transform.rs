@@ -0,0 +1,386 @@
+//! Experimental AST transformation passes.
+//!
+//! A transform is a pure function from a tree to a tree: children are
+//! rewritten first, then the node itself gets a chance to be replaced. The
+//! first pass is constant folding, used by preview tooltips and as the
+//! guinea pig for the transform machinery itself. Quick fixes live here
+//! too — they are transforms the user picks from a menu rather than ones
+//! applied wholesale.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::HasRepr;
+use crate::Infix;
+use crate::Number;
+use crate::Prefix;
+use crate::Shape;
+use crate::TextLineRaw;
+
+use std::collections::HashSet;
+
+
+
+// ========================
+// === Constant folding ===
+// ========================
+
+/// Folds literal-only arithmetic and string concatenation into literals.
+///
+/// Only `+`, `-` and `*` on plain (baseless, in-range, non-negative-result)
+/// integer literals and `+` on raw text literals are folded — the
+/// conservative whitelist keeps the pass a strict refactoring of what the
+/// runtime would compute. A replaced subtree keeps the id of its root, so
+/// metadata attached to the folded expression survives.
+pub fn const_fold(ast:&Ast) -> Ast {
+    let shape  = ast.shape().map_children(const_fold);
+    let folded = match &shape {
+        Shape::Infix(infix) => fold_infix(infix),
+        _                   => None,
+    };
+    match folded {
+        Some(literal) => Ast::new(literal, ast.id()),
+        None          => ast.with_shape(shape),
+    }
+}
+
+fn fold_infix(infix:&Infix<Ast>) -> Option<Shape<Ast>> {
+    let opr = match infix.opr.shape() {
+        Shape::Opr(opr) => opr.name.as_str(),
+        _               => return None,
+    };
+    match (infix.larg.shape(), infix.rarg.shape()) {
+        (Shape::Number(larg), Shape::Number(rarg)) =>
+            fold_numbers(larg, opr, rarg),
+        (Shape::TextLineRaw(larg), Shape::TextLineRaw(rarg)) if opr == "+" => {
+            let mut text = larg.text.clone();
+            text.extend(rarg.text.iter().cloned());
+            Some(Shape::TextLineRaw(TextLineRaw {text}))
+        }
+        _ => None,
+    }
+}
+
+fn fold_numbers(larg:&Number, opr:&str, rarg:&Number) -> Option<Shape<Ast>> {
+    // Radix-prefixed literals keep their spelling; folding them would have
+    // to pick a base for the result.
+    if larg.base.is_some() || rarg.base.is_some() {
+        return None;
+    }
+    let lhs:i64 = larg.int.parse().ok()?;
+    let rhs:i64 = rarg.int.parse().ok()?;
+    let result  = match opr {
+        "+" => lhs.checked_add(rhs)?,
+        "-" => lhs.checked_sub(rhs)?,
+        "*" => lhs.checked_mul(rhs)?,
+        _   => return None,
+    };
+    // A negative result has no literal spelling — it would need a section.
+    if result < 0 {
+        return None;
+    }
+    Some(Shape::Number(Number {base:None, int:result.to_string()}))
+}
+
+
+
+// ===================
+// === Quick fixes ===
+// ===================
+
+/// A candidate edit for a quick-fix menu: what to call it and the tree it
+/// produces.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct QuickFix {
+    /// The menu label.
+    pub label : String,
+    /// The node the fix replaces the broken one with.
+    pub ast : Ast,
+}
+
+/// The likely intents behind an `InvalidSuffix` node, e.g. `12abc`.
+///
+/// Two readings cover most cases: the suffix is a typo to be dropped, or a
+/// missing space hid an application. Both fixes are returned (the
+/// application one only when the suffix reads as an identifier) so the
+/// editor can offer the choice; for any other node the list is empty.
+pub fn invalid_suffix_fixes(node:&Ast) -> Vec<QuickFix> {
+    let shape = match node.shape() {
+        Shape::InvalidSuffix(shape) => shape,
+        _                           => return Vec::new(),
+    };
+    let mut fixes = vec![QuickFix {
+        label : format!("remove `{}`", shape.suffix),
+        ast   : shape.elem.clone(),
+    }];
+    if let Some(identifier) = as_identifier(&shape.suffix) {
+        fixes.push(QuickFix {
+            label : "insert a space".to_string(),
+            ast   : Ast::from_shape(Prefix {
+                func : shape.elem.clone(),
+                off  : 1,
+                arg  : identifier,
+            }),
+        });
+    }
+    fixes
+}
+
+/// The suffix as an identifier node, if it is a well-formed one.
+fn as_identifier(suffix:&str) -> Option<Ast> {
+    let mut chars = suffix.chars();
+    let first     = chars.next()?;
+    let body_ok   = chars.all(|chr| chr.is_alphanumeric() || chr == '_');
+    if first.is_lowercase() && body_ok {
+        Some(Ast::var(suffix))
+    } else if first.is_uppercase() && body_ok {
+        Some(Ast::cons(suffix))
+    } else {
+        None
+    }
+}
+
+
+
+// ============================
+// === Sections and lambdas ===
+// ============================
+
+/// Converts an operator section into the equivalent explicit lambda:
+/// `+ 1` becomes `x -> x + 1`, `1 +` becomes `x -> 1 + x`, and a bare `+`
+/// becomes `x -> y -> x + y`. A parenthesized section is unwrapped first.
+///
+/// The operand subtrees are moved, not rebuilt, so their ids survive; the
+/// lambda root takes over the section node's id. The parameter name is
+/// chosen to avoid the identifiers already used inside the operands.
+pub fn section_to_lambda(ast:&Ast) -> Option<Ast> {
+    if let Shape::Group(group) = ast.shape() {
+        return section_to_lambda(group.body.as_ref()?);
+    }
+    let used:HashSet<String> = ast.iterate_subtree()
+        .filter_map(|node| match node.shape() {
+            Shape::Var(var) => Some(var.name.clone()),
+            _               => None,
+        })
+        .collect();
+    let mut names = fresh_names(&used);
+    let shape     = match ast.shape() {
+        Shape::SectionRight(section) => {
+            let param = names.next().expect("names are unbounded");
+            lambda(&param, Infix {
+                larg : Ast::var(&param),
+                loff : 1,
+                opr  : section.opr.clone(),
+                roff : section.off,
+                rarg : section.arg.clone(),
+            })
+        }
+        Shape::SectionLeft(section) => {
+            let param = names.next().expect("names are unbounded");
+            lambda(&param, Infix {
+                larg : section.arg.clone(),
+                loff : section.off,
+                opr  : section.opr.clone(),
+                roff : 1,
+                rarg : Ast::var(&param),
+            })
+        }
+        Shape::SectionSides(section) => {
+            let left  = names.next().expect("names are unbounded");
+            let right = names.next().expect("names are unbounded");
+            let body  = Infix {
+                larg : Ast::var(&left),
+                loff : 1,
+                opr  : section.opr.clone(),
+                roff : 1,
+                rarg : Ast::var(&right),
+            };
+            lambda(&left, lambda(&right, body))
+        }
+        _ => return None,
+    };
+    Some(Ast::new(Shape::Infix(shape), ast.id()))
+}
+
+/// Converts a single-parameter lambda back into the section it denotes:
+/// `x -> x + 1` becomes `+ 1` provided `x` occurs nowhere else in the body.
+/// The untouched operand keeps its ids; the section takes the lambda's.
+pub fn lambda_to_section(ast:&Ast) -> Option<Ast> {
+    let (param,body) = match ast.shape() {
+        Shape::Infix(infix) if infix.opr.repr() == "->" => {
+            match infix.larg.shape() {
+                Shape::Var(var) => (var.name.clone(), infix.rarg.clone()),
+                _               => return None,
+            }
+        }
+        _ => return None,
+    };
+    let body = match body.shape() {
+        Shape::Infix(infix) => infix.clone(),
+        _                   => return None,
+    };
+    let is_param = |node:&Ast| matches!(node.shape(), Shape::Var(var) if var.name == param);
+    let mentions = |node:&Ast| node.iterate_subtree().any(&is_param);
+    let shape = if is_param(&body.larg) && !mentions(&body.rarg) {
+        Shape::SectionRight(crate::SectionRight {
+            opr : body.opr,
+            off : body.roff,
+            arg : body.rarg,
+        })
+    } else if is_param(&body.rarg) && !mentions(&body.larg) {
+        Shape::SectionLeft(crate::SectionLeft {
+            arg : body.larg,
+            off : body.loff,
+            opr : body.opr,
+        })
+    } else {
+        return None;
+    };
+    Some(Ast::new(shape, ast.id()))
+}
+
+/// A lambda with the given parameter name and body.
+fn lambda(param:&str, body:Infix<Ast>) -> Infix<Ast> {
+    Infix {
+        larg : Ast::var(param),
+        loff : 1,
+        opr  : Ast::opr("->"),
+        roff : 1,
+        rarg : Ast::from_shape(body),
+    }
+}
+
+/// Parameter names not clashing with the given identifiers: `x`, `y`, `z`,
+/// then `x1`, `x2`, …
+fn fresh_names(used:&HashSet<String>) -> impl Iterator<Item=String> + '_ {
+    let seeds    = ["x","y","z"].iter().map(|name| name.to_string());
+    let numbered = (1..).map(|counter| format!("x{}", counter));
+    seeds.chain(numbered).filter(move |name| !used.contains(name))
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Id;
+
+    #[test]
+    fn arithmetic_is_folded_bottom_up() {
+        let product = Ast::infix(Ast::number("2"), "*", Ast::number("3"));
+        let sum     = Ast::infix(Ast::number("1"), "+", product).with_id(Id::from_u128(3));
+
+        let folded = const_fold(&sum);
+        assert_eq!(folded.repr(), "7");
+        assert_eq!(folded.id(), Some(Id::from_u128(3)));
+    }
+
+    #[test]
+    fn non_whitelisted_operators_are_left_alone() {
+        let quotient = Ast::infix(Ast::number("6"), "/", Ast::number("2"));
+        assert_eq!(const_fold(&quotient).repr(), quotient.repr());
+
+        let negative = Ast::infix(Ast::number("1"), "-", Ast::number("2"));
+        assert_eq!(const_fold(&negative).repr(), negative.repr());
+    }
+
+    #[test]
+    fn folding_happens_inside_larger_expressions() {
+        let sum   = Ast::infix(Ast::number("1"), "+", Ast::number("2"));
+        let bound = Ast::infix(Ast::var("x"), "=", sum);
+        assert_eq!(const_fold(&bound).repr(), "x = 3");
+    }
+
+    #[test]
+    fn invalid_suffix_offers_both_readings() {
+        let broken = Ast::from_shape(crate::InvalidSuffix {
+            elem   : Ast::number("12"),
+            suffix : "abc".to_string(),
+        });
+        let fixes = invalid_suffix_fixes(&broken);
+        assert_eq!(fixes.len(), 2);
+        assert_eq!(fixes[0].ast.repr(), "12");
+        assert_eq!(fixes[1].ast.repr(), "12 abc");
+
+        // A suffix that is no identifier only offers the removal.
+        let broken = Ast::from_shape(crate::InvalidSuffix {
+            elem   : Ast::number("12"),
+            suffix : "%%".to_string(),
+        });
+        assert_eq!(invalid_suffix_fixes(&broken).len(), 1);
+        assert!(invalid_suffix_fixes(&Ast::var("fine")).is_empty());
+    }
+
+    #[test]
+    fn sections_become_lambdas_and_back() {
+        let operand = Ast::number("1").with_id(Id::from_u128(7));
+        let section = Ast::from_shape(crate::SectionRight {
+            opr : Ast::opr("+"),
+            off : 1,
+            arg : operand,
+        }).with_id(Id::from_u128(8));
+
+        let lambda = section_to_lambda(&section).unwrap();
+        assert_eq!(lambda.repr(), "x -> x + 1");
+        assert_eq!(lambda.id(), Some(Id::from_u128(8)));
+        // The operand moved; its id survived the trip.
+        assert_eq!(lambda.get_node(&[2,2]).unwrap().id(), Some(Id::from_u128(7)));
+
+        let back = lambda_to_section(&lambda).unwrap();
+        assert_eq!(back.repr(), "+ 1");
+        assert_eq!(back.id(), Some(Id::from_u128(8)));
+        assert_eq!(back.get_node(&[1]).unwrap().id(), Some(Id::from_u128(7)));
+    }
+
+    #[test]
+    fn left_sections_and_bare_operators_convert_too() {
+        let section = Ast::from_shape(crate::SectionLeft {
+            arg : Ast::number("1"),
+            off : 1,
+            opr : Ast::opr("+"),
+        });
+        assert_eq!(section_to_lambda(&section).unwrap().repr(), "x -> 1 + x");
+
+        let sides = Ast::section_sides("+");
+        assert_eq!(section_to_lambda(&sides).unwrap().repr(), "x -> y -> x + y");
+
+        // A grouped section unwraps before converting.
+        let grouped = Ast::grouped(section);
+        assert_eq!(section_to_lambda(&grouped).unwrap().repr(), "x -> 1 + x");
+    }
+
+    #[test]
+    fn parameter_names_dodge_the_operands() {
+        let section = Ast::from_shape(crate::SectionRight {
+            opr : Ast::opr("+"),
+            off : 1,
+            arg : Ast::var("x"),
+        });
+        assert_eq!(section_to_lambda(&section).unwrap().repr(), "y -> y + x");
+    }
+
+    #[test]
+    fn only_genuine_sections_roundtrip() {
+        // `x -> x + x` uses the parameter on both sides; no section says that.
+        let body = Ast::infix(Ast::var("x"), "+", Ast::var("x"));
+        let both = Ast::infix(Ast::var("x"), "->", body);
+        assert_eq!(lambda_to_section(&both), None);
+
+        assert_eq!(lambda_to_section(&Ast::var("f")), None);
+        assert_eq!(section_to_lambda(&Ast::var("f")), None);
+    }
+
+    #[test]
+    fn raw_text_concatenation_is_folded() {
+        let text = |s:&str| Ast::from_shape(TextLineRaw {
+            text : vec![crate::SegmentRaw::SegmentPlain(crate::SegmentPlain {value:s.to_string()})],
+        });
+        let joined = const_fold(&Ast::infix(text("foo"), "+", text("bar")));
+        assert_eq!(joined.repr(), "\"foobar\"");
+    }
+}
@@ -0,0 +1,173 @@
+//! Unicode-aware length measuring.
+//!
+//! `HasSpan` counts Unicode codepoints, but different consumers think in
+//! different units: the language-server protocol uses UTF-16 code units,
+//! byte offsets index the source buffer, and users perceive grapheme
+//! clusters. This module measures nodes under a chosen policy and converts
+//! positions between policies.
+
+use crate::HasTokens;
+use crate::Token;
+use crate::TokenConsumer;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+
+
+// ====================
+// === LengthPolicy ===
+// ====================
+
+/// The unit in which textual lengths and positions are measured.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub enum LengthPolicy {
+    /// Unicode codepoints — the `HasSpan` default.
+    Codepoints,
+    /// UTF-16 code units, as used by the language-server protocol.
+    Utf16,
+    /// UTF-8 bytes, as used to index the source buffer.
+    Bytes,
+    /// Grapheme clusters — what the user perceives as characters.
+    Graphemes,
+}
+
+impl LengthPolicy {
+    /// Length of a string under this policy.
+    pub fn str_len(self, text:&str) -> usize {
+        match self {
+            LengthPolicy::Codepoints => text.chars().count(),
+            LengthPolicy::Utf16      => text.chars().map(char::len_utf16).sum(),
+            LengthPolicy::Bytes      => text.len(),
+            LengthPolicy::Graphemes  => text.graphemes(true).count(),
+        }
+    }
+
+    /// Length of a single character under this policy.
+    pub fn char_len(self, c:char) -> usize {
+        match self {
+            LengthPolicy::Codepoints => 1,
+            LengthPolicy::Utf16      => c.len_utf16(),
+            LengthPolicy::Bytes      => c.len_utf8(),
+            // A lone char is always a single cluster.
+            LengthPolicy::Graphemes  => 1,
+        }
+    }
+}
+
+/// A consumer measuring the fed tokens under a given policy.
+#[derive(Debug)]
+struct PolicySpanConsumer {
+    policy : LengthPolicy,
+    span   : usize,
+}
+
+impl<'a> TokenConsumer<'a> for PolicySpanConsumer {
+    fn feed(&mut self, token:Token<'a>) {
+        self.span += match token {
+            Token::Off(off) => off,
+            Token::Chr(chr) => self.policy.char_len(chr),
+            Token::Str(str) => self.policy.str_len(str),
+            Token::Ast(ast) => span_with(ast.shape(), self.policy),
+        }
+    }
+}
+
+/// Measures the span of an entity under the given policy.
+pub fn span_with(entity:&impl HasTokens, policy:LengthPolicy) -> usize {
+    let mut consumer = PolicySpanConsumer {policy, span:0};
+    entity.feed_to(&mut consumer);
+    consumer.span
+}
+
+
+
+// =======================
+// === ConversionTable ===
+// =======================
+
+/// A table translating positions within a given text between policies.
+///
+/// Built once per module text and then queried for each position; queries
+/// are a binary search.
+#[derive(Clone,Debug)]
+pub struct ConversionTable {
+    /// Cumulative lengths at each character boundary, per policy, in the
+    /// order: codepoints, UTF-16, bytes, graphemes.
+    boundaries : Vec<[usize;4]>,
+}
+
+impl ConversionTable {
+    /// Builds a conversion table for the given text.
+    pub fn new(text:&str) -> ConversionTable {
+        let mut boundaries = vec![[0;4]];
+        let mut current    = [0;4];
+        let mut graphemes  = text.grapheme_indices(true).map(|(i,_)| i).peekable();
+        let mut grapheme_count = 0;
+        for (byte_index,c) in text.char_indices() {
+            while graphemes.peek().is_some_and(|start| *start <= byte_index) {
+                graphemes.next();
+                grapheme_count += 1;
+            }
+            current[0] += 1;
+            current[1] += c.len_utf16();
+            current[2]  = byte_index + c.len_utf8();
+            current[3]  = grapheme_count;
+            boundaries.push(current);
+        }
+        ConversionTable {boundaries}
+    }
+
+    fn policy_index(policy:LengthPolicy) -> usize {
+        match policy {
+            LengthPolicy::Codepoints => 0,
+            LengthPolicy::Utf16      => 1,
+            LengthPolicy::Bytes      => 2,
+            LengthPolicy::Graphemes  => 3,
+        }
+    }
+
+    /// Translates a position measured under `from` into the same position
+    /// measured under `to`. Returns `None` if the position does not lie on a
+    /// character boundary of the text.
+    pub fn convert(&self, position:usize, from:LengthPolicy, to:LengthPolicy) -> Option<usize> {
+        let from_index = Self::policy_index(from);
+        let to_index   = Self::policy_index(to);
+        let found      = self.boundaries.binary_search_by_key(&position, |b| b[from_index]);
+        found.ok().map(|i| self.boundaries[i][to_index])
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ast;
+    use crate::HasSpan;
+
+    #[test]
+    fn policies_disagree_on_non_ascii() {
+        let node = Ast::var("żółw🐢");
+        assert_eq!(node.span(), 5);
+        assert_eq!(span_with(node.shape(), LengthPolicy::Codepoints), 5);
+        assert_eq!(span_with(node.shape(), LengthPolicy::Utf16),      6);
+        assert_eq!(span_with(node.shape(), LengthPolicy::Bytes),      11);
+        assert_eq!(span_with(node.shape(), LengthPolicy::Graphemes),  5);
+    }
+
+    #[test]
+    fn position_conversion() {
+        let table = ConversionTable::new("aż🐢b");
+        // After `aż`: 2 codepoints, 2 UTF-16 units, 3 bytes.
+        assert_eq!(table.convert(2, LengthPolicy::Codepoints, LengthPolicy::Bytes), Some(3));
+        // After the turtle: 3 codepoints, 4 UTF-16 units, 7 bytes.
+        assert_eq!(table.convert(3, LengthPolicy::Codepoints, LengthPolicy::Utf16), Some(4));
+        assert_eq!(table.convert(7, LengthPolicy::Bytes, LengthPolicy::Codepoints), Some(3));
+        // Position inside the turtle is not a boundary in UTF-16.
+        assert_eq!(table.convert(3, LengthPolicy::Utf16, LengthPolicy::Bytes), None);
+    }
+}
@@ -0,0 +1,255 @@
+//! A structured view of vector literals, e.g. `[a, b, c]`.
+//!
+//! The parser represents a vector literal as a usage of the bracket macro —
+//! a `Match` whose segments are headed by `[` and `]`, with the elements
+//! inside as a comma chain. The list-editor widget needs more than that
+//! raw encoding: element enumeration with crumbs, insertion and removal
+//! that keep the `, ` spacing sensible, and construction from a plain
+//! `Vec` of nodes. All of that lives here, on top of the generic operator
+//! chain from the `opr` module.
+
+use prelude::*;
+
+use crate::opr;
+use crate::Ast;
+use crate::Crumbs;
+use crate::HasRepr;
+use crate::MacroMatchSegment;
+use crate::Match;
+use crate::Shape;
+use crate::Shifted;
+
+/// Name of the element separator operator.
+pub const SEPARATOR:&str = ",";
+
+/// Checks whether the node is a vector literal — a bracket macro usage,
+/// `[` segment first and `]` segment last.
+pub fn is_vector_literal(ast:&Ast) -> bool {
+    bracket_content(ast).is_some()
+}
+
+/// The content between the brackets (`None` when the literal is empty, and
+/// an outer `None` when the node is no vector literal at all).
+fn bracket_content(ast:&Ast) -> Option<Option<(usize,Ast,usize)>> {
+    let shape = match ast.shape() {
+        Shape::Match(shape) => shape,
+        _                   => return None,
+    };
+    if shape.pfx.is_some() || shape.segs.len() != 2 {
+        return None;
+    }
+    let open  = &shape.segs[0].wrapped;
+    let close = &shape.segs[1].wrapped;
+    if open.head.repr() != "[" || close.head.repr() != "]" || !close.body.is_empty() {
+        return None;
+    }
+    match open.body.as_slice() {
+        []        => Some(None),
+        [content] => Some(Some((content.off, content.wrapped.clone(), shape.segs[1].off))),
+        _         => None,
+    }
+}
+
+
+
+// ==================
+// === VectorView ===
+// ==================
+
+/// An editable view of a vector literal's elements.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct VectorView {
+    /// Space between `[` and the first element.
+    pub loff : usize,
+    /// Space between the last element and `]`.
+    pub roff : usize,
+    /// The comma chain between the brackets, `None` for `[]`.
+    content : Option<Ast>,
+}
+
+impl VectorView {
+    /// A view of the given elements, spaced as `[a, b, c]`.
+    pub fn new(elements:Vec<Ast>) -> VectorView {
+        let mut view = VectorView {loff:0, roff:0, content:None};
+        for (index,element) in elements.into_iter().enumerate() {
+            view.insert(index, element);
+        }
+        view
+    }
+
+    /// Deconstructs a vector literal node. Returns `None` for anything else.
+    pub fn from_ast(ast:&Ast) -> Option<VectorView> {
+        match bracket_content(ast)? {
+            None => Some(VectorView {loff:0, roff:0, content:None}),
+            Some((loff,content,roff)) => Some(VectorView {loff, roff, content:Some(content)}),
+        }
+    }
+
+    /// The elements, in textual order.
+    pub fn elements(&self) -> Vec<Ast> {
+        let content = match &self.content {
+            Some(content) => content,
+            None          => return Vec::new(),
+        };
+        match opr::Chain::from_ast(content, SEPARATOR) {
+            Some(chain) => chain.operands().into_iter()
+                .map(|operand| operand.cloned().unwrap_or_else(Ast::blank))
+                .collect(),
+            None => vec![content.clone()],
+        }
+    }
+
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.elements().len()
+    }
+
+    /// Checks whether the literal is `[]`.
+    pub fn is_empty(&self) -> bool {
+        self.content.is_none()
+    }
+
+    /// Inserts an element at the position, adding a separator with the
+    /// standard `, ` spacing. The spacing of untouched elements is kept.
+    pub fn insert(&mut self, index:usize, element:Ast) {
+        let content = match self.content.take() {
+            None          => element,
+            Some(content) => {
+                let mut chain = opr::Chain::from_ast(&content, SEPARATOR)
+                    .unwrap_or_else(|| single_chain(content));
+                chain.insert_operand_with_offsets(index, element, 0, 1);
+                chain.fold()
+            }
+        };
+        self.content = Some(content);
+    }
+
+    /// Removes and returns the element at the position, together with its
+    /// separator. Removing the last element leaves `[]`.
+    pub fn remove(&mut self, index:usize) -> Option<Ast> {
+        let content = self.content.take()?;
+        match opr::Chain::from_ast(&content, SEPARATOR) {
+            Some(mut chain) => {
+                let removed  = chain.remove_operand(index);
+                self.content = Some(chain.fold());
+                removed
+            }
+            None if index == 0 => Some(content),
+            None => {
+                self.content = Some(content);
+                None
+            }
+        }
+    }
+
+    /// Folds the view back into a vector literal node.
+    pub fn to_ast(&self) -> Ast {
+        let body = match &self.content {
+            None          => Vec::new(),
+            Some(content) => vec![Shifted {off:self.loff, wrapped:content.clone()}],
+        };
+        Ast::from_shape(Match {
+            pfx  : None,
+            segs : vec![
+                Shifted {off:0, wrapped:MacroMatchSegment {head:Ast::opr("["), body}},
+                Shifted {
+                    off     : self.roff,
+                    wrapped : MacroMatchSegment {head:Ast::opr("]"), body:vec![]},
+                },
+            ],
+            resolved : None,
+        })
+    }
+}
+
+/// A degenerate chain holding one operand, so insertion can extend it.
+fn single_chain(content:Ast) -> opr::Chain {
+    opr::Chain {
+        target   : Some(content),
+        elements : Vec::new(),
+        operator : SEPARATOR.to_string(),
+    }
+}
+
+/// The crumbs of each element of a vector literal node, in textual order,
+/// relative to that node. `None` for non-vector nodes.
+pub fn element_crumbs(ast:&Ast) -> Option<Vec<Crumbs>> {
+    let content = match bracket_content(ast)? {
+        Some((_,content,_)) => content,
+        None                => return Some(Vec::new()),
+    };
+    // The content is the node's second child: the `[` head comes first.
+    let mut crumbs = Vec::new();
+    collect_crumbs(&content, vec![1], &mut crumbs);
+    Some(crumbs)
+}
+
+fn collect_crumbs(node:&Ast, position:Crumbs, out:&mut Vec<Crumbs>) {
+    match node.shape() {
+        Shape::Infix(infix) if infix.opr.repr() == SEPARATOR => {
+            let mut left = position.clone();
+            left.push(0);
+            collect_crumbs(&infix.larg, left, out);
+            let mut right = position;
+            right.push(2);
+            out.push(right);
+        }
+        _ => out.push(position),
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(ints:&[&str]) -> Vec<Ast> {
+        ints.iter().map(|int| Ast::number(*int)).collect()
+    }
+
+    #[test]
+    fn construction_and_spacing() {
+        assert_eq!(VectorView::new(vec![]).to_ast().repr(), "[]");
+        assert_eq!(VectorView::new(numbers(&["1"])).to_ast().repr(), "[1]");
+        assert_eq!(VectorView::new(numbers(&["1","2","3"])).to_ast().repr(), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn roundtrip_and_enumeration() {
+        let literal = VectorView::new(numbers(&["1","2","3"])).to_ast();
+        assert!(is_vector_literal(&literal));
+        let view = VectorView::from_ast(&literal).unwrap();
+        let reprs:Vec<String> = view.elements().iter().map(|e| e.repr()).collect();
+        assert_eq!(reprs, vec!["1","2","3"]);
+
+        let crumbs = element_crumbs(&literal).unwrap();
+        assert_eq!(crumbs.len(), 3);
+        for (crumbs,expected) in crumbs.iter().zip(&reprs) {
+            assert_eq!(&literal.get_node(crumbs).unwrap().repr(), expected);
+        }
+
+        assert!(!is_vector_literal(&Ast::var("x")));
+        assert_eq!(VectorView::from_ast(&Ast::var("x")), None);
+    }
+
+    #[test]
+    fn insertion_and_removal_manage_separators() {
+        let mut view = VectorView::from_ast(
+            &VectorView::new(numbers(&["1","3"])).to_ast()).unwrap();
+        view.insert(1, Ast::number("2"));
+        assert_eq!(view.to_ast().repr(), "[1, 2, 3]");
+
+        let removed = view.remove(0).unwrap();
+        assert_eq!(removed.repr(), "1");
+        assert_eq!(view.to_ast().repr(), "[2, 3]");
+        view.remove(1).unwrap();
+        view.remove(0).unwrap();
+        assert_eq!(view.to_ast().repr(), "[]");
+        assert!(view.is_empty());
+    }
+}
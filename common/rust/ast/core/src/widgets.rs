@@ -0,0 +1,107 @@
+//! Expression kind probing for editing-widget selection.
+//!
+//! The GUI picks an editing widget per node — a slider for a number, a
+//! toggle for a boolean — and several views grew their own copies of the
+//! heuristics, which promptly drifted apart. `classify` is the one place
+//! the mapping lives; a view asks for a hint and falls back to the generic
+//! expression editor when there is none better.
+
+use prelude::*;
+
+use crate::Ast;
+use crate::Shape;
+
+
+
+// ==================
+// === WidgetHint ===
+// ==================
+
+/// The editing widget best suited to an expression.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum WidgetHint {
+    /// A draggable numeric slider.
+    Slider,
+    /// A free-form text field.
+    TextField,
+    /// A two-state toggle.
+    Toggle,
+    /// An element-by-element list editor.
+    ListEditor,
+    /// No specialized widget; the generic expression editor.
+    Generic,
+}
+
+/// The widget hint for the expression.
+pub fn classify(expr:&Ast) -> WidgetHint {
+    match expr.shape() {
+        Shape::Number {..} => WidgetHint::Slider,
+        Shape::TextLineRaw {..} | Shape::TextLineFmt {..}
+        | Shape::TextBlockRaw {..} | Shape::TextBlockFmt {..} => WidgetHint::TextField,
+        Shape::Cons(cons) if cons.name == "True" || cons.name == "False" =>
+            WidgetHint::Toggle,
+        _ if crate::vector::is_vector_literal(expr) => WidgetHint::ListEditor,
+        _ => WidgetHint::Generic,
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::MacroMatchSegment;
+    use crate::Match;
+    use crate::Shifted;
+
+    fn bracket_match(elements:Vec<Ast>) -> Ast {
+        let body = elements.into_iter()
+            .map(|elem| Shifted {off:0, wrapped:elem})
+            .collect();
+        Ast::from_shape(Match {
+            pfx  : None,
+            segs : vec![
+                Shifted {off:0, wrapped:MacroMatchSegment {head:Ast::opr("["), body}},
+                Shifted {off:0, wrapped:MacroMatchSegment {head:Ast::opr("]"), body:vec![]}},
+            ],
+            resolved : None,
+        })
+    }
+
+    #[test]
+    fn literals_map_to_their_widgets() {
+        assert_eq!(classify(&Ast::number("5")),       WidgetHint::Slider);
+        assert_eq!(classify(&Ast::cons("True")),      WidgetHint::Toggle);
+        assert_eq!(classify(&Ast::cons("False")),     WidgetHint::Toggle);
+        assert_eq!(classify(&Ast::cons("Vector")),    WidgetHint::Generic);
+        assert_eq!(classify(&Ast::var("x")),          WidgetHint::Generic);
+
+        let text = Ast::from_shape(crate::TextLineRaw {
+            text : vec![crate::SegmentRaw::SegmentPlain(crate::SegmentPlain {
+                value : "abc".to_string(),
+            })],
+        });
+        assert_eq!(classify(&text), WidgetHint::TextField);
+    }
+
+    #[test]
+    fn bracket_matches_get_the_list_editor() {
+        let vector = bracket_match(vec![Ast::number("1")]);
+        assert_eq!(classify(&vector), WidgetHint::ListEditor);
+        // A non-bracket macro usage is just an expression.
+        let other = Ast::from_shape(Match {
+            pfx      : None,
+            segs     : vec![Shifted {off:0, wrapped:MacroMatchSegment {
+                head : Ast::var("if"),
+                body : vec![Shifted {off:1, wrapped:Ast::var("c")}],
+            }}],
+            resolved : None,
+        });
+        assert_eq!(classify(&other), WidgetHint::Generic);
+    }
+}
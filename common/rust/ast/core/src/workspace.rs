@@ -0,0 +1,405 @@
+//! A workspace: several parsed modules and name resolution across them.
+//!
+//! Go-to-definition does not stop at file boundaries, so a single-module
+//! view is not enough: the workspace keeps every open module's tree under
+//! its qualified name (e.g. `Base.List`), answers where a symbol is
+//! defined — locally, through the module's imports, or by an explicitly
+//! qualified reference — and caches each module's symbol table, dropping
+//! exactly that table when the module's tree is replaced.
+
+use prelude::*;
+
+use crate::ascription;
+use crate::Ast;
+use crate::HasRepr;
+use crate::HasSpan;
+use crate::Id;
+use crate::Index;
+use crate::Shape;
+use crate::Size;
+use crate::Span;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+
+
+// ==================
+// === Definition ===
+// ==================
+
+/// A resolved symbol: where it is defined.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct Definition {
+    /// Qualified name of the defining module.
+    pub module : String,
+    /// Index of the defining line within that module.
+    pub line : usize,
+    /// Id of the defining line's node, if it carries one.
+    pub id : Option<Id>,
+}
+
+
+
+// =================
+// === Workspace ===
+// =================
+
+/// The open modules, keyed by qualified name.
+#[derive(Clone,Debug,Default)]
+pub struct Workspace {
+    modules : HashMap<String,Ast>,
+    /// Per-module symbol tables (name → defining line), computed lazily and
+    /// dropped when the module changes.
+    symbols : RefCell<HashMap<String,Rc<HashMap<String,Definition>>>>,
+}
+
+impl Workspace {
+    /// Creates an empty workspace.
+    pub fn new() -> Workspace {
+        default()
+    }
+
+    /// Adds a module or replaces its tree, invalidating its symbol table.
+    pub fn set_module(&mut self, name:impl Str, ast:Ast) {
+        let name = name.into();
+        self.symbols.borrow_mut().remove(&name);
+        self.modules.insert(name, ast);
+    }
+
+    /// Removes a module.
+    pub fn remove_module(&mut self, name:&str) {
+        self.symbols.borrow_mut().remove(name);
+        self.modules.remove(name);
+    }
+
+    /// The module's tree, if the module is open.
+    pub fn module(&self, name:&str) -> Option<&Ast> {
+        self.modules.get(name)
+    }
+
+    /// The qualified names of the modules a module imports, in source order.
+    pub fn imports(&self, module:&str) -> Vec<String> {
+        let lines = match self.modules.get(module) {
+            Some(ast) => module_lines(ast),
+            None      => return Vec::new(),
+        };
+        lines.into_iter()
+            .filter_map(|(_,line)| import_target(line))
+            .collect()
+    }
+
+    /// Resolves a name referenced from the given module.
+    ///
+    /// A qualified name (`Base.List.map`) is looked up in the named module
+    /// directly, longest known module prefix first. A plain name is looked
+    /// up in the referencing module, then in its imports in source order.
+    pub fn resolve(&self, from:&str, name:&str) -> Option<Definition> {
+        if let Some(definition) = self.resolve_qualified(name) {
+            return Some(definition);
+        }
+        if let Some(definition) = self.lookup(from, name) {
+            return Some(definition);
+        }
+        self.imports(from).iter()
+            .filter_map(|imported| self.lookup(imported, name))
+            .next()
+    }
+
+    /// Looks a plain name up in one module's symbol table.
+    pub fn lookup(&self, module:&str, name:&str) -> Option<Definition> {
+        self.symbols_of(module)?.get(name).cloned()
+    }
+
+    fn resolve_qualified(&self, name:&str) -> Option<Definition> {
+        // Longest prefix first: `A.B.c` prefers the module `A.B` over a
+        // module `A` that happens to define `B`.
+        let mut split = name.len();
+        while let Some(dot) = name[..split].rfind('.') {
+            split = dot;
+            let (module,symbol) = (&name[..dot], &name[dot+1..]);
+            if self.modules.contains_key(module) {
+                return self.lookup(module, symbol);
+            }
+        }
+        None
+    }
+
+    fn symbols_of(&self, module:&str) -> Option<Rc<HashMap<String,Definition>>> {
+        if let Some(cached) = self.symbols.borrow().get(module) {
+            return Some(cached.clone());
+        }
+        let ast   = self.modules.get(module)?;
+        let table = Rc::new(symbol_table(module, ast));
+        self.symbols.borrow_mut().insert(module.to_string(), table.clone());
+        Some(table)
+    }
+}
+
+/// The names a module's top level defines, with their defining lines.
+fn symbol_table(module:&str, ast:&Ast) -> HashMap<String,Definition> {
+    let mut table = HashMap::new();
+    for (index,line) in module_lines(ast) {
+        let name = match line.shape() {
+            Shape::Def(def) => Some(def.name.repr()),
+            _ => ascription::defined_name(line).map(|name| name.to_string()),
+        };
+        if let Some(name) = name {
+            // The first definition wins, as it does in scoping.
+            table.entry(name).or_insert_with(|| Definition {
+                module : module.to_string(),
+                line   : index,
+                id     : line.id(),
+            });
+        }
+    }
+    table
+}
+
+/// The non-empty lines of a module node, with their indices.
+fn module_lines(ast:&Ast) -> Vec<(usize,&Ast)> {
+    match ast.shape() {
+        Shape::Module(module) => module.lines.iter().enumerate()
+            .filter_map(|(index,line)| line.elem.as_ref().map(|elem| (index,elem)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The qualified name an import line brings in, e.g. `Base.List`.
+fn import_target(line:&Ast) -> Option<String> {
+    match line.shape() {
+        Shape::Import(import) => {
+            let segments:Vec<String> = import.path.iter()
+                .map(|segment| segment.wrapped.repr())
+                .collect();
+            Some(segments.join("."))
+        }
+        _ => None,
+    }
+}
+
+
+
+// ====================
+// === Import graph ===
+// ====================
+
+/// One edge of the import graph: which module imports which, and where the
+/// import line sits in the importing module's source.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ImportEdge {
+    /// The importing module.
+    pub from : String,
+    /// The imported module.
+    pub to : String,
+    /// The import line's location within the importing module.
+    pub span : Span,
+}
+
+/// A circular chain of imports.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ImportCycle {
+    /// The modules on the cycle, in import order.
+    pub modules : Vec<String>,
+    /// The import lines forming the cycle — one per module, pointing at the
+    /// next one. These spans are what the IDE underlines.
+    pub edges : Vec<ImportEdge>,
+}
+
+impl Workspace {
+    /// The import edges of one module, in source order, with spans.
+    pub fn import_edges_of(&self, module:&str) -> Vec<ImportEdge> {
+        let ast = match self.modules.get(module) {
+            Some(ast) => ast,
+            None      => return Vec::new(),
+        };
+        let lines = match ast.shape() {
+            Shape::Module(shape) => &shape.lines,
+            _                    => return Vec::new(),
+        };
+        let mut edges  = Vec::new();
+        let mut offset = 0;
+        for line in lines {
+            let mut length = 0;
+            if let Some(elem) = &line.elem {
+                length = elem.span();
+                if let Some(target) = import_target(elem) {
+                    edges.push(ImportEdge {
+                        from : module.to_string(),
+                        to   : target,
+                        span : Span::new(Index::new(offset), Size::new(length)),
+                    });
+                }
+            }
+            offset += length + line.off + 1;
+        }
+        edges
+    }
+
+    /// All import edges between the open modules, grouped by importing
+    /// module in name order. Imports of modules not open in the workspace
+    /// are omitted — they cannot take part in a cycle here.
+    pub fn import_edges(&self) -> Vec<ImportEdge> {
+        let mut names:Vec<&String> = self.modules.keys().collect();
+        names.sort();
+        names.iter()
+            .flat_map(|name| self.import_edges_of(name))
+            .filter(|edge| self.modules.contains_key(&edge.to))
+            .collect()
+    }
+
+    /// The modules ordered so that every module follows the ones it
+    /// imports, or the first import cycle found.
+    pub fn topological_order(&self) -> std::result::Result<Vec<String>,ImportCycle> {
+        let mut names:Vec<&String> = self.modules.keys().collect();
+        names.sort();
+        let mut order    = Vec::new();
+        let mut finished = HashSet::new();
+        let mut path     = Vec::new();
+        for name in names {
+            self.visit(name, &mut path, &mut finished, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn visit
+    ( &self
+    , module   : &str
+    , path     : &mut Vec<String>
+    , finished : &mut HashSet<String>
+    , order    : &mut Vec<String>
+    ) -> std::result::Result<(),ImportCycle> {
+        if finished.contains(module) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|entry| entry == module) {
+            return Err(self.cycle(&path[start..]));
+        }
+        path.push(module.to_string());
+        for edge in self.import_edges_of(module) {
+            if self.modules.contains_key(&edge.to) {
+                self.visit(&edge.to, path, finished, order)?;
+            }
+        }
+        path.pop();
+        finished.insert(module.to_string());
+        order.push(module.to_string());
+        Ok(())
+    }
+
+    /// Builds the cycle report for the given module sequence.
+    fn cycle(&self, modules:&[String]) -> ImportCycle {
+        let edges = modules.iter().enumerate()
+            .filter_map(|(index,from)| {
+                let to = &modules[(index + 1) % modules.len()];
+                self.import_edges_of(from).into_iter().find(|edge| &edge.to == to)
+            })
+            .collect();
+        ImportCycle {modules:modules.to_vec(), edges}
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(name:&str, body:Ast) -> Ast {
+        Ast::infix(Ast::var(name), "=", body)
+    }
+
+    fn workspace() -> Workspace {
+        let list = Ast::module(vec![
+            Some(definition("map",  Ast::var("a"))),
+            Some(definition("fold", Ast::var("b"))),
+        ]);
+        let main = Ast::module(vec![
+            Some(Ast::import(&["Base","List"])),
+            Some(definition("main", Ast::var("c"))),
+        ]);
+        let mut workspace = Workspace::new();
+        workspace.set_module("Base.List", list);
+        workspace.set_module("Project.Main", main);
+        workspace
+    }
+
+    #[test]
+    fn resolution_tries_local_then_imports_then_qualified() {
+        let workspace = workspace();
+        assert_eq!(workspace.imports("Project.Main"), vec!["Base.List"]);
+
+        let local = workspace.resolve("Project.Main", "main").unwrap();
+        assert_eq!((local.module.as_str(), local.line), ("Project.Main", 1));
+
+        let imported = workspace.resolve("Project.Main", "fold").unwrap();
+        assert_eq!((imported.module.as_str(), imported.line), ("Base.List", 1));
+
+        let qualified = workspace.resolve("Project.Main", "Base.List.map").unwrap();
+        assert_eq!((qualified.module.as_str(), qualified.line), ("Base.List", 0));
+
+        assert_eq!(workspace.resolve("Project.Main", "missing"), None);
+        assert_eq!(workspace.resolve("Project.Main", "Base.Other.map"), None);
+    }
+
+    #[test]
+    fn imports_come_before_importers_in_the_topological_order() {
+        let mut workspace = workspace();
+        workspace.set_module("Project.Util", Ast::module(vec![
+            Some(Ast::import(&["Base","List"])),
+            Some(Ast::import(&["Project","Main"])),
+        ]));
+        let order = workspace.topological_order().unwrap();
+        let position = |name:&str| order.iter().position(|entry| entry == name).unwrap();
+        assert!(position("Base.List")    < position("Project.Main"));
+        assert!(position("Base.List")    < position("Project.Util"));
+        assert!(position("Project.Main") < position("Project.Util"));
+    }
+
+    #[test]
+    fn circular_imports_are_reported_with_their_spans() {
+        let mut workspace = Workspace::new();
+        workspace.set_module("A", Ast::module(vec![
+            Some(definition("helper", Ast::var("h"))),
+            Some(Ast::import(&["B"])),
+        ]));
+        workspace.set_module("B", Ast::module(vec![Some(Ast::import(&["A"]))]));
+
+        let cycle = workspace.topological_order().unwrap_err();
+        assert_eq!(cycle.modules, vec!["A","B"]);
+        assert_eq!(cycle.edges.len(), 2);
+        // `A`'s import sits on its second line, after `helper = h\n`.
+        assert_eq!(cycle.edges[0].to, "B");
+        assert_eq!(cycle.edges[0].span, Span::new(Index::new(11), Size::new(8)));
+        assert_eq!(cycle.edges[1].to, "A");
+        assert_eq!(cycle.edges[1].span, Span::new(Index::new(0),  Size::new(8)));
+
+        // The edge listing skips imports of modules that are not open.
+        workspace.set_module("B", Ast::module(vec![Some(Ast::import(&["Gone"]))]));
+        assert!(workspace.topological_order().is_ok());
+        assert_eq!(workspace.import_edges().len(), 1);
+    }
+
+    #[test]
+    fn module_updates_invalidate_resolutions() {
+        let mut workspace = workspace();
+        assert_eq!(workspace.resolve("Project.Main", "map").unwrap().line, 0);
+
+        // `map` moves down a line in the replacement tree.
+        let list = Ast::module(vec![
+            Some(definition("reverse", Ast::var("r"))),
+            Some(definition("map",     Ast::var("a"))),
+        ]);
+        workspace.set_module("Base.List", list);
+        assert_eq!(workspace.resolve("Project.Main", "map").unwrap().line, 1);
+
+        workspace.remove_module("Base.List");
+        assert_eq!(workspace.resolve("Project.Main", "map"), None);
+        assert!(workspace.imports("Project.Main").contains(&"Base.List".to_string()));
+    }
+}
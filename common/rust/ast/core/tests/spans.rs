@@ -0,0 +1,85 @@
+//! Snapshot tests of span computation.
+//!
+//! Every `Ast` constructor gets one canonical example here. For each, the
+//! computed span must equal the repr's character count — the invariant the
+//! whole crate leans on — and the repr itself is compared against an
+//! embedded snapshot. A future change to spans, offsets or the repr
+//! constants then shows up as an explicit snapshot diff to review, not as
+//! a subtle off-by-one three crates away.
+
+use ast::Ast;
+use ast::HasRepr;
+use ast::HasSpan;
+
+/// The canonical example for each constructor, in the order they are
+/// declared in the crate.
+fn examples() -> Vec<(&'static str,Ast)> {
+    vec![
+        ("blank"         , Ast::blank()),
+        ("var"           , Ast::var("x")),
+        ("var-unicode"   , Ast::var("λ")),
+        ("cons"          , Ast::cons("Option")),
+        ("opr"           , Ast::opr("+")),
+        ("number"        , Ast::number("42")),
+        ("prefix"        , Ast::prefix(Ast::var("f"), Ast::var("x"))),
+        ("infix"         , Ast::infix(Ast::var("a"), "+", Ast::var("b"))),
+        ("section-left"  , Ast::section_left(Ast::var("a"), "+")),
+        ("section-right" , Ast::section_right("+", Ast::var("a"))),
+        ("section-sides" , Ast::section_sides("+")),
+        ("module"        , Ast::module(vec![Some(Ast::var("a")), None, Some(Ast::var("b"))])),
+        ("comment"       , Ast::comment(vec![" note".to_string()])),
+        ("import"        , Ast::import(&["Base","List"])),
+        ("grouped"       , Ast::grouped(Ast::var("x"))),
+    ]
+}
+
+/// The expected reprs. On an intentional change, update the affected line
+/// to what the failure message prints — that edit *is* the review trail.
+const SNAPSHOT:&[(&str,&str)] = &[
+    ("blank"         , "_"),
+    ("var"           , "x"),
+    ("var-unicode"   , "λ"),
+    ("cons"          , "Option"),
+    ("opr"           , "+"),
+    ("number"        , "42"),
+    ("prefix"        , "f x"),
+    ("infix"         , "a + b"),
+    ("section-left"  , "a+"),
+    ("section-right" , "+a"),
+    ("section-sides" , "+"),
+    ("module"        , "a\n\nb"),
+    ("comment"       , "# note"),
+    ("import"        , "import Base.List"),
+    ("grouped"       , "(x)"),
+];
+
+#[test]
+fn spans_agree_with_repr_lengths() {
+    for (name,ast) in examples() {
+        assert_eq!(ast.span(), ast.repr().chars().count(),
+            "`{}`: span disagrees with the repr {:?}", name, ast.repr());
+    }
+}
+
+#[test]
+fn reprs_match_the_snapshot() {
+    let examples = examples();
+    assert_eq!(examples.len(), SNAPSHOT.len(),
+        "the snapshot covers a different number of examples — update it");
+    for ((name,ast),(snap_name,snap_repr)) in examples.iter().zip(SNAPSHOT) {
+        assert_eq!(name, snap_name, "examples and snapshot are out of order");
+        assert_eq!(&ast.repr(), snap_repr,
+            "`{}`: repr changed; if intended, update the snapshot to {:?}",
+            name, ast.repr());
+    }
+}
+
+#[test]
+fn deep_spans_compose() {
+    // A nested tree exercises the span arithmetic across constructors.
+    let body = Ast::infix(Ast::grouped(Ast::number("1")), "*", Ast::section_sides("+"));
+    let line = Ast::infix(Ast::var("main"), "=", Ast::prefix(Ast::var("f"), body));
+    let tree = Ast::module(vec![Some(Ast::import(&["Base"])), Some(line)]);
+    assert_eq!(tree.repr(), "import Base\nmain = f (1) * +");
+    assert_eq!(tree.span(), tree.repr().chars().count());
+}
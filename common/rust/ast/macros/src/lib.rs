@@ -0,0 +1,459 @@
+//! Proc macros supporting the `ast` crate.
+//!
+//! The core of the crate is the `#[ast(flat)]` attribute. Applied to the
+//! `Shape` enum, it generates for each named-field variant a standalone
+//! struct of the same name (generic only over the type parameters the
+//! variant actually uses) and rewrites the variant to wrap it, together
+//! with the conversions between the structs, the enum and `Ast` itself. A
+//! variant may also be written nested — a single unnamed payload such as
+//! `Import(Import<T>)` — in which case the written type is used as the
+//! payload and only the conversions are generated. The per-variant structs
+//! are what most of the codebase works with — they make a single shape's
+//! fields nameable without matching on the whole enum every time.
+
+extern crate proc_macro;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse_macro_input;
+
+
+
+// ===================
+// === Entry point ===
+// ===================
+
+/// Generates the flattened per-variant structs and conversions for a shape
+/// enum. See the crate docs. The arguments form a comma-separated list:
+/// `flat` is required, `constructors` additionally emits `new` constructors
+/// for the generated structs, and `no_from` suppresses the `From` impls for
+/// the whole enum. `debug` dumps the expansion; see `dump_expansion`.
+#[proc_macro_attribute]
+pub fn ast
+( attr  : proc_macro::TokenStream
+, input : proc_macro::TokenStream
+) -> proc_macro::TokenStream {
+    let mut options = MacroOptions::default();
+    for arg in attr.to_string().split(',').map(str::trim).filter(|arg| !arg.is_empty()) {
+        match arg {
+            "flat"         => options.flat         = true,
+            "constructors" => options.constructors = true,
+            "no_from"      => options.no_from      = true,
+            "debug"        => options.debug        = true,
+            other => {
+                let msg   = format!(
+                    "unsupported ast macro argument `{}`; expected `flat`, \
+                    `constructors`, `no_from` or `debug`", other);
+                let error = syn::Error::new(proc_macro2::Span::call_site(), msg);
+                return error.to_compile_error().into();
+            }
+        }
+    }
+    if !options.flat {
+        let msg   = "the ast macro requires the `flat` argument";
+        let error = syn::Error::new(proc_macro2::Span::call_site(), msg);
+        return error.to_compile_error().into();
+    }
+    let definition = parse_macro_input!(input as syn::ItemEnum);
+    match generate(definition, options) {
+        Ok(tokens) => {
+            dump_expansion(&tokens, options);
+            tokens.into()
+        }
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Writes the expansion out for inspection — but only on request, so normal
+/// builds stay quiet.
+///
+/// The dump is enabled either per-invocation with `#[ast(..,debug)]` or
+/// globally by setting the `AST_MACROS_EXPANSION` environment variable. When
+/// the variable holds a path the expansion is appended to that file (each
+/// invocation appends, so one file collects a whole build); otherwise it
+/// goes to stderr.
+fn dump_expansion(tokens:&TokenStream, options:MacroOptions) {
+    use std::io::Write;
+    let path = std::env::var("AST_MACROS_EXPANSION").ok();
+    if !options.debug && path.is_none() {
+        return;
+    }
+    match path {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(&path);
+            if let Ok(mut file) = file {
+                let _ = writeln!(file, "{}", tokens);
+            }
+        }
+        None => eprintln!("{}", tokens),
+    }
+}
+
+/// Options of the whole macro invocation, parsed from its arguments.
+#[derive(Clone,Copy,Debug,Default)]
+struct MacroOptions {
+    /// `flat` — generate the per-variant structs and conversions.
+    flat : bool,
+    /// `constructors` — additionally generate `new` constructors for the
+    /// structs.
+    constructors : bool,
+    /// `no_from` — do not generate the `From<Variant> for Enum` impls. The
+    /// enum-wide counterpart of the per-variant `#[ast(skip_from)]`, for
+    /// enums that define their own conversions.
+    no_from : bool,
+    /// `debug` — dump the expansion of this invocation for inspection.
+    debug : bool,
+}
+
+
+
+// ==================
+// === Generation ===
+// ==================
+
+/// Per-variant flags parsed from `#[ast(...)]` attributes on the variants.
+/// They let a special-case variant opt out of a single generated impl
+/// without the whole enum giving up on generation.
+#[derive(Default)]
+struct VariantFlags {
+    /// `#[ast(skip_from)]` — no `From<Variant> for Enum` impl.
+    skip_from : bool,
+    /// `#[ast(skip_try_from)]` — no `TryFrom` downcasts and no `is_*`
+    /// predicate.
+    skip_try_from : bool,
+}
+
+fn variant_flags(variant:&mut syn::Variant) -> syn::Result<VariantFlags> {
+    let mut flags = VariantFlags::default();
+    let ast_attrs:Vec<syn::Attribute> = variant.attrs.iter()
+        .filter(|attr| attr.path.is_ident("ast"))
+        .cloned()
+        .collect();
+    variant.attrs.retain(|attr| !attr.path.is_ident("ast"));
+    for attr in ast_attrs {
+        type Args = syn::punctuated::Punctuated<syn::Ident,syn::Token![,]>;
+        let args = attr.parse_args_with(Args::parse_terminated)?;
+        for arg in args {
+            match arg.to_string().as_str() {
+                "skip_from"     => flags.skip_from     = true,
+                "skip_try_from" => flags.skip_try_from = true,
+                other => {
+                    let msg = format!(
+                        "unknown ast variant flag `{}`; expected `skip_from` or \
+                        `skip_try_from`", other);
+                    return Err(syn::Error::new(arg.span(), msg));
+                }
+            }
+        }
+    }
+    Ok(flags)
+}
+
+/// Everything the impl generation needs to know about one variant after the
+/// flattening pre-pass: its payload type (the generated struct for flat
+/// variants, the written one for nested variants) and the subset of the
+/// enum's parameters that type mentions.
+struct VariantInfo {
+    name    : syn::Ident,
+    payload : TokenStream,
+    flags   : VariantFlags,
+}
+
+fn generate(mut definition:syn::ItemEnum, options:MacroOptions) -> syn::Result<TokenStream> {
+    let enum_name = definition.ident.clone();
+    let params:Vec<syn::Ident> = definition.generics.type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+
+    // Attributes of the enum (e.g. the conditional serde derives) are copied
+    // onto every generated struct, so the structs stay (de)serializable
+    // exactly when the enum is.
+    let copied_attrs:Vec<syn::Attribute> = definition.attrs.iter()
+        .filter(|attr| !attr.path.is_ident("doc"))
+        .cloned()
+        .collect();
+
+    // The flattening pre-pass. Variants with named fields get those fields
+    // moved out into a standalone struct, and the variant itself is
+    // rewritten to wrap it. A variant already written as a single unnamed
+    // payload (the nested style, e.g. `Import(Import<T>)`) is kept as it is
+    // and only gets the conversion impls. Either way the payload is generic
+    // exactly over the parameters its fields mention, which may be any
+    // subset of the enum's — the impls below are derived from the payload
+    // type, not from the enum's parameter list.
+    let mut structs  = TokenStream::new();
+    let mut variants = Vec::new();
+    for variant in &mut definition.variants {
+        let flags        = variant_flags(variant)?;
+        let variant_name = variant.ident.clone();
+        match &variant.fields {
+            syn::Fields::Named(named) => {
+                let fields = named.named.clone();
+                let variant_docs:Vec<&syn::Attribute> = variant.attrs.iter()
+                    .filter(|attr| attr.path.is_ident("doc"))
+                    .collect();
+
+                let used:Vec<&syn::Ident> = params.iter()
+                    .filter(|param| fields.iter().any(|field| uses_param(&field.ty,param)))
+                    .collect();
+                let struct_generics = if used.is_empty() {
+                    quote! {}
+                } else {
+                    quote! { <#(#used),*> }
+                };
+
+                let field_names:Vec<&syn::Ident> =
+                    fields.iter().map(|field| field.ident.as_ref().unwrap()).collect();
+                let field_types:Vec<&syn::Type> = fields.iter().map(|field| &field.ty).collect();
+
+                structs.extend(quote! {
+                    #(#variant_docs)*
+                    #(#copied_attrs)*
+                    #[derive(Clone,Debug,PartialEq,Eq)]
+                    #[allow(missing_docs)]
+                    pub struct #variant_name #struct_generics {
+                        #(pub #field_names : #field_types),*
+                    }
+                });
+                if options.constructors {
+                    structs.extend(constructor(&variant_name,&struct_generics,&fields));
+                }
+
+                let unnamed:syn::FieldsUnnamed =
+                    syn::parse_quote! { (#variant_name #struct_generics) };
+                variant.fields = syn::Fields::Unnamed(unnamed);
+                let payload = quote! { #variant_name #struct_generics };
+                variants.push(VariantInfo {
+                    name : variant_name,
+                    payload,
+                    flags,
+                });
+            }
+            syn::Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+                let payload = &unnamed.unnamed[0].ty;
+                variants.push(VariantInfo {
+                    name    : variant_name,
+                    payload : quote! { #payload },
+                    flags,
+                });
+            }
+            _ => {
+                let msg = "#[ast(flat)] supports only variants with named fields \
+                           or a single unnamed payload";
+                return Err(syn::Error::new_spanned(&*variant, msg));
+            }
+        }
+    }
+
+    let definition = &definition;
+    let (impl_generics,ty_generics,where_clause) = definition.generics.split_for_impl();
+
+    let mut output     = TokenStream::new();
+    let mut predicates = TokenStream::new();
+
+    output.extend(quote! {
+        #[derive(Clone,Debug,PartialEq,Eq)]
+        #definition
+    });
+    output.extend(structs);
+
+    for info in &variants {
+        let variant_name = &info.name;
+        let payload      = &info.payload;
+
+        if !options.no_from && !info.flags.skip_from {
+            output.extend(quote! {
+                impl #impl_generics From<#payload>
+                for #enum_name #ty_generics #where_clause {
+                    fn from(t:#payload) -> Self {
+                        #enum_name::#variant_name(t)
+                    }
+                }
+            });
+        }
+
+        // Downcasts from the enum and from `Ast` itself, plus an `is_*`
+        // predicate, are only generated for single-parameter enums — the
+        // `Ast`-level impls instantiate that parameter with `Ast`.
+        if params.len() == 1 && !info.flags.skip_try_from {
+            let ast_ty = substitute_param(payload.clone(), &params[0], &quote! { crate::Ast });
+            let predicate_name = syn::Ident::new(
+                &format!("is_{}", to_snake_case(&variant_name.to_string())),
+                variant_name.span());
+            let predicate_doc = format!(
+                "Checks whether the node holds a `{}` shape.", variant_name);
+
+            output.extend(quote! {
+                impl<'t> ::std::convert::TryFrom<&'t crate::Ast> for &'t #ast_ty {
+                    type Error = crate::UnexpectedShape;
+                    fn try_from(ast:&'t crate::Ast)
+                    -> ::std::result::Result<Self,Self::Error> {
+                        match ast.shape() {
+                            #enum_name::#variant_name(t) => Ok(t),
+                            _ => Err(crate::UnexpectedShape),
+                        }
+                    }
+                }
+
+                impl ::std::convert::TryFrom<crate::Ast> for #ast_ty {
+                    type Error = crate::UnexpectedShape;
+                    fn try_from(ast:crate::Ast)
+                    -> ::std::result::Result<Self,Self::Error> {
+                        match ast.shape() {
+                            #enum_name::#variant_name(t) => Ok(t.clone()),
+                            _ => Err(crate::UnexpectedShape),
+                        }
+                    }
+                }
+            });
+
+            predicates.extend(quote! {
+                #[doc = #predicate_doc]
+                pub fn #predicate_name(&self) -> bool {
+                    matches!(self.shape(), #enum_name::#variant_name(_))
+                }
+            });
+        }
+    }
+
+    if !predicates.is_empty() {
+        output.extend(quote! {
+            impl crate::Ast {
+                #predicates
+            }
+        });
+    }
+
+    Ok(output)
+}
+
+
+
+/// Generates a `new` constructor for a per-variant struct.
+///
+/// Offset-typed fields (`off`, `loff`, `roff`) are not parameters — they
+/// default to a single space; `indent` defaults to zero. Every other field
+/// is taken as `impl Into<T>`, so e.g. an `Ast` child can be passed as any
+/// shape convertible into it. Code that needs a non-default offset sets the
+/// public field afterwards.
+fn constructor
+( struct_name     : &syn::Ident
+, struct_generics : &TokenStream
+, fields          : &syn::punctuated::Punctuated<syn::Field,syn::Token![,]>
+) -> TokenStream {
+    let mut parameters   = TokenStream::new();
+    let mut initializers = TokenStream::new();
+    for field in fields {
+        let name = field.ident.as_ref().unwrap();
+        let ty   = &field.ty;
+        match name.to_string().as_str() {
+            "off" | "loff" | "roff" => initializers.extend(quote! { #name : 1, }),
+            "indent"                => initializers.extend(quote! { #name : 0, }),
+            _ => {
+                parameters  .extend(quote! { #name : impl Into<#ty>, });
+                initializers.extend(quote! { #name : #name.into(), });
+            }
+        }
+    }
+    let doc = format!("Creates a `{}` with offsets defaulted to single spaces.", struct_name);
+    quote! {
+        impl #struct_generics #struct_name #struct_generics {
+            #[doc = #doc]
+            pub fn new(#parameters) -> Self {
+                #struct_name { #initializers }
+            }
+        }
+    }
+}
+
+
+
+// ===============
+// === Helpers ===
+// ===============
+
+/// Replaces every mention of the parameter in the token stream with the
+/// replacement tokens, recursing into groups — turns e.g. `Prefix<T>` into
+/// `Prefix<crate::Ast>`.
+fn substitute_param
+(stream:TokenStream, param:&syn::Ident, replacement:&TokenStream) -> TokenStream {
+    stream.into_iter().flat_map(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) if &ident == param =>
+            replacement.clone().into_iter().collect::<Vec<_>>(),
+        proc_macro2::TokenTree::Group(group) => {
+            let inner       = substitute_param(group.stream(), param, replacement);
+            let mut rebuilt = proc_macro2::Group::new(group.delimiter(), inner);
+            rebuilt.set_span(group.span());
+            vec![proc_macro2::TokenTree::Group(rebuilt)]
+        }
+        other => vec![other],
+    }).collect()
+}
+
+/// Checks whether the type mentions the given parameter anywhere in its
+/// tokens.
+fn uses_param(ty:&syn::Type, param:&syn::Ident) -> bool {
+    fn walk(stream:TokenStream, param:&syn::Ident) -> bool {
+        stream.into_iter().any(|token| match token {
+            proc_macro2::TokenTree::Ident(ident) => &ident == param,
+            proc_macro2::TokenTree::Group(group) => walk(group.stream(), param),
+            _ => false,
+        })
+    }
+    walk(quote! {#ty}, param)
+}
+
+/// Converts a camel-case name to snake case, e.g. `TextLineRaw` to
+/// `text_line_raw`.
+fn to_snake_case(name:&str) -> String {
+    let mut result = String::new();
+    for (index,chr) in name.chars().enumerate() {
+        if chr.is_uppercase() && index > 0 {
+            result.push('_');
+        }
+        result.extend(chr.to_lowercase());
+    }
+    result
+}
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_case_conversion() {
+        assert_eq!(to_snake_case("Var"),         "var");
+        assert_eq!(to_snake_case("TextLineRaw"), "text_line_raw");
+        assert_eq!(to_snake_case("SectionLeft"), "section_left");
+    }
+
+    #[test]
+    fn generic_parameter_detection() {
+        let param:syn::Ident = syn::parse_str("T").unwrap();
+        let uses = |ty:&str| uses_param(&syn::parse_str(ty).unwrap(), &param);
+        assert!( uses("T"));
+        assert!( uses("Vec<T>"));
+        assert!( uses("Vec<BlockLine<Option<T>>>"));
+        assert!(!uses("String"));
+        assert!(!uses("Vec<U>"));
+        // A type named like the parameter with a suffix must not count.
+        assert!(!uses("Token"));
+    }
+
+    #[test]
+    fn parameter_substitution() {
+        let param:syn::Ident = syn::parse_str("T").unwrap();
+        let subst = |ty:&str| {
+            let tokens:TokenStream = syn::parse_str(ty).unwrap();
+            substitute_param(tokens, &param, &quote! { crate::Ast }).to_string()
+        };
+        assert_eq!(subst("Prefix<T>"),  "Prefix < crate :: Ast >");
+        assert_eq!(subst("Var"),        "Var");
+        assert_eq!(subst("Vec<(T,U)>"), "Vec < (crate :: Ast , U) >");
+    }
+}
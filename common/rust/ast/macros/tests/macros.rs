@@ -0,0 +1,17 @@
+//! Compilation tests of the `ast` macro.
+//!
+//! The macro's regressions used to surface only as inscrutable compile
+//! errors in the `ast` crate. These cases pin down both the accepted inputs
+//! (including generic parameter filtering) and the exact errors reported for
+//! the unsupported ones.
+
+#[test]
+fn compilation_cases() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/generic-filtering.rs");
+    cases.pass("tests/ui/nested-variant.rs");
+    cases.compile_fail("tests/ui/tuple-variant.rs");
+    cases.compile_fail("tests/ui/union-input.rs");
+    cases.compile_fail("tests/ui/unknown-argument.rs");
+    cases.compile_fail("tests/ui/unknown-variant-flag.rs");
+}
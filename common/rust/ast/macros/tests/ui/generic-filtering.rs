@@ -0,0 +1,25 @@
+// The generated structs must be generic only over the parameters their
+// fields actually use, and the `constructors` option must default offsets
+// and accept `impl Into` children.
+
+use ast_macros::ast;
+
+#[ast(flat, constructors)]
+pub enum Shape<T, U> {
+    /// A variant using no parameters.
+    Leaf { name: String, off: usize },
+    /// A variant using both parameters.
+    Pair { first: T, second: U },
+    /// A variant using only the first parameter.
+    Left { only: T },
+}
+
+fn main() {
+    let leaf: Leaf = Leaf::new("x");
+    assert_eq!(leaf.off, 1);
+    let pair: Pair<i32, bool> = Pair::new(1, true);
+    let left: Left<i32> = Left::new(2);
+    let _shape: Shape<i32, bool> = Shape::Pair(pair);
+    let _shape: Shape<i32, bool> = Shape::Left(left);
+    let _ = leaf;
+}
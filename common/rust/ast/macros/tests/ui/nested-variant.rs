@@ -0,0 +1,34 @@
+// A variant may carry its payload struct explicitly — the nested style —
+// and the conversions must still infer the payload's generic subset rather
+// than assuming the enum's full parameter list.
+
+use ast_macros::ast;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Wrapper<T> {
+    pub item: T,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plain {
+    pub name: String,
+}
+
+#[ast(flat)]
+pub enum Mixed<T, U> {
+    /// A flat variant, struct generated by the macro.
+    Leaf { name: String },
+    /// A flat variant using one of the two parameters.
+    Left { only: T },
+    /// A nested variant over a subset of the parameters.
+    Wrapped(Wrapper<U>),
+    /// A nested variant over no parameters at all.
+    Bare(Plain),
+}
+
+fn main() {
+    let _leaf: Mixed<i32, bool> = Leaf { name: "x".to_string() }.into();
+    let _left: Mixed<i32, bool> = Left { only: 1 }.into();
+    let _wrapped: Mixed<i32, bool> = Wrapper { item: true }.into();
+    let _bare: Mixed<i32, bool> = Plain { name: "y".to_string() }.into();
+}
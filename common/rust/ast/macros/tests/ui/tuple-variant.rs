@@ -0,0 +1,12 @@
+// A tuple variant with several fields has neither names to flatten into a
+// struct nor a single payload to nest; the macro must say so instead of
+// emitting nonsense.
+
+use ast_macros::ast;
+
+#[ast(flat)]
+pub enum Shape<T> {
+    Bad(T, usize),
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+// The macro flattens enums; any other item is rejected up front.
+
+use ast_macros::ast;
+
+#[ast(flat)]
+pub union Shape {
+    raw: u32,
+}
+
+fn main() {}
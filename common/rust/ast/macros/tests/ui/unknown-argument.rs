@@ -0,0 +1,10 @@
+// Misspelled macro arguments must be reported, not silently ignored.
+
+use ast_macros::ast;
+
+#[ast(flatten)]
+pub enum Shape<T> {
+    Good { child: T },
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+// Unknown per-variant flags must point at the flag itself.
+
+use ast_macros::ast;
+
+#[ast(flat)]
+pub enum Shape<T> {
+    #[ast(skip_everything)]
+    Good { child: T },
+}
+
+fn main() {}
@@ -0,0 +1,144 @@
+//! The wasm facade over the `ast` crate.
+//!
+//! The JS side of the IDE holds ASTs only as opaque handles; every query it
+//! used to make meant serializing the whole tree to JSON and back. This
+//! crate keeps the tree on the Rust side of the boundary instead: JSON
+//! crosses it once on `parse`, afterwards the queries — repr, spans, the
+//! node under the cursor, the id map — cross only their (small) results.
+//!
+//! Result-returning methods hand structured answers back as JSON strings;
+//! the payloads are small and this keeps the boundary free of custom ABI
+//! types.
+
+#![warn(missing_docs)]
+
+use ast::Ast;
+use ast::HasRepr;
+use ast::HasSpan;
+use ast::Index;
+use ast::Span;
+
+use wasm_bindgen::prelude::*;
+
+
+
+// =================
+// === AstHandle ===
+// =================
+
+/// An AST kept on the Rust side of the wasm boundary.
+#[wasm_bindgen]
+#[derive(Clone,Debug)]
+pub struct AstHandle {
+    ast : Ast,
+}
+
+/// A serializable search answer: where a node is and how to address it.
+#[derive(Clone,Debug,serde::Serialize)]
+struct NodeRef {
+    crumbs : ast::Crumbs,
+    span   : Span,
+}
+
+/// An entry of the id map: the span of a node carrying an id.
+#[derive(Clone,Debug,serde::Serialize)]
+struct IdMapEntry {
+    span : Span,
+    id   : ast::Id,
+}
+
+impl AstHandle {
+    /// Deserializes an AST from its JSON form. Kept off the wasm boundary so
+    /// that native tests can exercise it without touching `JsValue`.
+    fn from_json(json:&str) -> Result<AstHandle,serde_json::Error> {
+        serde_json::from_str(json).map(|ast| AstHandle {ast})
+    }
+}
+
+#[wasm_bindgen]
+impl AstHandle {
+    /// Deserializes an AST from its JSON form, as produced by the parser
+    /// service.
+    pub fn parse(json:&str) -> Result<AstHandle,JsValue> {
+        AstHandle::from_json(json).map_err(|error| JsValue::from_str(&error.to_string()))
+    }
+
+    /// The text the tree represents.
+    pub fn repr(&self) -> String {
+        self.ast.repr()
+    }
+
+    /// Length of the text the tree represents, in characters.
+    pub fn span(&self) -> usize {
+        self.ast.span()
+    }
+
+    /// The innermost node containing the given position, as a JSON
+    /// `{crumbs,span}` object; `null` when the position is out of bounds.
+    pub fn node_at_offset(&self, offset:usize) -> Option<String> {
+        let index    = Index::new(offset);
+        let mut best = None;
+        for hit in ast::search::all_nodes(&self.ast) {
+            if hit.span.contains(index) {
+                // Hits come parents-first, so the last match is innermost.
+                best = Some(NodeRef {crumbs:hit.crumbs, span:hit.span});
+            }
+        }
+        best.map(|node| serde_json::to_string(&node).expect("node refs serialize"))
+    }
+
+    /// Spans of all nodes carrying ids, as a JSON list of `{span,id}`
+    /// objects, in textual order.
+    pub fn id_map(&self) -> String {
+        let mut entries = Vec::new();
+        for hit in ast::search::all_nodes(&self.ast) {
+            let node = self.ast.get_node(&hit.crumbs).expect("crumbs from search are valid");
+            if let Some(id) = node.id() {
+                entries.push(IdMapEntry {span:hit.span, id});
+            }
+        }
+        serde_json::to_string(&entries).expect("id map entries serialize")
+    }
+
+    /// The repr of the node under the given crumbs, or `null` for invalid
+    /// crumbs. Crumbs are passed as a JSON list of child indexes.
+    pub fn repr_at(&self, crumbs_json:&str) -> Option<String> {
+        let crumbs:ast::Crumbs = serde_json::from_str(crumbs_json).ok()?;
+        self.ast.get_node(&crumbs).map(|node| node.repr())
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle() -> AstHandle {
+        let ast  = Ast::infix(Ast::var("foo"), "+", Ast::var("bar"));
+        let json = serde_json::to_string(&ast).unwrap();
+        AstHandle::from_json(&json).unwrap()
+    }
+
+    #[test]
+    fn queries_answer_without_reserializing_the_tree() {
+        let handle = handle();
+        assert_eq!(handle.repr(), "foo + bar");
+        assert_eq!(handle.span(), "foo + bar".len());
+
+        let node:serde_json::Value =
+            serde_json::from_str(&handle.node_at_offset(7).unwrap()).unwrap();
+        assert_eq!(node["crumbs"], serde_json::json!([2]));
+        assert_eq!(handle.repr_at("[2]").unwrap(), "bar");
+        assert!(handle.node_at_offset(100).is_none());
+    }
+
+    #[test]
+    fn malformed_json_is_an_error() {
+        assert!(AstHandle::from_json("{not json").is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! Client library for the JSON-RPC-based File Manager service.
+//!
+//! The module provides a strongly typed wrapper over the JSON-RPC protocol
+//! handler. Remote calls are exposed as methods returning `Future`s and
+//! server-generated notifications are exposed as typed `Stream`s.
+
+#![warn(missing_docs)]
+
+use prelude::*;
+
+use json_rpc::api::Result;
+use json_rpc::Handler;
+use json_rpc::Transport;
+use futures::Stream;
+use futures::StreamExt;
+use serde::Serialize;
+use serde::Deserialize;
+use std::future::Future;
+use uuid::Uuid;
+
+
+
+// ============
+// === Path ===
+// ============
+
+/// Path to a file or directory, as understood by the File Manager.
+#[derive(Clone,Debug,Display,Hash,PartialEq,Eq,Serialize,Deserialize,Shrinkwrap)]
+pub struct Path(pub String);
+
+impl Path {
+    /// Creates a new path from a `String`-like value.
+    pub fn new(path:impl Str) -> Path {
+        Path(path.into())
+    }
+}
+
+
+
+// ====================
+// === Content Root ===
+// ====================
+
+/// Identifier of a content root.
+pub type ContentRootId = Uuid;
+
+/// A content root - a top-level directory visible to the File Manager.
+///
+/// All paths used in the protocol are relative to one of the content roots.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct ContentRoot {
+    /// Unique identifier of this content root.
+    pub id : ContentRootId,
+    /// Absolute path of the root on the server's filesystem.
+    pub path : Path,
+}
+
+/// Response of the `file/contentRoots` method.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct ContentRootsResponse {
+    /// All content roots currently visible to the server.
+    pub content_roots : Vec<ContentRoot>,
+}
+
+
+
+// ==================
+// === Capability ===
+// ==================
+
+/// A capability that the client may acquire from the server.
+///
+/// Currently the only supported capability is `receivesTreeUpdates`, which
+/// subscribes the client to `file/event` notifications under a given path.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct CapabilityRegistration {
+    /// Name of the capability, e.g. `receivesTreeUpdates`.
+    pub method : String,
+    /// Capability-specific registration options.
+    pub register_options : RegisterOptions,
+}
+
+/// Options passed when registering a capability.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct RegisterOptions {
+    /// Path that the capability applies to.
+    pub path : Path,
+}
+
+impl CapabilityRegistration {
+    /// Creates a `receivesTreeUpdates` capability registration for given path.
+    pub fn receives_tree_updates(path:Path) -> CapabilityRegistration {
+        let method           = "receivesTreeUpdates".to_string();
+        let register_options = RegisterOptions {path};
+        CapabilityRegistration {method,register_options}
+    }
+}
+
+
+
+// =============
+// === Event ===
+// =============
+
+/// Kind of a filesystem event.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub enum EventKind {
+    /// A new file under path was created.
+    Added,
+    /// Existing file under path was removed.
+    Removed,
+    /// File under path was modified.
+    Modified,
+}
+
+/// Notification that a filesystem event has occurred under a watched path.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(rename_all="camelCase")]
+pub struct FileEvent {
+    /// Path of the file the event applies to.
+    pub path : Path,
+    /// What happened to the file.
+    pub kind : EventKind,
+}
+
+
+
+// ====================
+// === Notification ===
+// ====================
+
+/// Notification generated by the File Manager.
+#[derive(Clone,Debug,PartialEq,Deserialize)]
+#[serde(tag="method", content="params")]
+pub enum Notification {
+    /// Filesystem event occurred under a watched path.
+    #[serde(rename="file/event")]
+    FileEvent(FileEvent),
+}
+
+
+
+// ==============
+// === Client ===
+// ==============
+
+/// File Manager client. Contains the JSON-RPC protocol handler and tracks
+/// capabilities acquired by this client, so they can be re-acquired after the
+/// connection is re-established.
+#[derive(Debug)]
+pub struct Client {
+    /// JSON-RPC protocol handler.
+    handler : Handler<Notification>,
+    /// Capabilities acquired so far. Used for re-acquisition after reconnect.
+    acquired : Rc<RefCell<Vec<CapabilityRegistration>>>,
+}
+
+impl Client {
+    /// Creates a new client that will use given transport.
+    pub fn new(transport:impl Transport + 'static) -> Client {
+        let handler  = Handler::new(transport);
+        let acquired = default();
+        Client {handler,acquired}
+    }
+
+    /// Asynchronous event stream with typed filesystem events.
+    ///
+    /// On a repeated call, the previous stream is closed.
+    pub fn file_events(&mut self) -> impl Stream<Item = FileEvent> {
+        self.handler.notifications().map(|Notification::FileEvent(event)| event)
+    }
+
+    /// Lists the content roots currently visible to the server.
+    pub fn content_roots(&mut self) -> impl Future<Output = Result<ContentRootsResponse>> {
+        self.handler.open_request(ContentRootsRequest {})
+    }
+
+    /// Acquires a capability from the server.
+    ///
+    /// On success the registration is remembered, so that it can be restored
+    /// by `reacquire_capabilities` after the connection is re-established.
+    pub fn acquire_capability
+    (&mut self, registration:CapabilityRegistration) -> impl Future<Output = Result<()>> {
+        let acquired = self.acquired.clone_ref();
+        let request  = AcquireCapabilityRequest {registration:registration.clone()};
+        let reply    = self.handler.open_request(request);
+        async move {
+            reply.await?;
+            acquired.borrow_mut().push(registration);
+            Ok(())
+        }
+    }
+
+    /// Subscribes to `file/event` notifications under given path by acquiring
+    /// the `receivesTreeUpdates` capability.
+    pub fn watch(&mut self, path:Path) -> impl Future<Output = Result<()>> {
+        self.acquire_capability(CapabilityRegistration::receives_tree_updates(path))
+    }
+
+    /// Re-acquires all previously acquired capabilities.
+    ///
+    /// Capability registrations do not survive the connection being dropped,
+    /// so this must be called whenever the transport reconnects. The client
+    /// calls this automatically when the handler reports that the connection
+    /// was re-established.
+    pub fn reacquire_capabilities(&mut self) -> impl Future<Output = Result<()>> {
+        let registrations = self.acquired.borrow().clone();
+        let requests      = registrations.into_iter().map(|registration| {
+            self.handler.open_request(AcquireCapabilityRequest {registration})
+        }).collect_vec();
+        async move {
+            for request in requests {
+                request.await?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Processes an incoming transport event. Must be called for each event
+    /// the transport produces.
+    pub fn process_event(&mut self, event:json_rpc::TransportEvent) {
+        let reconnected = matches!(event, json_rpc::TransportEvent::Opened);
+        self.handler.process_event(event);
+        if reconnected && !self.acquired.borrow().is_empty() {
+            let job = self.reacquire_capabilities();
+            self.handler.spawn(async move {
+                // Failure here means the connection broke again; the next
+                // reconnect will retry.
+                let _ = job.await;
+            });
+        }
+    }
+}
+
+
+
+// ================
+// === Requests ===
+// ================
+
+/// Request payload of the `file/contentRoots` method.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct ContentRootsRequest {}
+
+impl json_rpc::RemoteMethodCall for ContentRootsRequest {
+    const NAME:&'static str = "file/contentRoots";
+    type Returned  = ContentRootsResponse;
+    type ErrorData = serde_json::Value;
+}
+
+/// Request payload of the `capability/acquire` method.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct AcquireCapabilityRequest {
+    /// The capability being requested.
+    #[serde(flatten)]
+    pub registration : CapabilityRegistration,
+}
+
+impl json_rpc::RemoteMethodCall for AcquireCapabilityRequest {
+    const NAME:&'static str = "capability/acquire";
+    type Returned  = ();
+    type ErrorData = serde_json::Value;
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use json_rpc::test_util::transport::mock::MockTransport;
+    use serde_json::json;
+
+    #[test]
+    fn file_event_deserialization() {
+        let json  = json!({
+            "method" : "file/event",
+            "params" : {"path":"./Main.enso", "kind":"Modified"}
+        });
+        let event : Notification = serde_json::from_value(json).unwrap();
+        let expected = Notification::FileEvent(FileEvent {
+            path : Path::new("./Main.enso"),
+            kind : EventKind::Modified,
+        });
+        assert_eq!(event, expected);
+    }
+
+    #[test]
+    fn capability_registration_serialization() {
+        let registration = CapabilityRegistration::receives_tree_updates(Path::new("./src"));
+        let json         = serde_json::to_value(&registration).unwrap();
+        let expected     = json!({
+            "method"          : "receivesTreeUpdates",
+            "registerOptions" : {"path":"./src"}
+        });
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn tracks_acquired_capabilities() {
+        let transport  = MockTransport::new();
+        let mut client = Client::new(transport);
+        let _fut       = client.watch(Path::new("./src"));
+        // The capability is recorded only once the server confirms it.
+        assert!(client.acquired.borrow().is_empty());
+    }
+}
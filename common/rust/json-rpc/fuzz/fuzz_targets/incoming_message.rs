@@ -0,0 +1,19 @@
+//! Fuzzes the decoding path every network message goes through.
+//!
+//! The handler feeds untrusted peer traffic into `exceeds_depth` and
+//! `decode_incoming_message`; both must reject garbage gracefully —
+//! returning an error is fine, panicking or overflowing is a bug. Run
+//! with `cargo fuzz run incoming_message` from the crate root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data:&[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        // The depth scan runs first in production; keep that order so the
+        // fuzzer exercises the same path the handler does.
+        let _ = json_rpc::messages::exceeds_depth(text, 128);
+        let _ = json_rpc::messages::decode_incoming_message(text);
+    }
+});
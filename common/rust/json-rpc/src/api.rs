@@ -0,0 +1,95 @@
+//! The typed API that client crates use to describe remote methods.
+
+use prelude::*;
+
+use crate::messages;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fmt::Display;
+
+
+
+// ========================
+// === RemoteMethodCall ===
+// ========================
+
+/// A description of a remote method: its name on the wire, the type of its
+/// parameters (the implementing type itself) and the type of the returned
+/// value.
+pub trait RemoteMethodCall : Serialize + Debug {
+    /// Name of the remote method.
+    const NAME:&'static str;
+    /// A type of value returned from successful remote call.
+    type Returned : DeserializeOwned;
+    /// The protocol-specific payload of a failed call's `Error.data` field,
+    /// decodable through `RpcError::error_data`. Protocols that define no
+    /// structured error details use `serde_json::Value`.
+    type ErrorData : DeserializeOwned;
+}
+
+/// Converts a typed call into an untyped method-call message payload.
+pub fn into_method_call<Call:RemoteMethodCall>
+(call:Call) -> messages::MethodCall<serde_json::Value> {
+    let method = Call::NAME.to_string();
+    let params = serde_json::to_value(call).expect("serialization of a request cannot fail");
+    messages::MethodCall {method,params}
+}
+
+
+
+// ================
+// === RpcError ===
+// ================
+
+/// Errors that may happen when making a remote call.
+#[derive(Clone,Debug,PartialEq)]
+pub enum RpcError {
+    /// The peer has replied with an error message.
+    RemoteError(messages::Error),
+    /// The peer's reply could not be decoded into the expected type.
+    DeserializationFailed(String),
+    /// The connection was closed before the reply arrived.
+    LostConnection,
+    /// The call was aborted, because the handler was closed on our side.
+    ConnectionClosed,
+    /// The call did not complete within the configured time limit.
+    TimeoutError,
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RpcError::RemoteError(e) =>
+                write!(f, "peer has replied with an error: {} ({})", e.message, e.code),
+            RpcError::DeserializationFailed(e) =>
+                write!(f, "failed to deserialize peer's reply: {}", e),
+            RpcError::LostConnection =>
+                write!(f, "connection was lost before the reply arrived"),
+            RpcError::ConnectionClosed =>
+                write!(f, "handler was closed before the reply arrived"),
+            RpcError::TimeoutError =>
+                write!(f, "call did not complete within the time limit"),
+        }
+    }
+}
+
+impl RpcError {
+    /// For a remote error, its `data` payload decoded as the call's
+    /// declared `ErrorData` type. `None` when this is not a remote error,
+    /// the error carries no data, or the data does not decode.
+    pub fn error_data<Call:RemoteMethodCall>(&self) -> Option<Call::ErrorData> {
+        match self {
+            RpcError::RemoteError(error) => {
+                let data = error.data.clone()?;
+                serde_json::from_value(data).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// A result of a remote call.
+pub type Result<T> = std::result::Result<T,RpcError>;
@@ -0,0 +1,222 @@
+//! Coalescing of high-frequency notifications.
+//!
+//! Some notification streams update the same logical entity over and over —
+//! visualization data for an expression, progress of a long job. When such
+//! updates arrive faster than the owner can usefully render them, only the
+//! latest one per entity matters. A coalescing rule picks a key out of a
+//! notification's params (e.g. the expression id) and withholds delivery for
+//! a debounce window; newer notifications with the same key overwrite the
+//! withheld one, so when the window elapses only the last value is delivered.
+//!
+//! Rules are configured per subscription, i.e. per notification method name;
+//! methods without a rule are delivered immediately and unchanged.
+
+use prelude::*;
+
+use std::time::Duration;
+use std::time::Instant;
+
+
+
+// ============
+// === Rule ===
+// ============
+
+/// The coalescing rule of a single notification method.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Rule {
+    /// JSON pointer into the notification's params picking the coalescing
+    /// key, e.g. `/expressionId`. Notifications where the pointer matches
+    /// nothing are delivered immediately, as if no rule was set.
+    pub key : String,
+    /// How long the delivery of a keyed notification may be withheld while
+    /// waiting for a newer one.
+    pub window : Duration,
+}
+
+/// A set of per-method coalescing rules.
+#[derive(Clone,Debug,Default)]
+pub struct Rules {
+    by_method : HashMap<String,Rule>,
+}
+
+impl Rules {
+    /// Creates an empty rule set — nothing is coalesced.
+    pub fn new() -> Rules {
+        default()
+    }
+
+    /// Sets the rule for a notification method.
+    pub fn set(&mut self, method:impl Str, rule:Rule) -> &mut Rules {
+        self.by_method.insert(method.into(),rule);
+        self
+    }
+
+    /// The rule for a given method, if any was set.
+    pub fn get(&self, method:&str) -> Option<&Rule> {
+        self.by_method.get(method)
+    }
+}
+
+
+
+// =================
+// === Coalescer ===
+// =================
+
+/// A withheld notification awaiting the end of its debounce window.
+#[derive(Debug)]
+struct Entry {
+    /// The latest notification received for the key.
+    value : serde_json::Value,
+    /// When the debounce window elapses and the value is due for delivery.
+    due : Instant,
+    /// Arrival order tie-breaker for entries due at the same time.
+    seq : u64,
+}
+
+/// The coalescing stage itself. Like the heartbeat machinery, it has no
+/// clock of its own: the caller passes the current time into `absorb` and
+/// periodically drains `flush_due`.
+#[derive(Debug,Default)]
+pub struct Coalescer {
+    rules    : Rules,
+    pending  : HashMap<(String,String),Entry>,
+    next_seq : u64,
+}
+
+impl Coalescer {
+    /// Creates a coalescer with the given rules.
+    pub fn new(rules:Rules) -> Coalescer {
+        Coalescer {rules, pending:default(), next_seq:0}
+    }
+
+    /// Feeds a notification into the stage. Returns the notification back
+    /// when it should be delivered immediately (no rule for the method, or
+    /// the key is absent from the params); otherwise withholds it, replacing
+    /// any previously withheld notification with the same key.
+    ///
+    /// The debounce window is measured from the first withheld notification,
+    /// not the latest one, so a steady stream of updates still delivers one
+    /// value per window instead of being starved forever.
+    pub fn absorb
+    (&mut self, method:&str, notification:serde_json::Value, now:Instant)
+    -> Option<serde_json::Value> {
+        let rule = match self.rules.get(method) {
+            Some(rule) => rule,
+            None       => return Some(notification),
+        };
+        let params = notification.get("params").unwrap_or(&serde_json::Value::Null);
+        let key = match params.pointer(&rule.key) {
+            Some(key) => key.to_string(),
+            None      => return Some(notification),
+        };
+        let due   = now + rule.window;
+        let seq   = self.next_seq;
+        self.next_seq += 1;
+        self.pending.entry((method.to_string(),key))
+            .and_modify(|entry| entry.value = notification.clone())
+            .or_insert(Entry {value:notification,due,seq});
+        None
+    }
+
+    /// Takes the withheld notifications whose debounce window has elapsed,
+    /// in the order they became due.
+    pub fn flush_due(&mut self, now:Instant) -> Vec<serde_json::Value> {
+        let due_keys = self.pending.iter()
+            .filter(|(_,entry)| entry.due <= now)
+            .map(|(key,_)| key.clone())
+            .collect_vec();
+        let mut ready = due_keys.into_iter()
+            .filter_map(|key| self.pending.remove(&key))
+            .collect_vec();
+        ready.sort_by_key(|entry| (entry.due,entry.seq));
+        ready.into_iter().map(|entry| entry.value).collect()
+    }
+
+    /// Takes all the withheld notifications, regardless of their windows.
+    /// Used when the connection goes down, so that no update is lost.
+    pub fn flush_all(&mut self) -> Vec<serde_json::Value> {
+        let far_future = Instant::now() + Duration::from_secs(60 * 60);
+        self.flush_due(far_future)
+    }
+
+    /// Number of notifications currently withheld.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn update(id:&str, value:i64) -> serde_json::Value {
+        json!({"method":"visualization/update", "params":{"expressionId":id, "value":value}})
+    }
+
+    fn coalescer() -> Coalescer {
+        let mut rules = Rules::new();
+        rules.set("visualization/update", Rule {
+            key    : "/expressionId".to_string(),
+            window : Duration::from_millis(100),
+        });
+        Coalescer::new(rules)
+    }
+
+    #[test]
+    fn unmatched_notifications_pass_through() {
+        let mut coalescer = coalescer();
+        let now           = Instant::now();
+        let other         = json!({"method":"file/event", "params":{"path":"a"}});
+        assert_eq!(coalescer.absorb("file/event", other.clone(), now), Some(other));
+        // A matching method but no key in the params — also immediate.
+        let keyless = json!({"method":"visualization/update", "params":{}});
+        assert_eq!(coalescer.absorb("visualization/update", keyless.clone(), now),
+                   Some(keyless));
+        assert_eq!(coalescer.pending_count(), 0);
+    }
+
+    #[test]
+    fn bursts_deliver_only_the_last_value_per_key() {
+        let mut coalescer = coalescer();
+        let now           = Instant::now();
+        assert!(coalescer.absorb("visualization/update", update("a",1), now).is_none());
+        assert!(coalescer.absorb("visualization/update", update("a",2), now).is_none());
+        assert!(coalescer.absorb("visualization/update", update("b",3), now).is_none());
+        assert_eq!(coalescer.pending_count(), 2);
+
+        // Nothing is due before the window elapses.
+        assert!(coalescer.flush_due(now).is_empty());
+        let ready = coalescer.flush_due(now + Duration::from_millis(100));
+        assert_eq!(ready, vec![update("a",2), update("b",3)]);
+        assert_eq!(coalescer.pending_count(), 0);
+    }
+
+    #[test]
+    fn window_is_measured_from_the_first_withheld_update() {
+        let mut coalescer = coalescer();
+        let now           = Instant::now();
+        coalescer.absorb("visualization/update", update("a",1), now);
+        // A newer update near the end of the window must not postpone it.
+        coalescer.absorb("visualization/update", update("a",2),
+                         now + Duration::from_millis(90));
+        let ready = coalescer.flush_due(now + Duration::from_millis(100));
+        assert_eq!(ready, vec![update("a",2)]);
+    }
+
+    #[test]
+    fn flush_all_drains_everything() {
+        let mut coalescer = coalescer();
+        coalescer.absorb("visualization/update", update("a",1), Instant::now());
+        assert_eq!(coalescer.flush_all(), vec![update("a",1)]);
+        assert_eq!(coalescer.pending_count(), 0);
+    }
+}
@@ -0,0 +1,69 @@
+//! Errors raised while handling incoming messages. These are not errors of
+//! any particular remote call — those are covered by `api::RpcError` — but
+//! problems with the incoming traffic itself.
+
+use prelude::*;
+
+use crate::messages::ReplyMessage;
+
+
+
+// =====================
+// === HandlingError ===
+// =====================
+
+/// An error occurred while handling an incoming message.
+#[derive(Debug)]
+pub enum HandlingError {
+    /// The incoming text could not be decoded as a JSON-RPC message.
+    InvalidMessage {
+        /// The offending text.
+        message : String,
+        /// The decoding error.
+        error : serde_json::Error,
+    },
+    /// A well-formed notification arrived but could not be decoded into the
+    /// client's typed notification.
+    InvalidNotification {
+        /// The notification payload.
+        notification : serde_json::Value,
+        /// The decoding error.
+        error : serde_json::Error,
+    },
+    /// A response arrived with an id that matches no pending request.
+    UnexpectedResponse {
+        /// The whole response.
+        response : ReplyMessage,
+    },
+    /// A second response arrived for a request that was already answered.
+    DuplicateResponse {
+        /// The whole response.
+        response : ReplyMessage,
+    },
+    /// A notification arrived with a method name reserved by the JSON-RPC
+    /// spec (the `rpc.` prefix).
+    ReservedNotification {
+        /// The reserved method name.
+        method : String,
+    },
+    /// A message arrived with a missing or JSON-RPC 1.0 `jsonrpc` field and
+    /// the handler is configured to reject legacy peers.
+    UnsupportedVersion {
+        /// The offending text.
+        message : String,
+    },
+    /// An incoming frame exceeded the configured size limit and was dropped
+    /// without being parsed.
+    MessageTooLarge {
+        /// Size of the offending frame in bytes.
+        size : usize,
+        /// The configured limit in bytes.
+        limit : usize,
+    },
+    /// An incoming message nested deeper than the configured limit and was
+    /// dropped without being fully parsed.
+    MessageTooDeep {
+        /// The configured nesting limit.
+        limit : usize,
+    },
+}
@@ -0,0 +1,1459 @@
+//! The JSON-RPC protocol handler. It owns the transport, matches responses
+//! with previously made requests and dispatches notifications to the client.
+
+use prelude::*;
+
+use crate::api;
+use crate::api::RemoteMethodCall;
+use crate::coalesce;
+use crate::api::Result;
+use crate::api::RpcError;
+use crate::error::HandlingError;
+use crate::messages;
+use crate::messages::Id;
+use crate::messages::IncomingMessage;
+use crate::messages::Message;
+use crate::messages::ReplyMessage;
+use crate::messages::Request;
+use crate::transport::Transport;
+use crate::transport::TransportEvent;
+
+use crate::messages::MethodCall;
+
+use futures::channel::oneshot;
+use futures::Stream;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use std::time::Duration;
+use std::time::Instant;
+
+
+
+// ===================
+// === IdGenerator ===
+// ===================
+
+/// Simple counter-based generator of unique request ids.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct IdGenerator {
+    counter : i64,
+}
+
+impl IdGenerator {
+    /// Obtains an id not generated before by this generator.
+    pub fn generate(&mut self) -> Id {
+        let id = self.counter;
+        self.counter += 1;
+        Id(id)
+    }
+}
+
+
+
+// ==============
+// === Limits ===
+// ==============
+
+/// Limits applied to the incoming traffic before it reaches the deserializer.
+/// They protect the process against unbounded allocation caused by a
+/// misbehaving peer.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Limits {
+    /// Maximum size of a single incoming frame, in bytes.
+    pub max_message_bytes : usize,
+    /// Maximum nesting depth of objects and arrays in an incoming message.
+    pub max_json_depth : usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_message_bytes : 16 * 1024 * 1024,
+            max_json_depth    : 128,
+        }
+    }
+}
+
+
+
+// ==================
+// === Strictness ===
+// ==================
+
+/// How the handler treats protocol violations that do not break any of our
+/// calls: a duplicate or unknown-id response, or a notification with a
+/// reserved method name.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Strictness {
+    /// Violations are surfaced as `Event::Error` to the handler's owner.
+    Strict,
+    /// Violations are silently dropped.
+    Lenient,
+}
+
+
+
+// =====================
+// === VersionPolicy ===
+// =====================
+
+/// How the handler treats messages whose `jsonrpc` field is missing or
+/// declares the legacy 1.0 protocol.
+///
+/// Some embedded servers predate JSON-RPC 2.0 and omit the field entirely;
+/// the lenient mode keeps the handler interoperable with them.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum VersionPolicy {
+    /// Only spec-compliant 2.0 messages are accepted. Others are answered
+    /// with an "Invalid Request" error response and surfaced as
+    /// `HandlingError::UnsupportedVersion`.
+    Strict,
+    /// Messages with a missing or 1.0 `jsonrpc` field are processed as if
+    /// they were spec-compliant.
+    Lenient,
+}
+
+
+
+// ===================
+// === Interceptor ===
+// ===================
+
+/// A middleware hook on the handler's traffic.
+///
+/// Interceptors can inspect and modify outgoing requests and incoming
+/// responses — inject an auth token, stamp tracing ids, rewrite payloads in
+/// tests — without forking the handler for every cross-cutting concern.
+/// They form an ordered chain: each message is passed through all of them
+/// in registration order.
+pub trait Interceptor : Debug {
+    /// Called with every outgoing message (request or notification) after
+    /// serialization to a JSON value, before it is sent.
+    fn on_request(&mut self, _request:&mut serde_json::Value) {}
+
+    /// Called with every incoming message that passed the traffic limits,
+    /// before it is decoded.
+    fn on_response(&mut self, _response:&mut serde_json::Value) {}
+}
+
+
+
+// ===============
+// === Tracing ===
+// ===============
+
+/// The tracing context of one outgoing request.
+///
+/// The context is injected into a reserved `_trace` field of the request's
+/// params, so a backend that participates in distributed tracing can pick
+/// it up; a backend that does not simply ignores the extra field. On our
+/// side the handler remembers which request id carries which context and,
+/// when the response arrives, reports the completed span to the trace
+/// sink — re-associated by id, so no echo from the backend is required.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct TraceContext {
+    /// The id of the whole end-to-end trace this request belongs to.
+    pub trace_id : String,
+    /// The id of the span that issued the request.
+    pub span_id : String,
+}
+
+/// One completed request span, as reported to the trace sink.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct TraceRecord {
+    /// The context the request was issued under.
+    pub context : TraceContext,
+    /// The remote method's name.
+    pub method : String,
+    /// Time from sending the request to decoding its response.
+    pub latency : Duration,
+    /// Whether the response was an error.
+    pub failed : bool,
+}
+
+/// A destination for completed request spans.
+pub trait TraceSink : Debug {
+    /// Accepts one completed span.
+    fn record(&self, record:TraceRecord);
+}
+
+/// A sink keeping the spans in memory, for tests and in-IDE inspection.
+#[derive(Debug,Default)]
+pub struct TraceLog {
+    records : RefCell<Vec<TraceRecord>>,
+}
+
+impl TraceLog {
+    /// The spans recorded so far, in completion order.
+    pub fn records(&self) -> Vec<TraceRecord> {
+        self.records.borrow().clone()
+    }
+}
+
+impl TraceSink for TraceLog {
+    fn record(&self, record:TraceRecord) {
+        self.records.borrow_mut().push(record);
+    }
+}
+
+
+
+// =============
+// === Event ===
+// =============
+
+/// An event notifying the handler's owner about something that is not a
+/// response to any request.
+#[derive(Debug)]
+pub enum Event<N> {
+    /// A typed notification from the peer.
+    Notification(N),
+    /// An error that did not break the connection but could not be attributed
+    /// to any request, e.g. a malformed message.
+    Error(HandlingError),
+    /// The peer stopped answering heartbeat pings. The connection itself is
+    /// still nominally open — see `Handler::enable_heartbeat`.
+    ConnectionUnhealthy,
+    /// The connection has been closed.
+    Closed,
+}
+
+
+
+// =================
+// === Heartbeat ===
+// =================
+
+/// Configuration of protocol-level heartbeats.
+///
+/// A half-open WebSocket — the peer's machine went to sleep, a NAT entry
+/// expired — produces no transport event at all and looks exactly like an
+/// infinitely slow backend. Heartbeats make that state observable: the
+/// handler periodically sends a ping request and, after `max_missed`
+/// consecutive pings time out, emits `Event::ConnectionUnhealthy`.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct HeartbeatConfig {
+    /// The method name of the ping request. Protocol-dependent; the engine
+    /// services answer `heartbeat/ping`.
+    pub method : String,
+    /// How often a ping is sent, measured from the previous ping.
+    pub interval : Duration,
+    /// How long to wait for the pong before counting the ping as missed.
+    pub timeout : Duration,
+    /// How many consecutive pings must time out before the connection is
+    /// declared unhealthy.
+    pub max_missed : usize,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> HeartbeatConfig {
+        HeartbeatConfig {
+            method     : "heartbeat/ping".to_string(),
+            interval   : Duration::from_secs(10),
+            timeout    : Duration::from_secs(5),
+            max_missed : 3,
+        }
+    }
+}
+
+/// The heartbeat bookkeeping. See `HeartbeatConfig`.
+#[derive(Debug)]
+struct Heartbeat {
+    config : HeartbeatConfig,
+    /// The id of the ping awaiting its pong, and when it was sent.
+    outstanding : Option<(Id,Instant)>,
+    /// Ids of pings that timed out; a pong may still arrive for them.
+    timed_out : HashSet<Id>,
+    /// When the last ping was sent; `None` before the first one.
+    last_ping : Option<Instant>,
+    /// Consecutive pings that timed out.
+    missed : usize,
+    unhealthy : bool,
+}
+
+impl Heartbeat {
+    fn new(config:HeartbeatConfig) -> Heartbeat {
+        Heartbeat {
+            config,
+            outstanding : None,
+            timed_out   : default(),
+            last_ping   : None,
+            missed      : 0,
+            unhealthy   : false,
+        }
+    }
+
+    /// Notes a pong (even a late one — it still proves the peer is alive).
+    /// Returns `true` if the given id was one of our pings.
+    fn note_pong(&mut self, id:Id) -> bool {
+        let outstanding = self.outstanding.map(|(ping,_)| ping) == Some(id);
+        if outstanding {
+            self.outstanding = None;
+        }
+        if outstanding || self.timed_out.remove(&id) {
+            self.missed    = 0;
+            self.unhealthy = false;
+            return true;
+        }
+        false
+    }
+}
+
+
+
+// ====================
+// === Backpressure ===
+// ====================
+
+/// What happens to an incoming event when the owner does not keep up with
+/// the stream and the buffer is at capacity. Prevents a flood of
+/// notifications (e.g. `expressionValuesComputed` updates) from growing the
+/// buffer without bound.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum BackpressurePolicy {
+    /// The buffer grows as needed. The default.
+    Unbounded,
+    /// The oldest buffered event is dropped to make room for the new one.
+    DropOldest {
+        /// Maximum number of buffered events.
+        capacity : usize,
+    },
+    /// The new event is dropped.
+    DropNewest {
+        /// Maximum number of buffered events.
+        capacity : usize,
+    },
+    /// The event is still buffered, but `Handler::is_backpressured` turns
+    /// `true` until the owner drains the buffer below the capacity. The
+    /// transport pump should stop feeding `process_event` meanwhile —
+    /// actual blocking is not possible on a single-threaded executor, so
+    /// the flow control is delegated to the transport (e.g. the socket
+    /// stops being read, and the peer eventually stalls on TCP).
+    Block {
+        /// Buffer size above which the handler reports backpressure.
+        capacity : usize,
+    },
+}
+
+/// The buffer of events awaiting the owner, with its delivery bookkeeping.
+#[derive(Debug)]
+struct EventQueue<N> {
+    queue      : VecDeque<Event<N>>,
+    policy     : BackpressurePolicy,
+    dropped    : u64,
+    waker      : Option<Waker>,
+    terminated : bool,
+}
+
+impl<N> Default for EventQueue<N> {
+    fn default() -> EventQueue<N> {
+        EventQueue {
+            queue      : default(),
+            policy     : BackpressurePolicy::Unbounded,
+            dropped    : 0,
+            waker      : None,
+            terminated : false,
+        }
+    }
+}
+
+impl<N> EventQueue<N> {
+    /// Buffers an event according to the backpressure policy.
+    fn push(&mut self, event:Event<N>) {
+        match self.policy {
+            BackpressurePolicy::Unbounded => self.queue.push_back(event),
+            BackpressurePolicy::DropOldest {capacity} => {
+                if self.queue.len() >= capacity {
+                    self.queue.pop_front();
+                    self.dropped += 1;
+                }
+                self.queue.push_back(event);
+            }
+            BackpressurePolicy::DropNewest {capacity} => {
+                if self.queue.len() >= capacity {
+                    self.dropped += 1;
+                } else {
+                    self.queue.push_back(event);
+                }
+            }
+            BackpressurePolicy::Block {..} => self.queue.push_back(event),
+        }
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn is_backpressured(&self) -> bool {
+        match self.policy {
+            BackpressurePolicy::Block {capacity} => self.queue.len() >= capacity,
+            _                                    => false,
+        }
+    }
+}
+
+/// The stream of the handler's events. See `Handler::events`.
+#[derive(Debug)]
+pub struct EventStream<N> {
+    queue : Rc<RefCell<EventQueue<N>>>,
+}
+
+impl<N> Stream for EventStream<N> {
+    type Item = Event<N>;
+    fn poll_next(self:Pin<&mut Self>, cx:&mut Context) -> Poll<Option<Event<N>>> {
+        let mut queue = self.queue.borrow_mut();
+        match queue.queue.pop_front() {
+            Some(event)                 => Poll::Ready(Some(event)),
+            None if queue.terminated    => Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+
+
+// ==============
+// === Shared ===
+// ==============
+
+/// Mutable state of the handler, shared between the handler and futures it
+/// has handed out.
+#[derive(Debug)]
+pub struct Shared {
+    /// Requests that were made but are still awaiting a reply.
+    pub ongoing_calls : HashMap<Id,oneshot::Sender<ReplyMessage>>,
+    /// Ids of requests that were already answered, kept to tell a duplicate
+    /// response apart from one with an id we never issued.
+    pub resolved_calls : HashSet<Id>,
+    /// Generator of ids for the outgoing requests.
+    pub id_generator : IdGenerator,
+    /// Whether the handler was explicitly closed on our side. Decides what
+    /// error the pending futures complete with.
+    pub closed : bool,
+}
+
+impl Default for Shared {
+    fn default() -> Shared {
+        Shared {
+            ongoing_calls  : default(),
+            resolved_calls : default(),
+            id_generator   : default(),
+            closed         : false,
+        }
+    }
+}
+
+impl Shared {
+    /// Creates a new request id and registers a channel to deliver its reply.
+    pub fn open_request(&mut self) -> (Id,oneshot::Receiver<ReplyMessage>) {
+        let id = self.id_generator.generate();
+        let (sender,receiver) = oneshot::channel();
+        self.ongoing_calls.insert(id,sender);
+        (id,receiver)
+    }
+
+    /// Delivers the reply to the future awaiting it. Returns `false` if there
+    /// was no request with such id.
+    pub fn deliver_reply(&mut self, message:ReplyMessage) -> bool {
+        match self.ongoing_calls.remove(&message.id) {
+            Some(sender) => {
+                self.resolved_calls.insert(message.id);
+                // Ignore a failure to deliver — it only means that the caller
+                // is no longer interested in the reply.
+                let _ = sender.send(message);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops all the pending requests. Their futures will complete with
+    /// `LostConnection` error.
+    pub fn clear_ongoing_calls(&mut self) {
+        self.ongoing_calls.clear();
+    }
+}
+
+
+
+// ===============
+// === Handler ===
+// ===============
+
+/// The JSON-RPC protocol handler, parametrized by the type of notifications
+/// that the peer may send.
+pub struct Handler<Notification> {
+    /// The underlying connection.
+    transport : Box<dyn Transport>,
+    /// State shared with the futures returned from `open_request`.
+    state : Rc<RefCell<Shared>>,
+    /// Buffer of events awaiting the handler's owner.
+    events_queue : Rc<RefCell<EventQueue<Notification>>>,
+    /// Whether `events` was already called; the stream may be taken once.
+    events_taken : bool,
+    /// Executor used to spawn internal tasks.
+    spawner : Option<Box<dyn futures::task::LocalSpawn>>,
+    /// Store of the per-method traffic metrics.
+    metrics : crate::metrics::Registry,
+    /// Limits applied to the incoming traffic.
+    limits : Limits,
+    /// How protocol violations are treated.
+    strictness : Strictness,
+    /// How messages from legacy (pre-2.0) peers are treated.
+    version_policy : VersionPolicy,
+    /// The middleware chain, applied to the traffic in registration order.
+    interceptors : Vec<Box<dyn Interceptor>>,
+    /// The heartbeat bookkeeping, when heartbeats are enabled.
+    heartbeat : Option<Heartbeat>,
+    /// The notification coalescing stage, when coalescing is enabled.
+    coalescer : Option<coalesce::Coalescer>,
+    /// The destination for completed request spans, when tracing is on.
+    trace_sink : Option<Rc<dyn TraceSink>>,
+    /// The tracing contexts of the requests still in flight.
+    traces : HashMap<Id,(TraceContext,&'static str,Instant)>,
+}
+
+// Hand-written: the spawner is a `dyn LocalSpawn`, which has no `Debug`.
+impl<Notification> Debug for Handler<Notification> {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Handler")
+            .field("transport",      &self.transport)
+            .field("has_spawner",    &self.spawner.is_some())
+            .field("limits",         &self.limits)
+            .field("strictness",     &self.strictness)
+            .field("version_policy", &self.version_policy)
+            .field("interceptors",   &self.interceptors)
+            .field("heartbeat",      &self.heartbeat)
+            .field("coalescer",      &self.coalescer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Notification:DeserializeOwned + Debug + 'static> Handler<Notification> {
+    /// Creates a new handler working on top of given transport.
+    pub fn new(transport:impl Transport + 'static) -> Handler<Notification> {
+        Handler {
+            transport      : Box::new(transport),
+            state          : default(),
+            events_queue   : default(),
+            events_taken   : false,
+            spawner        : None,
+            metrics        : default(),
+            limits         : default(),
+            strictness     : Strictness::Strict,
+            version_policy : VersionPolicy::Strict,
+            interceptors   : default(),
+            heartbeat      : None,
+            coalescer      : None,
+            trace_sink     : None,
+            traces         : default(),
+        }
+    }
+
+    /// Overrides the limits applied to the incoming traffic.
+    pub fn set_limits(&mut self, limits:Limits) {
+        self.limits = limits;
+    }
+
+    /// Overrides how protocol violations are treated. The default is
+    /// `Strict` — backend bugs should be visible, not silently dropped.
+    pub fn set_strictness(&mut self, strictness:Strictness) {
+        self.strictness = strictness;
+    }
+
+    /// Overrides how messages from legacy peers are treated. The default is
+    /// `Strict`; see `VersionPolicy`.
+    pub fn set_version_policy(&mut self, policy:VersionPolicy) {
+        self.version_policy = policy;
+    }
+
+    /// Overrides what happens when the owner does not keep up with the event
+    /// stream. The default is an unbounded buffer.
+    pub fn set_backpressure_policy(&mut self, policy:BackpressurePolicy) {
+        self.events_queue.borrow_mut().policy = policy;
+    }
+
+    /// Enables protocol-level heartbeats. See `HeartbeatConfig`.
+    ///
+    /// The handler has no clock of its own: the owner's event loop is
+    /// expected to call `heartbeat_tick` periodically (any cadence at or
+    /// below the ping interval works). The first tick sends the first ping
+    /// immediately, so a dead connection is noticed at startup too.
+    pub fn enable_heartbeat(&mut self, config:HeartbeatConfig) {
+        self.heartbeat = Some(Heartbeat::new(config));
+    }
+
+    /// Advances the heartbeat state machine to the given point in time:
+    /// counts a timed-out ping, emits `Event::ConnectionUnhealthy` when the
+    /// configured run of misses is reached, and sends the next ping when it
+    /// is due. A no-op unless `enable_heartbeat` was called.
+    pub fn heartbeat_tick(&mut self, now:Instant) {
+        let mut became_unhealthy = false;
+        let mut send_ping        = false;
+        if let Some(heartbeat) = &mut self.heartbeat {
+            if let Some((id,sent)) = heartbeat.outstanding {
+                if now.duration_since(sent) >= heartbeat.config.timeout {
+                    heartbeat.outstanding = None;
+                    heartbeat.timed_out.insert(id);
+                    heartbeat.missed += 1;
+                    let exhausted = heartbeat.missed >= heartbeat.config.max_missed;
+                    if exhausted && !heartbeat.unhealthy {
+                        heartbeat.unhealthy = true;
+                        became_unhealthy    = true;
+                    }
+                }
+            }
+            let due = heartbeat.last_ping
+                .is_none_or(|last| now.duration_since(last) >= heartbeat.config.interval);
+            send_ping = heartbeat.outstanding.is_none() && due;
+        }
+        if became_unhealthy {
+            self.emit_event(Event::ConnectionUnhealthy);
+        }
+        if send_ping {
+            self.send_ping(now);
+        }
+    }
+
+    /// Whether the peer has been answering the heartbeat pings. Always
+    /// `true` when heartbeats are disabled.
+    pub fn is_healthy(&self) -> bool {
+        self.heartbeat.as_ref().is_none_or(|heartbeat| !heartbeat.unhealthy)
+    }
+
+    /// Sends one heartbeat ping.
+    fn send_ping(&mut self, now:Instant) {
+        let method = match &self.heartbeat {
+            Some(heartbeat) => heartbeat.config.method.clone(),
+            None            => return,
+        };
+        let id         = self.state.borrow_mut().id_generator.generate();
+        let call       = MethodCall {method, params:serde_json::json!({})};
+        let message    = Message::new(Request::new(id,call));
+        let serialized = self.serialize_outgoing(&message);
+        self.transport.send_text(serialized);
+        if let Some(heartbeat) = &mut self.heartbeat {
+            heartbeat.outstanding = Some((id,now));
+            heartbeat.last_ping   = Some(now);
+        }
+    }
+
+    /// Enables notification coalescing with the given per-method rules. See
+    /// the `coalesce` module.
+    ///
+    /// Like heartbeats, flushing is driven by the owner's event loop calling
+    /// `coalesce_tick` periodically (any cadence at or below the debounce
+    /// windows works).
+    pub fn set_coalescing(&mut self, rules:coalesce::Rules) {
+        self.coalescer = Some(coalesce::Coalescer::new(rules));
+    }
+
+    /// Delivers the coalesced notifications whose debounce window elapsed by
+    /// the given point in time. A no-op unless `set_coalescing` was called.
+    pub fn coalesce_tick(&mut self, now:Instant) {
+        let ready = match &mut self.coalescer {
+            Some(coalescer) => coalescer.flush_due(now),
+            None            => return,
+        };
+        for notification in ready {
+            self.deliver_notification(notification);
+        }
+    }
+
+    /// Appends an interceptor to the middleware chain.
+    pub fn add_interceptor(&mut self, interceptor:impl Interceptor + 'static) {
+        self.interceptors.push(Box::new(interceptor));
+    }
+
+    /// Whether the `Block` backpressure policy is in effect and the buffer
+    /// is at capacity. The transport pump should pause feeding
+    /// `process_event` until this clears.
+    pub fn is_backpressured(&self) -> bool {
+        self.events_queue.borrow().is_backpressured()
+    }
+
+    /// Number of events dropped so far due to the backpressure policy.
+    pub fn dropped_events_count(&self) -> u64 {
+        self.events_queue.borrow().dropped
+    }
+
+    /// Sets the executor that the handler will use for its internal tasks.
+    pub fn set_spawner(&mut self, spawner:impl futures::task::LocalSpawn + 'static) {
+        self.spawner = Some(Box::new(spawner));
+    }
+
+    /// Spawns a task on the handler's executor. Panics if no executor was set.
+    pub fn spawn(&mut self, task:impl Future<Output=()> + 'static) {
+        let spawner = self.spawner.as_mut().expect("handler has no executor set");
+        let task    = futures::future::FutureExt::boxed_local(task);
+        spawner.spawn_local_obj(task.into()).expect("failed to spawn a task");
+    }
+
+    /// Sends a request to the peer and returns a future that will yield its
+    /// reply (or an error).
+    pub fn open_request<Call:RemoteMethodCall>
+    (&mut self, call:Call) -> impl Future<Output=Result<Call::Returned>> {
+        let (id,receiver) = self.state.borrow_mut().open_request();
+        let call          = api::into_method_call(call);
+        let message       = Message::new(Request::new(id,call));
+        let serialized    = self.serialize_outgoing(&message);
+        let guard = self.metrics.call_started(Call::NAME);
+        let state = self.state.clone_ref();
+        self.transport.send_text(serialized);
+        Self::await_reply(receiver, state, guard)
+    }
+
+    /// Sets the sink that completed request spans are reported to. Tracing
+    /// contexts attached via `open_request_traced` are a no-op without one.
+    pub fn set_trace_sink(&mut self, sink:Rc<dyn TraceSink>) {
+        self.trace_sink = Some(sink);
+    }
+
+    /// Like `open_request`, with a tracing context attached.
+    ///
+    /// The context rides along in the reserved `_trace` params field, and
+    /// when the response arrives the completed span — context, method,
+    /// latency, outcome — is reported to the trace sink. See `TraceContext`.
+    pub fn open_request_traced<Call:RemoteMethodCall>
+    (&mut self, call:Call, context:TraceContext) -> impl Future<Output=Result<Call::Returned>> {
+        let (id,receiver) = self.state.borrow_mut().open_request();
+        let call          = api::into_method_call(call);
+        let message       = Message::new(Request::new(id,call));
+        let mut value     = serde_json::to_value(&message)
+            .expect("serialization of an outgoing message cannot fail");
+        value["params"]["_trace"] = serde_json::json!({
+            "trace_id" : context.trace_id,
+            "span_id"  : context.span_id,
+        });
+        for interceptor in &mut self.interceptors {
+            interceptor.on_request(&mut value);
+        }
+        self.traces.insert(id, (context, Call::NAME, Instant::now()));
+        let guard = self.metrics.call_started(Call::NAME);
+        let state = self.state.clone_ref();
+        self.transport.send_text(value.to_string());
+        Self::await_reply(receiver, state, guard)
+    }
+
+    /// The common tail of `open_request` and `open_request_traced`: turns
+    /// the reply channel into the typed result future.
+    async fn await_reply<Ret:DeserializeOwned>
+    ( receiver : oneshot::Receiver<ReplyMessage>
+    , state    : Rc<RefCell<Shared>>
+    , guard    : crate::metrics::CallGuard
+    ) -> Result<Ret> {
+        let result = match receiver.await {
+            Ok(reply) => messages::decode_result(reply.result),
+            Err(_) if state.borrow().closed => Err(RpcError::ConnectionClosed),
+            Err(_)    => Err(RpcError::LostConnection),
+        };
+        guard.finish(result.is_err());
+        result
+    }
+
+    /// Sends a notification to the peer. No reply is expected.
+    pub fn notify<Call:RemoteMethodCall>(&mut self, call:Call) {
+        let call       = api::into_method_call(call);
+        let message    = Message::new(messages::Notification(call));
+        let serialized = self.serialize_outgoing(&message);
+        self.transport.send_text(serialized);
+    }
+
+    /// Serializes an outgoing message, passing it through the middleware
+    /// chain on the way.
+    fn serialize_outgoing(&mut self, message:&impl serde::Serialize) -> String {
+        let mut value = serde_json::to_value(message)
+            .expect("serialization of an outgoing message cannot fail");
+        for interceptor in &mut self.interceptors {
+            interceptor.on_request(&mut value);
+        }
+        value.to_string()
+    }
+
+    /// Processes a single event coming from the transport.
+    pub fn process_event(&mut self, event:TransportEvent) {
+        match event {
+            TransportEvent::TextMessage(text) => self.process_incoming_text(&text),
+            TransportEvent::Opened            => {}
+            TransportEvent::Closed            => {
+                self.state.borrow_mut().clear_ongoing_calls();
+                self.traces.clear();
+                // Withheld notifications must not be lost with the connection.
+                let withheld = match &mut self.coalescer {
+                    Some(coalescer) => coalescer.flush_all(),
+                    None            => default(),
+                };
+                for notification in withheld {
+                    self.deliver_notification(notification);
+                }
+                self.emit_event(Event::Closed);
+            }
+        }
+    }
+
+    /// Decodes and processes an incoming text message.
+    fn process_incoming_text(&mut self, text:&str) {
+        if text.len() > self.limits.max_message_bytes {
+            let size  = text.len();
+            let limit = self.limits.max_message_bytes;
+            self.emit_event(Event::Error(HandlingError::MessageTooLarge {size,limit}));
+            return;
+        }
+        if messages::exceeds_depth(text, self.limits.max_json_depth) {
+            let limit = self.limits.max_json_depth;
+            self.emit_event(Event::Error(HandlingError::MessageTooDeep {limit}));
+            return;
+        }
+        let decoded = if self.interceptors.is_empty() {
+            messages::decode_incoming_message_lenient(text)
+        } else {
+            self.decode_intercepted(text)
+        };
+        match decoded {
+            Ok(message) => {
+                let legacy = message.jsonrpc != Some(messages::Version::V2);
+                if legacy && self.version_policy == VersionPolicy::Strict {
+                    self.reject_legacy_message(text, &message.payload);
+                    return;
+                }
+                match message.payload {
+                    IncomingMessage::Response(response) => self.process_response(response),
+                    IncomingMessage::Notification(notification) =>
+                        self.process_notification(notification.0),
+                }
+            }
+            Err(error) => {
+                let message = text.to_string();
+                self.emit_event(Event::Error(HandlingError::InvalidMessage {message,error}));
+            }
+        }
+    }
+
+    /// Decodes an incoming message through the middleware chain. Compared
+    /// with the direct path this parses to a JSON value first, so it is only
+    /// used when interceptors are present.
+    fn decode_intercepted
+    (&mut self, text:&str) -> serde_json::Result<messages::LenientMessage<IncomingMessage>> {
+        let mut value = serde_json::from_str::<serde_json::Value>(text)?;
+        for interceptor in &mut self.interceptors {
+            interceptor.on_response(&mut value);
+        }
+        serde_json::from_value(value)
+    }
+
+    /// Rejects a message from a legacy peer: answers it with a spec-compliant
+    /// "Invalid Request" error response, echoing the offender's id when it
+    /// has one, and surfaces the violation to the owner.
+    fn reject_legacy_message(&mut self, text:&str, message:&IncomingMessage) {
+        let id = match message {
+            IncomingMessage::Response(response) => serde_json::json!(response.id.0),
+            IncomingMessage::Notification(messages::Notification(value)) =>
+                value.get("id").cloned().unwrap_or(serde_json::Value::Null),
+        };
+        let reply = serde_json::json!({
+            "jsonrpc" : "2.0",
+            "id"      : id,
+            "error"   : {
+                "code"    : messages::INVALID_REQUEST_CODE,
+                "message" : "unsupported jsonrpc protocol version",
+            }
+        });
+        let serialized = self.serialize_outgoing(&reply);
+        self.transport.send_text(serialized);
+        let message = text.to_string();
+        self.emit_event(Event::Error(HandlingError::UnsupportedVersion {message}));
+    }
+
+    /// Delivers a reply to the future awaiting it.
+    fn process_response(&mut self, response:ReplyMessage) {
+        if let Some(heartbeat) = &mut self.heartbeat {
+            if heartbeat.note_pong(response.id) {
+                return;
+            }
+        }
+        if let Some((context,method,sent)) = self.traces.remove(&response.id) {
+            if let Some(sink) = &self.trace_sink {
+                sink.record(TraceRecord {
+                    context,
+                    method  : method.to_string(),
+                    latency : sent.elapsed(),
+                    failed  : matches!(response.result, messages::Result::Error {..}),
+                });
+            }
+        }
+        let delivered = self.state.borrow_mut().deliver_reply(response.clone());
+        if !delivered {
+            let error = if self.state.borrow().resolved_calls.contains(&response.id) {
+                HandlingError::DuplicateResponse {response}
+            } else {
+                HandlingError::UnexpectedResponse {response}
+            };
+            self.emit_violation(error);
+        }
+    }
+
+    /// Routes a notification: violations are reported, coalesced methods go
+    /// through the coalescing stage, everything else is delivered directly.
+    fn process_notification(&mut self, notification:serde_json::Value) {
+        let method = notification.get("method")
+            .and_then(serde_json::Value::as_str)
+            .map(|method| method.to_string());
+        if let Some(method) = &method {
+            if method.starts_with("rpc.") {
+                let method = method.clone();
+                self.emit_violation(HandlingError::ReservedNotification {method});
+                return;
+            }
+        }
+        let notification = match (&mut self.coalescer, method) {
+            (Some(coalescer), Some(method)) => {
+                match coalescer.absorb(&method, notification, Instant::now()) {
+                    Some(notification) => notification,
+                    None               => return,
+                }
+            }
+            _ => notification,
+        };
+        self.deliver_notification(notification);
+    }
+
+    /// Decodes a notification and passes it to the owner's event stream.
+    ///
+    /// The owner's notification type may describe either the bare `params`
+    /// payload or the whole envelope (an enum internally tagged by
+    /// `method`, as the generated stubs produce); the payload is tried
+    /// first, the envelope second.
+    fn deliver_notification(&mut self, notification:serde_json::Value) {
+        let params  = notification.get("params").cloned().unwrap_or(serde_json::Value::Null);
+        let decoded = serde_json::from_value(params)
+            .or_else(|_| serde_json::from_value(notification.clone()));
+        match decoded {
+            Ok(typed) => self.emit_event(Event::Notification(typed)),
+            Err(error) => {
+                self.emit_event(Event::Error(HandlingError::InvalidNotification {
+                    notification,
+                    error,
+                }));
+            }
+        }
+    }
+
+    /// Emits an event to the owner, subject to the backpressure policy.
+    fn emit_event(&mut self, event:Event<Notification>) {
+        self.events_queue.borrow_mut().push(event);
+    }
+
+    /// Surfaces a protocol violation according to the configured strictness.
+    fn emit_violation(&mut self, error:HandlingError) {
+        match self.strictness {
+            Strictness::Strict  => self.emit_event(Event::Error(error)),
+            Strictness::Lenient => {}
+        }
+    }
+
+    /// The stream of events that are not replies to our requests.
+    ///
+    /// Events are yielded in arrival order. Replies complete their futures
+    /// during `process_event`, so anything received before a reply is
+    /// buffered in this stream before that reply's future resolves.
+    ///
+    /// May be taken only once; panics on a repeated call.
+    pub fn events(&mut self) -> EventStream<Notification> {
+        assert!(!self.events_taken, "events stream was already taken");
+        self.events_taken = true;
+        EventStream {queue : self.events_queue.clone_ref()}
+    }
+
+    /// The stream of typed notifications from the peer. Errors and connection
+    /// state changes are dropped; use `events` for the full stream.
+    pub fn notifications(&mut self) -> impl Stream<Item=Notification> {
+        self.events().filter_map(|event| {
+            futures::future::ready(match event {
+                Event::Notification(n) => Some(n),
+                _                      => None,
+            })
+        })
+    }
+
+    /// Number of requests that still await a reply.
+    pub fn pending_requests_count(&self) -> usize {
+        self.state.borrow().ongoing_calls.len()
+    }
+
+    /// An owned snapshot of the per-method traffic metrics.
+    pub fn metrics(&self) -> HashMap<String,crate::metrics::MethodMetrics> {
+        self.metrics.snapshot()
+    }
+
+    /// Closes the handler.
+    ///
+    /// All pending requests are rejected with `ConnectionClosed`, the
+    /// `Closed` event is emitted and the transport connection is torn down.
+    /// The returned future completes once the outgoing traffic has been
+    /// flushed; our transports send eagerly, so this is immediate, but
+    /// callers should still await it for forward compatibility.
+    pub fn close(&mut self) -> impl Future<Output=()> {
+        self.close_internal();
+        futures::future::ready(())
+    }
+
+    /// Synchronous part of `close`. Idempotent.
+    fn close_internal(&mut self) {
+        let mut state = self.state.borrow_mut();
+        if state.closed {
+            return;
+        }
+        state.closed = true;
+        state.clear_ongoing_calls();
+        drop(state);
+        self.traces.clear();
+        self.transport.close();
+        self.emit_event(Event::Closed);
+    }
+}
+
+impl<Notification> Drop for Handler<Notification> {
+    /// A best-effort version of `close`: pending futures complete with
+    /// `ConnectionClosed` and the transport is torn down. Unlike `close`,
+    /// there is no way to await the flush.
+    fn drop(&mut self) {
+        let mut state   = self.state.borrow_mut();
+        let was_closed  = state.closed;
+        if !was_closed {
+            state.closed = true;
+            state.clear_ongoing_calls();
+        }
+        drop(state);
+        if !was_closed {
+            self.transport.close();
+        }
+        let mut queue = self.events_queue.borrow_mut();
+        if !was_closed && !queue.terminated {
+            queue.push(Event::Closed);
+        }
+        queue.terminated = true;
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::transport::mock::MockTransport;
+    use serde::Deserialize;
+    use serde::Serialize;
+    use serde_json::json;
+
+    #[derive(Clone,Debug,PartialEq,Deserialize)]
+    struct MockNotification {
+        text : String,
+    }
+
+    #[derive(Clone,Debug,PartialEq,Serialize)]
+    struct Ping {}
+
+    impl RemoteMethodCall for Ping {
+        const NAME:&'static str = "ping";
+        type Returned  = bool;
+        type ErrorData = serde_json::Value;
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        let mut generator = IdGenerator::default();
+        let id1 = generator.generate();
+        let id2 = generator.generate();
+        assert_ne!(id1,id2);
+    }
+
+    #[test]
+    fn request_reply_roundtrip() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        let mut future  = Box::pin(handler.open_request(Ping {}));
+        assert_eq!(handler.pending_requests_count(), 1);
+
+        let request = transport.expect_message_json();
+        let id      = request["id"].clone();
+        let reply   = json!({"jsonrpc":"2.0","id":id,"result":true});
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        assert_eq!(handler.pending_requests_count(), 0);
+
+        let result = crate::test_util::poll_future_output(&mut future);
+        assert_eq!(result, Some(Ok(true)));
+    }
+
+    #[test]
+    fn metrics_cover_the_call() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        let mut future  = Box::pin(handler.open_request(Ping {}));
+        assert_eq!(handler.metrics()["ping"].in_flight, 1);
+
+        let request = transport.expect_message_json();
+        let reply   = json!({"jsonrpc":"2.0","id":request["id"],"result":true});
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        let _ = crate::test_util::poll_future_output(&mut future);
+
+        let metrics = handler.metrics();
+        assert_eq!(metrics["ping"].calls, 1);
+        assert_eq!(metrics["ping"].in_flight, 0);
+        assert_eq!(metrics["ping"].latency.count, 1);
+    }
+
+    #[derive(Debug)]
+    struct TokenInjector;
+    impl Interceptor for TokenInjector {
+        fn on_request(&mut self, request:&mut serde_json::Value) {
+            request["params"]["token"] = json!("secret");
+        }
+    }
+
+    #[derive(Debug)]
+    struct ResultRewriter;
+    impl Interceptor for ResultRewriter {
+        fn on_response(&mut self, response:&mut serde_json::Value) {
+            if let Some(result) = response.get_mut("result") {
+                *result = json!(false);
+            }
+        }
+    }
+
+    #[test]
+    fn interceptors_see_outgoing_and_incoming_traffic() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        handler.add_interceptor(TokenInjector);
+        handler.add_interceptor(ResultRewriter);
+
+        let mut future = Box::pin(handler.open_request(Ping {}));
+        let request    = transport.expect_message_json();
+        assert_eq!(request["params"]["token"], json!("secret"));
+
+        let reply = json!({"jsonrpc":"2.0","id":request["id"],"result":true});
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        let result = crate::test_util::poll_future_output(&mut future);
+        assert_eq!(result, Some(Ok(false)));
+    }
+
+    #[test]
+    fn backpressure_policies_bound_the_event_buffer() {
+        let notification = |i:usize| {
+            let text = json!({"jsonrpc":"2.0", "method":"event", "params":{"text":i.to_string()}});
+            TransportEvent::TextMessage(text.to_string())
+        };
+
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        handler.set_backpressure_policy(BackpressurePolicy::DropOldest {capacity:2});
+        let mut events = handler.events();
+        for i in 0..4 {
+            handler.process_event(notification(i));
+        }
+        assert_eq!(handler.dropped_events_count(), 2);
+        // The oldest events were dropped; the newest two remain, in order.
+        for expected in &["2","3"] {
+            match crate::test_util::poll_stream_output(&mut events) {
+                Some(Event::Notification(n)) => assert_eq!(&n.text, expected),
+                other => panic!("expected a notification, got {:?}", other),
+            }
+        }
+
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        handler.set_backpressure_policy(BackpressurePolicy::DropNewest {capacity:2});
+        let mut events = handler.events();
+        for i in 0..4 {
+            handler.process_event(notification(i));
+        }
+        assert_eq!(handler.dropped_events_count(), 2);
+        for expected in &["0","1"] {
+            match crate::test_util::poll_stream_output(&mut events) {
+                Some(Event::Notification(n)) => assert_eq!(&n.text, expected),
+                other => panic!("expected a notification, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn block_policy_reports_backpressure() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport);
+        handler.set_backpressure_policy(BackpressurePolicy::Block {capacity:1});
+        let mut events = handler.events();
+        assert!(!handler.is_backpressured());
+        let text = json!({"jsonrpc":"2.0", "method":"event", "params":{"text":"x"}});
+        handler.process_event(TransportEvent::TextMessage(text.to_string()));
+        assert!(handler.is_backpressured());
+        let _ = crate::test_util::poll_stream_output(&mut events);
+        assert!(!handler.is_backpressured());
+    }
+
+    #[test]
+    fn duplicate_and_unknown_responses_are_told_apart() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        let mut events  = handler.events();
+        let mut future  = Box::pin(handler.open_request(Ping {}));
+
+        let request = transport.expect_message_json();
+        let reply   = json!({"jsonrpc":"2.0","id":request["id"],"result":true});
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        let _ = crate::test_util::poll_future_output(&mut future);
+
+        // The same reply again — the id was already resolved.
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        let event = crate::test_util::poll_stream_output(&mut events);
+        assert!(matches!(event, Some(Event::Error(HandlingError::DuplicateResponse {..}))));
+
+        // A reply with an id we never issued.
+        let unknown = json!({"jsonrpc":"2.0","id":999,"result":true});
+        handler.process_event(TransportEvent::TextMessage(unknown.to_string()));
+        let event = crate::test_util::poll_stream_output(&mut events);
+        assert!(matches!(event, Some(Event::Error(HandlingError::UnexpectedResponse {..}))));
+    }
+
+    #[test]
+    fn reserved_notifications_are_reported_unless_lenient() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport);
+        let mut events  = handler.events();
+
+        let reserved = r#"{"jsonrpc":"2.0","method":"rpc.discover","params":{}}"#;
+        handler.process_event(TransportEvent::TextMessage(reserved.to_string()));
+        let event = crate::test_util::poll_stream_output(&mut events);
+        assert!(matches!(event, Some(Event::Error(HandlingError::ReservedNotification {..}))));
+
+        handler.set_strictness(Strictness::Lenient);
+        handler.process_event(TransportEvent::TextMessage(reserved.to_string()));
+        assert!(crate::test_util::poll_stream_output(&mut events).is_none());
+    }
+
+    #[test]
+    fn legacy_peers_are_rejected_when_strict() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        let mut events  = handler.events();
+        let mut future  = Box::pin(handler.open_request(Ping {}));
+
+        // A reply from a peer that omits the `jsonrpc` field.
+        let request = transport.expect_message_json();
+        let reply   = json!({"id":request["id"],"result":true});
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+
+        // The reply was not delivered and the peer was told why.
+        assert!(crate::test_util::poll_future_output(&mut future).is_none());
+        let response = transport.expect_message_json();
+        assert_eq!(response["error"]["code"], json!(messages::INVALID_REQUEST_CODE));
+        assert_eq!(response["id"], request["id"]);
+        let event = crate::test_util::poll_stream_output(&mut events);
+        assert!(matches!(event, Some(Event::Error(HandlingError::UnsupportedVersion {..}))));
+    }
+
+    #[test]
+    fn legacy_peers_are_accepted_when_lenient() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        handler.set_version_policy(VersionPolicy::Lenient);
+        let mut events  = handler.events();
+        let mut future  = Box::pin(handler.open_request(Ping {}));
+
+        let request = transport.expect_message_json();
+        let reply   = json!({"jsonrpc":"1.0","id":request["id"],"result":true});
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        assert_eq!(crate::test_util::poll_future_output(&mut future), Some(Ok(true)));
+
+        // Notifications without the field flow through as well.
+        let event = json!({"method":"event","params":{"text":"hello"}});
+        handler.process_event(TransportEvent::TextMessage(event.to_string()));
+        match crate::test_util::poll_stream_output(&mut events) {
+            Some(Event::Notification(n)) => assert_eq!(n.text, "hello"),
+            other => panic!("expected a notification, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn coalesced_notifications_deliver_the_last_value() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport);
+        let mut rules   = coalesce::Rules::new();
+        rules.set("event", coalesce::Rule {
+            key    : "/key".to_string(),
+            window : Duration::from_millis(100),
+        });
+        handler.set_coalescing(rules);
+        let mut events = handler.events();
+
+        for text in &["first","second"] {
+            let event = json!({"jsonrpc":"2.0", "method":"event",
+                               "params":{"key":"a", "text":text}});
+            handler.process_event(TransportEvent::TextMessage(event.to_string()));
+        }
+        // Nothing is delivered until the debounce window elapses.
+        assert!(crate::test_util::poll_stream_output(&mut events).is_none());
+
+        handler.coalesce_tick(Instant::now() + Duration::from_millis(100));
+        match crate::test_util::poll_stream_output(&mut events) {
+            Some(Event::Notification(n)) => assert_eq!(n.text, "second"),
+            other => panic!("expected a notification, got {:?}", other),
+        }
+        assert!(crate::test_util::poll_stream_output(&mut events).is_none());
+    }
+
+    #[test]
+    fn oversized_message_is_rejected_before_parsing() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport);
+        handler.set_limits(Limits {max_message_bytes:16, max_json_depth:4});
+        let mut events  = handler.events();
+
+        let too_large = r#"{"jsonrpc":"2.0","method":"x","params":{}}"#.to_string();
+        handler.process_event(TransportEvent::TextMessage(too_large));
+        let event = crate::test_util::poll_stream_output(&mut events);
+        assert!(matches!(event, Some(Event::Error(HandlingError::MessageTooLarge {..}))));
+
+        let too_deep = r#"{"a":[[[[[[1]]]]]]}"#.to_string();
+        handler.set_limits(Limits {max_message_bytes:1024, max_json_depth:4});
+        handler.process_event(TransportEvent::TextMessage(too_deep));
+        let event = crate::test_util::poll_stream_output(&mut events);
+        assert!(matches!(event, Some(Event::Error(HandlingError::MessageTooDeep {..}))));
+    }
+
+    #[test]
+    fn traced_requests_report_completed_spans() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        let sink        = Rc::new(TraceLog::default());
+        handler.set_trace_sink(sink.clone());
+        let context = TraceContext {
+            trace_id : "trace-1".to_string(),
+            span_id  : "span-7".to_string(),
+        };
+
+        let mut future = Box::pin(handler.open_request_traced(Ping {}, context.clone()));
+        let request    = transport.expect_message_json();
+        assert_eq!(request["params"]["_trace"]["trace_id"], json!("trace-1"));
+        assert_eq!(request["params"]["_trace"]["span_id"],  json!("span-7"));
+
+        let reply = json!({"jsonrpc":"2.0","id":request["id"],"result":true});
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        assert_eq!(crate::test_util::poll_future_output(&mut future), Some(Ok(true)));
+
+        let records = sink.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].context, context);
+        assert_eq!(records[0].method, "ping");
+        assert!(!records[0].failed);
+
+        // An error reply still completes the span — as a failed one.
+        let mut future = Box::pin(handler.open_request_traced(Ping {}, context.clone()));
+        let request    = transport.expect_message_json();
+        let reply      = json!({
+            "jsonrpc" : "2.0",
+            "id"      : request["id"],
+            "error"   : {"code":1, "message":"no", "data":null}
+        });
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        assert!(matches!(crate::test_util::poll_future_output(&mut future),
+                         Some(Err(RpcError::RemoteError(_)))));
+        assert!(sink.records()[1].failed);
+
+        // Untraced requests report nothing.
+        let _future = Box::pin(handler.open_request(Ping {}));
+        let request = transport.expect_message_json();
+        assert_eq!(request["params"].get("_trace"), None);
+        let reply = json!({"jsonrpc":"2.0","id":request["id"],"result":true});
+        handler.process_event(TransportEvent::TextMessage(reply.to_string()));
+        assert_eq!(sink.records().len(), 2);
+    }
+
+    #[test]
+    fn heartbeats_detect_a_silent_peer() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        handler.enable_heartbeat(HeartbeatConfig {
+            method     : "heartbeat/ping".to_string(),
+            interval   : Duration::from_secs(10),
+            timeout    : Duration::from_secs(5),
+            max_missed : 2,
+        });
+        let mut events = handler.events();
+        let t0 = Instant::now();
+
+        // The first tick pings immediately; the pong keeps things healthy.
+        handler.heartbeat_tick(t0);
+        let ping = transport.expect_message_json();
+        assert_eq!(ping["method"], json!("heartbeat/ping"));
+        let pong = json!({"jsonrpc":"2.0","id":ping["id"],"result":null});
+        handler.process_event(TransportEvent::TextMessage(pong.to_string()));
+        assert!(handler.is_healthy());
+        assert_eq!(handler.pending_requests_count(), 0);
+
+        // Two consecutive pings go unanswered.
+        handler.heartbeat_tick(t0 + Duration::from_secs(10));
+        let _ping = transport.expect_message_json();
+        handler.heartbeat_tick(t0 + Duration::from_secs(15));
+        assert!(handler.is_healthy());
+        handler.heartbeat_tick(t0 + Duration::from_secs(20));
+        let last_ping = transport.expect_message_json();
+        handler.heartbeat_tick(t0 + Duration::from_secs(25));
+
+        assert!(!handler.is_healthy());
+        let event = crate::test_util::poll_stream_output(&mut events);
+        assert!(matches!(event, Some(Event::ConnectionUnhealthy)));
+        // The event is emitted once, not on every further tick.
+        handler.heartbeat_tick(t0 + Duration::from_secs(40));
+        assert!(crate::test_util::poll_stream_output(&mut events).is_none());
+
+        // Even a late pong proves the peer is alive after all.
+        let pong = json!({"jsonrpc":"2.0","id":last_ping["id"],"result":null});
+        handler.process_event(TransportEvent::TextMessage(pong.to_string()));
+        assert!(handler.is_healthy());
+    }
+
+    #[test]
+    fn closed_connection_rejects_pending() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport);
+        let mut future  = Box::pin(handler.open_request(Ping {}));
+        handler.process_event(TransportEvent::Closed);
+        let result = crate::test_util::poll_future_output(&mut future);
+        assert_eq!(result, Some(Err(RpcError::LostConnection)));
+    }
+
+    #[test]
+    fn explicit_close_rejects_pending_and_closes_transport() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        let mut future  = Box::pin(handler.open_request(Ping {}));
+        let mut closing = Box::pin(handler.close());
+        assert_eq!(crate::test_util::poll_future_output(&mut closing), Some(()));
+        let result = crate::test_util::poll_future_output(&mut future);
+        assert_eq!(result, Some(Err(RpcError::ConnectionClosed)));
+        assert!(!transport.is_open());
+    }
+
+    #[test]
+    fn drop_rejects_pending_and_closes_transport() {
+        let transport   = MockTransport::new();
+        let mut handler = Handler::<MockNotification>::new(transport.clone());
+        let mut future  = Box::pin(handler.open_request(Ping {}));
+        drop(handler);
+        let result = crate::test_util::poll_future_output(&mut future);
+        assert_eq!(result, Some(Err(RpcError::ConnectionClosed)));
+        assert!(!transport.is_open());
+    }
+}
@@ -0,0 +1,47 @@
+//! An implementation of the JSON-RPC 2.0 protocol, developed for the needs of
+//! the typed clients of our backend services (File Manager, Parser Service).
+//!
+//! The crate is structured as follows:
+//! * `messages` defines the wire format;
+//! * `transport` abstracts over the underlying connection (e.g. WebSocket);
+//! * `handler` matches responses with requests and dispatches notifications;
+//! * `api` defines the typed remote call interface used by client crates;
+//! * `coalesce` merges bursts of keyed notifications before delivery;
+//! * `retry` provides an optional retry/backoff middleware;
+//! * `multiplexer` shares one transport between several logical clients;
+//! * `replay` records sessions to a file and replays them deterministically;
+//! * `stubs` generates typed client and server stubs from a protocol spec;
+//! * `schema` (feature-gated) describes the wire format as a JSON Schema.
+
+#![warn(missing_docs)]
+
+pub mod api;
+pub mod coalesce;
+pub mod error;
+pub mod handler;
+pub mod messages;
+pub mod metrics;
+pub mod multiplexer;
+pub mod replay;
+pub mod retry;
+pub mod stubs;
+#[cfg(feature="schema")]
+pub mod schema;
+pub mod test_util;
+pub mod transport;
+
+pub use api::RemoteMethodCall;
+pub use api::Result;
+pub use api::RpcError;
+pub use handler::Handler;
+pub use transport::Transport;
+pub use transport::TransportEvent;
+
+/// Module gathering all the commonly used re-exports.
+pub mod prelude {
+    pub use crate::api::RemoteMethodCall;
+    pub use crate::api::RpcError;
+    pub use crate::handler::Handler;
+    pub use crate::transport::Transport;
+    pub use crate::transport::TransportEvent;
+}
@@ -0,0 +1,411 @@
+//! Structures describing the JSON-RPC 2.0 wire format.
+
+use prelude::*;
+
+use serde::Serialize;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use shrinkwraprs::Shrinkwrap;
+
+
+
+// ===============
+// === Message ===
+// ===============
+
+/// All JSON-RPC messages bear `jsonrpc` version number.
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize,Shrinkwrap)]
+pub struct Message<T> {
+    /// JSON-RPC protocol version, should be "2.0".
+    pub jsonrpc : Version,
+    /// The rest of the message.
+    #[serde(flatten)]
+    #[shrinkwrap(main_field)]
+    pub payload : T,
+}
+
+impl<T> Message<T> {
+    /// Wraps given payload into a JSON-RPC 2.0 message.
+    pub fn new(payload:T) -> Message<T> {
+        Message {
+            jsonrpc : Version::V2,
+            payload,
+        }
+    }
+}
+
+/// JSON-RPC protocol version. The client itself always speaks 2.0; the
+/// legacy 1.0 is modeled so that messages from old peers can be recognized
+/// and either rejected or tolerated, as the handler's `VersionPolicy`
+/// decides.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub enum Version {
+    /// The legacy JSON-RPC 1.0 protocol.
+    #[serde(rename="1.0")]
+    V1,
+    /// JSON-RPC 2.0 mandates this to be the exact string "2.0".
+    #[serde(rename="2.0")]
+    V2,
+}
+
+/// The error code of an "Invalid Request" response, as assigned by the
+/// JSON-RPC 2.0 spec.
+pub const INVALID_REQUEST_CODE:i64 = -32600;
+
+/// An id identifying the call request.
+///
+/// Each request made by the client should get a unique id (unique in a scope
+/// of a single connection).
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,PartialOrd,Ord,Serialize,Deserialize)]
+pub struct Id(pub i64);
+
+
+
+// ===============
+// === Request ===
+// ===============
+
+/// A request message.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct Request<Call> {
+    /// An identifier for this request, unique in the connection scope.
+    pub id : Id,
+    /// The method and its parameters.
+    #[serde(flatten)]
+    pub call : Call,
+}
+
+impl<Call> Request<Call> {
+    /// Creates a new request.
+    pub fn new(id:Id, call:Call) -> Request<Call> {
+        Request {id,call}
+    }
+}
+
+impl<In:Serialize> Message<Request<MethodCall<In>>> {
+    /// Serializes the request with the parameters under the legacy `input`
+    /// field name, for peers predating the spec-compliant `params`.
+    pub fn to_legacy_value(&self) -> serde_json::Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            if let Some(params) = object.remove("params") {
+                object.insert("input".to_string(), params);
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// A call to a remote method, i.e. method name paired with its parameters.
+///
+/// The parameters are serialized under `params`, as the JSON-RPC 2.0 spec
+/// mandates. Early versions of our services used `input` instead; the field
+/// accepts both on input, and `Request::to_legacy_value` is the migration
+/// switch for talking to peers that were not updated yet.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct MethodCall<In> {
+    /// Name of the remote method.
+    pub method : String,
+    /// Method's parameters. Peers may omit the field entirely for calls
+    /// that take no parameters; it then decodes to the type's default.
+    #[serde(alias="input", default)]
+    pub params : In,
+}
+
+
+
+// ================
+// === Response ===
+// ================
+
+/// A response to a request.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(bound(deserialize="Res : Deserialize<'de> + Default"))]
+pub struct Response<Res> {
+    /// Id of the request that this response answers.
+    pub id : Id,
+    /// The result of the call or an error.
+    #[serde(flatten)]
+    pub result : Result<Res>,
+}
+
+/// A result of a remote call — either a returned value or an error.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+/// The `Error` variant must come first: with the `result` field being
+/// optional, the `Success` variant matches error replies too.
+#[serde(untagged)]
+// Spelled out because the `default` on `Success::result` needs it; serde
+// only infers bounds from its own fields, not from nested types.
+#[serde(bound(deserialize="Res : Deserialize<'de> + Default"))]
+pub enum Result<Res> {
+    /// Error value from a failed call.
+    Error {
+        /// Description of the failure.
+        error : Error,
+    },
+    /// Returned value of a successful call.
+    Success(Success<Res>),
+}
+
+impl<Res> Result<Res> {
+    /// Creates a successful result value.
+    pub fn new_success(result:Res) -> Result<Res> {
+        Result::Success(Success {result})
+    }
+
+    /// Creates an error result value.
+    pub fn new_error(code:i64, message:String, data:Option<serde_json::Value>) -> Result<Res> {
+        Result::Error {error : Error {code,message,data}}
+    }
+}
+
+/// Value yielded by a successful remote call.
+///
+/// The value may be omitted on the wire — several backend endpoints reply
+/// with no `result` at all (or an explicit `null`) to a successful call.
+/// Such replies decode with the type's default value, so they can be
+/// modeled as `Response<()>`.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct Success<Res> {
+    /// The returned value.
+    #[serde(default)]
+    pub result : Res,
+}
+
+/// Error raised on a failed remote call.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct Error {
+    /// A number indicating the error type that occurred.
+    pub code : i64,
+    /// A short description of the error.
+    pub message : String,
+    /// Optional value with additional information about the error.
+    pub data : Option<serde_json::Value>,
+}
+
+
+
+// ====================
+// === Notification ===
+// ====================
+
+/// A notification message. Notifications are requests without an `id` — the
+/// peer does not reply to them.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct Notification<N>(pub N);
+
+
+
+// =======================
+// === IncomingMessage ===
+// =======================
+
+/// Any message that can come from the peer.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(untagged)]
+pub enum IncomingMessage {
+    /// A response to a request made by us.
+    Response(Response<serde_json::Value>),
+    /// A notification from the peer.
+    Notification(Notification<serde_json::Value>),
+}
+
+/// As `Message`, but tolerating a missing `jsonrpc` field. Used on the
+/// incoming path, where which versions are acceptable is the handler's
+/// policy decision rather than a parse error: some embedded servers omit
+/// the field entirely.
+#[derive(Clone,Debug,PartialEq,Deserialize)]
+pub struct LenientMessage<T> {
+    /// The protocol version the peer declared, if any.
+    pub jsonrpc : Option<Version>,
+    /// The rest of the message.
+    #[serde(flatten)]
+    pub payload : T,
+}
+
+/// Partially decodes an incoming message from its text representation.
+pub fn decode_incoming_message(message:&str) -> serde_json::Result<IncomingMessage> {
+    serde_json::from_str::<Message<IncomingMessage>>(message).map(|msg| msg.payload)
+}
+
+/// As `decode_incoming_message`, but accepting messages with a missing
+/// `jsonrpc` field. The declared version (if any) is returned alongside the
+/// payload, so the caller can apply its version policy.
+pub fn decode_incoming_message_lenient
+(message:&str) -> serde_json::Result<LenientMessage<IncomingMessage>> {
+    serde_json::from_str(message)
+}
+
+/// Checks whether the JSON text nests objects or arrays deeper than `limit`.
+///
+/// This is a cheap, allocation-free scan done before handing the text to the
+/// deserializer, so that a maliciously nested message is rejected without
+/// building its value tree. The scan is conservative: it only tracks brackets
+/// outside of string literals and never errs on valid JSON.
+pub fn exceeds_depth(message:&str, limit:usize) -> bool {
+    let mut depth     = 0;
+    let mut in_string = false;
+    let mut escaped   = false;
+    for byte in message.bytes() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\'        => escaped = true,
+                b'"'         => in_string = false,
+                _            => {}
+            }
+        } else {
+            match byte {
+                b'"'          => in_string = true,
+                b'{' | b'['   => {
+                    depth += 1;
+                    if depth > limit {
+                        return true;
+                    }
+                }
+                b'}' | b']'   => depth = depth.saturating_sub(1),
+                _             => {}
+            }
+        }
+    }
+    false
+}
+
+/// Message from a served that is a reply to our request.
+pub type ReplyMessage = Response<serde_json::Value>;
+
+/// A response carrying no result value.
+pub type EmptyResponse = Response<()>;
+
+impl Response<()> {
+    /// Creates a successful response with an empty result.
+    pub fn new_empty(id:Id) -> EmptyResponse {
+        Response {id, result:Result::new_success(())}
+    }
+}
+
+/// Converts a decoded reply into a typed result.
+pub fn decode_result<Ret:DeserializeOwned>
+(result:Result<serde_json::Value>) -> std::result::Result<Ret,crate::api::RpcError> {
+    match result {
+        Result::Success(success) => serde_json::from_value(success.result)
+            .map_err(|e| crate::api::RpcError::DeserializationFailed(e.to_string())),
+        Result::Error {error} => Err(crate::api::RpcError::RemoteError(error)),
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn request_serialization() {
+        let call    = MethodCall {method:"ping".to_string(), params:json!({})};
+        let request = Message::new(Request::new(Id(1),call));
+        let json    = serde_json::to_value(&request).unwrap();
+        let expected = json!({
+            "jsonrpc" : "2.0",
+            "id"      : 1,
+            "method"  : "ping",
+            "params"  : {}
+        });
+        assert_eq!(json,expected);
+    }
+
+    #[test]
+    fn legacy_input_field_is_accepted_and_emittable() {
+        // A reference payload from a peer that still uses `input`.
+        let text = r#"{"id":1, "method":"ping", "input":{"a":1}}"#;
+        let call:Request<MethodCall<serde_json::Value>> = serde_json::from_str(text).unwrap();
+        assert_eq!(call.call.params, json!({"a":1}));
+
+        let call    = MethodCall {method:"ping".to_string(), params:json!({"a":1})};
+        let request = Message::new(Request::new(Id(1),call));
+        let legacy  = request.to_legacy_value().unwrap();
+        let expected = json!({
+            "jsonrpc" : "2.0",
+            "id"      : 1,
+            "method"  : "ping",
+            "input"   : {"a":1}
+        });
+        assert_eq!(legacy,expected);
+    }
+
+    #[test]
+    fn incoming_response_deserialization() {
+        let text    = r#"{"jsonrpc":"2.0","id":5,"result":true}"#;
+        let message = decode_incoming_message(text).unwrap();
+        match message {
+            IncomingMessage::Response(response) => {
+                assert_eq!(response.id, Id(5));
+                assert_eq!(response.result, Result::new_success(json!(true)));
+            }
+            other => panic!("expected a response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_results_decode_to_unit() {
+        let texts = [r#"{"jsonrpc":"2.0","id":5,"result":null}"#,
+                     r#"{"jsonrpc":"2.0","id":5}"#];
+        for text in &texts {
+            let message = decode_incoming_message(text).unwrap();
+            match message {
+                IncomingMessage::Response(response) => {
+                    super::decode_result::<()>(response.result).unwrap();
+                }
+                other => panic!("expected a response, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn notification_without_params_is_accepted() {
+        let text    = r#"{"jsonrpc":"2.0","method":"connection/ready"}"#;
+        let message = decode_incoming_message(text).unwrap();
+        match message {
+            IncomingMessage::Notification(Notification(call)) => {
+                let call:MethodCall<serde_json::Value> = serde_json::from_value(call).unwrap();
+                assert_eq!(call.params, serde_json::Value::Null);
+            }
+            other => panic!("expected a notification, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn legacy_messages_decode_leniently() {
+        let texts = [(r#"{"id":5,"result":true}"#,                 None),
+                     (r#"{"jsonrpc":"1.0","id":5,"result":true}"#, Some(Version::V1)),
+                     (r#"{"jsonrpc":"2.0","id":5,"result":true}"#, Some(Version::V2))];
+        for (text,version) in &texts {
+            let message = decode_incoming_message_lenient(text).unwrap();
+            assert_eq!(message.jsonrpc, *version);
+            assert!(matches!(message.payload, IncomingMessage::Response(_)));
+        }
+        // An unknown version string is still garbage, not a legacy peer.
+        assert!(decode_incoming_message_lenient(r#"{"jsonrpc":"3.0","id":5}"#).is_err());
+    }
+
+    #[test]
+    fn depth_scan() {
+        assert!(!exceeds_depth(r#"{"a":[1,2,3]}"#, 2));
+        assert!(exceeds_depth(r#"{"a":[[1]]}"#, 2));
+        // Brackets inside string literals do not count.
+        assert!(!exceeds_depth(r#"{"a":"[[[[["}"#, 2));
+        assert!(!exceeds_depth(r#"{"a":"\"[["}"#, 2));
+    }
+
+    #[test]
+    fn incoming_notification_deserialization() {
+        let text    = r#"{"jsonrpc":"2.0","method":"event","params":{"a":1}}"#;
+        let message = decode_incoming_message(text).unwrap();
+        assert!(matches!(message, IncomingMessage::Notification(_)));
+    }
+}
@@ -0,0 +1,207 @@
+//! Metrics of the RPC traffic: per-method call counts, latency histograms and
+//! in-flight request gauges.
+//!
+//! The handler records into a `Registry` and exposes an owned snapshot
+//! through `Handler::metrics`, so the numbers can be inspected (or logged)
+//! without holding any borrow of the handler. When the `metrics` feature is
+//! enabled, the same observations are additionally forwarded to the
+//! facade of the `metrics` crate.
+
+use prelude::*;
+
+use std::time::Duration;
+use std::time::Instant;
+
+
+
+// =================
+// === Histogram ===
+// =================
+
+/// Upper bounds (in milliseconds) of the latency histogram buckets. The last
+/// bucket is unbounded.
+pub const BUCKET_BOUNDS_MS:[u64;10] = [1,2,5,10,25,50,100,250,1000,5000];
+
+/// A fixed-bucket latency histogram.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Histogram {
+    /// Counts of observations per bucket. One entry more than
+    /// `BUCKET_BOUNDS_MS`, for the unbounded overflow bucket.
+    pub buckets : [u64;11],
+    /// Sum of all observed latencies.
+    pub total : Duration,
+    /// Number of observations.
+    pub count : u64,
+}
+
+impl Histogram {
+    /// Records a single latency observation.
+    pub fn observe(&mut self, latency:Duration) {
+        let ms     = latency.as_millis() as u64;
+        let bucket = BUCKET_BOUNDS_MS.iter().position(|bound| ms <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[bucket] += 1;
+        self.total += latency;
+        self.count += 1;
+    }
+
+    /// Mean observed latency, if anything was observed at all.
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.total / self.count as u32)
+    }
+}
+
+
+
+// =====================
+// === MethodMetrics ===
+// =====================
+
+/// Metrics gathered for a single remote method.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct MethodMetrics {
+    /// Number of calls started.
+    pub calls : u64,
+    /// Number of calls that completed with an error.
+    pub errors : u64,
+    /// Number of calls started but not yet completed.
+    pub in_flight : u64,
+    /// Latencies of the completed calls.
+    pub latency : Histogram,
+}
+
+
+
+// ================
+// === Registry ===
+// ================
+
+/// The mutable store that the handler records observations into. Shared by
+/// reference with the in-flight guards handed out for each call.
+#[derive(Clone,Debug,Default)]
+pub struct Registry {
+    methods : Rc<RefCell<HashMap<String,MethodMetrics>>>,
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Registry {
+        default()
+    }
+
+    /// Records that a call to given method has started. The returned guard
+    /// must be kept alive for the duration of the call and fed its outcome.
+    pub fn call_started(&self, method:&'static str) -> CallGuard {
+        {
+            let mut methods = self.methods.borrow_mut();
+            let metrics     = methods.entry(method.to_string()).or_default();
+            metrics.calls     += 1;
+            metrics.in_flight += 1;
+        }
+        #[cfg(feature="metrics")]
+        metrics::increment_gauge!("json_rpc.in_flight", 1.0, "method" => method);
+        CallGuard {
+            registry : self.clone(),
+            method,
+            started  : Instant::now(),
+            finished : false,
+        }
+    }
+
+    /// An owned snapshot of all per-method metrics.
+    pub fn snapshot(&self) -> HashMap<String,MethodMetrics> {
+        self.methods.borrow().clone()
+    }
+
+    fn call_finished(&self, method:&str, latency:Duration, is_error:bool) {
+        let mut methods = self.methods.borrow_mut();
+        let metrics     = methods.entry(method.to_string()).or_default();
+        metrics.in_flight = metrics.in_flight.saturating_sub(1);
+        metrics.latency.observe(latency);
+        if is_error {
+            metrics.errors += 1;
+        }
+        #[cfg(feature="metrics")]
+        {
+            let method = method.to_string();
+            metrics::decrement_gauge!("json_rpc.in_flight", 1.0, "method" => method.clone());
+            metrics::histogram!("json_rpc.latency", latency, "method" => method);
+        }
+    }
+}
+
+
+
+// =================
+// === CallGuard ===
+// =================
+
+/// Tracks a single in-flight call. Records the latency when fed the call's
+/// outcome; if simply dropped (the caller lost interest in the reply), the
+/// in-flight gauge is still decremented.
+#[derive(Debug)]
+pub struct CallGuard {
+    registry : Registry,
+    method   : &'static str,
+    started  : Instant,
+    finished : bool,
+}
+
+impl CallGuard {
+    /// Records the completion of the call.
+    pub fn finish(mut self, is_error:bool) {
+        self.finished = true;
+        self.registry.call_finished(self.method, self.started.elapsed(), is_error);
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.registry.call_finished(self.method, self.started.elapsed(), false);
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_bucketing() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(1));
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_secs(60));
+        assert_eq!(histogram.buckets[0], 1);
+        assert_eq!(histogram.buckets[2], 1);
+        assert_eq!(histogram.buckets[10], 1);
+        assert_eq!(histogram.count, 3);
+    }
+
+    #[test]
+    fn guard_tracks_in_flight() {
+        let registry = Registry::new();
+        let guard    = registry.call_started("ping");
+        assert_eq!(registry.snapshot()["ping"].in_flight, 1);
+        guard.finish(true);
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot["ping"].in_flight, 0);
+        assert_eq!(snapshot["ping"].calls, 1);
+        assert_eq!(snapshot["ping"].errors, 1);
+    }
+
+    #[test]
+    fn dropped_guard_decrements_gauge() {
+        let registry = Registry::new();
+        let guard    = registry.call_started("ping");
+        drop(guard);
+        assert_eq!(registry.snapshot()["ping"].in_flight, 0);
+    }
+}
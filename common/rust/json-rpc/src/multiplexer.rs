@@ -0,0 +1,292 @@
+//! Multiplexing of several logical JSON-RPC clients over one transport.
+//!
+//! Backends may expose a single combined endpoint serving several protocols
+//! (e.g. file manager and execution context) over one WebSocket. The
+//! `Multiplexer` owns the shared transport and hands out logical `Channel`s
+//! which implement `Transport` themselves, so each protocol client can keep
+//! its own `Handler` unaware of the sharing.
+//!
+//! Routing works as follows:
+//! * outgoing requests get their ids rewritten to be unique across all the
+//!   channels, and responses are routed back by that id (with the channel's
+//!   original id restored);
+//! * incoming notifications are routed by method namespace — the part of the
+//!   method name before the first `/` (e.g. `file/event` goes to the channel
+//!   registered under `file`);
+//! * connection state changes (`Opened`/`Closed`) are broadcast to all the
+//!   channels.
+
+use prelude::*;
+
+use crate::transport::Transport;
+use crate::transport::TransportEvent;
+
+use futures::channel::mpsc::UnboundedSender;
+
+
+
+// ==============
+// === Shared ===
+// ==============
+
+/// State of a single logical channel.
+#[derive(Debug)]
+struct ChannelState {
+    /// The method namespace this channel receives notifications for.
+    namespace : String,
+    /// Event sink of the channel's owner (typically a `Handler`).
+    transmitter : Option<UnboundedSender<TransportEvent>>,
+    /// Whether the channel was closed by its owner.
+    closed : bool,
+}
+
+/// State shared between the multiplexer and its channels.
+#[derive(Debug)]
+struct Shared {
+    transport : Box<dyn Transport>,
+    channels  : Vec<ChannelState>,
+    /// Maps a wire-level request id to the issuing channel and the id the
+    /// channel used, so the response can be routed and translated back.
+    routes  : HashMap<i64,(usize,serde_json::Value)>,
+    next_id : i64,
+}
+
+impl Shared {
+    fn deliver(&mut self, index:usize, event:TransportEvent) {
+        if let Some(transmitter) = &mut self.channels[index].transmitter {
+            let _ = transmitter.unbounded_send(event);
+        }
+    }
+
+    fn broadcast(&mut self, event:TransportEvent) {
+        for index in 0..self.channels.len() {
+            self.deliver(index, event.clone());
+        }
+    }
+}
+
+
+
+// ===================
+// === Multiplexer ===
+// ===================
+
+/// Shares a single transport between several logical JSON-RPC clients.
+#[derive(Debug)]
+pub struct Multiplexer {
+    shared : Rc<RefCell<Shared>>,
+}
+
+impl Multiplexer {
+    /// Creates a multiplexer owning the given transport.
+    ///
+    /// Like with `Handler`, the owner is responsible for pumping the
+    /// transport's events into `process_event`.
+    pub fn new(transport:impl Transport + 'static) -> Multiplexer {
+        let shared = Shared {
+            transport : Box::new(transport),
+            channels  : default(),
+            routes    : default(),
+            next_id   : 0,
+        };
+        Multiplexer {shared : Rc::new(RefCell::new(shared))}
+    }
+
+    /// Creates a logical channel receiving notifications whose method names
+    /// live in the given namespace (`file` receives `file/event` etc.).
+    pub fn add_channel(&mut self, namespace:impl Str) -> Channel {
+        let mut shared = self.shared.borrow_mut();
+        let index      = shared.channels.len();
+        shared.channels.push(ChannelState {
+            namespace   : namespace.into(),
+            transmitter : None,
+            closed      : false,
+        });
+        Channel {index, shared:self.shared.clone_ref()}
+    }
+
+    /// Processes a single event coming from the shared transport, routing it
+    /// to the right channel.
+    pub fn process_event(&mut self, event:TransportEvent) {
+        match event {
+            TransportEvent::TextMessage(text) => self.route_text(&text),
+            other => self.shared.borrow_mut().broadcast(other),
+        }
+    }
+
+    /// Number of requests whose responses are still awaited.
+    pub fn pending_routes_count(&self) -> usize {
+        self.shared.borrow().routes.len()
+    }
+
+    fn route_text(&mut self, text:&str) {
+        let value:serde_json::Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            // Not JSON at all — let every channel surface the problem.
+            Err(_) => {
+                let event = TransportEvent::TextMessage(text.to_string());
+                self.shared.borrow_mut().broadcast(event);
+                return;
+            }
+        };
+        let method = value.get("method").and_then(serde_json::Value::as_str);
+        if let Some(method) = method {
+            self.route_notification(method.to_string(), text);
+        } else if let Some(wire_id) = value.get("id").and_then(serde_json::Value::as_i64) {
+            self.route_response(wire_id, value);
+        }
+    }
+
+    fn route_notification(&mut self, method:String, text:&str) {
+        let namespace  = method.split('/').next().unwrap_or(&method).to_string();
+        let mut shared = self.shared.borrow_mut();
+        let matching   = shared.channels.iter().position(|c| c.namespace == namespace);
+        if let Some(index) = matching {
+            shared.deliver(index, TransportEvent::TextMessage(text.to_string()));
+        }
+    }
+
+    fn route_response(&mut self, wire_id:i64, mut value:serde_json::Value) {
+        let mut shared = self.shared.borrow_mut();
+        if let Some((index,original_id)) = shared.routes.remove(&wire_id) {
+            value["id"] = original_id;
+            shared.deliver(index, TransportEvent::TextMessage(value.to_string()));
+        }
+    }
+}
+
+
+
+// ===============
+// === Channel ===
+// ===============
+
+/// A logical transport multiplexed over the shared one. See `Multiplexer`.
+#[derive(Debug)]
+pub struct Channel {
+    index  : usize,
+    shared : Rc<RefCell<Shared>>,
+}
+
+impl Transport for Channel {
+    fn send_text(&mut self, message:String) {
+        let mut shared = self.shared.borrow_mut();
+        let rewritten  = match serde_json::from_str::<serde_json::Value>(&message) {
+            Ok(mut value) => match value.get("id").cloned() {
+                Some(original_id) => {
+                    let wire_id = shared.next_id;
+                    shared.next_id += 1;
+                    shared.routes.insert(wire_id, (self.index,original_id));
+                    value["id"] = serde_json::json!(wire_id);
+                    value.to_string()
+                }
+                None => message,
+            },
+            Err(_) => message,
+        };
+        shared.transport.send_text(rewritten);
+    }
+
+    fn close(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.channels[self.index].closed = true;
+        shared.routes.retain(|_,(index,_)| *index != self.index);
+        // The shared connection goes down once no channel needs it anymore.
+        if shared.channels.iter().all(|c| c.closed) {
+            shared.transport.close();
+        }
+    }
+
+    fn set_event_transmitter(&mut self, transmitter:UnboundedSender<TransportEvent>) {
+        self.shared.borrow_mut().channels[self.index].transmitter = Some(transmitter);
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::transport::mock::MockTransport;
+
+    use futures::channel::mpsc::unbounded;
+    use futures::channel::mpsc::UnboundedReceiver;
+    use serde_json::json;
+
+    fn channel_with_events
+    (mux:&mut Multiplexer, namespace:&str) -> (Channel,UnboundedReceiver<TransportEvent>) {
+        let mut channel = mux.add_channel(namespace);
+        let (transmitter,receiver) = unbounded();
+        channel.set_event_transmitter(transmitter);
+        (channel,receiver)
+    }
+
+    fn next_event(receiver:&mut UnboundedReceiver<TransportEvent>) -> Option<TransportEvent> {
+        crate::test_util::poll_stream_output(receiver)
+    }
+
+    #[test]
+    fn responses_are_routed_by_rewritten_id() {
+        let transport = MockTransport::new();
+        let mut mux   = Multiplexer::new(transport.clone());
+        let (mut file,mut file_events) = channel_with_events(&mut mux, "file");
+        let (mut exec,mut exec_events) = channel_with_events(&mut mux, "exec");
+
+        // Both clients use their own id 0 — the wire must see distinct ones.
+        file.send_text(json!({"jsonrpc":"2.0","id":0,"method":"file/read","params":{}})
+            .to_string());
+        exec.send_text(json!({"jsonrpc":"2.0","id":0,"method":"exec/run","params":{}})
+            .to_string());
+        let first  = transport.expect_message_json();
+        let second = transport.expect_message_json();
+        assert_ne!(first["id"], second["id"]);
+
+        // The response to the second request goes to `exec`, with its own id.
+        let reply = json!({"jsonrpc":"2.0","id":second["id"],"result":true});
+        mux.process_event(TransportEvent::TextMessage(reply.to_string()));
+        match next_event(&mut exec_events) {
+            Some(TransportEvent::TextMessage(text)) => {
+                let value:serde_json::Value = serde_json::from_str(&text).unwrap();
+                assert_eq!(value["id"], json!(0));
+            }
+            other => panic!("expected a text message, got {:?}", other),
+        }
+        assert!(next_event(&mut file_events).is_none());
+        assert_eq!(mux.pending_routes_count(), 1);
+    }
+
+    #[test]
+    fn notifications_are_routed_by_namespace() {
+        let transport = MockTransport::new();
+        let mut mux   = Multiplexer::new(transport);
+        let (_file,mut file_events) = channel_with_events(&mut mux, "file");
+        let (_exec,mut exec_events) = channel_with_events(&mut mux, "exec");
+
+        let event = json!({"jsonrpc":"2.0","method":"file/event","params":{}});
+        mux.process_event(TransportEvent::TextMessage(event.to_string()));
+        assert!(matches!(next_event(&mut file_events),
+            Some(TransportEvent::TextMessage(_))));
+        assert!(next_event(&mut exec_events).is_none());
+    }
+
+    #[test]
+    fn connection_state_is_broadcast_and_close_is_shared() {
+        let transport = MockTransport::new();
+        let mut mux   = Multiplexer::new(transport.clone());
+        let (mut file,mut file_events) = channel_with_events(&mut mux, "file");
+        let (mut exec,mut exec_events) = channel_with_events(&mut mux, "exec");
+
+        mux.process_event(TransportEvent::Opened);
+        assert!(matches!(next_event(&mut file_events), Some(TransportEvent::Opened)));
+        assert!(matches!(next_event(&mut exec_events), Some(TransportEvent::Opened)));
+
+        file.close();
+        assert!(transport.is_open());
+        exec.close();
+        assert!(!transport.is_open());
+    }
+}
@@ -0,0 +1,206 @@
+//! Retry middleware for remote calls.
+//!
+//! Allows wrapping a call future so that transient failures of idempotent
+//! methods are retried with exponential backoff, while non-idempotent calls
+//! are never retried. Policies are chosen per method name, so a single policy
+//! set applies uniformly to all methods of a typed client.
+
+use prelude::*;
+
+use crate::api::RemoteMethodCall;
+use crate::api::Result;
+use crate::api::RpcError;
+use crate::handler::Handler;
+
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::time::Duration;
+
+
+
+// ===================
+// === RetryPolicy ===
+// ===================
+
+/// Describes if and how calls to a given method should be retried.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum RetryPolicy {
+    /// Never retry. The right choice for calls with side effects, as a lost
+    /// reply does not mean that the request was not acted upon.
+    Never,
+    /// Retry with exponential backoff. Only safe for idempotent calls.
+    Backoff(Backoff),
+}
+
+impl Default for RetryPolicy {
+    /// Calls are not retried unless explicitly opted in.
+    fn default() -> RetryPolicy {
+        RetryPolicy::Never
+    }
+}
+
+/// Parameters of the exponential backoff.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub initial_delay : Duration,
+    /// Each subsequent delay is the previous one multiplied by this factor.
+    pub multiplier : u32,
+    /// Maximum number of retries (not counting the initial attempt).
+    pub max_retries : usize,
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff {
+            initial_delay : Duration::from_millis(100),
+            multiplier    : 2,
+            max_retries   : 3,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay to wait before the retry with given 0-based index.
+    pub fn delay(&self, retry:usize) -> Duration {
+        self.initial_delay * self.multiplier.saturating_pow(retry as u32)
+    }
+}
+
+/// Decides whether an error is transient, i.e. whether retrying the call can
+/// possibly help.
+pub fn is_transient(error:&RpcError) -> bool {
+    matches!(error, RpcError::LostConnection | RpcError::TimeoutError)
+}
+
+
+
+// =================
+// === Policies ===
+// =================
+
+/// A set of per-method retry policies.
+#[derive(Clone,Debug,Default)]
+pub struct Policies {
+    by_method : HashMap<String,RetryPolicy>,
+}
+
+impl Policies {
+    /// Creates an empty policy set — nothing is retried.
+    pub fn new() -> Policies {
+        default()
+    }
+
+    /// Sets the policy for a method. Typed method descriptions name their
+    /// methods through `RemoteMethodCall::NAME`.
+    pub fn set(&mut self, method:impl Str, policy:RetryPolicy) -> &mut Policies {
+        self.by_method.insert(method.into(),policy);
+        self
+    }
+
+    /// Marks a method as an idempotent read, retried with default backoff.
+    pub fn idempotent(&mut self, method:impl Str) -> &mut Policies {
+        self.set(method, RetryPolicy::Backoff(default()))
+    }
+
+    /// The policy for a given method, `Never` if none was set.
+    pub fn get(&self, method:&str) -> RetryPolicy {
+        self.by_method.get(method).copied().unwrap_or_default()
+    }
+}
+
+
+
+// ====================
+// === RetryingCall ===
+// ====================
+
+/// A timer abstraction, so that tests can run without real delays.
+pub trait Sleeper {
+    /// A future completing after given duration.
+    fn sleep(&mut self, duration:Duration) -> LocalBoxFuture<'static,()>;
+}
+
+/// Wraps a handler so that calls made through the wrapper respect the retry
+/// policies. The wrapper exposes the same `open_request` entry point that the
+/// generated client methods use, so switching a client to retrying calls does
+/// not require touching each method.
+#[derive(Debug)]
+pub struct RetryingHandler<Notification> {
+    handler  : Rc<RefCell<Handler<Notification>>>,
+    policies : Rc<Policies>,
+}
+
+impl<Notification:DeserializeOwned + Debug + 'static> RetryingHandler<Notification> {
+    /// Wraps a handler with given policies.
+    pub fn new(handler:Handler<Notification>, policies:Policies) -> RetryingHandler<Notification> {
+        RetryingHandler {
+            handler  : Rc::new(RefCell::new(handler)),
+            policies : Rc::new(policies),
+        }
+    }
+
+    /// Makes a call, retrying according to the policy of the called method.
+    pub fn open_request<Call>
+    (&self, call:Call, sleeper:impl Sleeper + 'static) -> impl Future<Output=Result<Call::Returned>>
+    where Call : RemoteMethodCall + Clone + 'static {
+        let policy   = self.policies.get(Call::NAME);
+        let handler  = self.handler.clone_ref();
+        let mut sleeper = sleeper;
+        async move {
+            let mut attempt = 0;
+            loop {
+                let request = handler.borrow_mut().open_request(call.clone());
+                let result  = request.await;
+                let backoff = match policy {
+                    RetryPolicy::Never            => return result,
+                    RetryPolicy::Backoff(backoff) => backoff,
+                };
+                match result {
+                    Err(ref error) if is_transient(error) && attempt < backoff.max_retries => {
+                        sleeper.sleep(backoff.delay(attempt)).await;
+                        attempt += 1;
+                    }
+                    _ => return result,
+                }
+            }
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let backoff = Backoff::default();
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(1), Duration::from_millis(200));
+        assert_eq!(backoff.delay(2), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn default_policy_is_never() {
+        let policies = Policies::new();
+        assert_eq!(policies.get("anything"), RetryPolicy::Never);
+    }
+
+    #[test]
+    fn remote_errors_are_not_transient() {
+        let error = RpcError::RemoteError(crate::messages::Error {
+            code    : 1,
+            message : "file not found".to_string(),
+            data    : None,
+        });
+        assert!(!is_transient(&error));
+        assert!(is_transient(&RpcError::LostConnection));
+    }
+}
@@ -0,0 +1,199 @@
+//! JSON Schema description of the wire format.
+//!
+//! The schema (draft-07) is built from the same structure as the types in
+//! `messages`, and the tests in this module serialize representative values
+//! of those types and check them against it. The Scala side runs the same
+//! schema against its own serializers, so the two ends of the protocol are
+//! cross-checked mechanically instead of drifting silently.
+//!
+//! Method parameters and results are intentionally left open (any JSON
+//! value) — their shapes belong to the individual services, not to the
+//! protocol layer.
+
+use serde_json::json;
+use serde_json::Value;
+
+
+
+// ==============
+// === Schema ===
+// ==============
+
+/// The JSON Schema of a single JSON-RPC 2.0 message as used by this crate:
+/// a request, a notification, or a response (successful or failed).
+pub fn message_schema() -> Value {
+    json!({
+        "$schema"     : "http://json-schema.org/draft-07/schema#",
+        "title"       : "JSON-RPC 2.0 message",
+        "oneOf"       : [
+            {"$ref" : "#/definitions/request"},
+            {"$ref" : "#/definitions/notification"},
+            {"$ref" : "#/definitions/response"},
+        ],
+        "definitions" : {
+            "jsonrpc" : {"enum" : ["2.0"]},
+            "id"      : {"type" : "integer"},
+            "request" : {
+                "type"       : "object",
+                "required"   : ["jsonrpc","id","method","params"],
+                "properties" : {
+                    "jsonrpc" : {"$ref" : "#/definitions/jsonrpc"},
+                    "id"      : {"$ref" : "#/definitions/id"},
+                    "method"  : {"type" : "string"},
+                    "params"  : {},
+                },
+            },
+            "notification" : {
+                "type"       : "object",
+                "required"   : ["jsonrpc","method","params"],
+                "not"        : {"required" : ["id"]},
+                "properties" : {
+                    "jsonrpc" : {"$ref" : "#/definitions/jsonrpc"},
+                    "method"  : {"type" : "string"},
+                    "params"  : {},
+                },
+            },
+            "response" : {
+                "type"       : "object",
+                "required"   : ["jsonrpc","id"],
+                "oneOf"      : [
+                    {"required" : ["result"]},
+                    {"required" : ["error"]},
+                ],
+                "properties" : {
+                    "jsonrpc" : {"$ref" : "#/definitions/jsonrpc"},
+                    "id"      : {"$ref" : "#/definitions/id"},
+                    "result"  : {},
+                    "error"   : {"$ref" : "#/definitions/error"},
+                },
+            },
+            "error" : {
+                "type"       : "object",
+                "required"   : ["code","message"],
+                "properties" : {
+                    "code"    : {"type" : "integer"},
+                    "message" : {"type" : "string"},
+                    "data"    : {},
+                },
+            },
+        },
+    })
+}
+
+
+
+// =================
+// === Conforms ===
+// =================
+
+/// Checks whether the value conforms to the schema.
+///
+/// This is not a general JSON Schema validator — it supports exactly the
+/// vocabulary that `message_schema` uses (`type`, `enum`, `properties`,
+/// `required`, `oneOf`, `not`, `$ref` into `#/definitions`), which keeps it
+/// small while still catching format drift in tests.
+pub fn conforms(value:&Value, schema:&Value) -> bool {
+    conforms_in(value, schema, schema)
+}
+
+fn conforms_in(value:&Value, schema:&Value, root:&Value) -> bool {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name     = reference.trim_start_matches("#/definitions/");
+        let resolved = &root["definitions"][name];
+        return conforms_in(value, resolved, root);
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return false;
+        }
+    }
+    if let Some(ty) = schema.get("type").and_then(Value::as_str) {
+        let ok = match ty {
+            "object"  => value.is_object(),
+            "array"   => value.is_array(),
+            "string"  => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number"  => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null"    => value.is_null(),
+            _         => false,
+        };
+        if !ok {
+            return false;
+        }
+    }
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        let has = |name:&Value| name.as_str().map_or(false, |n| value.get(n).is_some());
+        if !required.iter().all(has) {
+            return false;
+        }
+    }
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        for (name,property) in properties {
+            if let Some(field) = value.get(name) {
+                if !conforms_in(field, property, root) {
+                    return false;
+                }
+            }
+        }
+    }
+    if let Some(branches) = schema.get("oneOf").and_then(Value::as_array) {
+        let matching = branches.iter().filter(|b| conforms_in(value,b,root)).count();
+        if matching != 1 {
+            return false;
+        }
+    }
+    if let Some(negated) = schema.get("not") {
+        if conforms_in(value, negated, root) {
+            return false;
+        }
+    }
+    true
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::Id;
+    use crate::messages::Message;
+    use crate::messages::MethodCall;
+    use crate::messages::Request;
+
+    fn check(value:Value) {
+        let schema = message_schema();
+        assert!(conforms(&value,&schema), "value does not match the schema: {}", value);
+    }
+
+    #[test]
+    fn our_request_matches_the_schema() {
+        let call    = MethodCall {method:"ping".to_string(), params:json!({})};
+        let request = Message::new(Request::new(Id(1),call));
+        check(serde_json::to_value(&request).unwrap());
+    }
+
+    #[test]
+    fn reference_payloads_match_the_schema() {
+        check(json!({"jsonrpc":"2.0", "id":5, "result":true}));
+        check(json!({"jsonrpc":"2.0", "id":5, "error":{"code":1, "message":"err"}}));
+        check(json!({"jsonrpc":"2.0", "method":"event", "params":{"a":1}}));
+    }
+
+    #[test]
+    fn malformed_payloads_are_rejected() {
+        let schema = message_schema();
+        // Wrong version.
+        assert!(!conforms(&json!({"jsonrpc":"1.0", "id":1, "result":true}), &schema));
+        // Both result and error.
+        let both = json!({"jsonrpc":"2.0", "id":1, "result":true,
+                          "error":{"code":1,"message":"m"}});
+        assert!(!conforms(&both, &schema));
+        // A response missing both result and error.
+        assert!(!conforms(&json!({"jsonrpc":"2.0", "id":1}), &schema));
+    }
+}
@@ -0,0 +1,133 @@
+//! Facilities for testing the protocol layer and the typed clients built on
+//! top of it.
+
+use std::future::Future;
+
+
+
+// ========================
+// === Future utilities ===
+// ========================
+
+/// Polls the future once. Returns `Some` if the future has completed.
+pub fn poll_future_output<F:Future + Unpin>(future:&mut F) -> Option<F::Output> {
+    let mut context = futures::task::Context::from_waker(futures::task::noop_waker_ref());
+    match futures::Future::poll(std::pin::Pin::new(future), &mut context) {
+        std::task::Poll::Ready(output) => Some(output),
+        std::task::Poll::Pending       => None,
+    }
+}
+
+/// Polls the stream once. Returns `Some` if the stream has yielded a value.
+pub fn poll_stream_output<S:futures::Stream + Unpin>(stream:&mut S) -> Option<S::Item> {
+    let mut context = futures::task::Context::from_waker(futures::task::noop_waker_ref());
+    match futures::Stream::poll_next(std::pin::Pin::new(stream), &mut context) {
+        std::task::Poll::Ready(output) => output,
+        std::task::Poll::Pending       => None,
+    }
+}
+
+
+
+// =================
+// === Transport ===
+// =================
+
+/// Mock transport implementations.
+pub mod transport {
+    /// A mock transport that records sent messages and allows tests to inject
+    /// incoming events.
+    pub mod mock {
+        use prelude::*;
+
+        use crate::transport::Transport;
+        use crate::transport::TransportEvent;
+
+        use futures::channel::mpsc::UnboundedSender;
+
+        /// Shared state of the mock transport.
+        #[derive(Debug,Default)]
+        pub struct MockTransportData {
+            /// Events transmitter set by the handler.
+            pub event_transmitter : Option<UnboundedSender<TransportEvent>>,
+            /// Messages sent through this transport, oldest first.
+            pub sent_messages : VecDeque<String>,
+            /// Whether the connection is currently up.
+            pub is_open : bool,
+        }
+
+        /// A mock transport for tests. Cloning shares the underlying state.
+        #[derive(Clone,Debug,Default)]
+        pub struct MockTransport(Rc<RefCell<MockTransportData>>);
+
+        impl Transport for MockTransport {
+            fn send_text(&mut self, message:String) {
+                self.0.borrow_mut().sent_messages.push_back(message);
+            }
+
+            fn close(&mut self) {
+                self.0.borrow_mut().is_open = false;
+            }
+
+            fn set_event_transmitter(&mut self, transmitter:UnboundedSender<TransportEvent>) {
+                self.0.borrow_mut().event_transmitter = Some(transmitter);
+            }
+        }
+
+        impl MockTransport {
+            /// Creates a new, open mock transport.
+            pub fn new() -> MockTransport {
+                let transport = MockTransport::default();
+                transport.0.borrow_mut().is_open = true;
+                transport
+            }
+
+            /// Simulates the peer sending us a text message.
+            pub fn mock_peer_message_text(&self, message:impl Str) {
+                let message = message.into();
+                if let Some(transmitter) = &self.0.borrow().event_transmitter {
+                    let _ = transmitter.unbounded_send(TransportEvent::TextMessage(message));
+                }
+            }
+
+            /// Simulates the peer sending us a JSON message.
+            pub fn mock_peer_message<T:serde::Serialize>(&self, message:T) {
+                let text = serde_json::to_string(&message).expect("serialization failed");
+                self.mock_peer_message_text(text)
+            }
+
+            /// Simulates the connection going down.
+            pub fn mock_connection_closed(&self) {
+                let mut data = self.0.borrow_mut();
+                data.is_open = false;
+                if let Some(transmitter) = &data.event_transmitter {
+                    let _ = transmitter.unbounded_send(TransportEvent::Closed);
+                }
+            }
+
+            /// Takes the oldest message sent through this transport.
+            ///
+            /// Panics if no message was sent.
+            pub fn expect_message_text(&self) -> String {
+                self.0.borrow_mut().sent_messages.pop_front()
+                    .expect("no message was sent through the transport")
+            }
+
+            /// Takes the oldest sent message and decodes it as JSON.
+            pub fn expect_message_json(&self) -> serde_json::Value {
+                let text = self.expect_message_text();
+                serde_json::from_str(&text).expect("sent message is not valid JSON")
+            }
+
+            /// Whether the connection is currently up.
+            pub fn is_open(&self) -> bool {
+                self.0.borrow().is_open
+            }
+
+            /// Whether any message awaits taking by `expect_message_text`.
+            pub fn has_sent_messages(&self) -> bool {
+                !self.0.borrow().sent_messages.is_empty()
+            }
+        }
+    }
+}
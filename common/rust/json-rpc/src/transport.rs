@@ -0,0 +1,268 @@
+//! An abstraction over the connection that carries JSON-RPC messages.
+
+use prelude::*;
+
+use futures::channel::mpsc::UnboundedSender;
+
+
+
+// ======================
+// === TransportEvent ===
+// ======================
+
+/// An event produced by the transport.
+#[derive(Clone,Debug,PartialEq)]
+pub enum TransportEvent {
+    /// A text message has arrived.
+    TextMessage(String),
+    /// The connection has been established (or re-established).
+    Opened,
+    /// The connection has been closed.
+    Closed,
+}
+
+
+
+// =================
+// === Transport ===
+// =================
+
+/// An entity that is able to send text messages to the peer and emit events
+/// describing incoming messages and changes of the connection state.
+///
+/// The transport does not deal with the JSON-RPC protocol itself — it only
+/// carries opaque text frames. A WebSocket connection is the typical
+/// implementor.
+pub trait Transport : Debug {
+    /// Sends a text message to the peer.
+    ///
+    /// Note that it is not guaranteed that the peer receives the message —
+    /// the connection may fail at any point. Delivery guarantees must be
+    /// built on top, in the protocol layer.
+    fn send_text(&mut self, message:String);
+
+    /// Closes the underlying connection.
+    ///
+    /// The default implementation does nothing; transports that own a real
+    /// connection should override it. After this call the transport must not
+    /// emit any events besides `Closed`.
+    fn close(&mut self) {}
+
+    /// Sets the channel that the transport will use to emit its events.
+    ///
+    /// The transport is expected to emit `Opened` once connected, a
+    /// `TextMessage` for each incoming frame, and `Closed` when the
+    /// connection goes down.
+    fn set_event_transmitter(&mut self, transmitter:UnboundedSender<TransportEvent>);
+}
+
+
+
+// ===============
+// === Framing ===
+// ===============
+
+/// How messages are delimited on a raw byte stream.
+///
+/// A WebSocket gives us message boundaries for free; a plain TCP socket or
+/// a stdio pipe does not, so a transport built on one pushes its bytes
+/// through a `FramingCodec`. Which framing to use is a property of the
+/// peer, chosen per connection.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Framing {
+    /// One message per line, terminated by `\n` (an optional preceding `\r`
+    /// is stripped). Fits backends that speak one JSON value per line.
+    Newline,
+    /// LSP-style: a `Content-Length: N` header block terminated by
+    /// `\r\n\r\n`, followed by exactly `N` bytes of payload.
+    ContentLength,
+}
+
+/// A problem with an incoming byte stream.
+///
+/// Framing errors are not recoverable: once the stream is out of sync
+/// there is no way to find the next message boundary, so the connection
+/// should be dropped.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum FramingError {
+    /// The header block could not be parsed.
+    InvalidHeader(String),
+    /// A frame's payload is not valid UTF-8.
+    InvalidUtf8,
+}
+
+impl Display for FramingError {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FramingError::InvalidHeader(header) =>
+                write!(f, "cannot parse framing header: {}", header),
+            FramingError::InvalidUtf8 =>
+                write!(f, "the frame payload is not valid UTF-8"),
+        }
+    }
+}
+
+/// An incremental encoder/decoder of message frames on a byte stream.
+///
+/// Feed incoming chunks — of whatever size the socket hands over — to
+/// `decode`; it buffers partial frames internally and returns each message
+/// exactly once, as soon as it is complete.
+#[derive(Clone,Debug)]
+pub struct FramingCodec {
+    framing : Framing,
+    buffer  : Vec<u8>,
+}
+
+impl FramingCodec {
+    /// A fresh codec for the given framing.
+    pub fn new(framing:Framing) -> FramingCodec {
+        FramingCodec {framing, buffer:Vec::new()}
+    }
+
+    /// Wraps an outgoing message into a frame.
+    ///
+    /// With `Newline` framing the message itself must not contain a
+    /// newline; JSON-RPC messages never do unless pretty-printed.
+    pub fn encode(&self, message:&str) -> Vec<u8> {
+        match self.framing {
+            Framing::Newline => {
+                debug_assert!(!message.contains('\n'),
+                    "a newline-framed message must not contain newlines");
+                let mut frame = message.as_bytes().to_vec();
+                frame.push(b'\n');
+                frame
+            }
+            Framing::ContentLength => {
+                let header = format!("Content-Length: {}\r\n\r\n", message.len());
+                let mut frame = header.into_bytes();
+                frame.extend_from_slice(message.as_bytes());
+                frame
+            }
+        }
+    }
+
+    /// Consumes an incoming chunk and returns the messages it completed.
+    pub fn decode(&mut self, chunk:&[u8]) -> Result<Vec<String>,FramingError> {
+        self.buffer.extend_from_slice(chunk);
+        let mut messages = Vec::new();
+        loop {
+            let message = match self.framing {
+                Framing::Newline       => self.take_line()?,
+                Framing::ContentLength => self.take_content_length()?,
+            };
+            match message {
+                Some(message) => messages.push(message),
+                None          => break,
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Takes one complete newline-terminated frame off the buffer.
+    fn take_line(&mut self) -> Result<Option<String>,FramingError> {
+        let end = match self.buffer.iter().position(|byte| *byte == b'\n') {
+            Some(end) => end,
+            None      => return Ok(None),
+        };
+        let mut line:Vec<u8> = self.buffer.drain(..=end).collect();
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(Some(into_text(line)?))
+    }
+
+    /// Takes one complete header-plus-payload frame off the buffer.
+    fn take_content_length(&mut self) -> Result<Option<String>,FramingError> {
+        const SEPARATOR:&[u8] = b"\r\n\r\n";
+        let headers_end = match find(&self.buffer, SEPARATOR) {
+            Some(position) => position,
+            None           => return Ok(None),
+        };
+        let headers = String::from_utf8(self.buffer[..headers_end].to_vec())
+            .map_err(|_| FramingError::InvalidUtf8)?;
+        let length = content_length(&headers)
+            .ok_or_else(|| FramingError::InvalidHeader(headers.clone()))?;
+        let payload_start = headers_end + SEPARATOR.len();
+        if self.buffer.len() < payload_start + length {
+            return Ok(None);
+        }
+        self.buffer.drain(..payload_start);
+        let payload:Vec<u8> = self.buffer.drain(..length).collect();
+        Ok(Some(into_text(payload)?))
+    }
+}
+
+/// The value of the `Content-Length` header in the header block, matched
+/// case-insensitively as the LSP specification requires.
+fn content_length(headers:&str) -> Option<usize> {
+    for header in headers.split("\r\n") {
+        let mut parts = header.splitn(2, ':');
+        let name  = parts.next()?.trim();
+        let value = parts.next()?.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            return value.parse().ok();
+        }
+    }
+    None
+}
+
+/// The position of the first occurrence of `pattern` in `haystack`.
+fn find(haystack:&[u8], pattern:&[u8]) -> Option<usize> {
+    haystack.windows(pattern.len()).position(|window| window == pattern)
+}
+
+fn into_text(bytes:Vec<u8>) -> Result<String,FramingError> {
+    String::from_utf8(bytes).map_err(|_| FramingError::InvalidUtf8)
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newline_frames_survive_arbitrary_chunking() {
+        let mut codec = FramingCodec::new(Framing::Newline);
+        assert_eq!(codec.encode("{}"), b"{}\n");
+
+        // Two messages arriving in three chunks, with a CRLF ending.
+        assert_eq!(codec.decode(b"{\"a\":1}\r").unwrap(), Vec::<String>::new());
+        assert_eq!(codec.decode(b"\n{\"b\"").unwrap(), vec!["{\"a\":1}".to_string()]);
+        assert_eq!(codec.decode(b":2}\n").unwrap(), vec!["{\"b\":2}".to_string()]);
+    }
+
+    #[test]
+    fn content_length_frames_roundtrip() {
+        let mut codec = FramingCodec::new(Framing::ContentLength);
+        let frame = codec.encode("{\"x\":1}");
+        assert_eq!(frame, b"Content-Length: 7\r\n\r\n{\"x\":1}");
+
+        // Delivered byte by byte, the message still comes out whole, once.
+        let mut received = Vec::new();
+        for byte in frame {
+            received.extend(codec.decode(&[byte]).unwrap());
+        }
+        assert_eq!(received, vec!["{\"x\":1}".to_string()]);
+
+        // Unknown extra headers are allowed; the casing is not significant.
+        let exotic = b"content-length: 2\r\nContent-Type: application/json\r\n\r\n[]";
+        assert_eq!(codec.decode(exotic).unwrap(), vec!["[]".to_string()]);
+    }
+
+    #[test]
+    fn malformed_streams_are_rejected() {
+        let mut codec = FramingCodec::new(Framing::ContentLength);
+        let result = codec.decode(b"Content-Size: 2\r\n\r\n[]");
+        assert_eq!(result, Err(FramingError::InvalidHeader(
+            "Content-Size: 2".to_string())));
+
+        let mut codec = FramingCodec::new(Framing::Newline);
+        assert_eq!(codec.decode(&[0xff, b'\n']), Err(FramingError::InvalidUtf8));
+    }
+}
@@ -0,0 +1,51 @@
+//! Packages the ScalaJS parser artifact with the crate.
+//!
+//! The Scala build (see `build.sbt`) emits the parser as a single JS file;
+//! this script copies it into `OUT_DIR/parser.js`, where embedders can pick
+//! it up and serve it under the path configured in `jsclient`. The artifact
+//! itself is used as-is: the `__ScalaJSEnv` setup happens at runtime in
+//! `jsclient`, so the same file works in a browser and under Node.
+//!
+//! The artifact location can be overridden with the `ENSO_PARSER_JS_PATH`
+//! environment variable. When no artifact is found — e.g. in environments
+//! without the Scala toolchain — packaging is skipped with a warning, so the
+//! crate still builds; only the wasm client will be unable to find its
+//! module at runtime.
+
+use std::env;
+use std::path::PathBuf;
+
+/// Environment variable overriding where the ScalaJS artifact is taken from.
+const ARTIFACT_PATH_VAR:&str = "ENSO_PARSER_JS_PATH";
+
+/// Where `build.sbt` puts the artifact, relative to the repository root.
+const DEFAULT_ARTIFACT_PATH:&str = "target/scala-parser.js";
+
+/// The artifact to package: the override from the environment if set,
+/// otherwise the Scala build's default output location.
+fn artifact_path() -> PathBuf {
+    match env::var_os(ARTIFACT_PATH_VAR) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let crate_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            crate_root.join("../../..").join(DEFAULT_ARTIFACT_PATH)
+        }
+    }
+}
+
+fn main() {
+    let source = artifact_path();
+    println!("cargo:rerun-if-env-changed={}", ARTIFACT_PATH_VAR);
+    println!("cargo:rerun-if-changed={}", source.display());
+    if !source.exists() {
+        println!("cargo:warning=ScalaJS parser artifact not found at {}; \
+                  packaging skipped (set {} or run the Scala build)",
+                 source.display(), ARTIFACT_PATH_VAR);
+        return;
+    }
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let target  = out_dir.join("parser.js");
+    if let Err(error) = std::fs::copy(&source,&target) {
+        panic!("cannot copy {} to {}: {}", source.display(), target.display(), error);
+    }
+}
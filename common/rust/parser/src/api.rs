@@ -0,0 +1,159 @@
+//! The API of the parser — the types exchanged with its implementations and
+//! the errors they may raise.
+
+use prelude::*;
+
+use ast::Ast;
+
+use std::time::Duration;
+
+
+
+// ==============
+// === Error ===
+// ==============
+
+/// A result of parsing.
+pub type Result<T> = std::result::Result<T,Error>;
+
+/// An error that may happen when asking the parser to process a program.
+///
+/// The variants distinguish the failure modes a caller can meaningfully act
+/// upon: retrying (transport, timeout), prompting the user to start the
+/// backend, reporting a program error, or filing a schema-drift bug.
+#[derive(Debug)]
+pub enum Error {
+    /// The communication with the backend broke down mid-call.
+    TransportFailure {
+        /// Description of the failure.
+        details : String,
+        /// The underlying error, if any.
+        source : Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// The parser backend is not running or could not be reached at all.
+    BackendUnavailable {
+        /// Description of what was attempted.
+        details : String,
+    },
+    /// The backend panicked while parsing — a syntax-handling bug on its
+    /// side.
+    SyntaxPanic {
+        /// The panic message reported by the backend.
+        message : String,
+    },
+    /// The backend replied with JSON that does not match our AST schema.
+    SchemaMismatch {
+        /// An excerpt of the offending JSON.
+        excerpt : String,
+        /// The decoding error.
+        source : serde_json::Error,
+    },
+    /// The backend did not reply within the time limit.
+    Timeout {
+        /// The limit that was exceeded.
+        after : Duration,
+    },
+    /// The caller's input does not fit the requested entry point, e.g.
+    /// multi-line text passed to the single-line parser.
+    InvalidInput {
+        /// Description of the mismatch.
+        details : String,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::TransportFailure {details,..} =>
+                write!(f, "error in the communication with the parser: {}", details),
+            Error::BackendUnavailable {details} =>
+                write!(f, "the parser service is not available: {}", details),
+            Error::SyntaxPanic {message} =>
+                write!(f, "the parser panicked while parsing: {}", message),
+            Error::SchemaMismatch {excerpt,..} =>
+                write!(f, "failed to interpret parser's reply (near: {})", excerpt),
+            Error::Timeout {after} =>
+                write!(f, "the parser did not reply within {:?}", after),
+            Error::InvalidInput {details} =>
+                write!(f, "invalid parser input: {}", details),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::TransportFailure {source,..} =>
+                source.as_ref().map(|e| e.as_ref() as &(dyn std::error::Error + 'static)),
+            Error::SchemaMismatch {source,..} => Some(source),
+            _                                 => None,
+        }
+    }
+}
+
+impl Error {
+    /// Creates a schema-mismatch error, storing an excerpt of the offending
+    /// JSON alongside the decoding error.
+    pub fn schema_mismatch(json:&str, source:serde_json::Error) -> Error {
+        const EXCERPT_LEN:usize = 256;
+        let excerpt = json.chars().take(EXCERPT_LEN).collect();
+        Error::SchemaMismatch {excerpt,source}
+    }
+}
+
+/// Wraps an arbitrary error as a transport failure.
+pub fn interop_error<E>(error:E) -> Error
+where E : std::error::Error + Send + Sync + 'static {
+    Error::TransportFailure {
+        details : error.to_string(),
+        source  : Some(Box::new(error)),
+    }
+}
+
+
+
+// ==============
+// === Parser ===
+// ==============
+
+/// An entity that parses Enso programs.
+pub trait IsParser : Debug {
+    /// Parses the whole program source into its AST representation.
+    fn parse(&mut self, program:String) -> Result<Ast>;
+
+    /// Parses a single inline expression, returning its AST directly —
+    /// without the `Module` and line layers that `parse` wraps it in.
+    ///
+    /// Multi-line input is rejected, as is input that parses to anything
+    /// other than exactly one expression line.
+    fn parse_line(&mut self, line:String) -> Result<Ast> {
+        if line.contains('\n') {
+            let details = "expected a single line of code".to_string();
+            return Err(Error::InvalidInput {details});
+        }
+        let module = self.parse(line)?;
+        let lines  = match module.shape() {
+            ast::Shape::Module(module) => &module.lines,
+            _ => {
+                let details = "the parser did not return a module".to_string();
+                return Err(Error::InvalidInput {details});
+            }
+        };
+        let mut expressions = lines.iter().filter_map(|line| line.elem.as_ref());
+        match (expressions.next(), expressions.next()) {
+            (Some(expression), None) => Ok(expression.clone()),
+            _ => {
+                let details = "expected exactly one expression".to_string();
+                Err(Error::InvalidInput {details})
+            }
+        }
+    }
+
+    /// Parses a single expression, ignoring surrounding whitespace. See
+    /// `parse_line`.
+    ///
+    /// Takes a plain `&str` so the trait stays usable as an object.
+    fn parse_expression(&mut self, text:&str) -> Result<Ast> {
+        self.parse_line(text.trim().to_string())
+    }
+}
@@ -0,0 +1,192 @@
+//! A command-line AST inspector.
+//!
+//! `enso-ast <command> <file>` parses a file with the configured backend
+//! and lets you look at the result without starting the IDE — invaluable
+//! when debugging parser integration:
+//!
+//! * `parse` — parse and print the reproduced source (a quick roundtrip
+//!   smoke test);
+//! * `dump` — print the AST as `--format` `json` (default), `sexp` or
+//!   `dot`;
+//! * `validate` — check that the reproduced source matches the file and
+//!   all spans are consistent;
+//! * `diagnostics` — list the parse problems (unrecognized tokens, invalid
+//!   suffixes, unclosed literals) with their spans.
+
+use ast::Ast;
+use ast::HasRepr;
+use parser::api::IsParser;
+use parser::Parser;
+
+use std::process::exit;
+
+
+
+// ===================
+// === Entry point ===
+// ===================
+
+fn main() {
+    let args:Vec<String> = std::env::args().skip(1).collect();
+    let (command,path,format) = match parse_args(&args) {
+        Some(parsed) => parsed,
+        None => {
+            eprintln!("usage: enso-ast <parse|dump|validate|diagnostics> <file> \
+                       [--format json|sexp|dot]");
+            exit(2);
+        }
+    };
+
+    let program = match std::fs::read_to_string(path) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("enso-ast: cannot read {}: {}", path, error);
+            exit(2);
+        }
+    };
+    let ast = match Parser::new_or_panic().parse(program.clone()) {
+        Ok(ast) => ast,
+        Err(error) => {
+            eprintln!("enso-ast: parse failed: {}", error);
+            exit(1);
+        }
+    };
+
+    let ok = match command {
+        "parse"       => { println!("{}", ast.repr()); true }
+        "dump"        => dump(&ast,format),
+        "validate"    => validate(&ast,&program),
+        "diagnostics" => diagnostics(&ast),
+        _             => unreachable!("commands are checked in parse_args"),
+    };
+    if !ok {
+        exit(1);
+    }
+}
+
+fn parse_args(args:&[String]) -> Option<(&str,&str,&str)> {
+    let command = args.first()?.as_str();
+    if !["parse","dump","validate","diagnostics"].contains(&command) {
+        return None;
+    }
+    let path = args.get(1)?.as_str();
+    let format = match args.get(2).map(String::as_str) {
+        None             => "json",
+        Some("--format") => args.get(3)?.as_str(),
+        Some(_)          => return None,
+    };
+    if !["json","sexp","dot"].contains(&format) {
+        return None;
+    }
+    Some((command,path,format))
+}
+
+
+
+// ================
+// === Commands ===
+// ================
+
+fn dump(ast:&Ast, format:&str) -> bool {
+    match format {
+        "json" => match serde_json::to_string_pretty(ast) {
+            Ok(json) => { println!("{}", json); true }
+            Err(error) => { eprintln!("enso-ast: cannot serialize: {}", error); false }
+        },
+        "sexp" => { println!("{}", sexp(ast)); true }
+        "dot"  => { print_dot(ast); true }
+        _      => unreachable!("formats are checked in parse_args"),
+    }
+}
+
+/// Checks that the tree reproduces the source text and that the spans are
+/// internally consistent: every node's span slices the text at character
+/// boundaries and contains the spans of all its children.
+fn validate(ast:&Ast, program:&str) -> bool {
+    let mut ok = true;
+    if ast.repr() != program {
+        eprintln!("repr differs from the source text");
+        ok = false;
+    }
+    for hit in ast::search::all_nodes(ast) {
+        let end = hit.span.end().value;
+        if end > program.chars().count() {
+            eprintln!("span of node at {:?} runs past the end of the text", hit.crumbs);
+            ok = false;
+            continue;
+        }
+        let node = ast.get_node(&hit.crumbs).unwrap();
+        let text:String = program.chars()
+            .skip(hit.span.index.value)
+            .take(hit.span.size.value)
+            .collect();
+        if text != node.repr() {
+            eprintln!("node at {:?} claims {:?} but the text there is {:?}",
+                hit.crumbs, node.repr(), text);
+            ok = false;
+        }
+    }
+    if ok {
+        println!("OK: {} nodes, {} characters", ast::search::all_nodes(ast).len(),
+            program.chars().count());
+    }
+    ok
+}
+
+fn diagnostics(ast:&Ast) -> bool {
+    let mut clean = true;
+    for hit in ast::search::all_nodes(ast) {
+        let node = ast.get_node(&hit.crumbs).unwrap();
+        if node.shape().is_error() {
+            println!("{}..{}: {}: {:?}", hit.span.index.value, hit.span.end().value,
+                node.shape().name(), node.repr());
+            clean = false;
+        }
+    }
+    if clean {
+        println!("no diagnostics");
+    }
+    true
+}
+
+
+
+// ===============
+// === Formats ===
+// ===============
+
+/// Renders the tree as a compact s-expression of shape names, with the
+/// token text attached to the leaves.
+fn sexp(ast:&Ast) -> String {
+    let children = ast.children();
+    if children.is_empty() {
+        format!("({} {:?})", ast.shape().name(), ast.repr())
+    } else {
+        let children:Vec<String> = children.into_iter().map(sexp).collect();
+        format!("({} {})", ast.shape().name(), children.join(" "))
+    }
+}
+
+/// Renders the tree as a GraphViz digraph, one node per AST node.
+fn print_dot(ast:&Ast) {
+    println!("digraph ast {{");
+    let mut counter = 0;
+    dot_node(ast, &mut counter);
+    println!("}}");
+}
+
+fn dot_node(ast:&Ast, counter:&mut usize) -> usize {
+    let this = *counter;
+    *counter += 1;
+    let label = if ast.children().is_empty() {
+        format!("{}\\n{}", ast.shape().name(), ast.repr().replace('"',"\\\""))
+    } else {
+        ast.shape().name().to_string()
+    };
+    println!("    n{} [label=\"{}\"];", this, label);
+    for child in ast.children() {
+        let child_id = dot_node(child, counter);
+        println!("    n{} -> n{};", this, child_id);
+    }
+    this
+}
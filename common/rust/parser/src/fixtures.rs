@@ -0,0 +1,179 @@
+//! A curated corpus of golden Enso snippets.
+//!
+//! Each fixture pairs a source snippet with the exact AST the parser is
+//! expected to produce for it, as JSON in the wire schema. New AST
+//! features can pick realistic inputs by tag (`literal`, `operator`,
+//! `block`, …) instead of inventing trees inline, and the embedded JSON
+//! doubles as a schema regression test: if the serialization format
+//! drifts, the fixtures stop deserializing.
+//!
+//! The corpus needs no parser backend — the expected trees are embedded —
+//! so unlike the round-trip suite it runs in every test run.
+
+use ast::Ast;
+use ast::HasRepr;
+use ast::HasSpan;
+
+
+
+// ===============
+// === Fixture ===
+// ===============
+
+/// One golden snippet: a source text and the AST it parses to.
+#[derive(Clone,Copy,Debug)]
+pub struct Fixture {
+    /// A unique, human-readable name.
+    pub name : &'static str,
+    /// The categories the snippet exercises.
+    pub tags : &'static [&'static str],
+    /// The source text — also the expected repr of the tree.
+    pub source : &'static str,
+    /// The expected tree, in the wire JSON schema, without ids.
+    pub json : &'static str,
+}
+
+impl Fixture {
+    /// The expected tree, deserialized from the embedded JSON.
+    pub fn expected_ast(&self) -> Ast {
+        serde_json::from_str(self.json)
+            .unwrap_or_else(|e| panic!("fixture `{}` has invalid JSON: {}", self.name, e))
+    }
+
+    /// Asserts that the given tree is the fixture's expected one: same
+    /// repr, consistent span, and the same structure once ids (which the
+    /// parser assigns freshly) are ignored.
+    pub fn assert_matches(&self, ast:&Ast) {
+        assert_eq!(ast.repr(), self.source,
+            "fixture `{}`: repr differs from the source", self.name);
+        assert_eq!(ast.span(), ast.repr().chars().count(),
+            "fixture `{}`: span disagrees with repr length", self.name);
+        assert_eq!(without_ids(ast), self.expected_ast(),
+            "fixture `{}`: tree structure differs", self.name);
+    }
+}
+
+/// The tree with every id dropped, for structural comparison.
+fn without_ids(ast:&Ast) -> Ast {
+    let shape = ast.shape().map_children(without_ids);
+    Ast::new(shape, None)
+}
+
+
+
+// ==============
+// === Corpus ===
+// ==============
+
+/// The whole corpus.
+pub const ALL:&[Fixture] = &[
+    Fixture {
+        name   : "variable",
+        tags   : &["identifier"],
+        source : "foo",
+        json   : r#"{"Var":{"name":"foo"},"len":3,"id":null}"#,
+    },
+    Fixture {
+        name   : "number-literal",
+        tags   : &["literal"],
+        source : "42",
+        json   : r#"{"Number":{"base":null,"int":"42"},"len":2,"id":null}"#,
+    },
+    Fixture {
+        name   : "hex-literal",
+        tags   : &["literal"],
+        source : "16_ff",
+        json   : r#"{"Number":{"base":"16","int":"ff"},"len":5,"id":null}"#,
+    },
+    Fixture {
+        name   : "sum",
+        tags   : &["operator"],
+        source : "1 + 2",
+        json   : concat!(
+            r#"{"Infix":{"larg":{"Number":{"base":null,"int":"1"},"len":1,"id":null},"#,
+            r#""loff":1,"opr":{"Opr":{"name":"+"},"len":1,"id":null},"roff":1,"#,
+            r#""rarg":{"Number":{"base":null,"int":"2"},"len":1,"id":null}},"#,
+            r#""len":5,"id":null}"#),
+    },
+    Fixture {
+        name   : "application",
+        tags   : &["application"],
+        source : "f x",
+        json   : concat!(
+            r#"{"Prefix":{"func":{"Var":{"name":"f"},"len":1,"id":null},"off":1,"#,
+            r#""arg":{"Var":{"name":"x"},"len":1,"id":null}},"len":3,"id":null}"#),
+    },
+    Fixture {
+        name   : "plus-section",
+        tags   : &["operator","section"],
+        source : "+ 1",
+        json   : concat!(
+            r#"{"SectionRight":{"opr":{"Opr":{"name":"+"},"len":1,"id":null},"off":1,"#,
+            r#""arg":{"Number":{"base":null,"int":"1"},"len":1,"id":null}},"len":3,"id":null}"#),
+    },
+    Fixture {
+        name   : "two-line-module",
+        tags   : &["block","module"],
+        source : "a\nb",
+        json   : concat!(
+            r#"{"Module":{"lines":["#,
+            r#"{"elem":{"Var":{"name":"a"},"len":1,"id":null},"off":0},"#,
+            r#"{"elem":{"Var":{"name":"b"},"len":1,"id":null},"off":0}"#,
+            r#"]},"len":3,"id":null}"#),
+    },
+];
+
+/// The fixtures carrying the given tag.
+pub fn tagged(tag:&str) -> impl Iterator<Item=&'static Fixture> + '_ {
+    ALL.iter().filter(move |fixture| fixture.tags.contains(&tag))
+}
+
+/// The fixture with the given name. Panics on a typo, so tests fail loudly
+/// rather than silently testing nothing.
+pub fn by_name(name:&str) -> &'static Fixture {
+    ALL.iter().find(|fixture| fixture.name == name)
+        .unwrap_or_else(|| panic!("no fixture named `{}`", name))
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_fixture_is_self_consistent() {
+        for fixture in ALL {
+            let expected = fixture.expected_ast();
+            // The embedded tree must already satisfy its own assertions.
+            fixture.assert_matches(&expected);
+        }
+    }
+
+    #[test]
+    fn fixture_names_are_unique() {
+        for (ix,fixture) in ALL.iter().enumerate() {
+            assert!(ALL[ix+1..].iter().all(|other| other.name != fixture.name),
+                "duplicate fixture name `{}`", fixture.name);
+        }
+    }
+
+    #[test]
+    fn tags_select_subsets() {
+        let literals:Vec<_> = tagged("literal").map(|fixture| fixture.name).collect();
+        assert_eq!(literals, ["number-literal","hex-literal"]);
+        assert_eq!(by_name("sum").source, "1 + 2");
+        assert_eq!(tagged("no-such-tag").count(), 0);
+    }
+
+    #[test]
+    fn mismatches_are_caught() {
+        let sum = by_name("sum");
+        let other = Ast::infix(Ast::number("1"), "+", Ast::number("3"));
+        assert!(std::panic::catch_unwind(|| sum.assert_matches(&other)).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! A client of the ScalaJS parser build, used on wasm targets.
+//!
+//! The ScalaJS artifact is heavy, so it is not bundled with the wasm binary.
+//! Instead it is imported into the JS context lazily — on the first parse,
+//! or earlier if the owner awaits `Client::ready`. Where the artifact lives
+//! is the embedder's decision, so the module path is configurable instead of
+//! being baked in at build time.
+//!
+//! The ScalaJS runtime expects a `__ScalaJSEnv` global describing its host.
+//! It used to be patched into the artifact by the build script, which tied
+//! the artifact to the browser's `window`; the environment is now set up at
+//! runtime against whatever global object the platform provides, so the same
+//! artifact runs in a browser and under Node.
+
+use prelude::*;
+
+use crate::api;
+use crate::api::Error;
+use crate::api::IsParser;
+
+use ast::Ast;
+use js_sys::Function;
+use std::future::Future;
+use js_sys::Promise;
+use js_sys::Reflect;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+
+
+
+// ==============
+// === Config ===
+// ==============
+
+/// Path that the parser module is imported from when none is configured.
+pub const DEFAULT_MODULE_PATH:&str = "/assets/parser.js";
+
+/// Configuration of the ScalaJS parser client.
+#[derive(Clone,Debug)]
+pub struct Config {
+    /// Path or URL that the parser module is imported from. Resolved by the
+    /// platform's module loader, so both browser URLs and Node paths work.
+    pub module_path : String,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {module_path : DEFAULT_MODULE_PATH.to_string()}
+    }
+}
+
+
+
+// ===================
+// === ScalaJS Env ===
+// ===================
+
+/// Makes sure the `__ScalaJSEnv` global expected by the ScalaJS runtime is
+/// present, pointing it at the platform's global object (the browser's
+/// `window` or Node's `global`). An existing environment is left untouched.
+fn ensure_scalajs_env() -> std::result::Result<(),JsValue> {
+    let global = js_sys::global();
+    let name   = JsValue::from("__ScalaJSEnv");
+    if !Reflect::has(&global,&name)? {
+        let env = js_sys::Object::new();
+        Reflect::set(&env, &JsValue::from("global"), &global)?;
+        Reflect::set(&global, &name, &env)?;
+    }
+    Ok(())
+}
+
+/// Imports a JS module through the platform's dynamic `import`.
+fn import_module(path:&str) -> std::result::Result<Promise,JsValue> {
+    let importer = Function::new_with_args("path", "return import(path);");
+    let promise  = importer.call1(&JsValue::NULL, &JsValue::from(path))?;
+    promise.dyn_into().map_err(|value| value)
+}
+
+/// Describes a JS exception as an `api::Error` transport failure.
+fn js_error(details:&str, error:JsValue) -> Error {
+    let details = format!("{}: {:?}", details, error);
+    Error::TransportFailure {details, source:None}
+}
+
+
+
+// ==============
+// === Client ===
+// ==============
+
+/// The loading state of the ScalaJS parser module.
+#[derive(Debug)]
+enum State {
+    /// Nothing was loaded yet.
+    NotLoaded,
+    /// The module is loaded and its `parse` export is ready to be called.
+    Ready(Function),
+    /// Loading was attempted and failed. Kept, so that every parse reports
+    /// the original failure instead of a generic "not loaded".
+    Failed(String),
+}
+
+/// A lazily initialized client of the ScalaJS parser build.
+#[derive(Debug)]
+pub struct Client {
+    config : Config,
+    state  : Rc<RefCell<State>>,
+}
+
+impl Client {
+    /// Creates a client. Nothing is loaded until the first parse or until
+    /// `ready` is awaited.
+    pub fn new(config:Config) -> Client {
+        Client {config, state:Rc::new(RefCell::new(State::NotLoaded))}
+    }
+
+    /// A future resolving once the parser module is loaded and callable.
+    ///
+    /// Awaiting it up front hides the loading latency from the first parse.
+    /// Awaiting it multiple times is cheap once the module is loaded, and
+    /// concurrent first awaits are harmless — the platform's module cache
+    /// makes the repeated imports share one download.
+    pub fn ready(&self) -> impl Future<Output=api::Result<()>> {
+        let state = self.state.clone_ref();
+        let path  = self.config.module_path.clone();
+        async move {
+            if let State::Ready(_) = &*state.borrow() {
+                return Ok(());
+            }
+            let loaded = Client::load(&path).await;
+            match loaded {
+                Ok(parse) => {
+                    *state.borrow_mut() = State::Ready(parse);
+                    Ok(())
+                }
+                Err(error) => {
+                    *state.borrow_mut() = State::Failed(error.to_string());
+                    Err(error)
+                }
+            }
+        }
+    }
+
+    /// Imports the parser module from the given path and extracts its
+    /// `parse` export.
+    async fn load(path:&str) -> api::Result<Function> {
+        ensure_scalajs_env()
+            .map_err(|e| js_error("cannot set up the ScalaJS environment",e))?;
+        let promise = import_module(path)
+            .map_err(|e| js_error("cannot import the parser module",e))?;
+        let module  = JsFuture::from(promise).await.map_err(|error| {
+            let details = format!("cannot load the parser module from {}: {:?}", path, error);
+            Error::BackendUnavailable {details}
+        })?;
+        let parse = Reflect::get(&module, &JsValue::from("parse"))
+            .map_err(|e| js_error("the parser module has no `parse` export",e))?;
+        parse.dyn_into().map_err(|_| {
+            let details = "the module's `parse` export is not a function".to_string();
+            Error::BackendUnavailable {details}
+        })
+    }
+}
+
+impl IsParser for Client {
+    fn parse(&mut self, program:String) -> api::Result<Ast> {
+        let state = self.state.borrow();
+        let parse = match &*state {
+            State::Ready(parse) => parse,
+            State::NotLoaded    => {
+                let details = "the parser module is not loaded yet; \
+                               await `Client::ready` first".to_string();
+                return Err(Error::BackendUnavailable {details});
+            }
+            State::Failed(details) => {
+                let details = details.clone();
+                return Err(Error::BackendUnavailable {details});
+            }
+        };
+        // A throw from the ScalaJS side is a parser panic, not ours.
+        let reply = parse.call1(&JsValue::NULL, &JsValue::from(program))
+            .map_err(|error| Error::SyntaxPanic {message:format!("{:?}",error)})?;
+        let json = reply.as_string().ok_or_else(|| {
+            let details = "the parser module returned a non-string value".to_string();
+            Error::TransportFailure {details, source:None}
+        })?;
+        serde_json::from_str(&json).map_err(|e| Error::schema_mismatch(&json,e))
+    }
+}
@@ -0,0 +1,66 @@
+//! Client of the Enso parser.
+//!
+//! The parser itself is implemented in Scala and runs out of process; this
+//! crate knows how to reach it (over a WebSocket on native targets, through
+//! the lazily loaded ScalaJS build on wasm) and how to decode its replies
+//! into the `ast` crate's types.
+
+#![warn(missing_docs)]
+
+pub mod api;
+pub mod fixtures;
+#[cfg(target_arch="wasm32")]
+pub mod jsclient;
+#[cfg(not(target_arch="wasm32"))]
+pub mod service;
+pub mod stub;
+#[cfg(not(target_arch="wasm32"))]
+pub mod websocket;
+
+use prelude::*;
+
+use api::IsParser;
+use ast::Ast;
+
+
+
+// ==============
+// === Parser ===
+// ==============
+
+/// The universal parser handle, hiding the platform-specific implementation.
+#[derive(Debug,Shrinkwrap)]
+#[shrinkwrap(mutable)]
+pub struct Parser(pub Box<dyn IsParser>);
+
+impl Parser {
+    /// Obtains the default parser implementation for the current platform.
+    #[cfg(not(target_arch="wasm32"))]
+    pub fn new() -> api::Result<Parser> {
+        let client = websocket::Client::new()?;
+        Ok(Parser(Box::new(client)))
+    }
+
+    /// Obtains the default parser implementation for the current platform.
+    ///
+    /// The ScalaJS parser module is loaded lazily; parses fail until the
+    /// client's readiness future resolves (see `jsclient::Client::ready`).
+    #[cfg(target_arch="wasm32")]
+    pub fn new() -> api::Result<Parser> {
+        let client = jsclient::Client::new(default());
+        Ok(Parser(Box::new(client)))
+    }
+
+    /// Obtains the default parser implementation, panicking if the backend
+    /// cannot be reached. Intended for tests and tools.
+    #[cfg(not(target_arch="wasm32"))]
+    pub fn new_or_panic() -> Parser {
+        Parser::new().expect("cannot connect to the parser service")
+    }
+}
+
+impl IsParser for Parser {
+    fn parse(&mut self, program:String) -> api::Result<Ast> {
+        self.0.parse(program)
+    }
+}
@@ -0,0 +1,172 @@
+//! Supervision of the Scala parser service process.
+//!
+//! On native targets the parser runs as a separate JVM process exposing a
+//! WebSocket. Historically users had to start it by hand and the client
+//! failed opaquely when it was missing. The `Service` type spawns the
+//! process itself, waits until the service is ready to accept connections,
+//! restarts it (with bounded retries) if it crashes, and shuts it down when
+//! dropped.
+
+use prelude::*;
+
+use crate::api;
+use crate::api::Error;
+
+use std::net::TcpStream;
+use std::net::SocketAddr;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+
+
+
+// ==============
+// === Config ===
+// ==============
+
+/// Description of how to start and supervise the parser service.
+#[derive(Clone,Debug)]
+pub struct Config {
+    /// The command used to start the service, e.g. `java`.
+    pub command : String,
+    /// Arguments passed to the command, e.g. the path to the service jar.
+    pub args : Vec<String>,
+    /// Port that the service will listen on.
+    pub port : u16,
+    /// How long to wait for the service to become ready after spawning.
+    pub startup_timeout : Duration,
+    /// How many times a crashed service will be restarted before giving up.
+    pub max_restarts : usize,
+}
+
+impl Config {
+    /// Configuration starting the service from given jar on given port.
+    pub fn from_jar(jar_path:impl Str, port:u16) -> Config {
+        Config {
+            command         : "java".to_string(),
+            args            : vec!["-jar".to_string(), jar_path.into(), port.to_string()],
+            port,
+            startup_timeout : Duration::from_secs(30),
+            max_restarts    : 3,
+        }
+    }
+
+    /// The address the service listens on.
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::from(([127,0,0,1], self.port))
+    }
+}
+
+
+
+// ===============
+// === Service ===
+// ===============
+
+/// A running, supervised parser service process.
+#[derive(Debug)]
+pub struct Service {
+    config   : Config,
+    child    : Child,
+    restarts : usize,
+}
+
+impl Service {
+    /// Spawns the service and waits until it accepts connections.
+    pub fn spawn(config:Config) -> api::Result<Service> {
+        let child    = Service::spawn_child(&config)?;
+        let restarts = 0;
+        let service  = Service {config,child,restarts};
+        service.wait_until_ready()?;
+        Ok(service)
+    }
+
+    /// The address the supervised service listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.config.address()
+    }
+
+    /// Checks the service and restarts it if it has crashed.
+    ///
+    /// Returns `true` if the service is (again) up. Once the configured
+    /// restart budget is exhausted, returns an error instead of looping on a
+    /// service that cannot stay alive.
+    pub fn ensure_running(&mut self) -> api::Result<bool> {
+        match self.child.try_wait() {
+            Ok(None)     => Ok(true),
+            Ok(Some(status)) => {
+                if self.restarts >= self.config.max_restarts {
+                    let msg = format!(
+                        "parser service keeps crashing (last status: {}), giving up after {} \
+                        restarts", status, self.restarts);
+                    return Err(Error::BackendUnavailable {details:msg});
+                }
+                self.restarts += 1;
+                self.child = Service::spawn_child(&self.config)?;
+                self.wait_until_ready()?;
+                Ok(true)
+            }
+            Err(error) => Err(api::interop_error(error)),
+        }
+    }
+
+    fn spawn_child(config:&Config) -> api::Result<Child> {
+        Command::new(&config.command)
+            .args(&config.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| Error::BackendUnavailable {
+                details : format!("cannot spawn parser service: {}", e),
+            })
+    }
+
+    /// Readiness handshake: the service is ready once it accepts a TCP
+    /// connection on its port.
+    fn wait_until_ready(&self) -> api::Result<()> {
+        let deadline = Instant::now() + self.config.startup_timeout;
+        let address  = self.config.address();
+        while Instant::now() < deadline {
+            if TcpStream::connect_timeout(&address, Duration::from_millis(250)).is_ok() {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        let details = format!("parser service did not become ready on {} within {:?}",
+            address, self.config.startup_timeout);
+        Err(Error::BackendUnavailable {details})
+    }
+}
+
+impl Drop for Service {
+    /// Shuts the service down. Any error is ignored — the process may have
+    /// already exited on its own.
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawning_nonexistent_command_fails_with_unavailable() {
+        let mut config = Config::from_jar("/nonexistent/parser.jar", 30617);
+        config.command = "surely-no-such-command".to_string();
+        match Service::spawn(config) {
+            Err(Error::BackendUnavailable {..}) => {}
+            other => panic!("expected BackendUnavailable, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! An offline parser stub for tests.
+//!
+//! Higher-level crates testing parser-dependent logic should not need wasm
+//! or the Scala service in their unit tests. The `StubParser` serves canned
+//! trees registered for exact program texts and errors on anything else, so
+//! a test whose input drifts away from the canned corpus fails loudly
+//! instead of silently testing nothing. The `FailoverParser` combines two
+//! parsers, so a suite can use the real backend where it is available and
+//! fall back to the canned corpus where it is not.
+
+use prelude::*;
+
+use crate::api;
+use crate::api::Error;
+use crate::api::IsParser;
+use crate::fixtures;
+
+use ast::Ast;
+
+
+
+// ==================
+// === StubParser ===
+// ==================
+
+/// A parser serving canned trees for registered program texts.
+#[derive(Debug,Default)]
+pub struct StubParser {
+    programs : HashMap<String,Ast>,
+}
+
+impl StubParser {
+    /// Creates a stub with no canned programs.
+    pub fn new() -> StubParser {
+        default()
+    }
+
+    /// A stub preloaded with the whole fixture corpus.
+    pub fn with_fixtures() -> StubParser {
+        let mut stub = StubParser::new();
+        for fixture in fixtures::ALL {
+            stub.register(fixture.source, fixture.expected_ast());
+        }
+        stub
+    }
+
+    /// Registers the tree to be served for the given program text.
+    ///
+    /// A tree that is not a module is wrapped in a one-line module,
+    /// mirroring what the real parser returns, so entry points built on
+    /// `parse` (like `parse_line`) work against the stub too.
+    pub fn register(&mut self, program:impl Str, ast:Ast) -> &mut StubParser {
+        let module = match ast.shape() {
+            ast::Shape::Module(_) => ast,
+            _                     => Ast::module(vec![Some(ast)]),
+        };
+        self.programs.insert(program.into(), module);
+        self
+    }
+}
+
+impl IsParser for StubParser {
+    fn parse(&mut self, program:String) -> api::Result<Ast> {
+        self.programs.get(&program).cloned().ok_or_else(|| {
+            let details = format!("the stub parser has no canned tree for {:?}", program);
+            Error::BackendUnavailable {details}
+        })
+    }
+}
+
+
+
+// ======================
+// === FailoverParser ===
+// ======================
+
+/// A parser that falls back to a second one when the first fails.
+///
+/// When both fail, the primary's error is reported — it names the real
+/// cause (e.g. the backend being down), while the fallback's one would only
+/// say that the corpus has a hole.
+#[derive(Debug)]
+pub struct FailoverParser {
+    primary  : Box<dyn IsParser>,
+    fallback : Box<dyn IsParser>,
+}
+
+impl FailoverParser {
+    /// Creates a parser trying `primary` first and `fallback` second.
+    pub fn new
+    (primary:impl IsParser + 'static, fallback:impl IsParser + 'static) -> FailoverParser {
+        FailoverParser {
+            primary  : Box::new(primary),
+            fallback : Box::new(fallback),
+        }
+    }
+}
+
+impl IsParser for FailoverParser {
+    fn parse(&mut self, program:String) -> api::Result<Ast> {
+        match self.primary.parse(program.clone()) {
+            Ok(ast)    => Ok(ast),
+            Err(error) => self.fallback.parse(program).map_err(|_| error),
+        }
+    }
+}
+
+
+
+// =============
+// === Tests ===
+// =============
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ast::HasRepr;
+
+    #[test]
+    fn stub_serves_registered_programs() {
+        let mut stub = StubParser::with_fixtures();
+        let module   = stub.parse("foo".to_string()).unwrap();
+        assert!(matches!(module.shape(), ast::Shape::Module(_)));
+        assert_eq!(module.repr(), "foo");
+        // The module wrapping keeps the line-based entry points working.
+        let expression = stub.parse_line("1 + 2".to_string()).unwrap();
+        fixtures::by_name("sum").assert_matches(&expression);
+    }
+
+    #[test]
+    fn unknown_programs_error() {
+        let mut stub = StubParser::with_fixtures();
+        let result   = stub.parse("unregistered".to_string());
+        assert!(matches!(result, Err(Error::BackendUnavailable {..})));
+    }
+
+    #[test]
+    fn failover_falls_back_to_the_canned_corpus() {
+        // An empty stub stands in for an unreachable backend.
+        let mut parser = FailoverParser::new(StubParser::new(), StubParser::with_fixtures());
+        let module     = parser.parse("f x".to_string()).unwrap();
+        assert_eq!(module.repr(), "f x");
+        // When neither side knows the program, the primary's error wins.
+        let result = parser.parse("unregistered".to_string());
+        assert!(matches!(result, Err(Error::BackendUnavailable {details})
+            if details.contains("unregistered")));
+    }
+}
@@ -0,0 +1,116 @@
+//! A blocking WebSocket client of the Scala parser service, used on native
+//! targets.
+
+use prelude::*;
+
+use crate::api;
+use crate::api::Error;
+use crate::api::IsParser;
+
+use ast::Ast;
+use serde::Serialize;
+use serde::Deserialize;
+use websocket::ClientBuilder;
+use websocket::stream::sync::TcpStream;
+use websocket::OwnedMessage;
+
+
+
+// ================
+// === Protocol ===
+// ================
+
+/// Default port that the parser service listens on.
+pub const DEFAULT_PORT:u16 = 30615;
+
+/// Request sent to the parser service.
+#[derive(Clone,Debug,Serialize)]
+#[serde(rename_all="camelCase")]
+pub enum Request {
+    /// Parse the given program source.
+    ParseRequest {
+        /// Program source code.
+        program : String,
+    },
+}
+
+/// Reply received from the parser service.
+#[derive(Clone,Debug,Deserialize)]
+#[serde(rename_all="camelCase")]
+pub enum Response {
+    /// The program was parsed.
+    Success {
+        /// The resulting module AST.
+        ast : Ast,
+    },
+    /// The parser failed.
+    Error {
+        /// Description of the failure.
+        message : String,
+    },
+}
+
+
+
+// ==============
+// === Client ===
+// ==============
+
+/// A connected parser service client.
+pub struct Client {
+    connection : websocket::sync::Client<TcpStream>,
+}
+
+// Hand-written: the underlying socket has no `Debug`.
+impl Debug for Client {
+    fn fmt(&self, f:&mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Client {{ connection:<websocket> }}")
+    }
+}
+
+impl Client {
+    /// Connects to the parser service on the default localhost port.
+    pub fn new() -> api::Result<Client> {
+        Client::from_address(&format!("ws://localhost:{}", DEFAULT_PORT))
+    }
+
+    /// Connects to the parser service at the given WebSocket address.
+    pub fn from_address(address:&str) -> api::Result<Client> {
+        let connection = ClientBuilder::new(address)
+            .map_err(|e| Error::BackendUnavailable {details:e.to_string()})?
+            .connect_insecure()
+            .map_err(|e| Error::BackendUnavailable {details:e.to_string()})?;
+        Ok(Client {connection})
+    }
+
+    /// Sends a request and receives the typed reply.
+    fn rpc_call(&mut self, request:Request) -> api::Result<Response> {
+        let request_text = serde_json::to_string(&request).map_err(api::interop_error)?;
+        self.connection.send_message(&OwnedMessage::Text(request_text))
+            .map_err(api::interop_error)?;
+        loop {
+            match self.connection.recv_message().map_err(api::interop_error)? {
+                OwnedMessage::Text(reply) =>
+                    return serde_json::from_str(&reply)
+                        .map_err(|e| Error::schema_mismatch(&reply,e)),
+                OwnedMessage::Ping(data) => {
+                    self.connection.send_message(&OwnedMessage::Pong(data))
+                        .map_err(api::interop_error)?;
+                }
+                other => {
+                    let details = format!("unexpected message from the parser: {:?}", other);
+                    return Err(Error::TransportFailure {details, source:None});
+                }
+            }
+        }
+    }
+}
+
+impl IsParser for Client {
+    fn parse(&mut self, program:String) -> api::Result<Ast> {
+        match self.rpc_call(Request::ParseRequest {program})? {
+            Response::Success {ast}    => Ok(ast),
+            Response::Error  {message} => Err(Error::SyntaxPanic {message}),
+        }
+    }
+}
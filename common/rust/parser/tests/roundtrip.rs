@@ -0,0 +1,65 @@
+//! Round-trip integration tests of the parser.
+//!
+//! They require a running parser service, so they are gated behind the
+//! `test-backend` feature and are not part of the default test run:
+//!
+//! ```text
+//! cargo test -p parser --features test-backend
+//! ```
+//!
+//! Each program in the corpus is parsed, the resulting AST is checked for
+//! span/repr consistency, serialized back to text and compared with the
+//! input byte-for-byte. This protects against silent drift between the Scala
+//! parser's output schema and our `ast` types.
+
+#![cfg(feature="test-backend")]
+
+use parser::api::IsParser;
+use parser::Parser;
+
+use ast::HasRepr;
+use ast::HasSpan;
+
+/// Representative Enso programs exercising the shapes the IDE manipulates.
+const CORPUS:&[&str] = &[
+    "",
+    "main = 1",
+    "foo a b = a + b",
+    "main =\n    x = 1\n    x + 2",
+    "import Base.List\n\nmain = List.sum [1, 2, 3]",
+    "add = a -> b -> a + b",
+    "main = here.foo 1 _ 3",
+    "greeting = 'Hello, World!'",
+    "block =\n    '''\n    multi\n    line",
+    "sum = 1 + 2 * 3 - 4 / 5",
+    "acc = .fold 0 (+)",
+];
+
+/// Asserts that the textual length of the AST agrees with its computed span.
+fn assert_span_consistency(ast:&ast::Ast) {
+    assert_eq!(ast.span(), ast.repr().chars().count(),
+        "span disagrees with repr length for: {}", ast.repr());
+}
+
+#[test]
+fn corpus_roundtrip() {
+    let mut parser = Parser::new_or_panic();
+    for program in CORPUS {
+        let ast = parser.parse(program.to_string())
+            .unwrap_or_else(|e| panic!("failed to parse {:?}: {}", program, e));
+        assert_span_consistency(&ast);
+        assert_eq!(&ast.repr(), program,
+            "parse/print roundtrip is not an identity for {:?}", program);
+    }
+}
+
+#[test]
+fn corpus_reparse_is_stable() {
+    let mut parser = Parser::new_or_panic();
+    for program in CORPUS {
+        let first  = parser.parse(program.to_string()).unwrap();
+        let second = parser.parse(first.repr()).unwrap();
+        assert_eq!(first.repr(), second.repr(),
+            "reparsing the printed output diverged for {:?}", program);
+    }
+}
@@ -0,0 +1,68 @@
+//! The common prelude of our Rust crates.
+//!
+//! Gathers the types, traits and helpers that practically every module
+//! needs, so that files can start with a single `use prelude::*;` instead
+//! of a screenful of imports. Only truly ubiquitous items belong here —
+//! anything domain-specific stays with its domain.
+
+#![warn(missing_docs)]
+
+pub use std::cell::Cell;
+pub use std::cell::RefCell;
+pub use std::collections::BTreeMap;
+pub use std::collections::HashMap;
+pub use std::collections::HashSet;
+pub use std::collections::VecDeque;
+pub use std::fmt::Debug;
+pub use std::fmt::Display;
+pub use std::marker::PhantomData;
+pub use std::ops::Deref;
+pub use std::ops::DerefMut;
+pub use std::rc::Rc;
+pub use std::rc::Weak;
+
+pub use derive_more::Display;
+pub use itertools::Itertools;
+pub use shrinkwraprs::Shrinkwrap;
+
+
+
+// ===============
+// === Default ===
+// ===============
+
+/// A shorter form of `Default::default()`, readable in field initializers.
+pub fn default<T:Default>() -> T {
+    Default::default()
+}
+
+
+
+// ===========
+// === Str ===
+// ===========
+
+/// Abstraction over types that can be both viewed and converted to an owned
+/// `String` — accepts `&str`, `String` and friends in string-taking APIs.
+pub trait Str : Into<String> + AsRef<str> {}
+
+impl<T:Into<String> + AsRef<str>> Str for T {}
+
+
+
+// ================
+// === CloneRef ===
+// ================
+
+/// Types whose `clone` only bumps a reference count. The separate name
+/// makes the cheapness explicit at the call site.
+pub trait CloneRef {
+    /// Returns a new reference to the same underlying value.
+    fn clone_ref(&self) -> Self;
+}
+
+impl<T:?Sized> CloneRef for Rc<T> {
+    fn clone_ref(&self) -> Self {
+        self.clone()
+    }
+}